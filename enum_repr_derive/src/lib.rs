@@ -0,0 +1,268 @@
+// Derives for C-like enums, so conversions to and from their integer
+// discriminants don't have to be hand-written matches kept in sync with the
+// enum. Use them through rust_practice's enum_repr module, which re-exports
+// them alongside the error types the generated code refers to. The code
+// looks for those at crate::enum_repr; a crate that has them somewhere
+// else says where with #[enum_repr(crate = "path::to::module")].
+//
+// An enum may also have one catch-all tuple variant holding the repr type,
+// or something that converts into it, like HttpStatus's
+// Unknown(UnknownCode). IntoRepr gives back the value it holds,
+// and FromRepr leaves it to the enum to decide which leftover values are
+// acceptable, so no TryFrom is generated for such enums.
+//
+// #[enum_repr(no_display)] stops EnumName generating Display and FromStr,
+// for enums that already print some other way. It's required with a
+// catch-all variant, whose value a variant name can't give back.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Literal, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Ident, LitStr, Path};
+
+const INTEGER_TYPES: &[&str] = &[
+    "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize"
+];
+
+// What the derives need to know about an enum
+struct EnumInfo {
+    name: Ident,
+    /// The integer type from #[repr(...)], or isize as Rust defaults to
+    repr: Ident,
+    /// Fieldless variants and expressions for their discriminants
+    unit_variants: Vec<(Ident, TokenStream2)>,
+    /// The one variant allowed to hold a value, of the repr type or
+    /// something converting into it
+    catch_all: Option<Ident>,
+    /// The module with the error types in, from #[enum_repr(crate = "...")]
+    module: Path,
+    /// False for #[enum_repr(no_display)]
+    display: bool
+}
+
+fn enum_info(input: &DeriveInput) -> syn::Result<EnumInfo> {
+    let data = match input.data {
+        Data::Enum(ref data) => data,
+        _ => return Err(syn::Error::new_spanned(&input.ident, "only enums can derive this"))
+    };
+
+    let mut module: Path = parse_quote!(crate::enum_repr);
+    let mut display = true;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("enum_repr")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                module = meta.value()?.parse::<LitStr>()?.parse()?;
+                Ok(())
+            } else if meta.path.is_ident("no_display") {
+                display = false;
+                Ok(())
+            } else {
+                Err(meta.error("expected crate = \"...\" or no_display"))
+            }
+        })?;
+    }
+
+    let mut repr = Ident::new("isize", input.ident.span());
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            if let Some(ident) = meta.path.get_ident() {
+                if INTEGER_TYPES.iter().any(|ty| ident == ty) {
+                    repr = ident.clone();
+                }
+            }
+            // Skip arguments, as in #[repr(align(8))]
+            if meta.input.peek(syn::token::Paren) {
+                let _arguments;
+                syn::parenthesized!(_arguments in meta.input);
+            }
+            Ok(())
+        })?;
+    }
+
+    // Implicit discriminants count up from the last explicit one, or zero
+    let mut unit_variants = Vec::new();
+    let mut catch_all = None;
+    let mut last_explicit: Option<&syn::Expr> = None;
+    let mut since_explicit = 0usize;
+    for variant in &data.variants {
+        if let Some((_, ref expr)) = variant.discriminant {
+            last_explicit = Some(expr);
+            since_explicit = 0;
+        }
+        let discriminant = match last_explicit {
+            Some(expr) if since_explicit == 0 => quote!((#expr)),
+            Some(expr) => {
+                let offset = Literal::usize_unsuffixed(since_explicit);
+                quote!((#expr) + #offset)
+            }
+            None => {
+                let value = Literal::usize_unsuffixed(since_explicit);
+                quote!(#value)
+            }
+        };
+        since_explicit += 1;
+
+        match variant.fields {
+            Fields::Unit => unit_variants.push((variant.ident.clone(), discriminant)),
+            Fields::Unnamed(ref fields) if fields.unnamed.len() == 1 && catch_all.is_none() =>
+                catch_all = Some(variant.ident.clone()),
+            _ => return Err(syn::Error::new_spanned(
+                &variant.ident,
+                "variants must be fieldless, apart from one catch-all with a single field"))
+        }
+    }
+
+    Ok(EnumInfo { name: input.ident.clone(), repr, unit_variants, catch_all, module, display })
+}
+
+fn derive_with(input: TokenStream, generate: fn(&EnumInfo) -> syn::Result<TokenStream2>) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    enum_info(&input)
+        .and_then(|info| generate(&info))
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Generates `from_repr(n) -> Option<Self>`, and `TryFrom<repr>` with
+/// `enum_repr::InvalidRepr` as the error when there's no catch-all variant
+#[proc_macro_derive(FromRepr, attributes(enum_repr))]
+pub fn derive_from_repr(input: TokenStream) -> TokenStream {
+    derive_with(input, |info| {
+        let EnumInfo { ref name, ref repr, ref module, .. } = *info;
+        let idents = info.unit_variants.iter().map(|(ident, _)| ident);
+        let values = info.unit_variants.iter().map(|(_, value)| value);
+
+        let mut tokens = quote! {
+            impl #name {
+                /// The fieldless variant whose discriminant is `n`
+                pub fn from_repr(n: #repr) -> ::std::option::Option<Self> {
+                    #(if n == #values { return ::std::option::Option::Some(#name::#idents); })*
+                    ::std::option::Option::None
+                }
+            }
+        };
+        if info.catch_all.is_none() {
+            tokens.extend(quote! {
+                impl ::std::convert::TryFrom<#repr> for #name {
+                    type Error = #module::InvalidRepr<#repr>;
+
+                    fn try_from(n: #repr) -> ::std::result::Result<Self, Self::Error> {
+                        #name::from_repr(n).ok_or(#module::InvalidRepr(n))
+                    }
+                }
+            });
+        }
+        Ok(tokens)
+    })
+}
+
+/// Generates `From<Enum>` for the repr type
+#[proc_macro_derive(IntoRepr, attributes(enum_repr))]
+pub fn derive_into_repr(input: TokenStream) -> TokenStream {
+    derive_with(input, |info| {
+        let EnumInfo { ref name, ref repr, .. } = *info;
+        let idents = info.unit_variants.iter().map(|(ident, _)| ident);
+        let values = info.unit_variants.iter().map(|(_, value)| value);
+        let catch_all = info.catch_all.iter();
+
+        Ok(quote! {
+            impl ::std::convert::From<#name> for #repr {
+                fn from(value: #name) -> #repr {
+                    match value {
+                        #(#name::#idents => #values,)*
+                        #(#name::#catch_all(n) => ::std::convert::From::from(n),)*
+                    }
+                }
+            }
+        })
+    })
+}
+
+/// Generates `VARIANT_COUNT`, `VARIANTS` and `iter()` over the fieldless variants
+#[proc_macro_derive(EnumIter, attributes(enum_repr))]
+pub fn derive_enum_iter(input: TokenStream) -> TokenStream {
+    derive_with(input, |info| {
+        let name = &info.name;
+        let idents: Vec<_> = info.unit_variants.iter().map(|(ident, _)| ident).collect();
+        let count = Literal::usize_unsuffixed(idents.len());
+
+        Ok(quote! {
+            impl #name {
+                /// How many fieldless variants there are
+                pub const VARIANT_COUNT: usize = #count;
+
+                /// Every fieldless variant, in declaration order
+                pub const VARIANTS: [#name; #count] = [#(#name::#idents),*];
+
+                /// Iterate over the fieldless variants in declaration order
+                pub fn iter() -> ::std::array::IntoIter<#name, #count> {
+                    ::std::iter::IntoIterator::into_iter(#name::VARIANTS)
+                }
+            }
+        })
+    })
+}
+
+/// Generates `variant_name()` and `from_variant_name()`, and `Display` and
+/// `FromStr` using them unless the enum has #[enum_repr(no_display)], with
+/// `enum_repr::ParseVariantError` as the error
+#[proc_macro_derive(EnumName, attributes(enum_repr))]
+pub fn derive_enum_name(input: TokenStream) -> TokenStream {
+    derive_with(input, |info| {
+        let EnumInfo { ref name, ref module, .. } = *info;
+        if let Some(ref catch_all) = info.catch_all {
+            if info.display {
+                return Err(syn::Error::new_spanned(
+                    catch_all, "EnumName needs #[enum_repr(no_display)] with a catch-all variant"));
+            }
+        }
+        let idents: Vec<_> = info.unit_variants.iter().map(|(ident, _)| ident).collect();
+        let names: Vec<_> = idents.iter().map(|ident| ident.to_string()).collect();
+        let catch_all = info.catch_all.iter();
+        let catch_all_name = info.catch_all.iter().map(|ident| ident.to_string());
+        let enum_name = name.to_string();
+
+        let mut tokens = quote! {
+            impl #name {
+                /// The name of this variant as written in the source
+                pub fn variant_name(&self) -> &'static str {
+                    match *self {
+                        #(#name::#idents => #names,)*
+                        #(#name::#catch_all(_) => #catch_all_name,)*
+                    }
+                }
+
+                /// The fieldless variant called `text` in the source
+                pub fn from_variant_name(text: &str) -> ::std::result::Result<Self, #module::ParseVariantError> {
+                    match text {
+                        #(#names => ::std::result::Result::Ok(#name::#idents),)*
+                        _ => ::std::result::Result::Err(#module::ParseVariantError {
+                            enum_name: #enum_name,
+                            text: text.to_string()
+                        })
+                    }
+                }
+            }
+        };
+        if info.display {
+            tokens.extend(quote! {
+                impl ::std::fmt::Display for #name {
+                    fn fmt(&self, dest: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                        dest.write_str(self.variant_name())
+                    }
+                }
+
+                impl ::std::str::FromStr for #name {
+                    type Err = #module::ParseVariantError;
+
+                    fn from_str(text: &str) -> ::std::result::Result<Self, Self::Err> {
+                        #name::from_variant_name(text)
+                    }
+                }
+            });
+        }
+        Ok(tokens)
+    })
+}
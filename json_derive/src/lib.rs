@@ -0,0 +1,250 @@
+// Derives for converting structs and enums to and from rust_practice's Json
+// values, so types don't need hand-written conversions field by field. Use
+// them through rust_practice's json_convert module, which re-exports them
+// next to the traits and helpers the generated code refers to.
+//
+// Structs with named fields become objects, newtype structs become what
+// they wrap, other tuple structs become arrays and unit structs null. Enum
+// variants without fields become their name as a string; the rest become
+// an object with the name as its one key, holding the fields as a struct
+// would. #[json(rename = "...")] changes the name used for a field or
+// variant. Type parameters get a ToJson or FromJson bound.
+//
+// The generated code uses the json_convert module as crate::json_convert.
+// A crate that has it, or a re-export of it, somewhere else names the
+// path with #[json(crate = "...")] on the type.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Fields, Generics, Ident, LitStr, Path};
+
+/// The name a field or variant has in JSON, from #[json(rename = "...")]
+/// or the identifier
+fn json_name(attrs: &[Attribute], ident: &Ident) -> syn::Result<String> {
+    let mut name = ident.to_string();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("json")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                name = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("the only json attribute is rename"))
+            }
+        })?;
+    }
+    Ok(name)
+}
+
+/// Where the generated code finds ToJson, FromJson, Json and the helpers
+/// it calls: crate::json_convert, or #[json(crate = "...")] on the type
+fn json_module(attrs: &[Attribute]) -> syn::Result<Path> {
+    let mut module = parse_quote!(crate::json_convert);
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("json")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                module = meta.value()?.parse::<LitStr>()?.parse()?;
+                Ok(())
+            } else {
+                Err(meta.error("the only json attribute on a type is crate"))
+            }
+        })?;
+    }
+    Ok(module)
+}
+
+fn with_bound(generics: &Generics, bound: TokenStream2) -> Generics {
+    let mut generics = generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    generics
+}
+
+/// An expression making Json out of `fields`, given expressions that
+/// borrow each field's value
+fn fields_to_json(module: &Path, fields: &Fields, values: &[TokenStream2]) -> syn::Result<TokenStream2> {
+    Ok(match *fields {
+        Fields::Named(ref named) => {
+            let names = named.named.iter()
+                .map(|field| json_name(&field.attrs, field.ident.as_ref().unwrap()))
+                .collect::<syn::Result<Vec<_>>>()?;
+            quote! {{
+                let mut members = ::std::collections::BTreeMap::new();
+                #(members.insert(::std::string::String::from(#names),
+                                 #module::ToJson::to_json(#values));)*
+                #module::Json::Object(::std::boxed::Box::new(members))
+            }}
+        }
+        Fields::Unnamed(ref unnamed) if unnamed.unnamed.len() == 1 => {
+            let value = &values[0];
+            quote!(#module::ToJson::to_json(#value))
+        }
+        Fields::Unnamed(_) => quote! {
+            #module::Json::Array(::std::vec![#(#module::ToJson::to_json(#values)),*])
+        },
+        Fields::Unit => quote!(#module::Json::Null)
+    })
+}
+
+/// An expression reading `fields` out of the Json in `json` and building
+/// them with `constructor`, as a Result
+fn fields_from_json(module: &Path, fields: &Fields, constructor: TokenStream2) -> syn::Result<TokenStream2> {
+    Ok(match *fields {
+        Fields::Named(ref named) => {
+            let idents: Vec<_> = named.named.iter().map(|field| field.ident.as_ref().unwrap()).collect();
+            let names = named.named.iter()
+                .map(|field| json_name(&field.attrs, field.ident.as_ref().unwrap()))
+                .collect::<syn::Result<Vec<_>>>()?;
+            quote! {{
+                let members = #module::object(json)?;
+                ::std::result::Result::Ok(#constructor {
+                    #(#idents: #module::field(members, #names)?),*
+                })
+            }}
+        }
+        Fields::Unnamed(ref unnamed) if unnamed.unnamed.len() == 1 => quote! {
+            ::std::result::Result::Ok(#constructor(#module::FromJson::from_json(json)?))
+        },
+        Fields::Unnamed(ref unnamed) => {
+            let indices = 0..unnamed.unnamed.len();
+            let count = unnamed.unnamed.len();
+            quote! {{
+                let elements = #module::elements(json, #count)?;
+                ::std::result::Result::Ok(#constructor(#(#module::element(elements, #indices)?),*))
+            }}
+        }
+        Fields::Unit => quote! {{
+            #module::null(json)?;
+            ::std::result::Result::Ok(#constructor)
+        }}
+    })
+}
+
+/// Names to bind a variant's fields to, and a pattern binding them
+fn variant_pattern(name: &Ident, variant: &syn::Variant) -> (Vec<TokenStream2>, TokenStream2) {
+    let ident = &variant.ident;
+    let bindings: Vec<_> = (0..variant.fields.len()).map(|i| format_ident!("field_{}", i)).collect();
+    let pattern = match variant.fields {
+        Fields::Named(ref named) => {
+            let idents = named.named.iter().map(|field| field.ident.as_ref().unwrap());
+            quote!(#name::#ident { #(#idents: #bindings),* })
+        }
+        Fields::Unnamed(_) => quote!(#name::#ident(#(#bindings),*)),
+        Fields::Unit => quote!(#name::#ident)
+    };
+    (bindings.iter().map(|binding| quote!(#binding)).collect(), pattern)
+}
+
+fn to_json(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let module = json_module(&input.attrs)?;
+    let body = match input.data {
+        Data::Struct(ref data) => {
+            let values: Vec<_> = data.fields.iter().enumerate().map(|(i, field)| match field.ident {
+                Some(ref ident) => quote!(&self.#ident),
+                None => {
+                    let index = syn::Index::from(i);
+                    quote!(&self.#index)
+                }
+            }).collect();
+            fields_to_json(&module, &data.fields, &values)?
+        }
+        Data::Enum(ref data) => {
+            let mut arms = Vec::new();
+            for variant in &data.variants {
+                let variant_name = json_name(&variant.attrs, &variant.ident)?;
+                let (bindings, pattern) = variant_pattern(name, variant);
+                arms.push(match variant.fields {
+                    Fields::Unit => quote! {
+                        #pattern => #module::Json::String(::std::string::String::from(#variant_name))
+                    },
+                    ref fields => {
+                        let value = fields_to_json(&module, fields, &bindings)?;
+                        quote!(#pattern => #module::tagged(#variant_name, #value))
+                    }
+                });
+            }
+            quote!(match self { #(#arms,)* })
+        }
+        Data::Union(_) => return Err(syn::Error::new_spanned(name, "unions can't derive ToJson"))
+    };
+
+    let generics = with_bound(&input.generics, quote!(#module::ToJson));
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #module::ToJson for #name #type_generics #where_clause {
+            fn to_json(&self) -> #module::Json {
+                #body
+            }
+        }
+    })
+}
+
+fn from_json(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let module = json_module(&input.attrs)?;
+    let body = match input.data {
+        Data::Struct(ref data) => fields_from_json(&module, &data.fields, quote!(#name))?,
+        Data::Enum(ref data) => {
+            let mut arms = Vec::new();
+            for variant in &data.variants {
+                let variant_name = json_name(&variant.attrs, &variant.ident)?;
+                let ident = &variant.ident;
+                arms.push(match variant.fields {
+                    Fields::Unit => quote! {
+                        (#variant_name, ::std::option::Option::None) => ::std::result::Result::Ok(#name::#ident)
+                    },
+                    ref fields => {
+                        let value = fields_from_json(&module, fields, quote!(#name::#ident))?;
+                        quote! {
+                            (#variant_name, ::std::option::Option::Some(json)) =>
+                                #module::within(#variant_name, || #value)
+                        }
+                    }
+                });
+            }
+            let enum_name = name.to_string();
+            quote! {
+                match #module::variant(json)? {
+                    #(#arms,)*
+                    (variant, _) => ::std::result::Result::Err(
+                        #module::FromJsonError::unknown_variant(#enum_name, variant))
+                }
+            }
+        }
+        Data::Union(_) => return Err(syn::Error::new_spanned(name, "unions can't derive FromJson"))
+    };
+
+    let generics = with_bound(&input.generics, quote!(#module::FromJson));
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #module::FromJson for #name #type_generics #where_clause {
+            fn from_json(json: &#module::Json)
+                -> ::std::result::Result<Self, #module::FromJsonError>
+            {
+                #body
+            }
+        }
+    })
+}
+
+fn derive_with(input: TokenStream, generate: fn(&DeriveInput) -> syn::Result<TokenStream2>) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    generate(&input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+/// Generates `json_convert::ToJson`
+#[proc_macro_derive(ToJson, attributes(json))]
+pub fn derive_to_json(input: TokenStream) -> TokenStream {
+    derive_with(input, to_json)
+}
+
+/// Generates `json_convert::FromJson`, reporting where in the Json a value
+/// didn't fit
+#[proc_macro_derive(FromJson, attributes(json))]
+pub fn derive_from_json(input: TokenStream) -> TokenStream {
+    derive_with(input, from_json)
+}
@@ -0,0 +1,838 @@
+#![allow(dead_code)]
+
+// A civil date and time on the proleptic Gregorian calendar, with no time
+// zone attached. Nothing here asks the operating system anything; dates are
+// converted to and from a plain count of days since 1970-01-01 with Howard
+// Hinnant's days_from_civil / civil_from_days algorithms. Where a DateTime
+// has to be pinned to an instant, as with Unix timestamps and RFC 3339, it
+// is taken to be in UTC.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Returns true if `year` has a February 29th
+pub fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+/// Number of days in `month` (1 to 12) of `year`
+pub fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 => if is_leap_year(year) { 29 } else { 28 },
+        _ => 31
+    }
+}
+
+/// Number of days from 1970-01-01 to the given date, negative before it
+pub fn days_from_civil(year: i32, month: u8, day: u8) -> i64 {
+    // Count years from March, so the leap day falls at the end of the year
+    let year = i64::from(year) - if month <= 2 { 1 } else { 0 };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5
+        + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The date `days` days after 1970-01-01, as (year, month, day). Returns
+/// `None` if the year doesn't fit in an `i32`.
+pub fn civil_from_days(days: i64) -> Option<(i32, u8, u8)> {
+    let days = days.checked_add(719_468)?;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524
+                       - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u8;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u8;
+    let year = era.checked_mul(400)?.checked_add(year_of_era)? + if month <= 2 { 1 } else { 0 };
+    if year < i64::from(i32::MIN) || year > i64::from(i32::MAX) {
+        return None;
+    }
+    Some((year as i32, month, day))
+}
+
+/// Number of days in `year`
+pub fn days_in_year(year: i32) -> u16 {
+    if is_leap_year(year) { 366 } else { 365 }
+}
+
+/// A day of the week. Weeks start on Monday, as in ISO 8601.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday
+}
+
+impl Weekday {
+    pub const ALL: [Weekday; 7] = [Weekday::Monday, Weekday::Tuesday, Weekday::Wednesday,
+                                   Weekday::Thursday, Weekday::Friday, Weekday::Saturday,
+                                   Weekday::Sunday];
+
+    /// The weekday of the day `days` days after 1970-01-01, which was a Thursday
+    pub fn from_days(days: i64) -> Weekday {
+        Weekday::ALL[(days + 3).rem_euclid(7) as usize]
+    }
+
+    /// 1 for Monday up to 7 for Sunday
+    pub fn number_from_monday(self) -> u8 {
+        self as u8 + 1
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Weekday::Monday => "Monday",
+            Weekday::Tuesday => "Tuesday",
+            Weekday::Wednesday => "Wednesday",
+            Weekday::Thursday => "Thursday",
+            Weekday::Friday => "Friday",
+            Weekday::Saturday => "Saturday",
+            Weekday::Sunday => "Sunday"
+        }
+    }
+
+    /// The first three letters of the name, like "Tue"
+    pub fn abbreviation(self) -> &'static str {
+        &self.name()[..3]
+    }
+}
+
+/// Number of ISO 8601 weeks in `year`, either 52 or 53. Years that start
+/// on a Thursday, and leap years that start on a Wednesday, get 53.
+pub fn iso_weeks_in_year(year: i32) -> u8 {
+    match Weekday::from_days(days_from_civil(year, 1, 1)) {
+        Weekday::Thursday => 53,
+        Weekday::Wednesday if is_leap_year(year) => 53,
+        _ => 52
+    }
+}
+
+const MONTH_NAMES: [&str; 12] = ["January", "February", "March", "April", "May", "June", "July",
+                                 "August", "September", "October", "November", "December"];
+
+/// The English name of `month` (1 to 12)
+pub fn month_name(month: u8) -> &'static str {
+    MONTH_NAMES[usize::from(month - 1)]
+}
+
+/// The English ordinal suffix for `n`: "st", "nd", "rd" or "th"
+pub fn ordinal_suffix(n: u32) -> &'static str {
+    match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th"
+    }
+}
+
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+const NANOS_PER_DAY: i128 = 86_400 * NANOS_PER_SECOND;
+
+/// A date and time of day, to the nanosecond, with no time zone.
+///
+/// Fields are ordered from most to least significant, so the derived
+/// comparisons are chronological.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    year: i32,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+    nanosecond: u32
+}
+
+impl DateTime {
+    /// A date and time, or `None` if any part is out of range
+    pub fn new(year: i32, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Option<DateTime> {
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month)
+            || hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+        Some(DateTime { year, month, day, hour, minute, second, nanosecond: 0 })
+    }
+
+    /// Midnight at the start of a date, or `None` if the date doesn't exist
+    pub fn from_ymd(year: i32, month: u8, day: u8) -> Option<DateTime> {
+        DateTime::new(year, month, day, 0, 0, 0)
+    }
+
+    /// The same time with the fraction of a second replaced
+    pub fn with_nanosecond(self, nanosecond: u32) -> Option<DateTime> {
+        if nanosecond >= 1_000_000_000 {
+            return None;
+        }
+        Some(DateTime { nanosecond, ..self })
+    }
+
+    pub fn year(&self) -> i32 { self.year }
+    pub fn month(&self) -> u8 { self.month }
+    pub fn day(&self) -> u8 { self.day }
+    pub fn hour(&self) -> u8 { self.hour }
+    pub fn minute(&self) -> u8 { self.minute }
+    pub fn second(&self) -> u8 { self.second }
+    pub fn nanosecond(&self) -> u32 { self.nanosecond }
+
+    /// Nanoseconds since midnight
+    fn nanos_of_day(&self) -> i128 {
+        let seconds = i128::from(self.hour) * 3600 + i128::from(self.minute) * 60
+            + i128::from(self.second);
+        seconds * NANOS_PER_SECOND + i128::from(self.nanosecond)
+    }
+
+    /// Move by a fixed number of nanoseconds, which may be negative
+    pub fn add_nanos(self, nanos: i128) -> Option<DateTime> {
+        let total = (i128::from(days_from_civil(self.year, self.month, self.day)) * NANOS_PER_DAY)
+            .checked_add(self.nanos_of_day())?
+            .checked_add(nanos)?;
+        let days = total.div_euclid(NANOS_PER_DAY);
+        let nanos_of_day = total.rem_euclid(NANOS_PER_DAY);
+        if days < i128::from(i64::MIN) || days > i128::from(i64::MAX) {
+            return None;
+        }
+        let (year, month, day) = civil_from_days(days as i64)?;
+        let seconds = (nanos_of_day / NANOS_PER_SECOND) as u32;
+        Some(DateTime {
+            year,
+            month,
+            day,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+            nanosecond: (nanos_of_day % NANOS_PER_SECOND) as u32
+        })
+    }
+
+    /// Move by whole calendar months, which may be negative. If the day
+    /// doesn't exist in the new month it's clamped to the month's last
+    /// day, so January 31st plus one month is February 28th or 29th.
+    pub fn add_months(self, months: i64) -> Option<DateTime> {
+        let index = i64::from(self.year).checked_mul(12)?
+            .checked_add(i64::from(self.month) - 1)?
+            .checked_add(months)?;
+        let year = index.div_euclid(12);
+        if year < i64::from(i32::MIN) || year > i64::from(i32::MAX) {
+            return None;
+        }
+        let year = year as i32;
+        let month = index.rem_euclid(12) as u8 + 1;
+        let day = self.day.min(days_in_month(year, month));
+        Some(DateTime { year, month, day, ..self })
+    }
+
+    /// Move by whole years, which may be negative. February 29th becomes
+    /// February 28th in years without one.
+    pub fn add_years(self, years: i64) -> Option<DateTime> {
+        self.add_months(years.checked_mul(12)?)
+    }
+
+    /// Move by whole days, which may be negative
+    pub fn add_days(self, days: i64) -> Option<DateTime> {
+        self.add_nanos(i128::from(days) * NANOS_PER_DAY)
+    }
+
+    /// Number of days from 1970-01-01 to this date
+    pub fn days_since_epoch(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day)
+    }
+
+    pub fn weekday(&self) -> Weekday {
+        Weekday::from_days(self.days_since_epoch())
+    }
+
+    /// The day of the year, from 1 for January 1st up to 365 or 366
+    pub fn ordinal(&self) -> u16 {
+        (self.days_since_epoch() - days_from_civil(self.year, 1, 1) + 1) as u16
+    }
+
+    /// The ISO 8601 week-numbering year and week (1 to 53). Week 1 is the
+    /// week with the year's first Thursday in it, so early January can
+    /// belong to the last week of the year before, and late December to
+    /// week 1 of the year after.
+    pub fn iso_week(&self) -> (i32, u8) {
+        let weekday = i32::from(self.weekday().number_from_monday());
+        let week = (i32::from(self.ordinal()) - weekday + 10) / 7;
+        if week < 1 {
+            (self.year - 1, iso_weeks_in_year(self.year - 1))
+        } else if week > i32::from(iso_weeks_in_year(self.year)) {
+            (self.year + 1, 1)
+        } else {
+            (self.year, week as u8)
+        }
+    }
+
+    /// Nanoseconds from `earlier` to this time, negative if `earlier` is
+    /// actually later
+    pub fn nanos_since(&self, earlier: DateTime) -> i128 {
+        self.to_unix_nanos() - earlier.to_unix_nanos()
+    }
+}
+
+#[test]
+fn test_leap_years_and_month_lengths() {
+    assert!(is_leap_year(2016));
+    assert!(is_leap_year(2000));
+    assert!(!is_leap_year(1900));
+    assert!(!is_leap_year(2019));
+    assert!(is_leap_year(-4));
+    assert_eq!(days_in_month(2016, 2), 29);
+    assert_eq!(days_in_month(2019, 2), 28);
+    assert_eq!(days_in_month(2019, 4), 30);
+    assert_eq!(days_in_month(2019, 12), 31);
+}
+
+#[test]
+fn test_civil_day_numbers() {
+    assert_eq!(days_from_civil(1970, 1, 1), 0);
+    assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+    assert_eq!(days_from_civil(1969, 12, 31), -1);
+    assert_eq!(civil_from_days(11_016), Some((2000, 2, 29)));
+    assert_eq!(civil_from_days(-719_468), Some((0, 3, 1)));
+
+    // Every day for a few centuries either side of the epoch round trips
+    let mut expected = (1600, 1, 1);
+    for days in days_from_civil(1600, 1, 1)..days_from_civil(2400, 1, 1) {
+        assert_eq!(civil_from_days(days), Some(expected));
+        assert_eq!(days_from_civil(expected.0, expected.1, expected.2), days);
+        expected = if expected.2 < days_in_month(expected.0, expected.1) {
+            (expected.0, expected.1, expected.2 + 1)
+        } else if expected.1 < 12 {
+            (expected.0, expected.1 + 1, 1)
+        } else {
+            (expected.0 + 1, 1, 1)
+        };
+    }
+}
+
+#[test]
+fn test_date_time_arithmetic() {
+    let start = DateTime::new(2016, 2, 9, 21, 42, 0).unwrap();
+    assert!(DateTime::new(2019, 2, 29, 0, 0, 0).is_none());
+    assert!(DateTime::new(2019, 1, 1, 24, 0, 0).is_none());
+
+    assert_eq!(start.add_nanos(3 * 3600 * NANOS_PER_SECOND),
+               DateTime::new(2016, 2, 10, 0, 42, 0));
+    assert_eq!(start.add_nanos(-22 * 3600 * NANOS_PER_SECOND),
+               DateTime::new(2016, 2, 8, 23, 42, 0));
+    assert_eq!(start.add_nanos(1).unwrap().nanosecond(), 1);
+    assert!(start < start.add_nanos(1).unwrap());
+
+    let end_of_january = DateTime::from_ymd(2016, 1, 31).unwrap();
+    assert_eq!(end_of_january.add_months(1), DateTime::from_ymd(2016, 2, 29));
+    assert_eq!(end_of_january.add_months(13), DateTime::from_ymd(2017, 2, 28));
+    assert_eq!(end_of_january.add_months(-2), DateTime::from_ymd(2015, 11, 30));
+    assert!(end_of_january.add_months(i64::MAX).is_none());
+}
+
+#[test]
+fn test_weekdays_and_weeks() {
+    let date = |y, m, d| DateTime::from_ymd(y, m, d).unwrap();
+
+    assert_eq!(date(1970, 1, 1).weekday(), Weekday::Thursday);
+    assert_eq!(date(2016, 2, 9).weekday(), Weekday::Tuesday);
+    assert_eq!(date(1969, 12, 28).weekday(), Weekday::Sunday);
+    assert_eq!(date(2000, 1, 1).weekday().abbreviation(), "Sat");
+
+    assert_eq!(date(2016, 1, 1).ordinal(), 1);
+    assert_eq!(date(2016, 12, 31).ordinal(), 366);
+    assert_eq!(date(2019, 12, 31).ordinal(), 365);
+    assert_eq!(date(2016, 3, 1).ordinal(), 61);
+
+    assert_eq!(date(2016, 1, 1).iso_week(), (2015, 53));
+    assert_eq!(date(2016, 1, 4).iso_week(), (2016, 1));
+    assert_eq!(date(2016, 2, 9).iso_week(), (2016, 6));
+    assert_eq!(date(2019, 12, 30).iso_week(), (2020, 1));
+    assert_eq!(date(2020, 12, 31).iso_week(), (2020, 53));
+    assert_eq!(date(2021, 1, 3).iso_week(), (2020, 53));
+    assert_eq!(iso_weeks_in_year(2015), 53);
+    assert_eq!(iso_weeks_in_year(2016), 52);
+
+    assert_eq!(date(2016, 2, 28).add_days(2), Some(date(2016, 3, 1)));
+    assert_eq!(date(2016, 3, 1).add_days(-366), Some(date(2015, 3, 1)));
+    assert_eq!(date(2016, 2, 29).add_years(1), Some(date(2017, 2, 28)));
+    assert_eq!(date(2016, 2, 29).add_years(-4), Some(date(2012, 2, 29)));
+    assert_eq!(date(2016, 3, 1).nanos_since(date(2016, 2, 28)), 2 * NANOS_PER_DAY);
+
+    let suffixes: Vec<&str> = [1, 2, 3, 4, 11, 12, 13, 21, 22, 23, 111].iter()
+        .map(|&n| ordinal_suffix(n))
+        .collect();
+    assert_eq!(suffixes, vec!["st", "nd", "rd", "th", "th", "th", "th", "st", "nd", "rd", "th"]);
+}
+
+// Unix time
+
+impl DateTime {
+    /// The time `secs` seconds after 1970-01-01T00:00:00Z, ignoring leap
+    /// seconds as Unix time does
+    pub fn from_unix(secs: i64) -> Option<DateTime> {
+        DateTime::from_unix_nanos(i128::from(secs) * NANOS_PER_SECOND)
+    }
+
+    /// The time `nanos` nanoseconds after 1970-01-01T00:00:00Z
+    pub fn from_unix_nanos(nanos: i128) -> Option<DateTime> {
+        DateTime::from_ymd(1970, 1, 1)?.add_nanos(nanos)
+    }
+
+    /// Whole seconds since 1970-01-01T00:00:00Z, rounded down
+    pub fn to_unix(&self) -> i64 {
+        self.days_since_epoch() * 86_400
+            + i64::from(self.hour) * 3600 + i64::from(self.minute) * 60 + i64::from(self.second)
+    }
+
+    /// Nanoseconds since 1970-01-01T00:00:00Z
+    pub fn to_unix_nanos(&self) -> i128 {
+        i128::from(self.days_since_epoch()) * NANOS_PER_DAY + self.nanos_of_day()
+    }
+
+    /// The UTC date and time of a `SystemTime`
+    pub fn from_system_time(time: SystemTime) -> Option<DateTime> {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => DateTime::from_unix_nanos(after.as_nanos() as i128),
+            Err(before) => DateTime::from_unix_nanos(-(before.duration().as_nanos() as i128))
+        }
+    }
+
+    /// This UTC date and time as a `SystemTime`, or `None` if the system
+    /// clock can't represent it
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        let nanos = self.to_unix_nanos();
+        let magnitude = Duration::new((nanos.unsigned_abs() / 1_000_000_000) as u64,
+                                      (nanos.unsigned_abs() % 1_000_000_000) as u32);
+        if nanos < 0 {
+            UNIX_EPOCH.checked_sub(magnitude)
+        } else {
+            UNIX_EPOCH.checked_add(magnitude)
+        }
+    }
+
+    /// The current UTC date and time from the system clock
+    pub fn now() -> DateTime {
+        DateTime::from_system_time(SystemTime::now()).expect("system clock out of range")
+    }
+}
+
+#[test]
+fn test_unix_time() {
+    let feb_9th = DateTime::new(2016, 2, 9, 21, 42, 0).unwrap();
+    assert_eq!(feb_9th.to_unix(), 1_455_054_120);
+    assert_eq!(DateTime::from_unix(1_455_054_120), Some(feb_9th));
+    assert_eq!(DateTime::from_unix(0), DateTime::from_ymd(1970, 1, 1));
+    assert_eq!(DateTime::from_unix(-1), DateTime::new(1969, 12, 31, 23, 59, 59));
+
+    let before_epoch = DateTime::from_unix_nanos(-1).unwrap();
+    assert_eq!(before_epoch.nanosecond(), 999_999_999);
+    assert_eq!(before_epoch.to_unix(), -1);
+    assert_eq!(before_epoch.to_unix_nanos(), -1);
+
+    let time = UNIX_EPOCH + Duration::new(1_455_054_120, 500);
+    let date_time = DateTime::from_system_time(time).unwrap();
+    assert_eq!(date_time, feb_9th.with_nanosecond(500).unwrap());
+    assert_eq!(date_time.to_system_time(), Some(time));
+    assert!(DateTime::from_unix(i64::MAX).is_none());
+}
+
+// RFC 3339 timestamps, like "2016-02-09T21:42:00Z" or
+// "2016-02-09T22:42:00.25+01:00"
+
+/// Why a string isn't an RFC 3339 timestamp
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseDateTimeError {
+    /// Byte offset of the problem in the input
+    pub position: usize,
+    pub reason: &'static str
+}
+
+impl fmt::Display for ParseDateTimeError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(dest, "invalid RFC 3339 timestamp at offset {}: {}", self.position, self.reason)
+    }
+}
+
+impl std::error::Error for ParseDateTimeError {}
+
+// Reads an RFC 3339 timestamp a field at a time
+struct Rfc3339Reader<'t> {
+    bytes: &'t [u8],
+    at: usize
+}
+
+impl<'t> Rfc3339Reader<'t> {
+    fn error(&self, reason: &'static str) -> ParseDateTimeError {
+        ParseDateTimeError { position: self.at, reason }
+    }
+
+    /// Exactly `digits` decimal digits
+    fn number(&mut self, digits: usize, reason: &'static str) -> Result<u32, ParseDateTimeError> {
+        let field = self.bytes.get(self.at..self.at + digits)
+            .filter(|field| field.iter().all(u8::is_ascii_digit))
+            .ok_or_else(|| self.error(reason))?;
+        self.at += digits;
+        Ok(field.iter().fold(0, |n, digit| n * 10 + u32::from(digit - b'0')))
+    }
+
+    fn expect(&mut self, allowed: &[u8], reason: &'static str) -> Result<u8, ParseDateTimeError> {
+        match self.bytes.get(self.at) {
+            Some(byte) if allowed.contains(byte) => {
+                self.at += 1;
+                Ok(*byte)
+            }
+            _ => Err(self.error(reason))
+        }
+    }
+
+    /// A field that must lie in `range`, reporting errors at its start
+    fn field(&mut self, digits: usize, range: std::ops::RangeInclusive<u32>, reason: &'static str)
+             -> Result<u8, ParseDateTimeError> {
+        let start = self.at;
+        let n = self.number(digits, reason)?;
+        if !range.contains(&n) {
+            return Err(ParseDateTimeError { position: start, reason });
+        }
+        Ok(n as u8)
+    }
+}
+
+impl DateTime {
+    /// Read an RFC 3339 timestamp, giving the UTC date and time it names.
+    /// A leap second (":60") is read as the first instant of the next minute.
+    pub fn parse_rfc3339(text: &str) -> Result<DateTime, ParseDateTimeError> {
+        DateTime::parse_timestamp(text, false)
+    }
+
+    /// Read an RFC 3339 timestamp, or if `expanded` one whose year may be
+    /// written with a sign and more digits, as Display writes years
+    /// outside 0 to 9999
+    fn parse_timestamp(text: &str, expanded: bool) -> Result<DateTime, ParseDateTimeError> {
+        let mut reader = Rfc3339Reader { bytes: text.as_bytes(), at: 0 };
+
+        let year = match reader.bytes.first() {
+            Some(b'+') | Some(b'-') if expanded => {
+                reader.at += 1;
+                while reader.bytes.get(reader.at).is_some_and(u8::is_ascii_digit) {
+                    reader.at += 1;
+                }
+                let year = if reader.at < 5 { None } else { text[..reader.at].parse::<i32>().ok() };
+                year.ok_or(ParseDateTimeError { position: 0, reason: "expected a year of four or more digits" })?
+            }
+            _ => reader.number(4, "expected a four digit year")? as i32
+        };
+        reader.expect(b"-", "expected '-'")?;
+        let month = reader.field(2, 1..=12, "expected a month from 01 to 12")?;
+        reader.expect(b"-", "expected '-'")?;
+        let day_start = reader.at;
+        let day = reader.field(2, 1..=31, "expected a day of the month")?;
+        if day > days_in_month(year, month) {
+            return Err(ParseDateTimeError { position: day_start, reason: "no such day in that month" });
+        }
+        reader.expect(b"Tt ", "expected 'T' between the date and time")?;
+        let hour = reader.field(2, 0..=23, "expected an hour from 00 to 23")?;
+        reader.expect(b":", "expected ':'")?;
+        let minute = reader.field(2, 0..=59, "expected a minute from 00 to 59")?;
+        reader.expect(b":", "expected ':'")?;
+        let second = reader.field(2, 0..=60, "expected a second from 00 to 60")?;
+
+        let mut nanosecond = 0;
+        if reader.expect(b".", "").is_ok() {
+            let start = reader.at;
+            while reader.bytes.get(reader.at).is_some_and(u8::is_ascii_digit) {
+                // Digits past the ninth are more precise than we can hold
+                if reader.at - start < 9 {
+                    nanosecond = nanosecond * 10 + u32::from(reader.bytes[reader.at] - b'0');
+                }
+                reader.at += 1;
+            }
+            if reader.at == start {
+                return Err(reader.error("expected digits after '.'"));
+            }
+            nanosecond *= 10u32.pow(9u32.saturating_sub((reader.at - start) as u32));
+        }
+
+        let offset_secs = match reader.expect(b"Zz+-", "expected 'Z' or a UTC offset")? {
+            b'+' | b'-' => {
+                let sign = if reader.bytes[reader.at - 1] == b'-' { -1 } else { 1 };
+                let hours = reader.field(2, 0..=23, "expected offset hours from 00 to 23")?;
+                reader.expect(b":", "expected ':'")?;
+                let minutes = reader.field(2, 0..=59, "expected offset minutes from 00 to 59")?;
+                sign * (i64::from(hours) * 3600 + i64::from(minutes) * 60)
+            }
+            _ => 0
+        };
+        if reader.at != reader.bytes.len() {
+            return Err(reader.error("unexpected text after the timestamp"));
+        }
+
+        let local = DateTime { year, month, day, hour, minute, second: second.min(59), nanosecond };
+        let leap_second = if second == 60 { NANOS_PER_SECOND } else { 0 };
+        local.add_nanos(leap_second - i128::from(offset_secs) * NANOS_PER_SECOND)
+            .ok_or_else(|| reader.error("timestamp out of range"))
+    }
+
+    /// Write this UTC date and time as an RFC 3339 timestamp ending in
+    /// "Z". Returns `None` outside the years 0 to 9999, which RFC 3339
+    /// can't write; Display writes those with a sign instead.
+    pub fn to_rfc3339(&self) -> Option<String> {
+        if (0..=9999).contains(&self.year) { Some(self.to_string()) } else { None }
+    }
+
+    /// Write this UTC date and time as it would read in a zone
+    /// `offset_secs` seconds ahead of UTC, like "2016-02-09T22:42:00+01:00".
+    /// Returns `None` if the offset isn't a whole number of minutes under
+    /// a day, or the shifted time is out of range or outside the years 0
+    /// to 9999.
+    pub fn to_rfc3339_with_offset(&self, offset_secs: i32) -> Option<String> {
+        if offset_secs % 60 != 0 || offset_secs.abs() >= 86_400 {
+            return None;
+        }
+        let local = self.add_nanos(i128::from(offset_secs) * NANOS_PER_SECOND)?;
+        if !(0..=9999).contains(&local.year) {
+            return None;
+        }
+        if offset_secs == 0 {
+            return local.to_rfc3339();
+        }
+        let sign = if offset_secs < 0 { '-' } else { '+' };
+        let minutes = offset_secs.abs() / 60;
+        Some(format!("{}{}{:02}:{:02}", local.format_local(), sign, minutes / 60, minutes % 60))
+    }
+
+    /// The date and time with no zone designator, like "2016-02-09T21:42:00.25".
+    /// Years outside 0 to 9999 get a sign, like "+10000" or "-0001".
+    fn format_local(&self) -> String {
+        let year = if (0..=9999).contains(&self.year) {
+            format!("{:04}", self.year)
+        } else {
+            format!("{:+05}", self.year)
+        };
+        let mut text = format!("{}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                               year, self.month, self.day,
+                               self.hour, self.minute, self.second);
+        if self.nanosecond > 0 {
+            let digits = format!("{:09}", self.nanosecond);
+            text.push('.');
+            text.push_str(digits.trim_end_matches('0'));
+        }
+        text
+    }
+
+    /// The date in English, like "Feb 9th, 2016"
+    pub fn to_english_date(&self) -> String {
+        format!("{} {}{}, {}", &month_name(self.month)[..3], self.day,
+                ordinal_suffix(u32::from(self.day)), self.year)
+    }
+}
+
+/// Prints an RFC 3339 timestamp in UTC, like "2016-02-09T21:42:00Z", or
+/// for years outside 0 to 9999 the same with a signed year, like
+/// "+10000-01-01T00:00:00Z" as in ISO 8601's expanded form
+impl fmt::Display for DateTime {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(dest, "{}Z", self.format_local())
+    }
+}
+
+/// Reads what Display writes: RFC 3339, or a signed year for years outside
+/// 0 to 9999
+impl FromStr for DateTime {
+    type Err = ParseDateTimeError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        DateTime::parse_timestamp(text, true)
+    }
+}
+
+#[test]
+fn test_rfc3339_round_trip() {
+    let feb_9th = DateTime::new(2016, 2, 9, 21, 42, 0).unwrap();
+    assert_eq!(feb_9th.to_string(), "2016-02-09T21:42:00Z");
+    assert_eq!("2016-02-09T21:42:00Z".parse(), Ok(feb_9th));
+    assert_eq!("2016-02-09t22:42:00+01:00".parse(), Ok(feb_9th));
+    assert_eq!("2016-02-09 16:12:00-05:30".parse(), Ok(feb_9th));
+    assert_eq!(feb_9th.to_rfc3339_with_offset(-5 * 3600 - 1800).unwrap(),
+               "2016-02-09T16:12:00-05:30");
+    assert_eq!(feb_9th.to_rfc3339_with_offset(0).unwrap(), "2016-02-09T21:42:00Z");
+    assert!(feb_9th.to_rfc3339_with_offset(30).is_none());
+
+    let precise = feb_9th.with_nanosecond(250_000_000).unwrap();
+    assert_eq!(precise.to_rfc3339().unwrap(), "2016-02-09T21:42:00.25Z");
+    assert_eq!("2016-02-09T21:42:00.25Z".parse(), Ok(precise));
+    assert_eq!("2016-02-09T21:42:00.2500000001Z".parse(), Ok(precise));
+
+    let leap_second: DateTime = "2016-12-31T23:59:60Z".parse().unwrap();
+    assert_eq!(leap_second, DateTime::from_ymd(2017, 1, 1).unwrap());
+    assert_eq!("0001-01-01T00:00:00+00:01".parse(),
+               Ok(DateTime::new(0, 12, 31, 23, 59, 0).unwrap()));
+
+    // RFC 3339 only has four digit years
+    let far_future = DateTime::from_ymd(10_000, 1, 1).unwrap();
+    assert_eq!(far_future.to_rfc3339(), None);
+    assert_eq!(far_future.to_string(), "+10000-01-01T00:00:00Z");
+    assert_eq!("+10000-01-01T00:00:00Z".parse(), Ok(far_future));
+    let before_year_zero = DateTime::from_ymd(-1, 12, 31).unwrap();
+    assert_eq!(before_year_zero.to_string(), "-0001-12-31T00:00:00Z");
+    assert_eq!("-0001-12-31T00:00:00Z".parse(), Ok(before_year_zero));
+    assert_eq!("+2016-02-09T21:42:00Z".parse(), Ok(feb_9th));
+    assert_eq!(DateTime::new(9999, 12, 31, 23, 30, 0).unwrap().to_rfc3339_with_offset(3600), None);
+    assert_eq!(DateTime::from_ymd(0, 1, 1).unwrap().to_rfc3339_with_offset(-60), None);
+    assert!(DateTime::parse_rfc3339("+2016-02-09T21:42:00Z").is_err());
+    assert_eq!("+016-02-09T21:42:00Z".parse::<DateTime>().unwrap_err().reason,
+               "expected a year of four or more digits");
+}
+
+#[test]
+fn test_rfc3339_errors() {
+    let error = |text: &str| {
+        let e = DateTime::parse_rfc3339(text).unwrap_err();
+        (e.position, e.reason)
+    };
+    assert_eq!(error("16-02-09T21:42:00Z"), (0, "expected a four digit year"));
+    assert_eq!(error("2016-13-09T21:42:00Z"), (5, "expected a month from 01 to 12"));
+    assert_eq!(error("2015-02-29T21:42:00Z"), (8, "no such day in that month"));
+    assert_eq!(error("2016-02-09"), (10, "expected 'T' between the date and time"));
+    assert_eq!(error("2016-02-09T24:00:00Z"), (11, "expected an hour from 00 to 23"));
+    assert_eq!(error("2016-02-09T21:42:00"), (19, "expected 'Z' or a UTC offset"));
+    assert_eq!(error("2016-02-09T21:42:00.Z"), (20, "expected digits after '.'"));
+    assert_eq!(error("2016-02-09T21:42:00+1:00"), (20, "expected offset hours from 00 to 23"));
+    assert_eq!(error("2016-02-09T21:42:00Z junk"), (20, "unexpected text after the timestamp"));
+
+    let message = DateTime::parse_rfc3339("2016-02-09").unwrap_err().to_string();
+    assert_eq!(message, "invalid RFC 3339 timestamp at offset 10: expected 'T' between the date and time");
+}
+
+// HTTP dates (RFC 9110 section 5.6.7), like "Sun, 06 Nov 1994 08:49:37 GMT".
+// Only this IMF-fixdate form is written, but the obsolete RFC 850 and
+// asctime forms are read too, since HTTP recipients have to accept them.
+
+/// The month whose three letter abbreviation is `text`, like 11 for "Nov"
+fn month_from_abbreviation(text: &str) -> Option<u8> {
+    MONTH_NAMES.iter().position(|name| &name[..3] == text).map(|i| i as u8 + 1)
+}
+
+/// "08:49:37" as hours, minutes and seconds
+fn parse_time_of_day(text: &str) -> Option<(u8, u8, u8)> {
+    let bytes = text.as_bytes();
+    if bytes.len() != 8 || bytes[2] != b':' || bytes[5] != b':' {
+        return None;
+    }
+    let field = |at: usize| text.get(at..at + 2).filter(|f| f.bytes().all(|c| c.is_ascii_digit()))
+        .and_then(|f| f.parse().ok());
+    Some((field(0)?, field(3)?, field(6)?))
+}
+
+/// A number made of exactly `digits` digits
+fn parse_digits(text: &str, digits: usize) -> Option<u32> {
+    if text.len() != digits || !text.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+impl DateTime {
+    /// Write this UTC date and time as an HTTP date, dropping any fraction
+    /// of a second
+    pub fn to_http_date(&self) -> String {
+        format!("{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT", self.weekday().abbreviation(),
+                self.day, &month_name(self.month)[..3], self.year,
+                self.hour, self.minute, self.second)
+    }
+
+    /// Read an HTTP date in any of its three forms. Returns `None` for
+    /// anything else, including a weekday that doesn't match the date,
+    /// since HTTP says to ignore dates that don't parse.
+    pub fn parse_http_date(text: &str) -> Option<DateTime> {
+        let fields: Vec<&str> = text.split(' ').filter(|field| !field.is_empty()).collect();
+        let (weekday, year, month, day, time) = match fields[..] {
+            // IMF-fixdate: Sun, 06 Nov 1994 08:49:37 GMT
+            [weekday, day, month, year, time, "GMT"] if weekday.ends_with(',') =>
+                (&weekday[..weekday.len() - 1], parse_digits(year, 4)? as i32,
+                 month_from_abbreviation(month)?, parse_digits(day, 2)?, time),
+            // RFC 850: Sunday, 06-Nov-94 08:49:37 GMT
+            [weekday, date, time, "GMT"] if weekday.ends_with(',') => {
+                let mut parts = date.split('-');
+                let day = parse_digits(parts.next()?, 2)?;
+                let month = month_from_abbreviation(parts.next()?)?;
+                let short_year = parse_digits(parts.next()?, 2)? as i32;
+                // A two digit year more than 50 years ahead is in the past
+                let this_year = DateTime::now().year;
+                let mut year = this_year - this_year % 100 + short_year;
+                if year > this_year + 50 {
+                    year -= 100;
+                }
+                (&weekday[..weekday.len() - 1], year, month, day, time)
+            }
+            // asctime: Sun Nov  6 08:49:37 1994
+            [weekday, month, day, time, year] if !day.is_empty() && day.len() <= 2 =>
+                (weekday, parse_digits(year, 4)? as i32, month_from_abbreviation(month)?,
+                 parse_digits(day, day.len())?, time),
+            _ => return None
+        };
+        let (hour, minute, second) = parse_time_of_day(time)?;
+        let date_time = DateTime::new(year, month, day as u8, hour, minute, second)?;
+        let expected = date_time.weekday();
+        if weekday != expected.abbreviation() && weekday != expected.name() {
+            return None;
+        }
+        Some(date_time)
+    }
+}
+
+#[test]
+fn test_http_dates() {
+    let date = DateTime::new(1994, 11, 6, 8, 49, 37).unwrap();
+    assert_eq!(date.to_http_date(), "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(date.with_nanosecond(5).unwrap().to_http_date(), "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(DateTime::parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(date));
+    assert_eq!(DateTime::parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), Some(date));
+    assert_eq!(DateTime::parse_http_date("Sun Nov  6 08:49:37 1994"), Some(date));
+
+    for bad in &["Mon, 06 Nov 1994 08:49:37 GMT", "Sun, 06 Nov 1994 08:49:37 UTC",
+                 "Sun, 6 Nov 1994 08:49:37 GMT", "Sun, 06 November 1994 08:49:37 GMT",
+                 "Sun, 06 Nov 1994 8:49:37 GMT", "Sun, 06 Nov 1994 24:00:00 GMT",
+                 "Thu, 29 Feb 2018 00:00:00 GMT", "1994-11-06T08:49:37Z", ""] {
+        assert_eq!(DateTime::parse_http_date(bad), None, "{}", bad);
+    }
+}
+
+// Json
+
+use crate::json::Json;
+use crate::json_convert::{FromJson, FromJsonError, ToJson};
+
+/// A date and time is its RFC 3339 timestamp, as Display writes it
+impl ToJson for DateTime {
+    fn to_json(&self) -> Json {
+        Json::String(self.to_string())
+    }
+}
+
+impl FromJson for DateTime {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+        let text = String::from_json(json)?;
+        text.parse::<DateTime>().map_err(|error| FromJsonError::new(error.to_string()))
+    }
+}
+
+#[test]
+fn test_json_conversions() {
+    let moment = DateTime::new(2016, 2, 9, 21, 42, 0).unwrap().with_nanosecond(250_000_000).unwrap();
+    assert_eq!(moment.to_json(), Json::from("2016-02-09T21:42:00.25Z"));
+    assert_eq!(DateTime::from_json(&moment.to_json()), Ok(moment));
+    assert!(DateTime::from_json(&Json::from("2016-02-30T00:00:00Z")).is_err());
+
+    // Years RFC 3339 can't write still come back
+    for &year in &[-1, 10_000, -44, i32::MAX, i32::MIN] {
+        let moment = DateTime::from_ymd(year, 3, 15).unwrap();
+        assert_eq!(DateTime::from_json(&moment.to_json()), Ok(moment));
+    }
+}
@@ -14,8 +14,11 @@ fn compare(n: i32, m:i32) -> Ordering {
     }
 }
 
-// Declare our own enum
-enum Pet {
+// Declare our own enum, and let the enum_repr derives write the
+// conversions to and from its discriminants
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, FromRepr, IntoRepr, EnumIter, EnumName, ToJson, FromJson)]
+#[repr(u8)]
+pub enum Pet {
     Orca,
     Giraffe,
     Dog,
@@ -24,23 +27,19 @@ enum Pet {
 
 // use our own
 use self::Pet::*;
+use crate::enum_repr::{EnumIter, EnumName, FromRepr, IntoRepr};
+
+// HttpStatus started out here as a C-like enum with numbers instead of
+// the standard discriminants. It now covers the whole registry and keeps
+// codes it doesn't know, so it lives in its own module.
+pub use crate::http_status::HttpStatus;
 
-// In this enum, we provide the numbers instead of the standard
-enum HttpStatus {
-    Ok = 200,
-    NotModified = 304,
-    NotFound = 404
-}
 /// Implements converting from integer to enum
 ///
-/// This works this way but not explicitly
+/// Returns `None` if `n` isn't a three digit status code
 fn http_status_from_u32(n: u32) -> Option<HttpStatus> {
-    match n {
-        200 => Some(HttpStatus::Ok),
-        304 => Some(HttpStatus::NotModified),
-        404 => Some(HttpStatus::NotFound),
-        _ => None
-    }
+    use std::convert::TryFrom;
+    u16::try_from(n).ok().and_then(|n| HttpStatus::try_from(n).ok())
 }
 
 #[test]
@@ -48,43 +47,128 @@ fn test_generic_enums() {
     // Rust stores the numbers in the smallest numbers that will fit, generally 1byte
     use std::mem::size_of;
     assert_eq!(size_of::<Ordering>(), 1);
-    assert_eq!(size_of::<HttpStatus>(), 2);
     assert_eq!(size_of::<Pet>(), 1);
 
     // We can cast the enums as a C style int if needed
-    assert_eq!(HttpStatus::Ok as i32, 200);
+    assert_eq!(Giraffe as i32, 1);
 
     // Test conversion
     let status = http_status_from_u32(404);
-    assert_eq!(status.unwrap() as u32, 404);
+    assert_eq!(status.unwrap().code(), 404);
+    let teapot = http_status_from_u32(418).unwrap();
+    assert_eq!((teapot.code(), teapot.is_registered()), (418, false));
+    assert_eq!(http_status_from_u32(70_000), None);
+
+    // or have the conversions derived
+    use std::convert::TryFrom;
+    assert_eq!(u8::from(Dog), 2);
+    assert_eq!(Pet::try_from(3), Ok(Cat));
+    assert!(Pet::try_from(4).is_err());
+    assert_eq!(Pet::iter().map(|pet| pet.to_string()).collect::<Vec<_>>(),
+               vec!["Orca", "Giraffe", "Dog", "Cat"]);
+    assert_eq!("Orca".parse(), Ok(Orca));
+    assert_eq!(TimeUnit::try_from(3), Ok(TimeUnit::Hours));
+    assert_eq!(u8::from(TimeUnit::Decades) as usize, TimeUnit::VARIANT_COUNT - 1);
+    assert_eq!(TimeUnit::Hours.variant_name(), "Hours");
+    assert_eq!(TimeUnit::from_variant_name("Weeks"), Ok(TimeUnit::Weeks));
+    assert!(TimeUnit::from_variant_name("weeks").is_err());
 }
 
-// We can let the compiler implement the basics for us
-#[derive(Copy, Clone, Debug, PartialEq)]
-enum TimeUnit {
-    Seconds, Minutes, Hours, Days, Months, Years
+// We can let the compiler implement the basics for us. Units are listed
+// shortest first, so the derived ordering compares them by length. Display
+// and FromStr are written out below, to use the plural nouns.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, FromRepr, IntoRepr, EnumIter,
+         EnumName, ToJson, FromJson)]
+#[enum_repr(no_display)]
+#[repr(u8)]
+pub enum TimeUnit {
+    Milliseconds, Seconds, Minutes, Hours, Days, Weeks, Months, Years, Decades
 }
 
 // and implement functions just like we expect
 impl TimeUnit {
     /// Return the plural noun for this time unit
-    fn plural(self) -> &'static str {
+    pub fn plural(self) -> &'static str {
         match self {
+            TimeUnit::Milliseconds => "milliseconds",
             TimeUnit::Seconds => "seconds",
             TimeUnit::Minutes => "minutes",
             TimeUnit::Hours => "hours",
             TimeUnit::Days => "days",
+            TimeUnit::Weeks => "weeks",
             TimeUnit::Months => "months",
-            TimeUnit::Years => "years"
+            TimeUnit::Years => "years",
+            TimeUnit::Decades => "decades"
         }
     }
 
     /// Return the singular noun for this time unit.
-    fn singular(self) -> &'static str {
-        self.plural().trim_right_matches('s')
+    pub fn singular(self) -> &'static str {
+        self.plural().trim_end_matches('s')
+    }
+
+    /// Return the short form of this unit, as in "5 min"
+    pub fn abbreviation(self) -> &'static str {
+        match self {
+            TimeUnit::Milliseconds => "ms",
+            TimeUnit::Seconds => "s",
+            TimeUnit::Minutes => "min",
+            TimeUnit::Hours => "hr",
+            TimeUnit::Days => "d",
+            TimeUnit::Weeks => "wk",
+            TimeUnit::Months => "mo",
+            TimeUnit::Years => "yr",
+            TimeUnit::Decades => "dec"
+        }
+    }
+
+    /// Return the indefinite article to put before the singular noun
+    fn article(self) -> &'static str {
+        match self {
+            TimeUnit::Hours => "an",
+            _ => "a"
+        }
+    }
+
+    /// Return the nominal length of this unit in nanoseconds. Months and
+    /// years use the average Gregorian lengths.
+    pub fn as_nanos(self) -> u64 {
+        const SECOND: u64 = 1_000_000_000;
+        match self {
+            TimeUnit::Milliseconds => SECOND / 1000,
+            TimeUnit::Seconds => SECOND,
+            TimeUnit::Minutes => 60 * SECOND,
+            TimeUnit::Hours => 60 * 60 * SECOND,
+            TimeUnit::Days => 24 * 60 * 60 * SECOND,
+            TimeUnit::Weeks => 7 * 24 * 60 * 60 * SECOND,
+            TimeUnit::Months => 2_629_746 * SECOND,
+            TimeUnit::Years => 31_556_952 * SECOND,
+            TimeUnit::Decades => 315_569_520 * SECOND
+        }
+    }
+
+    /// Return the nominal length of this unit in seconds. Months and years
+    /// use the average Gregorian lengths.
+    pub fn as_seconds(self) -> f64 {
+        self.as_nanos() as f64 / 1e9
+    }
+
+    /// Move `date` by `n` of this unit, which may be negative. Months, years
+    /// and decades follow the calendar, so adding a month to January 31st
+    /// lands on the last day of February, whether that's the 28th or 29th.
+    /// Returns `None` if the result is out of range.
+    pub fn add_to(self, date: DateTime, n: i64) -> Option<DateTime> {
+        match self {
+            TimeUnit::Months => date.add_months(n),
+            TimeUnit::Years => date.add_months(n.checked_mul(12)?),
+            TimeUnit::Decades => date.add_months(n.checked_mul(120)?),
+            _ => date.add_nanos(i128::from(n) * i128::from(self.as_nanos()))
+        }
     }
 }
 
+use crate::calendar::DateTime;
+
 #[test]
 fn test_timeunit() {
     let plural = TimeUnit::Seconds.plural();
@@ -93,27 +177,125 @@ fn test_timeunit() {
     assert_eq!(singular, "month");
 }
 
+#[test]
+fn test_timeunit_lengths_and_order() {
+    assert_eq!(TimeUnit::Milliseconds.as_seconds(), 0.001);
+    assert_eq!(TimeUnit::Weeks.as_seconds(), 604_800.0);
+    assert_eq!(TimeUnit::Decades.as_seconds(), 10.0 * TimeUnit::Years.as_seconds());
+
+    assert!(TimeUnit::Minutes < TimeUnit::Hours);
+    assert!(TimeUnit::Decades > TimeUnit::Months);
+    assert_eq!(TimeUnit::iter().max(), Some(TimeUnit::Decades));
+    for pair in TimeUnit::VARIANTS.windows(2) {
+        assert!(pair[0] < pair[1]);
+        assert!(pair[0].as_nanos() < pair[1].as_nanos());
+    }
+}
+
+#[test]
+fn test_timeunit_add_to() {
+    let leap_day = DateTime::new(2016, 2, 29, 21, 42, 0).unwrap();
+    assert_eq!(TimeUnit::Years.add_to(leap_day, 1), DateTime::new(2017, 2, 28, 21, 42, 0));
+    assert_eq!(TimeUnit::Years.add_to(leap_day, 4), DateTime::new(2020, 2, 29, 21, 42, 0));
+    assert_eq!(TimeUnit::Decades.add_to(leap_day, -1), DateTime::new(2006, 2, 28, 21, 42, 0));
+    assert_eq!(TimeUnit::Months.add_to(leap_day, 1), DateTime::new(2016, 3, 29, 21, 42, 0));
+    assert_eq!(TimeUnit::Weeks.add_to(leap_day, 1), DateTime::new(2016, 3, 7, 21, 42, 0));
+    assert_eq!(TimeUnit::Days.add_to(leap_day, -366), DateTime::new(2015, 2, 28, 21, 42, 0));
+    assert_eq!(TimeUnit::Hours.add_to(leap_day, 3), DateTime::new(2016, 3, 1, 0, 42, 0));
+    assert_eq!(TimeUnit::Seconds.add_to(leap_day, -1), DateTime::new(2016, 2, 29, 21, 41, 59));
+    assert_eq!(TimeUnit::Milliseconds.add_to(leap_day, 1500).unwrap().nanosecond(), 500_000_000);
+    assert_eq!(TimeUnit::Decades.add_to(leap_day, i64::MAX), None);
+}
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Prints the plural name, like "minutes", or with `{:#}` the
+/// abbreviation, like "min"
+impl fmt::Display for TimeUnit {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        if dest.alternate() {
+            dest.write_str(self.abbreviation())
+        } else {
+            dest.write_str(self.plural())
+        }
+    }
+}
+
+/// The text given to `TimeUnit::from_str` wasn't a unit name
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseTimeUnitError(pub String);
+
+impl fmt::Display for ParseTimeUnitError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(dest, "unknown time unit {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseTimeUnitError {}
+
+/// Parses singular and plural names and the usual abbreviations, ignoring
+/// case. "m" is minutes; months are "mo".
+impl FromStr for TimeUnit {
+    type Err = ParseTimeUnitError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let unit = match text.trim().to_lowercase().as_str() {
+            "ms" | "msec" | "msecs" | "millisecond" | "milliseconds" => TimeUnit::Milliseconds,
+            "s" | "sec" | "secs" | "second" | "seconds" => TimeUnit::Seconds,
+            "m" | "min" | "mins" | "minute" | "minutes" => TimeUnit::Minutes,
+            "h" | "hr" | "hrs" | "hour" | "hours" => TimeUnit::Hours,
+            "d" | "day" | "days" => TimeUnit::Days,
+            "w" | "wk" | "wks" | "week" | "weeks" => TimeUnit::Weeks,
+            "mo" | "mos" | "month" | "months" => TimeUnit::Months,
+            "y" | "yr" | "yrs" | "year" | "years" => TimeUnit::Years,
+            "dec" | "decs" | "decade" | "decades" => TimeUnit::Decades,
+            _ => return Err(ParseTimeUnitError(text.to_string()))
+        };
+        Ok(unit)
+    }
+}
+
+#[test]
+fn test_timeunit_display_and_parse() {
+    assert_eq!(TimeUnit::Hours.to_string(), "hours");
+    assert_eq!(format!("{:#}", TimeUnit::Hours), "hr");
+    assert_eq!(format!("{:#}", TimeUnit::Minutes), "min");
+
+    for unit in TimeUnit::VARIANTS.iter() {
+        assert_eq!(unit.to_string().parse(), Ok(*unit));
+        assert_eq!(format!("{:#}", unit).parse(), Ok(*unit));
+        assert_eq!(unit.singular().parse(), Ok(*unit));
+    }
+    assert_eq!("S".parse(), Ok(TimeUnit::Seconds));
+    assert_eq!(" Min ".parse(), Ok(TimeUnit::Minutes));
+    assert_eq!("m".parse(), Ok(TimeUnit::Minutes));
+    assert_eq!("mo".parse(), Ok(TimeUnit::Months));
+    assert_eq!("fortnight".parse::<TimeUnit>(),
+               Err(ParseTimeUnitError("fortnight".to_string())));
+}
+
 // Timestamps with data
 
 /// A timestamp that has been delibeartly rounded off, so our program
 /// says "6 months ago" instead of "Feb 9th, 2016 at 9:42pm"
-#[derive(Copy, Clone, Debug, PartialEq)]
-enum RoughTime {
+#[derive(Copy, Clone, Debug, PartialEq, ToJson, FromJson)]
+pub enum RoughTime {
     InThePast(TimeUnit, u32),
     JustNow,
     InTheFuture(TimeUnit, u32)
 }
 
-fn rough_time_to_english(rt: RoughTime) -> String {
+pub fn rough_time_to_english(rt: RoughTime) -> String {
     match rt {
         RoughTime::InThePast(unit, 1) =>
-            format!("a {} ago", unit.singular()),
+            format!("{} {} ago", unit.article(), unit.singular()),
         RoughTime::InThePast(units, count) =>
             format!("{} {} ago", count, units.plural()),
         RoughTime::JustNow =>
-            format!("just now"),
+            "just now".to_string(),
         RoughTime::InTheFuture(unit, 1) =>
-            format!("a {} from now", unit.singular()),
+            format!("{} {} from now", unit.article(), unit.singular()),
         RoughTime::InTheFuture(units, count) =>
             format!("{} {} from now", count, units.plural())
     }
@@ -139,30 +321,215 @@ fn test_rough_time_to_english() {
                rough_time_to_english(two_months_away));
 }
 
-/// A rich enum to encapsulate JSON values
-///
-/// Notice the Object or Array types, which are cool.
-/*
-enum Json {
-    Null,
-    Boolean(bool),
-    Number(f64),
-    String(String),
-    Array(Vec<Json>),
-    Object(Box<HashMap<String, Json>>)
+// Rounding real durations off into RoughTimes
+
+/// How to round a duration that isn't a whole number of units
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Rounding {
+    /// Round down, so 100 minutes is "an hour"
+    Floor,
+    /// Round to the closest count, so 100 minutes is "2 hours"
+    Nearest
+}
+
+/// Settings for turning an exact duration into a `RoughTime`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RoughTimeConfig {
+    /// Anything closer than this many seconds is "just now"
+    pub just_now_secs: u64,
+    /// How far into the next unit up a duration must be before we switch
+    /// to it. At 0.75, 45 minutes is "an hour" but 44 minutes is not.
+    pub unit_threshold: f64,
+    /// How to round the count once a unit is chosen
+    pub rounding: Rounding
+}
+
+impl Default for RoughTimeConfig {
+    fn default() -> Self {
+        RoughTimeConfig { just_now_secs: 10, unit_threshold: 0.75, rounding: Rounding::Nearest }
+    }
+}
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+impl RoughTime {
+    /// Round off a signed number of seconds, where positive is in the
+    /// future and negative is in the past, using the default settings
+    pub fn from_duration(signed_secs: i64) -> RoughTime {
+        RoughTime::from_duration_with(signed_secs, &RoughTimeConfig::default())
+    }
+
+    /// Round off a signed number of seconds using the given settings
+    pub fn from_duration_with(signed_secs: i64, config: &RoughTimeConfig) -> RoughTime {
+        let secs = signed_secs.unsigned_abs();
+        if secs < config.just_now_secs.max(1) {
+            return RoughTime::JustNow;
+        }
+
+        // Pick the biggest unit the duration has gotten far enough into
+        let units = [TimeUnit::Years, TimeUnit::Months, TimeUnit::Days,
+                     TimeUnit::Hours, TimeUnit::Minutes, TimeUnit::Seconds];
+        let unit = *units.iter()
+            .find(|unit| secs as f64 >= unit.as_seconds() * config.unit_threshold)
+            .unwrap_or(&TimeUnit::Seconds);
+
+        let unit_secs = unit.as_nanos() / 1_000_000_000;
+        let count = match config.rounding {
+            Rounding::Floor => secs / unit_secs,
+            Rounding::Nearest => (secs + unit_secs / 2) / unit_secs
+        };
+        let count = count.clamp(1, u64::from(u32::MAX)) as u32;
+
+        if signed_secs < 0 {
+            RoughTime::InThePast(unit, count)
+        } else {
+            RoughTime::InTheFuture(unit, count)
+        }
+    }
+
+    /// Describe when `then` happened as seen from `now`
+    pub fn between(then: SystemTime, now: SystemTime) -> RoughTime {
+        RoughTime::between_with(then, now, &RoughTimeConfig::default())
+    }
+
+    /// Describe when `then` happened as seen from `now`, using the given settings
+    pub fn between_with(then: SystemTime, now: SystemTime, config: &RoughTimeConfig) -> RoughTime {
+        let signed_secs = match then.duration_since(now) {
+            Ok(ahead) => ahead.as_secs().min(i64::MAX as u64) as i64,
+            Err(behind) => -(behind.duration().as_secs().min(i64::MAX as u64) as i64)
+        };
+        RoughTime::from_duration_with(signed_secs, config)
+    }
+
+    /// Describe when the Unix timestamp `then` happened as seen from `now`
+    pub fn between_unix(then: i64, now: i64) -> RoughTime {
+        RoughTime::between_unix_with(then, now, &RoughTimeConfig::default())
+    }
+
+    /// Describe when the Unix timestamp `then` happened as seen from `now`,
+    /// using the given settings
+    pub fn between_unix_with(then: i64, now: i64, config: &RoughTimeConfig) -> RoughTime {
+        RoughTime::from_duration_with(then.saturating_sub(now), config)
+    }
+
+    /// Describe when `then` happened as seen from the system clock
+    pub fn since(then: SystemTime) -> RoughTime {
+        RoughTime::between(then, SystemTime::now())
+    }
+
+    /// Describe when the calendar time `then` happened as seen from `now`
+    pub fn between_dates(then: DateTime, now: DateTime) -> RoughTime {
+        let signed_secs = then.nanos_since(now) / 1_000_000_000;
+        let signed_secs = signed_secs.clamp(i128::from(i64::MIN), i128::from(i64::MAX)) as i64;
+        RoughTime::from_duration(signed_secs)
+    }
 }
-*/
+
+/// Describe `then` as seen from `now`, roughly if it's no more than
+/// `cutoff_secs` away ("3 days ago"), and by its date if it's older or
+/// further off than that ("on Feb 9th, 2016")
+pub fn rough_time_or_date(then: DateTime, now: DateTime, cutoff_secs: u64) -> String {
+    if then.nanos_since(now).unsigned_abs() > u128::from(cutoff_secs) * 1_000_000_000 {
+        format!("on {}", then.to_english_date())
+    } else {
+        rough_time_to_english(RoughTime::between_dates(then, now))
+    }
+}
+
+#[test]
+fn test_rough_time_from_duration() {
+    use self::TimeUnit::*;
+
+    assert_eq!(RoughTime::from_duration(0), RoughTime::JustNow);
+    assert_eq!(RoughTime::from_duration(-9), RoughTime::JustNow);
+    assert_eq!(RoughTime::from_duration(-10), RoughTime::InThePast(Seconds, 10));
+    assert_eq!(RoughTime::from_duration(44), RoughTime::InTheFuture(Seconds, 44));
+    assert_eq!(RoughTime::from_duration(45), RoughTime::InTheFuture(Minutes, 1));
+    assert_eq!(RoughTime::from_duration(-44 * 60), RoughTime::InThePast(Minutes, 44));
+    assert_eq!(RoughTime::from_duration(-45 * 60), RoughTime::InThePast(Hours, 1));
+    assert_eq!(RoughTime::from_duration(-100 * 60), RoughTime::InThePast(Hours, 2));
+    assert_eq!(RoughTime::from_duration(-3 * 86_400), RoughTime::InThePast(Days, 3));
+    assert_eq!(RoughTime::from_duration(-25 * 86_400), RoughTime::InThePast(Months, 1));
+    assert_eq!(RoughTime::from_duration(-400 * 86_400), RoughTime::InThePast(Years, 1));
+    assert_eq!(RoughTime::from_duration(i64::MIN), RoughTime::InThePast(Years, u32::MAX));
+
+    assert_eq!(rough_time_to_english(RoughTime::from_duration(-45 * 60)), "an hour ago");
+    assert_eq!(rough_time_to_english(RoughTime::from_duration(3 * 86_400)), "3 days from now");
+}
+
+#[test]
+fn test_rough_time_config() {
+    use self::TimeUnit::*;
+
+    let strict = RoughTimeConfig { just_now_secs: 60, unit_threshold: 1.0, rounding: Rounding::Floor };
+    assert_eq!(RoughTime::from_duration_with(-59, &strict), RoughTime::JustNow);
+    assert_eq!(RoughTime::from_duration_with(-60, &strict), RoughTime::InThePast(Minutes, 1));
+    assert_eq!(RoughTime::from_duration_with(-45 * 60, &strict), RoughTime::InThePast(Minutes, 45));
+    assert_eq!(RoughTime::from_duration_with(-100 * 60, &strict), RoughTime::InThePast(Hours, 1));
+    assert_eq!(RoughTime::from_duration_with(-47 * 3600, &strict), RoughTime::InThePast(Days, 1));
+
+    let precise = RoughTimeConfig { just_now_secs: 0, ..RoughTimeConfig::default() };
+    assert_eq!(RoughTime::from_duration_with(0, &precise), RoughTime::JustNow);
+    assert_eq!(RoughTime::from_duration_with(1, &precise), RoughTime::InTheFuture(Seconds, 1));
+    assert_eq!(RoughTime::between_unix_with(1_000, 1_059, &strict), RoughTime::JustNow);
+    assert_eq!(RoughTime::between_unix_with(1_000, 1_000 + 100 * 60, &strict), RoughTime::InThePast(Hours, 1));
+    assert_eq!(RoughTime::between_unix_with(i64::MAX, i64::MIN, &precise), RoughTime::InTheFuture(Years, u32::MAX));
+}
+
+#[test]
+fn test_rough_time_between() {
+    use std::time::Duration;
+    use self::TimeUnit::*;
+
+    let now = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+    let then = now - Duration::from_secs(3 * 86_400 + 600);
+    assert_eq!(RoughTime::between(then, now), RoughTime::InThePast(Days, 3));
+    assert_eq!(RoughTime::between(now, then), RoughTime::InTheFuture(Days, 3));
+    assert_eq!(RoughTime::between(now, now), RoughTime::JustNow);
+
+    assert_eq!(RoughTime::between_unix(1_454_000_000, 1_500_000_000),
+               RoughTime::InThePast(Years, 1));
+    assert_eq!(RoughTime::between_unix(i64::MAX, i64::MIN),
+               RoughTime::from_duration(i64::MAX));
+
+    assert_eq!(RoughTime::since(SystemTime::now()), RoughTime::JustNow);
+}
+
+#[test]
+fn test_rough_time_or_date() {
+    let now = DateTime::new(2016, 8, 9, 12, 0, 0).unwrap();
+    let three_days_ago = DateTime::new(2016, 8, 6, 11, 0, 0).unwrap();
+    let back_in_february = DateTime::new(2016, 2, 9, 21, 42, 0).unwrap();
+    let ninety_days = 90 * 86_400;
+
+    assert_eq!(RoughTime::between_dates(three_days_ago, now),
+               RoughTime::InThePast(TimeUnit::Days, 3));
+    assert_eq!(rough_time_or_date(three_days_ago, now, ninety_days), "3 days ago");
+    assert_eq!(rough_time_or_date(back_in_february, now, ninety_days), "on Feb 9th, 2016");
+    assert_eq!(rough_time_or_date(back_in_february, now, 365 * 86_400), "6 months ago");
+    assert_eq!(rough_time_or_date(now.add_years(1).unwrap(), now, ninety_days),
+               "on Aug 9th, 2017");
+}
+
+// A rich enum to encapsulate JSON values
+//
+// Notice the Object or Array types, which are cool. It started out here as
+// a sketch; with a parser and printer to go with it, it lives in its own
+// module.
+pub use crate::json::Json;
 
 
 /// Data Structure for a Binary Tree
 // An ordered collection of `T`s
-enum BinaryTree<T> {
+#[derive(Debug, PartialEq)]
+pub enum BinaryTree<T> {
     Empty,
     NonEmpty(Box<TreeNode<T>>)
 }
 
 //A part of a BinaryTree.
-struct TreeNode<T> {
+#[derive(Debug, PartialEq, ToJson, FromJson)]
+pub struct TreeNode<T> {
     element: T,
     left: BinaryTree<T>,
     right: BinaryTree<T>
@@ -255,3 +622,458 @@ fn test_add_and_search_binary_tree() {
     assert!(planets.search("Uranus"));
     assert!(!planets.search("Pluto"));
 }
+
+// Building trees without the nested Box::new ladders
+
+impl<T> BinaryTree<T> {
+    /// Build a tree node out of an element and its two subtrees
+    pub fn node(element: T, left: BinaryTree<T>, right: BinaryTree<T>) -> BinaryTree<T> {
+        BinaryTree::NonEmpty(Box::new(TreeNode { element, left, right }))
+    }
+
+    /// Build a tree holding a single element
+    pub fn leaf(element: T) -> BinaryTree<T> {
+        BinaryTree::node(element, BinaryTree::Empty, BinaryTree::Empty)
+    }
+
+    /// Build a balanced tree from a slice that is already sorted.
+    ///
+    /// Each element is visited once, so this is O(n) rather than the
+    /// O(n log n) of calling `add` in a loop.
+    pub fn from_sorted(items: &[T]) -> BinaryTree<T>
+        where T: Clone
+    {
+        if items.is_empty() {
+            return BinaryTree::Empty;
+        }
+        let mid = items.len() / 2;
+        BinaryTree::node(items[mid].clone(),
+                         BinaryTree::from_sorted(&items[..mid]),
+                         BinaryTree::from_sorted(&items[mid + 1..]))
+    }
+}
+
+/// Build a `BinaryTree` using the same shape as its S-expression form.
+///
+/// `tree!()` is the empty tree, `tree!(x)` is a leaf, and
+/// `tree!(x left right)` is a node whose subtrees are written the same way,
+/// wrapped in parentheses. Elements must be single tokens such as literals
+/// or variable names, so bind anything longer to a variable first.
+#[macro_export]
+macro_rules! tree {
+    () => {
+        $crate::chapter_10::BinaryTree::Empty
+    };
+    ($element:tt $left:tt $right:tt) => {
+        $crate::chapter_10::BinaryTree::node($element, $crate::tree!($left), $crate::tree!($right))
+    };
+    (($($inner:tt)*)) => {
+        $crate::tree!($($inner)*)
+    };
+    ($element:expr) => {
+        $crate::chapter_10::BinaryTree::leaf($element)
+    };
+}
+
+#[test]
+fn test_tree_macro() {
+    use self::BinaryTree::*;
+
+    let by_hand = NonEmpty(Box::new(TreeNode {
+        element: "Saturn",
+        left: NonEmpty(Box::new(TreeNode {
+            element: "Mars",
+            left: BinaryTree::leaf("Jupiter"),
+            right: BinaryTree::leaf("Mercury")
+        })),
+        right: BinaryTree::leaf("Uranus")
+    }));
+
+    let by_macro = tree!("Saturn" ("Mars" "Jupiter" "Mercury") "Uranus");
+    assert_eq!(by_macro, by_hand);
+
+    let empty: BinaryTree<i32> = tree!();
+    assert_eq!(empty, Empty);
+    assert_eq!(tree!(7), BinaryTree::leaf(7));
+    let three = 1 + 2;
+    assert_eq!(tree!(three () 4),
+               BinaryTree::node(3, Empty, BinaryTree::leaf(4)));
+}
+
+use std::iter::FromIterator;
+
+impl<T: Ord> FromIterator<T> for BinaryTree<T> {
+    fn from_iter<I: IntoIterator<Item=T>>(iter: I) -> Self {
+        let mut tree = BinaryTree::Empty;
+        for value in iter {
+            tree.add(value);
+        }
+        tree
+    }
+}
+
+#[test]
+fn test_collect_and_from_sorted() {
+    let planets: BinaryTree<_> = vec!["Mercury", "Venus", "Earth", "Mars"]
+        .into_iter()
+        .collect();
+    assert!(planets.search("Earth"));
+    assert!(!planets.search("Pluto"));
+    assert_eq!(planets,
+               tree!("Mercury" ("Earth" () "Mars") "Venus"));
+
+    let balanced = BinaryTree::from_sorted(&[1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(balanced, tree!(4 (2 1 3) (6 5 7)));
+    assert!(balanced.search(5));
+
+    let empty: BinaryTree<i32> = BinaryTree::from_sorted(&[]);
+    assert_eq!(empty, BinaryTree::Empty);
+}
+
+// Printing and parsing trees as S-expressions, handy for test fixtures.
+//
+// The empty tree is `()`, a leaf is just its element, and any other node
+// is `(element left right)`, so the planets above are
+// `(Saturn (Mars Jupiter Mercury) Uranus)`. A leaf may also be written
+// `(element)` when parsing, as the tree! macro allows. Elements that are
+// empty or hold spaces, parentheses or quotes are written in double quotes,
+// with `\"` and `\\` inside, so they read back the same.
+
+use std::borrow::Cow;
+
+/// How deeply nodes with subtrees may nest in a tree being parsed
+pub const MAX_SEXPR_DEPTH: usize = 256;
+
+/// Whether `ch` ends an unquoted element
+fn ends_atom(ch: char) -> bool {
+    ch.is_whitespace() || ch == '(' || ch == ')'
+}
+
+/// Write an element, quoting it if it wouldn't read back as one atom
+fn write_atom(dest: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    if !text.is_empty() && !text.contains(|ch| ends_atom(ch) || ch == '"') {
+        return dest.write_str(text);
+    }
+    dest.write_str("\"")?;
+    for ch in text.chars() {
+        if ch == '"' || ch == '\\' {
+            dest.write_str("\\")?;
+        }
+        write!(dest, "{}", ch)?;
+    }
+    dest.write_str("\"")
+}
+
+impl<T: fmt::Display> fmt::Display for BinaryTree<T> {
+    // Keeps its own stack of what's left to write instead of recursing, so
+    // a lopsided tree can be as deep as it likes
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        enum Step<'a, T> {
+            Tree(&'a BinaryTree<T>),
+            Text(&'static str)
+        }
+
+        let mut steps = vec![Step::Tree(self)];
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Text(text) => dest.write_str(text)?,
+                Step::Tree(BinaryTree::Empty) => dest.write_str("()")?,
+                Step::Tree(BinaryTree::NonEmpty(node)) => {
+                    match (&node.left, &node.right) {
+                        (BinaryTree::Empty, BinaryTree::Empty) =>
+                            write_atom(dest, &node.element.to_string())?,
+                        (left, right) => {
+                            dest.write_str("(")?;
+                            write_atom(dest, &node.element.to_string())?;
+                            steps.push(Step::Text(")"));
+                            steps.push(Step::Tree(right));
+                            steps.push(Step::Text(" "));
+                            steps.push(Step::Tree(left));
+                            steps.push(Step::Text(" "));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Why an S-expression could not be turned into a `BinaryTree`.
+///
+/// Positions are byte offsets into the input string.
+#[derive(Debug, PartialEq)]
+pub enum ParseTreeError {
+    /// The input stopped in the middle of a tree or a quoted element
+    UnexpectedEnd,
+    /// A parenthesis, or something right after a quoted element, showed up
+    /// where it doesn't belong
+    UnexpectedToken { pos: usize, found: char },
+    /// An element's text couldn't be parsed as `T`
+    BadElement { pos: usize, text: String },
+    /// There was more input after a complete tree, or after both of a
+    /// node's subtrees
+    TrailingInput { pos: usize },
+    /// Nodes nested more than `MAX_SEXPR_DEPTH` deep
+    TooDeep { pos: usize }
+}
+
+impl fmt::Display for ParseTreeError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseTreeError::UnexpectedEnd =>
+                write!(dest, "unexpected end of input"),
+            ParseTreeError::UnexpectedToken { pos, found } =>
+                write!(dest, "unexpected '{}' at offset {}", found, pos),
+            ParseTreeError::BadElement { pos, ref text } =>
+                write!(dest, "invalid element {:?} at offset {}", text, pos),
+            ParseTreeError::TrailingInput { pos } =>
+                write!(dest, "unexpected input after tree at offset {}", pos),
+            ParseTreeError::TooDeep { pos } =>
+                write!(dest, "tree nested too deeply at offset {}", pos)
+        }
+    }
+}
+
+impl std::error::Error for ParseTreeError {}
+
+#[derive(Debug, PartialEq)]
+enum SexprToken<'a> {
+    Open,
+    Close,
+    Atom(Cow<'a, str>)
+}
+
+/// Split an S-expression into parentheses and atoms, keeping the offset of
+/// each and taking quoted atoms out of their quotes
+fn tokenize_sexpr(text: &str) -> Result<Vec<(usize, SexprToken<'_>)>, ParseTreeError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((pos, ch)) = chars.next() {
+        match ch {
+            '(' => tokens.push((pos, SexprToken::Open)),
+            ')' => tokens.push((pos, SexprToken::Close)),
+            '"' => {
+                let mut atom = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped)) => atom.push(escaped),
+                            None => return Err(ParseTreeError::UnexpectedEnd)
+                        },
+                        Some((_, ch)) => atom.push(ch),
+                        None => return Err(ParseTreeError::UnexpectedEnd)
+                    }
+                }
+                if let Some(&(after, ch)) = chars.peek() {
+                    if !ends_atom(ch) {
+                        return Err(ParseTreeError::UnexpectedToken { pos: after, found: ch });
+                    }
+                }
+                tokens.push((pos, SexprToken::Atom(Cow::Owned(atom))));
+            }
+            ch if ch.is_whitespace() => {}
+            _ => {
+                let mut end = text.len();
+                while let Some(&(next, ch)) = chars.peek() {
+                    if ends_atom(ch) {
+                        end = next;
+                        break;
+                    }
+                    chars.next();
+                }
+                tokens.push((pos, SexprToken::Atom(Cow::Borrowed(&text[pos..end]))));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_element<T: FromStr>(pos: usize, text: &str) -> Result<T, ParseTreeError> {
+    text.parse().map_err(|_| ParseTreeError::BadElement { pos, text: text.to_string() })
+}
+
+/// Parse one tree starting at `tokens[*next]`, leaving `next` just past it.
+/// `depth` is how many nodes it's inside of.
+fn parse_sexpr<T: FromStr>(tokens: &[(usize, SexprToken)], next: &mut usize, depth: usize)
+                           -> Result<BinaryTree<T>, ParseTreeError>
+{
+    let (pos, ref token) = *tokens.get(*next).ok_or(ParseTreeError::UnexpectedEnd)?;
+    *next += 1;
+
+    match *token {
+        SexprToken::Atom(ref text) =>
+            Ok(BinaryTree::leaf(parse_element(pos, text)?)),
+        SexprToken::Close =>
+            Err(ParseTreeError::UnexpectedToken { pos, found: ')' }),
+        SexprToken::Open => {
+            let open = pos;
+            let (pos, ref token) = *tokens.get(*next).ok_or(ParseTreeError::UnexpectedEnd)?;
+            *next += 1;
+            let element = match *token {
+                SexprToken::Close => return Ok(BinaryTree::Empty),
+                SexprToken::Open =>
+                    return Err(ParseTreeError::UnexpectedToken { pos, found: '(' }),
+                SexprToken::Atom(ref text) => parse_element(pos, text)?
+            };
+            // (x) is the same leaf as x
+            if let Some(&(_, SexprToken::Close)) = tokens.get(*next) {
+                *next += 1;
+                return Ok(BinaryTree::leaf(element));
+            }
+            if depth >= MAX_SEXPR_DEPTH {
+                return Err(ParseTreeError::TooDeep { pos: open });
+            }
+            let left = parse_sexpr(tokens, next, depth + 1)?;
+            let right = parse_sexpr(tokens, next, depth + 1)?;
+            match tokens.get(*next) {
+                Some(&(_, SexprToken::Close)) => {
+                    *next += 1;
+                    Ok(BinaryTree::node(element, left, right))
+                }
+                Some(&(pos, SexprToken::Open)) =>
+                    Err(ParseTreeError::UnexpectedToken { pos, found: '(' }),
+                Some(&(pos, SexprToken::Atom(_))) =>
+                    Err(ParseTreeError::TrailingInput { pos }),
+                None => Err(ParseTreeError::UnexpectedEnd)
+            }
+        }
+    }
+}
+
+impl<T: FromStr> FromStr for BinaryTree<T> {
+    type Err = ParseTreeError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize_sexpr(text)?;
+        let mut next = 0;
+        let tree = parse_sexpr(&tokens, &mut next, 0)?;
+        match tokens.get(next) {
+            Some(&(pos, _)) => Err(ParseTreeError::TrailingInput { pos }),
+            None => Ok(tree)
+        }
+    }
+}
+
+#[test]
+fn test_sexpr_round_trip() {
+    let planets: BinaryTree<String> = "(Saturn (Mars Jupiter Mercury) Uranus)"
+        .parse()
+        .unwrap();
+    assert!(planets.search("Jupiter".to_string()));
+    assert_eq!(planets.to_string(), "(Saturn (Mars Jupiter Mercury) Uranus)");
+
+    let lopsided: BinaryTree<i32> = " ( 5 ( 3 () 4 ) () ) ".parse().unwrap();
+    assert_eq!(lopsided, tree!(5 (3 () 4) ()));
+    assert_eq!(lopsided.to_string(), "(5 (3 () 4) ())");
+
+    assert_eq!("()".parse::<BinaryTree<i32>>(), Ok(BinaryTree::Empty));
+    assert_eq!("42".parse::<BinaryTree<i32>>(), Ok(BinaryTree::leaf(42)));
+    assert_eq!("(42)".parse::<BinaryTree<i32>>(), Ok(BinaryTree::leaf(42)));
+    assert_eq!("(1 (2) ( 3 ))".parse::<BinaryTree<i32>>(), Ok(tree!(1 2 3)));
+
+    // Elements that would split or vanish are quoted
+    let awkward = BinaryTree::node("New York".to_string(),
+                                   BinaryTree::leaf("(x)".to_string()),
+                                   BinaryTree::node(String::new(), BinaryTree::Empty,
+                                                    BinaryTree::leaf("say \"hi\" \\o/".to_string())));
+    let text = awkward.to_string();
+    assert_eq!(text, r#"("New York" "(x)" ("" () "say \"hi\" \\o/"))"#);
+    assert_eq!(text.parse::<BinaryTree<String>>(), Ok(awkward));
+    assert_eq!(r#"("1" "2" 3)"#.parse::<BinaryTree<i32>>(), Ok(tree!(1 2 3)));
+
+    // As deep as the parser allows still reads back
+    let mut deep = BinaryTree::leaf(0);
+    for _ in 0..MAX_SEXPR_DEPTH {
+        deep = BinaryTree::node(1, deep, BinaryTree::Empty);
+    }
+    assert_eq!(deep.to_string().parse::<BinaryTree<i32>>(), Ok(deep));
+}
+
+#[test]
+fn test_display_lopsided_tree() {
+    // Far deeper than printing could go if it recursed
+    let mut tree = BinaryTree::Empty;
+    for i in 0..100_000 {
+        tree = BinaryTree::node(i, tree, BinaryTree::Empty);
+    }
+    let text = tree.to_string();
+    assert!(text.starts_with("(99999 (99998 (99997 "));
+    assert!(text.contains(" (2 (1 0 ()) ()) ())"));
+    assert!(text.ends_with(" ()) ())"));
+
+    // Dropping the tree whole would recurse just as deep, so take it apart
+    while let BinaryTree::NonEmpty(node) = tree {
+        tree = node.left;
+    }
+}
+
+#[test]
+fn test_sexpr_errors() {
+    use self::ParseTreeError::*;
+
+    assert_eq!("".parse::<BinaryTree<i32>>(), Err(UnexpectedEnd));
+    assert_eq!("(1 2".parse::<BinaryTree<i32>>(), Err(UnexpectedEnd));
+    assert_eq!("(1 2 x)".parse::<BinaryTree<i32>>(),
+               Err(BadElement { pos: 5, text: "x".to_string() }));
+    assert_eq!("(1 2 3 4)".parse::<BinaryTree<i32>>(), Err(TrailingInput { pos: 7 }));
+    assert_eq!("(1 2 3 x)".parse::<BinaryTree<i32>>(), Err(TrailingInput { pos: 7 }));
+    assert_eq!("(x)".parse::<BinaryTree<i32>>(),
+               Err(BadElement { pos: 1, text: "x".to_string() }));
+    assert_eq!("((1) 2 3)".parse::<BinaryTree<i32>>(),
+               Err(UnexpectedToken { pos: 1, found: '(' }));
+    assert_eq!(")".parse::<BinaryTree<i32>>(),
+               Err(UnexpectedToken { pos: 0, found: ')' }));
+    assert_eq!("1 2".parse::<BinaryTree<i32>>(), Err(TrailingInput { pos: 2 }));
+    assert_eq!(r#"(1 "2 3)"#.parse::<BinaryTree<i32>>(), Err(UnexpectedEnd));
+    assert_eq!(r#"(1 "2"3 4)"#.parse::<BinaryTree<i32>>(), Err(UnexpectedToken { pos: 6, found: '3' }));
+    assert_eq!(r#""x y""#.parse::<BinaryTree<i32>>(),
+               Err(BadElement { pos: 0, text: "x y".to_string() }));
+
+    let too_deep = format!("{}0{}", "(1 ".repeat(MAX_SEXPR_DEPTH + 1), " ())".repeat(MAX_SEXPR_DEPTH + 1));
+    assert_eq!(too_deep.parse::<BinaryTree<i32>>(), Err(TooDeep { pos: 3 * MAX_SEXPR_DEPTH }));
+}
+
+// Converting to and from Json
+
+use crate::json_convert::{FromJson, FromJsonError, ToJson};
+
+/// An empty tree is null, and a node is an object with its element and
+/// both subtrees
+impl<T: ToJson> ToJson for BinaryTree<T> {
+    fn to_json(&self) -> Json {
+        match *self {
+            BinaryTree::Empty => Json::Null,
+            BinaryTree::NonEmpty(ref node) => node.to_json()
+        }
+    }
+}
+
+impl<T: FromJson> FromJson for BinaryTree<T> {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+        if json.is_null() {
+            return Ok(BinaryTree::Empty);
+        }
+        TreeNode::from_json(json).map(|node| BinaryTree::NonEmpty(Box::new(node)))
+    }
+}
+
+#[test]
+fn test_json_conversions() {
+    let tree: BinaryTree<i32> = "(2 1 ())".parse().unwrap();
+    let json = tree.to_json();
+    assert_eq!(json.to_string(),
+               r#"{"element":2,"left":{"element":1,"left":null,"right":null},"right":null}"#);
+    assert_eq!(BinaryTree::from_json(&json), Ok(tree));
+    let error = BinaryTree::<i32>::from_json(&Json::parse(r#"{"element": 2, "left": {"element": "x"}}"#).unwrap());
+    assert_eq!(error.unwrap_err().to_string(),
+               "can't convert the JSON at \"/left/element\": expected an integer, found a string");
+
+    let times = vec![RoughTime::InThePast(TimeUnit::Days, 2), RoughTime::JustNow,
+                     RoughTime::InTheFuture(TimeUnit::Hours, 1)];
+    let json = times.to_json();
+    assert_eq!(json.to_string(), r#"[{"InThePast":["Days",2]},"JustNow",{"InTheFuture":["Hours",1]}]"#);
+    assert_eq!(Vec::<RoughTime>::from_json(&json), Ok(times));
+}
@@ -1,9 +1,11 @@
+#![allow(dead_code)]
+
 //traits and generics
 
 use std::io::Write;
 
 /// A hello function that uses traits
-fn say_hello(out: &mut Write) -> std::io::Result<()> {
+pub fn say_hello(out: &mut dyn Write) -> std::io::Result<()> {
     out.write_all(b"hello world\n")?;
     out.flush()
 }
@@ -1,5 +1,7 @@
+#![allow(dead_code)]
 
-#[derive(Clone, Copy, Debug)]
+
+#[derive(Clone, Copy, Debug, ToJson, FromJson)]
 struct Complex<T> {
     /// Real portion of the complex number
     re: T,
@@ -152,3 +154,16 @@ fn test_fmt_display() {
     assert_eq!(format!("{}", two_forty),
                "-0.5 - 0.866i");
 }
+
+// Converting to and from Json
+
+use crate::json_convert::{FromJson, ToJson};
+
+#[test]
+fn test_complex_json() {
+    let z = Complex { re: 3, im: -4 };
+    let json = z.to_json();
+    assert_eq!(json.to_string(), r#"{"im":-4,"re":3}"#);
+    assert_eq!(Complex::<i32>::from_json(&json), Ok(z));
+    assert!(Complex::<u8>::from_json(&json).is_err());
+}
@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+
 
 /// A first in, first-out queue of characters
 pub struct Queue {
@@ -48,6 +50,12 @@ impl Queue {
     }
 }
 
+impl Default for Queue {
+    fn default() -> Self {
+        Queue::new()
+    }
+}
+
 #[test]
 fn test_queue() {
     let mut q = Queue { older: Vec::new(), younger: Vec::new() };
@@ -132,6 +140,12 @@ impl<T> GQueue<T> {
     }
 }
 
+impl<T> Default for GQueue<T> {
+    fn default() -> Self {
+        GQueue::new()
+    }
+}
+
 
 #[test]
 fn test_gqueue() {
@@ -163,9 +177,9 @@ fn find_extrema<'s>(slice: &'s [i32]) -> Extrema<'s> {
     let mut greatest = &slice[0];
     let mut least = &slice[0];
 
-    for i in 1..slice.len() {
-        if slice[i] < *least { least = &slice[i]; }
-        if slice[i] > *greatest { greatest = &slice[i]; }
+    for elt in &slice[1..] {
+        if elt < least { least = elt; }
+        if elt > greatest { greatest = elt; }
     }
     Extrema {greatest, least }
 }
@@ -185,7 +199,7 @@ fn test_find_extrema() {
 ///
 /// Allows us to use the == and != and = operators just like we
 /// expect. Note these are automatically public for obvious reasons
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, ToJson, FromJson)]
 struct Point {
     x: f64,
     y: f64
@@ -196,3 +210,41 @@ struct Point {
 //Nothing really to add here, remember Cell and RefCell for opening files and storing a smart
 //reference to the file. Easier to modify something like a log file which normally should not
 // be modifiable
+
+// Converting to and from Json
+
+use crate::json::Json;
+use crate::json_convert::{FromJson, FromJsonError, ToJson};
+
+/// A queue is an array, front first
+impl<T: ToJson> ToJson for GQueue<T> {
+    fn to_json(&self) -> Json {
+        let front_first = self.older.iter().rev().chain(&self.younger);
+        Json::Array(front_first.map(ToJson::to_json).collect())
+    }
+}
+
+impl<T: FromJson> FromJson for GQueue<T> {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+        let mut older = Vec::<T>::from_json(json)?;
+        older.reverse();
+        Ok(GQueue { older, younger: Vec::new() })
+    }
+}
+
+#[test]
+fn test_json_conversions() {
+    let mut q = GQueue::new();
+    q.push('a');
+    q.push('b');
+    q.pop();
+    q.push('c');
+    let json = q.to_json();
+    assert_eq!(json.to_string(), r#"["b","c"]"#);
+    let mut back = GQueue::<char>::from_json(&json).unwrap();
+    assert_eq!((back.pop(), back.pop(), back.pop()), (Some('b'), Some('c'), None));
+
+    let point = Point { x: 1.5, y: -2.0 };
+    assert_eq!(point.to_json().to_string(), r#"{"x":1.5,"y":-2}"#);
+    assert_eq!(Point::from_json(&point.to_json()), Ok(point));
+}
@@ -0,0 +1,402 @@
+#![allow(dead_code)]
+
+// chapter_10's `compare` only orders two i32s. This generalizes it: a
+// Comparator orders two values of any type, and small combinators build
+// bigger ones, like "by surname, then by age, oldest first". There are
+// comparators for the orderings std doesn't give directly: strings with
+// numbers in them ("file2" before "file10"), floats (NaN included), case
+// ignored, and Options with None at either end.
+//
+// `into_fn` turns a comparator into a closure for `sort_by` and friends.
+// For things that want Ord, like chapter_10's BinaryTree or a BinaryHeap
+// used as a priority queue, wrap values in OrderedBy. Comparators built
+// from closures that capture nothing are Copy, so every element can carry
+// its own.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Something that orders values of type `T`
+pub trait Comparator<T: ?Sized> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+
+    /// Break ties with `next`
+    fn then<C: Comparator<T>>(self, next: C) -> Then<Self, C>
+        where Self: Sized
+    {
+        Then(self, next)
+    }
+
+    /// Break ties by comparing the keys `key` picks out
+    fn then_comparing<K: Ord, F: Fn(&T) -> K>(self, key: F) -> Then<Self, Comparing<F>>
+        where Self: Sized
+    {
+        self.then(comparing(key))
+    }
+
+    /// The opposite order
+    fn reversed(self) -> Reversed<Self>
+        where Self: Sized
+    {
+        Reversed(self)
+    }
+
+    /// A closure for `sort_by`, `max_by` and the like
+    fn into_fn(self) -> impl Fn(&T, &T) -> Ordering
+        where Self: Sized
+    {
+        move |a, b| self.compare(a, b)
+    }
+}
+
+impl<T: ?Sized, F: Fn(&T, &T) -> Ordering> Comparator<T> for F {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
+
+/// The order `Ord` gives
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Ascending;
+
+pub fn ascending() -> Ascending {
+    Ascending
+}
+
+impl<T: Ord + ?Sized> Comparator<T> for Ascending {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+/// See `comparing`
+#[derive(Copy, Clone, Debug)]
+pub struct Comparing<F>(F);
+
+/// Order values by a key computed from each, like `sort_by_key`
+pub fn comparing<T: ?Sized, K: Ord, F: Fn(&T) -> K>(key: F) -> Comparing<F> {
+    Comparing(key)
+}
+
+impl<T: ?Sized, K: Ord, F: Fn(&T) -> K> Comparator<T> for Comparing<F> {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        (self.0)(a).cmp(&(self.0)(b))
+    }
+}
+
+/// See `comparing_by`
+#[derive(Copy, Clone, Debug)]
+pub struct ComparingBy<F, C>(F, C);
+
+/// Order values by a part of each, compared with `comparator`; for example
+/// `comparing_by(|file: &File| file.name.as_str(), human_order())`
+pub fn comparing_by<T: ?Sized, K: ?Sized, F, C>(key: F, comparator: C) -> ComparingBy<F, C>
+    where F: Fn(&T) -> &K, C: Comparator<K>
+{
+    ComparingBy(key, comparator)
+}
+
+impl<T: ?Sized, K: ?Sized, F, C> Comparator<T> for ComparingBy<F, C>
+    where F: Fn(&T) -> &K, C: Comparator<K>
+{
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self.1.compare((self.0)(a), (self.0)(b))
+    }
+}
+
+/// See `Comparator::then`
+#[derive(Copy, Clone, Debug)]
+pub struct Then<A, B>(A, B);
+
+impl<T: ?Sized, A: Comparator<T>, B: Comparator<T>> Comparator<T> for Then<A, B> {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self.0.compare(a, b).then_with(|| self.1.compare(a, b))
+    }
+}
+
+/// See `Comparator::reversed`
+#[derive(Copy, Clone, Debug)]
+pub struct Reversed<C>(C);
+
+impl<T: ?Sized, C: Comparator<T>> Comparator<T> for Reversed<C> {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self.0.compare(b, a)
+    }
+}
+
+/// See `nulls_first` and `nulls_last`
+#[derive(Copy, Clone, Debug)]
+pub struct Nulls<C> {
+    comparator: C,
+    first: bool
+}
+
+/// Order Options with None before everything else, and the values inside
+/// Some by `comparator`
+pub fn nulls_first<C>(comparator: C) -> Nulls<C> {
+    Nulls { comparator, first: true }
+}
+
+/// Like `nulls_first`, but with None after everything else
+pub fn nulls_last<C>(comparator: C) -> Nulls<C> {
+    Nulls { comparator, first: false }
+}
+
+impl<T, C: Comparator<T>> Comparator<Option<T>> for Nulls<C> {
+    fn compare(&self, a: &Option<T>, b: &Option<T>) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) => self.comparator.compare(a, b),
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => if self.first { Ordering::Less } else { Ordering::Greater },
+            (Some(_), None) => if self.first { Ordering::Greater } else { Ordering::Less }
+        }
+    }
+}
+
+/// See `human_order`
+#[derive(Copy, Clone, Debug, Default)]
+pub struct HumanOrder;
+
+/// Order strings the way people expect when they contain numbers
+pub fn human_order() -> HumanOrder {
+    HumanOrder
+}
+
+impl<S: AsRef<str> + ?Sized> Comparator<S> for HumanOrder {
+    fn compare(&self, a: &S, b: &S) -> Ordering {
+        compare_human(a.as_ref(), b.as_ref())
+    }
+}
+
+/// Split off the run of ASCII digits at the start of `text`
+fn split_digits(text: &str) -> (&str, &str) {
+    let end = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    text.split_at(end)
+}
+
+/// Compare strings with runs of digits compared by their numeric value, so
+/// "file2" comes before "file10". Numbers of any length work, since they're
+/// never converted. Strings that only differ in leading zeros, like "a01"
+/// and "a1", are ordered as plain strings.
+pub fn compare_human(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a, b);
+    loop {
+        let (x_char, y_char) = match (x.chars().next(), y.chars().next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x_char), Some(y_char)) => (x_char, y_char)
+        };
+        if x_char.is_ascii_digit() && y_char.is_ascii_digit() {
+            let (x_digits, x_rest) = split_digits(x);
+            let (y_digits, y_rest) = split_digits(y);
+            let x_number = x_digits.trim_start_matches('0');
+            let y_number = y_digits.trim_start_matches('0');
+            let order = x_number.len().cmp(&y_number.len()).then_with(|| x_number.cmp(y_number));
+            if order != Ordering::Equal {
+                return order;
+            }
+            x = x_rest;
+            y = y_rest;
+        } else {
+            if x_char != y_char {
+                return x_char.cmp(&y_char);
+            }
+            x = &x[x_char.len_utf8()..];
+            y = &y[y_char.len_utf8()..];
+        }
+    }
+}
+
+/// See `case_folded`
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CaseFolded;
+
+/// Order strings ignoring case, so "apple" and "Apple" are equal. This
+/// lowercases each character, which isn't full Unicode case folding:
+/// "Straße" and "STRASSE" still differ.
+pub fn case_folded() -> CaseFolded {
+    CaseFolded
+}
+
+impl<S: AsRef<str> + ?Sized> Comparator<S> for CaseFolded {
+    fn compare(&self, a: &S, b: &S) -> Ordering {
+        let (a, b) = (a.as_ref(), b.as_ref());
+        a.chars().flat_map(char::to_lowercase).cmp(b.chars().flat_map(char::to_lowercase))
+    }
+}
+
+/// See `float_order`
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FloatOrder;
+
+/// A total order for floats, which `PartialOrd` doesn't give: -0.0 comes
+/// before 0.0, and NaN after infinity (or before negative infinity, if its
+/// sign bit is set)
+pub fn float_order() -> FloatOrder {
+    FloatOrder
+}
+
+impl Comparator<f64> for FloatOrder {
+    fn compare(&self, a: &f64, b: &f64) -> Ordering {
+        a.total_cmp(b)
+    }
+}
+
+impl Comparator<f32> for FloatOrder {
+    fn compare(&self, a: &f32, b: &f32) -> Ordering {
+        a.total_cmp(b)
+    }
+}
+
+/// A value ordered by a comparator instead of its own Ord, for collections
+/// that need Ord. Every value in one collection should have the same
+/// comparator.
+#[derive(Copy, Clone)]
+pub struct OrderedBy<T, C> {
+    pub value: T,
+    comparator: C
+}
+
+impl<T, C: Comparator<T>> OrderedBy<T, C> {
+    ///Constructor
+    pub fn new(value: T, comparator: C) -> OrderedBy<T, C> {
+        OrderedBy { value, comparator }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T, C: Comparator<T>> PartialEq for OrderedBy<T, C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T, C: Comparator<T>> Eq for OrderedBy<T, C> {}
+
+impl<T, C: Comparator<T>> PartialOrd for OrderedBy<T, C> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T, C: Comparator<T>> Ord for OrderedBy<T, C> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.comparator.compare(&self.value, &other.value)
+    }
+}
+
+impl<T: fmt::Debug, C> fmt::Debug for OrderedBy<T, C> {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt(dest)
+    }
+}
+
+impl<T: fmt::Display, C> fmt::Display for OrderedBy<T, C> {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt(dest)
+    }
+}
+
+#[cfg(test)]
+#[derive(Clone, Debug, PartialEq)]
+struct Person {
+    name: &'static str,
+    age: Option<u32>,
+    height: f64
+}
+
+#[cfg(test)]
+fn people() -> Vec<Person> {
+    vec![
+        Person { name: "carol", age: Some(30), height: 1.62 },
+        Person { name: "Bob", age: None, height: 1.80 },
+        Person { name: "alice", age: Some(30), height: 1.70 },
+        Person { name: "Dave", age: Some(25), height: f64::NAN }
+    ]
+}
+
+#[cfg(test)]
+fn names(people: &[Person]) -> Vec<&'static str> {
+    people.iter().map(|person| person.name).collect()
+}
+
+#[test]
+fn test_combinators() {
+    let mut people = people();
+    people.sort_by(comparing(|person: &Person| person.age).into_fn());
+    assert_eq!(names(&people), ["Bob", "Dave", "carol", "alice"]);
+
+    let by_age = comparing_by(|person: &Person| &person.age, nulls_last(ascending()));
+    people.sort_by(by_age.then_comparing(|person: &Person| person.name).into_fn());
+    assert_eq!(names(&people), ["Dave", "alice", "carol", "Bob"]);
+
+    // Reversing puts None first too
+    people.sort_by(by_age.reversed().then(comparing_by(|person: &Person| &person.name, case_folded())).into_fn());
+    assert_eq!(names(&people), ["Bob", "alice", "carol", "Dave"]);
+
+    let by_age_first = comparing_by(|person: &Person| &person.age, nulls_first(|a: &u32, b: &u32| b.cmp(a)));
+    people.sort_by(by_age_first.then_comparing(|person: &Person| person.name).into_fn());
+    assert_eq!(names(&people), ["Bob", "alice", "carol", "Dave"]);
+    people.sort_by(by_age_first.then_comparing(|person: &Person| person.name).reversed().into_fn());
+    assert_eq!(names(&people), ["Dave", "carol", "alice", "Bob"]);
+
+    let by_height = comparing_by(|person: &&Person| &person.height, float_order());
+    let tallest = people.iter().max_by(by_height.into_fn());
+    assert_eq!(tallest.unwrap().name, "Dave");
+    let shortest = people.iter().filter(|person| !person.height.is_nan()).min_by(by_height.into_fn());
+    assert_eq!(shortest.unwrap().name, "carol");
+
+    // Plain closures are comparators too
+    let by_length = |a: &&str, b: &&str| a.len().cmp(&b.len());
+    let mut words = vec!["ccc", "a", "bb"];
+    words.sort_by(by_length.reversed().into_fn());
+    assert_eq!(words, ["ccc", "bb", "a"]);
+}
+
+#[test]
+fn test_string_and_float_orders() {
+    let mut files = vec!["file10.txt", "file2.txt", "File1.txt", "file02.txt", "file1.txt", "file", "img12b", "img12a"];
+    files.sort_by(human_order().into_fn());
+    assert_eq!(files, ["File1.txt", "file", "file1.txt", "file02.txt", "file2.txt", "file10.txt", "img12a", "img12b"]);
+    assert_eq!(compare_human("v1.10.0", "v1.9.3"), Ordering::Greater);
+    assert_eq!(compare_human("x99999999999999999999999", "x100000000000000000000000"), Ordering::Less);
+    assert_eq!(compare_human("a007", "a7"), Ordering::Less);
+    assert_eq!(compare_human("", ""), Ordering::Equal);
+
+    let strings = vec!["b".to_string(), "A".to_string(), "a".to_string(), "Émile".to_string(), "éa".to_string()];
+    let mut sorted = strings.clone();
+    sorted.sort_by(|a, b| case_folded().compare(a, b).then_with(|| a.cmp(b)));
+    assert_eq!(sorted, ["A", "a", "b", "éa", "Émile"]);
+    assert_eq!(case_folded().compare("HELLO", "hello"), Ordering::Equal);
+
+    let mut numbers = vec![2.5, f64::NAN, -0.0, f64::NEG_INFINITY, 0.0, -1.0];
+    numbers.sort_by(|a, b| float_order().compare(a, b));
+    assert_eq!(format!("{:?}", numbers), "[-inf, -1.0, -0.0, 0.0, 2.5, NaN]");
+    assert_eq!(FloatOrder.compare(&1.0f32, &f32::INFINITY), Ordering::Less);
+}
+
+#[test]
+fn test_ordered_collections() {
+    use crate::chapter_10::BinaryTree;
+    use std::collections::BinaryHeap;
+
+    let by_human = |a: &&str, b: &&str| compare_human(a, b);
+    let tree: BinaryTree<_> = vec!["page10", "page2", "page1", "page33", "page3"].into_iter()
+        .map(|page| OrderedBy::new(page, by_human))
+        .collect();
+    assert_eq!(tree.to_string(), "(page10 (page2 page1 page3) page33)");
+
+    // A priority queue, with the most urgent task (smallest priority)
+    // first, and the older of equally urgent tasks first
+    let urgent_first = comparing(|&(priority, id, _): &(u8, u32, &str)| (priority, id)).reversed();
+    let mut queue = BinaryHeap::new();
+    for (id, &(priority, task)) in [(2, "water plants"), (1, "feed cat"), (3, "nap"), (1, "walk dog")].iter().enumerate() {
+        queue.push(OrderedBy::new((priority, id as u32, task), urgent_first));
+    }
+    let order: Vec<_> = std::iter::from_fn(|| queue.pop()).map(|task| task.into_inner().2).collect();
+    assert_eq!(order, ["feed cat", "walk dog", "water plants", "nap"]);
+}
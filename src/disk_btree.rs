@@ -0,0 +1,1019 @@
+#![allow(dead_code)]
+
+// An on-disk B+tree, the big sibling of chapter_10's BinaryTree. The tree
+// lives in a file of fixed-size pages: page 0 is a header, and every other
+// page is a leaf (sorted keys and values, chained to the next leaf for range
+// scans), an internal node (separator keys and child page numbers), or a
+// free page waiting to be reused. Only the pages being touched need to be in
+// memory, and a small LRU cache keeps the hot ones around.
+//
+// Every change is made in the cache first. When an operation finishes, the
+// pages it dirtied are written to a journal file and synced, then copied into
+// the tree file, and only then is the journal deleted. If we crash part way
+// through, opening the tree replays a complete journal or throws away an
+// incomplete one, so the file always reflects whole operations.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Page size used by `DiskBTree::create`
+pub const DEFAULT_PAGE_SIZE: usize = 4096;
+
+/// Number of pages `DiskBTree` keeps cached unless told otherwise
+pub const DEFAULT_CACHE_PAGES: usize = 64;
+
+const MAGIC: &[u8; 8] = b"RPBTREE1";
+const JOURNAL_MAGIC: &[u8; 8] = b"RPJRNL01";
+const HEADER_PAGE: u64 = 0;
+const NODE_HEADER_SIZE: usize = 11;
+
+const FREE_PAGE: u8 = 0;
+const LEAF_PAGE: u8 = 1;
+const INTERNAL_PAGE: u8 = 2;
+
+/// A type that is always stored in the same number of bytes, so it can be
+/// packed into a page
+pub trait FixedSize: Sized {
+    /// Number of bytes `encode` writes
+    const SIZE: usize;
+
+    /// Write `self` into `out`, which is exactly `SIZE` bytes long
+    fn encode(&self, out: &mut [u8]);
+
+    /// Read back a value written by `encode`
+    fn decode(bytes: &[u8]) -> Self;
+}
+
+macro_rules! fixed_size_int {
+    ($($t:ty),*) => {
+        $(
+            impl FixedSize for $t {
+                const SIZE: usize = std::mem::size_of::<$t>();
+
+                fn encode(&self, out: &mut [u8]) {
+                    out.copy_from_slice(&self.to_be_bytes());
+                }
+
+                fn decode(bytes: &[u8]) -> Self {
+                    let mut raw = [0; std::mem::size_of::<$t>()];
+                    raw.copy_from_slice(bytes);
+                    <$t>::from_be_bytes(raw)
+                }
+            }
+        )*
+    }
+}
+
+fixed_size_int!(u16, u32, u64, i16, i32, i64);
+
+impl<const N: usize> FixedSize for [u8; N] {
+    const SIZE: usize = N;
+
+    fn encode(&self, out: &mut [u8]) {
+        out.copy_from_slice(self);
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        let mut raw = [0; N];
+        raw.copy_from_slice(bytes);
+        raw
+    }
+}
+
+fn corrupt(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::decode(&bytes[at..at + 8])
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::decode(&bytes[at..at + 4])
+}
+
+/// The contents of page 0
+#[derive(Clone, Debug, PartialEq)]
+struct Header {
+    page_size: usize,
+    root: u64,
+    page_count: u64,
+    free_head: u64, // first page of the free list, or 0 if it's empty
+    len: u64
+}
+
+impl Header {
+    fn encode(&self, key_size: usize, value_size: usize) -> Vec<u8> {
+        let mut page = vec![0; self.page_size];
+        page[0..8].copy_from_slice(MAGIC);
+        (self.page_size as u32).encode(&mut page[8..12]);
+        (key_size as u32).encode(&mut page[12..16]);
+        (value_size as u32).encode(&mut page[16..20]);
+        self.root.encode(&mut page[20..28]);
+        self.page_count.encode(&mut page[28..36]);
+        self.free_head.encode(&mut page[36..44]);
+        self.len.encode(&mut page[44..52]);
+        page
+    }
+}
+
+/// A decoded tree page
+#[derive(Clone, Debug, PartialEq)]
+enum Node<K, V> {
+    Free { next: u64 },
+    Leaf { keys: Vec<K>, values: Vec<V>, next: u64 },
+    // `children[i]` holds keys below `keys[i]`; the last child holds the rest
+    Internal { keys: Vec<K>, children: Vec<u64> }
+}
+
+impl<K: FixedSize, V: FixedSize> Node<K, V> {
+    fn empty_leaf() -> Self {
+        Node::Leaf { keys: Vec::new(), values: Vec::new(), next: 0 }
+    }
+
+    fn encode(&self, page_size: usize) -> Vec<u8> {
+        let mut page = vec![0; page_size];
+        let mut at = NODE_HEADER_SIZE;
+        match *self {
+            Node::Free { next } => {
+                page[0] = FREE_PAGE;
+                next.encode(&mut page[3..11]);
+            }
+            Node::Leaf { ref keys, ref values, next } => {
+                page[0] = LEAF_PAGE;
+                (keys.len() as u16).encode(&mut page[1..3]);
+                next.encode(&mut page[3..11]);
+                for (key, value) in keys.iter().zip(values) {
+                    key.encode(&mut page[at..at + K::SIZE]);
+                    at += K::SIZE;
+                    value.encode(&mut page[at..at + V::SIZE]);
+                    at += V::SIZE;
+                }
+            }
+            Node::Internal { ref keys, ref children } => {
+                page[0] = INTERNAL_PAGE;
+                (keys.len() as u16).encode(&mut page[1..3]);
+                children[0].encode(&mut page[3..11]);
+                for (key, child) in keys.iter().zip(&children[1..]) {
+                    key.encode(&mut page[at..at + K::SIZE]);
+                    at += K::SIZE;
+                    child.encode(&mut page[at..at + 8]);
+                    at += 8;
+                }
+            }
+        }
+        page
+    }
+
+    fn decode(page: &[u8]) -> io::Result<Self> {
+        let count = u16::decode(&page[1..3]) as usize;
+        let next = read_u64(page, 3);
+        let mut at = NODE_HEADER_SIZE;
+        match page[0] {
+            FREE_PAGE => Ok(Node::Free { next }),
+            LEAF_PAGE => {
+                if NODE_HEADER_SIZE + count * (K::SIZE + V::SIZE) > page.len() {
+                    return Err(corrupt("leaf page entry count is too large"));
+                }
+                let mut keys = Vec::with_capacity(count);
+                let mut values = Vec::with_capacity(count);
+                for _ in 0..count {
+                    keys.push(K::decode(&page[at..at + K::SIZE]));
+                    at += K::SIZE;
+                    values.push(V::decode(&page[at..at + V::SIZE]));
+                    at += V::SIZE;
+                }
+                Ok(Node::Leaf { keys, values, next })
+            }
+            INTERNAL_PAGE => {
+                if NODE_HEADER_SIZE + count * (K::SIZE + 8) > page.len() {
+                    return Err(corrupt("internal page entry count is too large"));
+                }
+                let mut keys = Vec::with_capacity(count);
+                let mut children = Vec::with_capacity(count + 1);
+                children.push(next);
+                for _ in 0..count {
+                    keys.push(K::decode(&page[at..at + K::SIZE]));
+                    at += K::SIZE;
+                    children.push(read_u64(page, at));
+                    at += 8;
+                }
+                Ok(Node::Internal { keys, children })
+            }
+            _ => Err(corrupt("unknown page type"))
+        }
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            Node::Free { .. } => 0,
+            Node::Leaf { ref keys, .. } | Node::Internal { ref keys, .. } => keys.len()
+        }
+    }
+}
+
+/// The separator key and page number of a newly split-off right sibling
+type Split<K> = Option<(K, u64)>;
+
+// One page held in the cache.
+struct CachedPage<K, V> {
+    node: Node<K, V>,
+    dirty: bool,
+    last_used: u64
+}
+
+/// An ordered map from `K` to `V` stored as a B+tree in a file
+pub struct DiskBTree<K, V> {
+    file: File,
+    journal_path: PathBuf,
+    header: Header,
+    header_dirty: bool,
+    cache: HashMap<u64, CachedPage<K, V>>,
+    cache_capacity: usize,
+    clock: u64,
+    leaf_capacity: usize,
+    internal_capacity: usize,
+    _types: PhantomData<(K, V)>
+}
+
+/// Where a tree file keeps its journal
+fn journal_path_for(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".journal");
+    PathBuf::from(name)
+}
+
+/// Page numbers and contents, as stored in a journal
+type JournalPages = Vec<(u64, Vec<u8>)>;
+
+/// FNV-1a, used to tell a complete journal from a torn one
+fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Write `pages` to a journal file and sync it. The layout is the magic,
+/// the page size and page count, each page number followed by its bytes,
+/// and finally a checksum of everything before it.
+fn write_journal(path: &Path, page_size: usize, pages: &JournalPages) -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(24 + pages.len() * (8 + page_size) + 8);
+    bytes.extend_from_slice(JOURNAL_MAGIC);
+    bytes.extend_from_slice(&(page_size as u64).to_be_bytes());
+    bytes.extend_from_slice(&(pages.len() as u64).to_be_bytes());
+    for (page_id, page) in pages {
+        bytes.extend_from_slice(&page_id.to_be_bytes());
+        bytes.extend_from_slice(page);
+    }
+    let sum = checksum(&bytes);
+    bytes.extend_from_slice(&sum.to_be_bytes());
+
+    let mut journal = File::create(path)?;
+    journal.write_all(&bytes)?;
+    journal.sync_all()
+}
+
+/// Read back a journal, returning `None` if it was never completely written
+fn read_journal(path: &Path) -> io::Result<Option<(usize, JournalPages)>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+
+    if bytes.len() < 32 || &bytes[0..8] != JOURNAL_MAGIC {
+        return Ok(None);
+    }
+    let (body, sum) = bytes.split_at(bytes.len() - 8);
+    if checksum(body) != u64::decode(sum) {
+        return Ok(None);
+    }
+
+    let page_size = read_u64(body, 8) as usize;
+    let count = read_u64(body, 16) as usize;
+    if body.len() != 24 + count * (8 + page_size) {
+        return Ok(None);
+    }
+    let mut pages = Vec::with_capacity(count);
+    let mut at = 24;
+    for _ in 0..count {
+        let page_id = read_u64(body, at);
+        pages.push((page_id, body[at + 8..at + 8 + page_size].to_vec()));
+        at += 8 + page_size;
+    }
+    Ok(Some((page_size, pages)))
+}
+
+fn write_page(file: &mut File, page_size: usize, page_id: u64, page: &[u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(page_id * page_size as u64))?;
+    file.write_all(page)
+}
+
+/// Finish or discard whatever a crash left behind in the journal
+fn recover(file: &mut File, journal_path: &Path) -> io::Result<()> {
+    if !journal_path.exists() {
+        return Ok(());
+    }
+    if let Some((page_size, pages)) = read_journal(journal_path)? {
+        for (page_id, page) in &pages {
+            write_page(file, page_size, *page_id, page)?;
+        }
+        file.sync_all()?;
+    }
+    // An incomplete journal means the tree file was never touched
+    fs::remove_file(journal_path)
+}
+
+/// How many entries fit in a leaf and an internal page of `page_size`
+/// bytes, or an error if it's less than three or more than the u16 entry
+/// count in a page header can say
+fn check_page_size<K: FixedSize, V: FixedSize>(page_size: usize) -> io::Result<(usize, usize)> {
+    let usable = page_size.saturating_sub(NODE_HEADER_SIZE);
+    let leaf_capacity = usable / (K::SIZE + V::SIZE);
+    let internal_capacity = usable / (K::SIZE + 8);
+    if page_size < 52 || leaf_capacity < 3 || internal_capacity < 3 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "page size too small for three entries"));
+    }
+    if leaf_capacity > u16::MAX as usize || internal_capacity > u16::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "page size too large to count its entries"));
+    }
+    Ok((leaf_capacity, internal_capacity))
+}
+
+impl<K, V> DiskBTree<K, V>
+    where K: FixedSize + Ord + Clone, V: FixedSize + Clone
+{
+    /// Create a new, empty tree at `path` using `DEFAULT_PAGE_SIZE`,
+    /// replacing any file already there
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        DiskBTree::create_with_page_size(path, DEFAULT_PAGE_SIZE)
+    }
+
+    /// Create a new, empty tree at `path` with pages of `page_size` bytes.
+    /// Pages must be big enough to hold at least three entries.
+    pub fn create_with_page_size<P: AsRef<Path>>(path: P, page_size: usize) -> io::Result<Self> {
+        let path = path.as_ref();
+        // Check the page size before touching whatever's at `path`
+        check_page_size::<K, V>(page_size)?;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let journal_path = journal_path_for(path);
+        if journal_path.exists() {
+            fs::remove_file(&journal_path)?;
+        }
+
+        let header = Header { page_size, root: 1, page_count: 2, free_head: 0, len: 0 };
+        let mut tree = DiskBTree::from_parts(file, journal_path, header)?;
+        tree.file.set_len(0)?;
+        tree.header_dirty = true;
+        tree.put_node(1, Node::empty_leaf());
+        tree.commit()?;
+        Ok(tree)
+    }
+
+    /// Open an existing tree, first recovering from any interrupted write
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let journal_path = journal_path_for(path);
+        recover(&mut file, &journal_path)?;
+
+        let mut page = [0; 52];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut page)?;
+        if &page[0..8] != MAGIC {
+            return Err(corrupt("not a B+tree file"));
+        }
+        if read_u32(&page, 12) as usize != K::SIZE || read_u32(&page, 16) as usize != V::SIZE {
+            return Err(corrupt("key or value size doesn't match the file"));
+        }
+        let header = Header {
+            page_size: read_u32(&page, 8) as usize,
+            root: read_u64(&page, 20),
+            page_count: read_u64(&page, 28),
+            free_head: read_u64(&page, 36),
+            len: read_u64(&page, 44)
+        };
+        DiskBTree::from_parts(file, journal_path, header)
+    }
+
+    fn from_parts(file: File, journal_path: PathBuf, header: Header) -> io::Result<Self> {
+        let (leaf_capacity, internal_capacity) = check_page_size::<K, V>(header.page_size)?;
+        Ok(DiskBTree {
+            file,
+            journal_path,
+            header,
+            header_dirty: false,
+            cache: HashMap::new(),
+            cache_capacity: DEFAULT_CACHE_PAGES,
+            clock: 0,
+            leaf_capacity,
+            internal_capacity,
+            _types: PhantomData
+        })
+    }
+
+    /// Change how many pages are kept in memory between operations
+    pub fn set_cache_capacity(&mut self, pages: usize) {
+        self.cache_capacity = pages;
+        self.evict();
+    }
+
+    /// Number of keys in the tree
+    pub fn len(&self) -> usize {
+        self.header.len as usize
+    }
+
+    /// Returns true if the tree holds no keys
+    pub fn is_empty(&self) -> bool {
+        self.header.len == 0
+    }
+
+    // Page cache
+
+    fn get_node(&mut self, page_id: u64) -> io::Result<Node<K, V>> {
+        self.clock += 1;
+        if let Some(cached) = self.cache.get_mut(&page_id) {
+            cached.last_used = self.clock;
+            return Ok(cached.node.clone());
+        }
+
+        let mut page = vec![0; self.header.page_size];
+        self.file.seek(SeekFrom::Start(page_id * self.header.page_size as u64))?;
+        self.file.read_exact(&mut page)?;
+        let node = Node::decode(&page)?;
+        self.cache.insert(page_id, CachedPage { node: node.clone(), dirty: false, last_used: self.clock });
+        self.evict();
+        Ok(node)
+    }
+
+    fn put_node(&mut self, page_id: u64, node: Node<K, V>) {
+        self.clock += 1;
+        self.cache.insert(page_id, CachedPage { node, dirty: true, last_used: self.clock });
+    }
+
+    /// Drop least recently used clean pages until the cache fits
+    fn evict(&mut self) {
+        while self.cache.len() > self.cache_capacity {
+            let victim = self.cache.iter()
+                .filter(|(_, cached)| !cached.dirty)
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(page_id, _)| *page_id);
+            match victim {
+                Some(page_id) => { self.cache.remove(&page_id); }
+                None => break
+            }
+        }
+    }
+
+    fn allocate_page(&mut self) -> io::Result<u64> {
+        self.header_dirty = true;
+        if self.header.free_head != 0 {
+            let page_id = self.header.free_head;
+            match self.get_node(page_id)? {
+                Node::Free { next } => self.header.free_head = next,
+                _ => return Err(corrupt("free list points at a page in use"))
+            }
+            return Ok(page_id);
+        }
+        let page_id = self.header.page_count;
+        self.header.page_count += 1;
+        Ok(page_id)
+    }
+
+    fn free_page(&mut self, page_id: u64) {
+        let next = self.header.free_head;
+        self.put_node(page_id, Node::Free { next });
+        self.header.free_head = page_id;
+        self.header_dirty = true;
+    }
+
+    /// Make every change since the last commit durable, through the journal
+    fn commit(&mut self) -> io::Result<()> {
+        let page_size = self.header.page_size;
+        let mut pages: JournalPages = self.cache.iter()
+            .filter(|(_, cached)| cached.dirty)
+            .map(|(page_id, cached)| (*page_id, cached.node.encode(page_size)))
+            .collect();
+        if self.header_dirty {
+            pages.push((HEADER_PAGE, self.header.encode(K::SIZE, V::SIZE)));
+        }
+        if pages.is_empty() {
+            return Ok(());
+        }
+        pages.sort_by_key(|(page_id, _)| *page_id);
+
+        write_journal(&self.journal_path, page_size, &pages)?;
+        for (page_id, page) in &pages {
+            write_page(&mut self.file, page_size, *page_id, page)?;
+        }
+        self.file.sync_all()?;
+        fs::remove_file(&self.journal_path)?;
+
+        for cached in self.cache.values_mut() {
+            cached.dirty = false;
+        }
+        self.header_dirty = false;
+        self.evict();
+        Ok(())
+    }
+
+    /// Forget every change since the last commit, after an operation failed
+    fn rollback(&mut self) -> io::Result<()> {
+        self.cache.retain(|_, cached| !cached.dirty);
+        if self.header_dirty {
+            let page_size = self.header.page_size;
+            let mut page = [0; 52];
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.read_exact(&mut page)?;
+            self.header = Header {
+                page_size,
+                root: read_u64(&page, 20),
+                page_count: read_u64(&page, 28),
+                free_head: read_u64(&page, 36),
+                len: read_u64(&page, 44)
+            };
+            self.header_dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Commit if `result` succeeded, roll back if it didn't
+    fn finish<T>(&mut self, result: io::Result<T>) -> io::Result<T> {
+        match result {
+            Ok(value) => {
+                self.commit()?;
+                Ok(value)
+            }
+            Err(error) => {
+                self.rollback()?;
+                Err(error)
+            }
+        }
+    }
+
+    // Lookups
+
+    /// Find the value stored under `key`
+    pub fn search(&mut self, key: &K) -> io::Result<Option<V>> {
+        let mut page_id = self.header.root;
+        loop {
+            match self.get_node(page_id)? {
+                Node::Internal { keys, children } => {
+                    page_id = children[keys.partition_point(|k| k <= key)];
+                }
+                Node::Leaf { keys, values, .. } => {
+                    return Ok(keys.binary_search(key).ok().map(|i| values[i].clone()));
+                }
+                Node::Free { .. } => return Err(corrupt("tree points at a free page"))
+            }
+        }
+    }
+
+    /// Every key and value with a key in `range`, in order, found by
+    /// walking the chain of leaves
+    pub fn range(&mut self, range: Range<K>) -> io::Result<Vec<(K, V)>> {
+        let mut found = Vec::new();
+        let mut page_id = self.header.root;
+        loop {
+            match self.get_node(page_id)? {
+                Node::Internal { keys, children } => {
+                    page_id = children[keys.partition_point(|k| *k <= range.start)];
+                }
+                Node::Leaf { .. } => break,
+                Node::Free { .. } => return Err(corrupt("tree points at a free page"))
+            }
+        }
+
+        while page_id != 0 {
+            let (keys, values, next) = match self.get_node(page_id)? {
+                Node::Leaf { keys, values, next } => (keys, values, next),
+                _ => return Err(corrupt("leaf chain points at a non-leaf page"))
+            };
+            for (key, value) in keys.into_iter().zip(values) {
+                if key >= range.end {
+                    return Ok(found);
+                }
+                if key >= range.start {
+                    found.push((key, value));
+                }
+            }
+            page_id = next;
+        }
+        Ok(found)
+    }
+
+    // Insertion
+
+    /// Store `value` under `key`, returning the value it replaced
+    pub fn add(&mut self, key: K, value: V) -> io::Result<Option<V>> {
+        let result = self.add_inner(key, value);
+        self.finish(result)
+    }
+
+    fn add_inner(&mut self, key: K, value: V) -> io::Result<Option<V>> {
+        let root = self.header.root;
+        let (replaced, split) = self.insert_at(root, key, value)?;
+        if let Some((separator, right)) = split {
+            let new_root = self.allocate_page()?;
+            self.put_node(new_root, Node::Internal { keys: vec![separator], children: vec![root, right] });
+            self.header.root = new_root;
+        }
+        if replaced.is_none() {
+            self.header.len += 1;
+            self.header_dirty = true;
+        }
+        Ok(replaced)
+    }
+
+    /// Insert below `page_id`. If the page had to split, also returns the
+    /// first key of the new right-hand page and that page's number.
+    fn insert_at(&mut self, page_id: u64, key: K, value: V)
+                 -> io::Result<(Option<V>, Split<K>)>
+    {
+        match self.get_node(page_id)? {
+            Node::Leaf { mut keys, mut values, next } => {
+                match keys.binary_search(&key) {
+                    Ok(i) => {
+                        let old = std::mem::replace(&mut values[i], value);
+                        self.put_node(page_id, Node::Leaf { keys, values, next });
+                        return Ok((Some(old), None));
+                    }
+                    Err(i) => {
+                        keys.insert(i, key);
+                        values.insert(i, value);
+                    }
+                }
+                if keys.len() <= self.leaf_capacity {
+                    self.put_node(page_id, Node::Leaf { keys, values, next });
+                    return Ok((None, None));
+                }
+
+                let mid = keys.len() / 2;
+                let right_keys = keys.split_off(mid);
+                let right_values = values.split_off(mid);
+                let right = self.allocate_page()?;
+                let separator = right_keys[0].clone();
+                self.put_node(right, Node::Leaf { keys: right_keys, values: right_values, next });
+                self.put_node(page_id, Node::Leaf { keys, values, next: right });
+                Ok((None, Some((separator, right))))
+            }
+            Node::Internal { mut keys, mut children } => {
+                let i = keys.partition_point(|k| *k <= key);
+                let (replaced, split) = self.insert_at(children[i], key, value)?;
+                let (child_separator, child_right) = match split {
+                    None => return Ok((replaced, None)),
+                    Some(split) => split
+                };
+                keys.insert(i, child_separator);
+                children.insert(i + 1, child_right);
+                if keys.len() <= self.internal_capacity {
+                    self.put_node(page_id, Node::Internal { keys, children });
+                    return Ok((replaced, None));
+                }
+
+                // The middle key moves up rather than being copied
+                let mid = keys.len() / 2;
+                let mut right_keys = keys.split_off(mid);
+                let separator = right_keys.remove(0);
+                let right_children = children.split_off(mid + 1);
+                let right = self.allocate_page()?;
+                self.put_node(right, Node::Internal { keys: right_keys, children: right_children });
+                self.put_node(page_id, Node::Internal { keys, children });
+                Ok((replaced, Some((separator, right))))
+            }
+            Node::Free { .. } => Err(corrupt("tree points at a free page"))
+        }
+    }
+
+    // Deletion
+
+    /// Remove `key`, returning its value if it was present
+    pub fn remove(&mut self, key: &K) -> io::Result<Option<V>> {
+        let result = self.remove_inner(key);
+        self.finish(result)
+    }
+
+    fn remove_inner(&mut self, key: &K) -> io::Result<Option<V>> {
+        let root = self.header.root;
+        let removed = self.remove_at(root, key)?;
+        if removed.is_some() {
+            self.header.len -= 1;
+            self.header_dirty = true;
+        }
+        // A root with a single child is just a longer path to that child
+        if let Node::Internal { ref keys, ref children } = self.get_node(root)? {
+            if keys.is_empty() {
+                self.header.root = children[0];
+                self.free_page(root);
+            }
+        }
+        Ok(removed)
+    }
+
+    fn min_len(&self, node: &Node<K, V>) -> usize {
+        match *node {
+            Node::Leaf { .. } => self.leaf_capacity / 2,
+            _ => self.internal_capacity / 2
+        }
+    }
+
+    fn remove_at(&mut self, page_id: u64, key: &K) -> io::Result<Option<V>> {
+        match self.get_node(page_id)? {
+            Node::Leaf { mut keys, mut values, next } => {
+                match keys.binary_search(key) {
+                    Ok(i) => {
+                        keys.remove(i);
+                        let value = values.remove(i);
+                        self.put_node(page_id, Node::Leaf { keys, values, next });
+                        Ok(Some(value))
+                    }
+                    Err(_) => Ok(None)
+                }
+            }
+            Node::Internal { mut keys, mut children } => {
+                let i = keys.partition_point(|k| k <= key);
+                let removed = self.remove_at(children[i], key)?;
+                if removed.is_some() {
+                    let child = self.get_node(children[i])?;
+                    if child.len() < self.min_len(&child) {
+                        self.rebalance(&mut keys, &mut children, i)?;
+                        self.put_node(page_id, Node::Internal { keys, children });
+                    }
+                }
+                Ok(removed)
+            }
+            Node::Free { .. } => Err(corrupt("tree points at a free page"))
+        }
+    }
+
+    /// Fix up `children[i]`, which has dropped below its minimum size, by
+    /// borrowing an entry from a sibling or merging with one
+    fn rebalance(&mut self, keys: &mut Vec<K>, children: &mut Vec<u64>, i: usize) -> io::Result<()> {
+        let child = self.get_node(children[i])?;
+        let min = self.min_len(&child);
+
+        if i > 0 {
+            let left = self.get_node(children[i - 1])?;
+            if left.len() > min {
+                return self.borrow_from_left(keys, children, i, left, child);
+            }
+        }
+        if i + 1 < children.len() {
+            let right = self.get_node(children[i + 1])?;
+            if right.len() > min {
+                return self.borrow_from_right(keys, children, i, child, right);
+            }
+        }
+
+        // Neither sibling can spare anything, so merge with one of them
+        let left_index = if i > 0 { i - 1 } else { i };
+        let left = self.get_node(children[left_index])?;
+        let right = self.get_node(children[left_index + 1])?;
+        self.merge(keys, children, left_index, left, right)
+    }
+
+    fn borrow_from_left(&mut self, keys: &mut [K], children: &[u64], i: usize,
+                        left: Node<K, V>, child: Node<K, V>) -> io::Result<()> {
+        match (left, child) {
+            (Node::Leaf { keys: mut lk, values: mut lv, next: ln },
+             Node::Leaf { keys: mut ck, values: mut cv, next: cn }) => {
+                ck.insert(0, lk.pop().unwrap());
+                cv.insert(0, lv.pop().unwrap());
+                keys[i - 1] = ck[0].clone();
+                self.put_node(children[i - 1], Node::Leaf { keys: lk, values: lv, next: ln });
+                self.put_node(children[i], Node::Leaf { keys: ck, values: cv, next: cn });
+            }
+            (Node::Internal { keys: mut lk, children: mut lc },
+             Node::Internal { keys: mut ck, children: mut cc }) => {
+                ck.insert(0, keys[i - 1].clone());
+                cc.insert(0, lc.pop().unwrap());
+                keys[i - 1] = lk.pop().unwrap();
+                self.put_node(children[i - 1], Node::Internal { keys: lk, children: lc });
+                self.put_node(children[i], Node::Internal { keys: ck, children: cc });
+            }
+            _ => return Err(corrupt("siblings are different kinds of page"))
+        }
+        Ok(())
+    }
+
+    fn borrow_from_right(&mut self, keys: &mut [K], children: &[u64], i: usize,
+                         child: Node<K, V>, right: Node<K, V>) -> io::Result<()> {
+        match (child, right) {
+            (Node::Leaf { keys: mut ck, values: mut cv, next: cn },
+             Node::Leaf { keys: mut rk, values: mut rv, next: rn }) => {
+                ck.push(rk.remove(0));
+                cv.push(rv.remove(0));
+                keys[i] = rk[0].clone();
+                self.put_node(children[i], Node::Leaf { keys: ck, values: cv, next: cn });
+                self.put_node(children[i + 1], Node::Leaf { keys: rk, values: rv, next: rn });
+            }
+            (Node::Internal { keys: mut ck, children: mut cc },
+             Node::Internal { keys: mut rk, children: mut rc }) => {
+                ck.push(keys[i].clone());
+                cc.push(rc.remove(0));
+                keys[i] = rk.remove(0);
+                self.put_node(children[i], Node::Internal { keys: ck, children: cc });
+                self.put_node(children[i + 1], Node::Internal { keys: rk, children: rc });
+            }
+            _ => return Err(corrupt("siblings are different kinds of page"))
+        }
+        Ok(())
+    }
+
+    /// Merge `children[i + 1]` into `children[i]` and free its page
+    fn merge(&mut self, keys: &mut Vec<K>, children: &mut Vec<u64>, i: usize,
+             left: Node<K, V>, right: Node<K, V>) -> io::Result<()> {
+        let separator = keys.remove(i);
+        let right_page = children.remove(i + 1);
+        match (left, right) {
+            (Node::Leaf { keys: mut lk, values: mut lv, .. },
+             Node::Leaf { keys: rk, values: rv, next }) => {
+                lk.extend(rk);
+                lv.extend(rv);
+                self.put_node(children[i], Node::Leaf { keys: lk, values: lv, next });
+            }
+            (Node::Internal { keys: mut lk, children: mut lc },
+             Node::Internal { keys: rk, children: rc }) => {
+                lk.push(separator);
+                lk.extend(rk);
+                lc.extend(rc);
+                self.put_node(children[i], Node::Internal { keys: lk, children: lc });
+            }
+            _ => return Err(corrupt("siblings are different kinds of page"))
+        }
+        self.free_page(right_page);
+        Ok(())
+    }
+}
+
+/// A path in the temp directory that no other test is using
+#[cfg(test)]
+fn scratch_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir()
+        .join(format!("rust_practice_{}_{}.btree", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(journal_path_for(&path));
+    path
+}
+
+#[test]
+fn test_disk_btree_add_and_search() {
+    let path = scratch_path("add_search");
+    {
+        let mut tree = DiskBTree::<u64, u64>::create_with_page_size(&path, 64).unwrap();
+        assert!(tree.is_empty());
+        for i in 0..500u64 {
+            // Scatter the insertion order so splits happen all over the tree
+            let key = (i * 7919) % 500;
+            assert_eq!(tree.add(key, key * 10).unwrap(), None);
+        }
+        assert_eq!(tree.len(), 500);
+        assert_eq!(tree.add(42, 1).unwrap(), Some(420));
+        assert_eq!(tree.search(&42).unwrap(), Some(1));
+        assert_eq!(tree.search(&499).unwrap(), Some(4990));
+        assert_eq!(tree.search(&500).unwrap(), None);
+    }
+
+    // Everything is still there after reopening with a tiny cache
+    let mut tree = DiskBTree::<u64, u64>::open(&path).unwrap();
+    tree.set_cache_capacity(4);
+    assert_eq!(tree.len(), 500);
+    for key in 0..500u64 {
+        let expected = if key == 42 { 1 } else { key * 10 };
+        assert_eq!(tree.search(&key).unwrap(), Some(expected));
+    }
+    assert!(tree.cache.len() <= 4);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_disk_btree_range() {
+    let path = scratch_path("range");
+    let mut tree = DiskBTree::<i32, [u8; 4]>::create_with_page_size(&path, 64).unwrap();
+    for key in (0..200).rev() {
+        tree.add(key * 2, (key as u32).to_be_bytes()).unwrap();
+    }
+
+    let found = tree.range(15..31).unwrap();
+    let keys: Vec<i32> = found.iter().map(|(key, _)| *key).collect();
+    assert_eq!(keys, vec![16, 18, 20, 22, 24, 26, 28, 30]);
+    assert_eq!(found[0].1, 8u32.to_be_bytes());
+
+    assert_eq!(tree.range(-10..1000).unwrap().len(), 200);
+    assert!(tree.range(1000..2000).unwrap().is_empty());
+    assert!(tree.range(7..8).unwrap().is_empty());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_disk_btree_remove() {
+    let path = scratch_path("remove");
+    let mut tree = DiskBTree::<u32, u32>::create_with_page_size(&path, 64).unwrap();
+    for key in 0..300 {
+        tree.add(key, key).unwrap();
+    }
+    let pages_when_full = tree.header.page_count;
+
+    for key in (0..300).filter(|key| key % 3 != 0) {
+        assert_eq!(tree.remove(&key).unwrap(), Some(key));
+    }
+    assert_eq!(tree.remove(&1).unwrap(), None);
+    assert_eq!(tree.len(), 100);
+    let expected: Vec<(u32, u32)> = (0..300).step_by(3).map(|key| (key, key)).collect();
+    assert_eq!(tree.range(0..300).unwrap(), expected);
+
+    // Freed pages get reused instead of growing the file
+    for key in 1000..1200 {
+        tree.add(key, key).unwrap();
+    }
+    assert_eq!(tree.header.page_count, pages_when_full);
+
+    for key in (0..300).step_by(3).chain(1000..1200) {
+        assert_eq!(tree.remove(&key).unwrap(), Some(key));
+    }
+    assert!(tree.is_empty());
+    assert!(tree.range(0..u32::MAX).unwrap().is_empty());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_disk_btree_recovery() {
+    let path = scratch_path("recovery");
+    let snapshot = scratch_path("recovery_snapshot");
+    {
+        let mut tree = DiskBTree::<u64, u64>::create_with_page_size(&path, 128).unwrap();
+        for key in 0..50 {
+            tree.add(key, key).unwrap();
+        }
+    }
+    fs::copy(&path, &snapshot).unwrap();
+    let before = fs::read(&path).unwrap();
+    {
+        let mut tree = DiskBTree::<u64, u64>::open(&path).unwrap();
+        for key in 50..100 {
+            tree.add(key, key).unwrap();
+        }
+    }
+
+    // Pretend we crashed after writing the journal for the second batch of
+    // adds but before copying any of it into the tree file
+    let mut bytes = Vec::new();
+    File::open(&path).unwrap().read_to_end(&mut bytes).unwrap();
+    let pages: JournalPages = bytes.chunks(128)
+        .enumerate()
+        .map(|(i, page)| (i as u64, page.to_vec()))
+        .collect();
+    let journal = journal_path_for(&snapshot);
+    write_journal(&journal, 128, &pages).unwrap();
+    {
+        let mut tree = DiskBTree::<u64, u64>::open(&snapshot).unwrap();
+        assert!(!journal.exists());
+        assert_eq!(tree.len(), 100);
+        assert_eq!(tree.search(&99).unwrap(), Some(99));
+    }
+
+    // A torn journal is thrown away, leaving the tree as it was before the
+    // second batch
+    fs::write(&snapshot, &before).unwrap();
+    write_journal(&journal, 128, &pages).unwrap();
+    let journal_len = fs::metadata(&journal).unwrap().len();
+    OpenOptions::new().write(true).open(&journal).unwrap().set_len(journal_len - 3).unwrap();
+    {
+        let mut tree = DiskBTree::<u64, u64>::open(&snapshot).unwrap();
+        assert!(!journal.exists());
+        assert_eq!(tree.len(), 50);
+        assert_eq!(tree.search(&49).unwrap(), Some(49));
+        assert_eq!(tree.search(&50).unwrap(), None);
+        let keys: Vec<u64> = tree.range(0..u64::MAX).unwrap().into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, (0..50).collect::<Vec<u64>>());
+    }
+    assert_eq!(fs::read(&snapshot).unwrap(), before);
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&snapshot).unwrap();
+}
+
+#[test]
+fn test_disk_btree_rejects_mismatched_types() {
+    let path = scratch_path("mismatch");
+    DiskBTree::<u64, u64>::create_with_page_size(&path, 64).unwrap();
+    let error = DiskBTree::<u32, u64>::open(&path).err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    // A bad page size leaves the existing tree alone
+    let error = DiskBTree::<u64, u64>::create_with_page_size(&path, 32).err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    // Page headers count entries in a u16, so 65536 small ones won't fit
+    let error = DiskBTree::<u16, u16>::create_with_page_size(&path, 1 << 20).err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(DiskBTree::<u64, u64>::open(&path).unwrap().len(), 0);
+    fs::remove_file(&path).unwrap();
+}
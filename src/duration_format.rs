@@ -0,0 +1,493 @@
+#![allow(dead_code)]
+
+// Precise durations, for when a RoughTime's single unit isn't enough.
+// A CompoundDuration is a handful of (TimeUnit, count) pairs, like
+// "2 hours 5 minutes" or "1y 3mo", which can be printed in long or short
+// form, capped at a number of components, written and read as ISO 8601
+// durations ("P1Y3M"), or added to a calendar::DateTime.
+
+use crate::calendar::DateTime;
+use crate::chapter_10::TimeUnit;
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::Duration;
+
+/// How to spell out each component of a duration
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Style {
+    /// "2 hours 5 minutes"
+    Long,
+    /// "2h 5m"
+    Short
+}
+
+/// The compact symbol for a unit in `Style::Short`
+fn short_symbol(unit: TimeUnit) -> &'static str {
+    match unit {
+        TimeUnit::Milliseconds => "ms",
+        TimeUnit::Seconds => "s",
+        TimeUnit::Minutes => "m",
+        TimeUnit::Hours => "h",
+        TimeUnit::Days => "d",
+        TimeUnit::Weeks => "w",
+        TimeUnit::Months => "mo",
+        TimeUnit::Years => "y",
+        TimeUnit::Decades => "dec"
+    }
+}
+
+/// A duration made of whole numbers of several units
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct CompoundDuration {
+    negative: bool,
+    parts: Vec<(TimeUnit, u64)> // largest unit first, each unit once, no zero counts
+}
+
+impl CompoundDuration {
+    ///Constructor, for a duration of zero
+    pub fn new() -> Self {
+        CompoundDuration::default()
+    }
+
+    /// The same duration with `count` more of `unit`. Counts stop at
+    /// u64::MAX rather than wrapping.
+    pub fn with(mut self, unit: TimeUnit, count: u64) -> Self {
+        if count == 0 {
+            return self;
+        }
+        match self.parts.binary_search_by(|&(existing, _)| unit.cmp(&existing)) {
+            Ok(i) => self.parts[i].1 = self.parts[i].1.saturating_add(count),
+            Err(i) => self.parts.insert(i, (unit, count))
+        }
+        self
+    }
+
+    /// The same duration pointing the other way
+    pub fn negated(mut self) -> Self {
+        self.negative = !self.negative;
+        self
+    }
+
+    /// Split `nanos` into whole units, greedily using the biggest of
+    /// `units` first. Months and years have their average lengths, and
+    /// anything smaller than the smallest unit is dropped.
+    pub fn from_nanos(nanos: i128, units: &[TimeUnit]) -> Self {
+        let mut units = units.to_vec();
+        units.sort_by(|a, b| b.cmp(a));
+        units.dedup();
+
+        let mut duration = CompoundDuration::new();
+        let mut rest = nanos.unsigned_abs();
+        for unit in units {
+            let length = u128::from(unit.as_nanos());
+            let count = (rest / length).min(u128::from(u64::MAX)) as u64;
+            rest -= u128::from(count) * length;
+            duration = duration.with(unit, count);
+        }
+        duration.negative = nanos < 0 && !duration.is_zero();
+        duration
+    }
+
+    /// Returns true for durations in the past
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Returns true if every count is zero
+    pub fn is_zero(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    /// The nonzero components, largest unit first
+    pub fn parts(&self) -> &[(TimeUnit, u64)] {
+        &self.parts
+    }
+
+    /// The count for one unit
+    pub fn get(&self, unit: TimeUnit) -> u64 {
+        self.parts.iter()
+            .find(|&&(existing, _)| existing == unit)
+            .map_or(0, |&(_, count)| count)
+    }
+
+    /// The length of this duration using each unit's nominal length
+    pub fn nominal_nanos(&self) -> i128 {
+        let total: i128 = self.parts.iter()
+            .map(|&(unit, count)| i128::from(count) * i128::from(unit.as_nanos()))
+            .sum();
+        if self.negative { -total } else { total }
+    }
+
+    /// Keep only the `max_components` largest components
+    pub fn truncated(&self, max_components: usize) -> Self {
+        let parts: Vec<_> = self.parts.iter().take(max_components).cloned().collect();
+        CompoundDuration { negative: self.negative && !parts.is_empty(), parts }
+    }
+
+    /// Move `date` by this duration, biggest units first, following the
+    /// calendar for months and years
+    pub fn add_to(&self, date: DateTime) -> Option<DateTime> {
+        let mut date = date;
+        for &(unit, count) in &self.parts {
+            let count = i64::try_from(count).ok()?;
+            date = unit.add_to(date, if self.negative { -count } else { count })?;
+        }
+        Some(date)
+    }
+
+    /// Spell out the components, without saying whether they're in the
+    /// past or future. A zero duration is "0 seconds" or "0s".
+    pub fn format(&self, style: Style) -> String {
+        if self.parts.is_empty() {
+            return match style {
+                Style::Long => "0 seconds".to_string(),
+                Style::Short => "0s".to_string()
+            };
+        }
+        let words: Vec<String> = self.parts.iter()
+            .map(|&(unit, count)| match style {
+                Style::Long if count == 1 => format!("1 {}", unit.singular()),
+                Style::Long => format!("{} {}", count, unit.plural()),
+                Style::Short => format!("{}{}", count, short_symbol(unit))
+            })
+            .collect();
+        words.join(" ")
+    }
+}
+
+/// Prints the long style, with a leading "-" for negative durations
+impl fmt::Display for CompoundDuration {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        if self.negative {
+            dest.write_str("-")?;
+        }
+        dest.write_str(&self.format(Style::Long))
+    }
+}
+
+/// Settings for `format_duration` and `format_relative`
+#[derive(Clone, Debug, PartialEq)]
+pub struct DurationFormat {
+    pub style: Style,
+    /// Show at most this many components, dropping the smaller ones
+    pub max_components: usize,
+    /// Which units to break the duration into
+    pub units: Vec<TimeUnit>
+}
+
+impl Default for DurationFormat {
+    fn default() -> Self {
+        DurationFormat {
+            style: Style::Long,
+            max_components: 2,
+            units: vec![TimeUnit::Years, TimeUnit::Months, TimeUnit::Days,
+                        TimeUnit::Hours, TimeUnit::Minutes, TimeUnit::Seconds]
+        }
+    }
+}
+
+/// Describe a length of time, like "2 hours 5 minutes" or "1y 3mo"
+pub fn format_duration(duration: Duration, format: &DurationFormat) -> String {
+    CompoundDuration::from_nanos(duration.as_nanos() as i128, &format.units)
+        .truncated(format.max_components)
+        .format(format.style)
+}
+
+/// Describe a time relative to now, where positive seconds are in the
+/// future: "2 hours 5 minutes ago", "3d 4h from now" or "just now"
+pub fn format_relative(signed_secs: i64, format: &DurationFormat) -> String {
+    let duration = CompoundDuration::from_nanos(i128::from(signed_secs) * 1_000_000_000,
+                                                &format.units)
+        .truncated(format.max_components);
+    if duration.is_zero() {
+        return "just now".to_string();
+    }
+    let text = duration.format(format.style);
+    if duration.is_negative() {
+        format!("{} ago", text)
+    } else {
+        format!("{} from now", text)
+    }
+}
+
+#[test]
+fn test_compound_duration_parts() {
+    let duration = CompoundDuration::new()
+        .with(TimeUnit::Minutes, 5)
+        .with(TimeUnit::Hours, 2)
+        .with(TimeUnit::Minutes, 1)
+        .with(TimeUnit::Days, 0);
+    assert_eq!(duration.parts(), &[(TimeUnit::Hours, 2), (TimeUnit::Minutes, 6)]);
+    assert_eq!(duration.get(TimeUnit::Minutes), 6);
+    assert_eq!(duration.get(TimeUnit::Days), 0);
+    assert_eq!(duration.nominal_nanos(), (2 * 3600 + 6 * 60) * 1_000_000_000);
+    assert_eq!(duration.to_string(), "2 hours 6 minutes");
+    assert_eq!(duration.clone().negated().to_string(), "-2 hours 6 minutes");
+    assert_eq!(duration.format(Style::Short), "2h 6m");
+    assert_eq!(duration.truncated(1).format(Style::Long), "2 hours");
+    assert_eq!(CompoundDuration::new().format(Style::Short), "0s");
+
+    // Huge counts neither wrap nor panic
+    let huge = CompoundDuration::new()
+        .with(TimeUnit::Decades, u64::MAX)
+        .with(TimeUnit::Decades, 5)
+        .with(TimeUnit::Years, u64::MAX)
+        .with(TimeUnit::Milliseconds, u64::MAX);
+    assert_eq!(huge.get(TimeUnit::Decades), u64::MAX);
+    assert_eq!(huge.to_iso8601(), "P202914184810805067765YT18446744073709551.615S");
+}
+
+#[test]
+fn test_format_duration() {
+    let hours = |h: u64, m: u64, s: u64| Duration::from_secs(h * 3600 + m * 60 + s);
+    let long = DurationFormat::default();
+    let short = DurationFormat { style: Style::Short, ..DurationFormat::default() };
+    let everything = DurationFormat { max_components: usize::MAX, ..DurationFormat::default() };
+
+    assert_eq!(format_duration(hours(2, 5, 30), &long), "2 hours 5 minutes");
+    assert_eq!(format_duration(hours(2, 5, 30), &everything), "2 hours 5 minutes 30 seconds");
+    assert_eq!(format_duration(hours(1, 0, 1), &everything), "1 hour 1 second");
+    assert_eq!(format_duration(hours(0, 0, 0), &long), "0 seconds");
+
+    let year_and_a_quarter = Duration::from_secs(31_556_952 + 3 * 2_629_746 + 100);
+    assert_eq!(format_duration(year_and_a_quarter, &short), "1y 3mo");
+
+    let with_weeks = DurationFormat {
+        units: vec![TimeUnit::Weeks, TimeUnit::Days, TimeUnit::Milliseconds],
+        max_components: 3,
+        style: Style::Short
+    };
+    assert_eq!(format_duration(Duration::from_millis(9 * 86_400_000 + 250), &with_weeks),
+               "1w 2d 250ms");
+}
+
+#[test]
+fn test_format_relative() {
+    let long = DurationFormat::default();
+    let short = DurationFormat { style: Style::Short, ..DurationFormat::default() };
+
+    assert_eq!(format_relative(-(2 * 3600 + 5 * 60), &long), "2 hours 5 minutes ago");
+    assert_eq!(format_relative(3 * 86_400 + 4 * 3600 + 59, &short), "3d 4h from now");
+    assert_eq!(format_relative(0, &long), "just now");
+
+    let minutes_only = DurationFormat { units: vec![TimeUnit::Minutes], ..DurationFormat::default() };
+    assert_eq!(format_relative(-59, &minutes_only), "just now");
+    assert_eq!(format_relative(-61, &minutes_only), "1 minute ago");
+}
+
+#[test]
+fn test_compound_add_to_date() {
+    let start = DateTime::new(2016, 1, 31, 12, 0, 0).unwrap();
+    let later = CompoundDuration::new().with(TimeUnit::Months, 1).with(TimeUnit::Hours, 13);
+    assert_eq!(later.add_to(start), DateTime::new(2016, 3, 1, 1, 0, 0));
+    assert_eq!(later.negated().add_to(start), DateTime::new(2015, 12, 30, 23, 0, 0));
+}
+
+// ISO 8601 durations: "P" then years, months, weeks and days, then "T" and
+// hours, minutes and seconds, like "P1Y3M" or "PT2H5M30.5S". We also accept
+// a leading "-" for negative durations, as many other tools do.
+
+/// Why a string isn't an ISO 8601 duration
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseIsoDurationError {
+    /// Byte offset of the problem in the input
+    pub position: usize,
+    pub reason: &'static str
+}
+
+impl fmt::Display for ParseIsoDurationError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(dest, "invalid ISO 8601 duration at offset {}: {}", self.position, self.reason)
+    }
+}
+
+impl std::error::Error for ParseIsoDurationError {}
+
+impl CompoundDuration {
+    /// Write this duration in ISO 8601 form. Decades become ten years each
+    /// and milliseconds become a fraction of a second.
+    pub fn to_iso8601(&self) -> String {
+        let mut text = String::new();
+        if self.negative {
+            text.push('-');
+        }
+        text.push('P');
+
+        // Counted in u128 so decades and milliseconds can't overflow when
+        // they're folded into years and seconds
+        let get = |unit| u128::from(self.get(unit));
+        let years = get(TimeUnit::Years) + 10 * get(TimeUnit::Decades);
+        let date_parts = [(years, 'Y'), (get(TimeUnit::Months), 'M'),
+                          (get(TimeUnit::Weeks), 'W'), (get(TimeUnit::Days), 'D')];
+        for &(count, designator) in &date_parts {
+            if count > 0 {
+                text.push_str(&format!("{}{}", count, designator));
+            }
+        }
+
+        let millis = get(TimeUnit::Milliseconds);
+        let seconds = get(TimeUnit::Seconds) + millis / 1000;
+        let fraction = millis % 1000;
+        let time_parts = [(get(TimeUnit::Hours), 'H'), (get(TimeUnit::Minutes), 'M')];
+        if time_parts.iter().any(|&(count, _)| count > 0) || seconds > 0 || fraction > 0 {
+            text.push('T');
+            for &(count, designator) in &time_parts {
+                if count > 0 {
+                    text.push_str(&format!("{}{}", count, designator));
+                }
+            }
+            if fraction > 0 {
+                let digits = format!("{:03}", fraction);
+                text.push_str(&format!("{}.{}S", seconds, digits.trim_end_matches('0')));
+            } else if seconds > 0 {
+                text.push_str(&format!("{}S", seconds));
+            }
+        }
+
+        if text.ends_with('P') {
+            text.push_str("T0S");
+        }
+        text
+    }
+
+    /// Read an ISO 8601 duration such as "P1Y3M" or "-PT1.5S". Fractions
+    /// are only allowed on seconds, to millisecond precision.
+    pub fn from_iso8601(text: &str) -> Result<Self, ParseIsoDurationError> {
+        let error = |position, reason| ParseIsoDurationError { position, reason };
+        let bytes = text.as_bytes();
+        let mut at = 0;
+
+        let negative = bytes.first() == Some(&b'-');
+        if negative || bytes.first() == Some(&b'+') {
+            at += 1;
+        }
+        if bytes.get(at) != Some(&b'P') {
+            return Err(error(at, "expected 'P'"));
+        }
+        at += 1;
+
+        // The designators allowed in each part, in the order they must appear
+        const DATE_UNITS: [(u8, TimeUnit); 4] = [(b'Y', TimeUnit::Years), (b'M', TimeUnit::Months),
+                                                 (b'W', TimeUnit::Weeks), (b'D', TimeUnit::Days)];
+        const TIME_UNITS: [(u8, TimeUnit); 3] = [(b'H', TimeUnit::Hours), (b'M', TimeUnit::Minutes),
+                                                 (b'S', TimeUnit::Seconds)];
+        let mut allowed: &[(u8, TimeUnit)] = &DATE_UNITS;
+        let mut in_time = false;
+        let mut components = 0;
+        let mut duration = CompoundDuration::new();
+
+        while at < bytes.len() {
+            if bytes[at] == b'T' {
+                if in_time {
+                    return Err(error(at, "'T' appears twice"));
+                }
+                in_time = true;
+                allowed = &TIME_UNITS;
+                at += 1;
+                if at == bytes.len() {
+                    return Err(error(at, "expected a time component after 'T'"));
+                }
+                continue;
+            }
+
+            let start = at;
+            while at < bytes.len() && bytes[at].is_ascii_digit() {
+                at += 1;
+            }
+            if at == start {
+                return Err(error(at, "expected a number"));
+            }
+            let count: u64 = text[start..at].parse()
+                .map_err(|_| error(start, "number is too large"))?;
+
+            let mut millis = 0;
+            if at < bytes.len() && (bytes[at] == b'.' || bytes[at] == b',') {
+                at += 1;
+                let fraction_start = at;
+                while at < bytes.len() && bytes[at].is_ascii_digit() {
+                    at += 1;
+                }
+                let digits = &text[fraction_start..at];
+                if digits.is_empty() || digits.len() > 3 {
+                    return Err(error(fraction_start, "expected one to three digits of fraction"));
+                }
+                if bytes.get(at) != Some(&b'S') || !in_time {
+                    return Err(error(fraction_start, "only seconds may have a fraction"));
+                }
+                millis = format!("{:0<3}", digits).parse::<u64>().unwrap();
+            }
+
+            let designator = *bytes.get(at).ok_or_else(|| error(at, "expected a designator"))?;
+            let position = allowed.iter()
+                .position(|&(allowed_designator, _)| allowed_designator == designator)
+                .ok_or_else(|| error(at, "unexpected or out of order designator"))?;
+            duration = duration.with(allowed[position].1, count)
+                .with(TimeUnit::Milliseconds, millis);
+            allowed = &allowed[position + 1..];
+            components += 1;
+            at += 1;
+        }
+
+        if components == 0 {
+            return Err(error(at, "expected at least one component"));
+        }
+        duration.negative = negative && !duration.is_zero();
+        Ok(duration)
+    }
+}
+
+#[test]
+fn test_iso8601_output() {
+    let year_and_a_quarter = CompoundDuration::new()
+        .with(TimeUnit::Years, 1)
+        .with(TimeUnit::Months, 3);
+    assert_eq!(year_and_a_quarter.to_iso8601(), "P1Y3M");
+    assert_eq!(CompoundDuration::new().to_iso8601(), "PT0S");
+
+    let precise = CompoundDuration::new()
+        .with(TimeUnit::Decades, 2)
+        .with(TimeUnit::Weeks, 1)
+        .with(TimeUnit::Hours, 2)
+        .with(TimeUnit::Seconds, 30)
+        .with(TimeUnit::Milliseconds, 1250);
+    assert_eq!(precise.to_iso8601(), "P20Y1WT2H31.25S");
+    assert_eq!(precise.negated().to_iso8601(), "-P20Y1WT2H31.25S");
+}
+
+#[test]
+fn test_iso8601_parsing() {
+    let parse = CompoundDuration::from_iso8601;
+
+    let duration = parse("P1Y3M").unwrap();
+    assert_eq!(duration.parts(), &[(TimeUnit::Years, 1), (TimeUnit::Months, 3)]);
+    assert_eq!(duration.format(Style::Short), "1y 3mo");
+
+    let duration = parse("-P2DT1M0,5S").unwrap();
+    assert!(duration.is_negative());
+    assert_eq!(duration.parts(),
+               &[(TimeUnit::Days, 2), (TimeUnit::Minutes, 1), (TimeUnit::Milliseconds, 500)]);
+
+    for text in &["P1Y3M", "PT0S", "P20Y1WT2H31.25S", "-P3W", "PT1M", "P1M"] {
+        assert_eq!(parse(text).unwrap().to_iso8601(), *text);
+    }
+    assert_eq!(parse("P1M").unwrap().get(TimeUnit::Months), 1);
+    assert_eq!(parse("PT1M").unwrap().get(TimeUnit::Minutes), 1);
+}
+
+#[test]
+fn test_iso8601_errors() {
+    let error = |text: &str| {
+        let e = CompoundDuration::from_iso8601(text).unwrap_err();
+        (e.position, e.reason)
+    };
+    assert_eq!(error("1Y"), (0, "expected 'P'"));
+    assert_eq!(error("P"), (1, "expected at least one component"));
+    assert_eq!(error("PT"), (2, "expected a time component after 'T'"));
+    assert_eq!(error("P3M1Y"), (4, "unexpected or out of order designator"));
+    assert_eq!(error("P1H"), (2, "unexpected or out of order designator"));
+    assert_eq!(error("P1D2"), (4, "expected a designator"));
+    assert_eq!(error("PTM"), (2, "expected a number"));
+    assert_eq!(error("P1.5D"), (3, "only seconds may have a fraction"));
+    assert_eq!(error("PT1.2345S"), (4, "expected one to three digits of fraction"));
+    assert_eq!(error("P99999999999999999999D"), (1, "number is too large"));
+}
@@ -0,0 +1,106 @@
+#![allow(dead_code)]
+
+// Conversions between C-like enums and their integer discriminants, done
+// by derives from the enum_repr_derive crate instead of by hand:
+//
+//     #[derive(FromRepr, IntoRepr, EnumIter, EnumName)]
+//     #[repr(u8)]
+//     enum Pet { Orca, Giraffe, Dog, Cat }
+//
+// FromRepr gives from_repr and TryFrom<u8>, IntoRepr gives From<Pet> for
+// u8, EnumIter gives VARIANT_COUNT, VARIANTS and iter(), and EnumName
+// gives Display and FromStr using the variant names. The generated code
+// refers to the error types here as crate::enum_repr; an enum that sees
+// them by another path names it with #[enum_repr(crate = "...")].
+// #[enum_repr(no_display)] leaves out EnumName's Display and FromStr, for
+// enums that print some other way, keeping variant_name and
+// from_variant_name.
+
+pub use enum_repr_derive::{EnumIter, EnumName, FromRepr, IntoRepr};
+use std::fmt;
+
+/// No variant has this discriminant
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InvalidRepr<R>(pub R);
+
+impl<R: fmt::Display> fmt::Display for InvalidRepr<R> {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(dest, "no variant has the discriminant {}", self.0)
+    }
+}
+
+impl<R: fmt::Debug + fmt::Display> std::error::Error for InvalidRepr<R> {}
+
+/// No variant has this name
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseVariantError {
+    pub enum_name: &'static str,
+    pub text: String
+}
+
+impl fmt::Display for ParseVariantError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(dest, "{:?} isn't a variant of {}", self.text, self.enum_name)
+    }
+}
+
+impl std::error::Error for ParseVariantError {}
+
+#[cfg(test)]
+#[derive(Copy, Clone, Debug, PartialEq, FromRepr, IntoRepr, EnumIter, EnumName)]
+#[repr(i16)]
+enum Mixed {
+    Start,
+    Low = -3,
+    Next,
+    High = 1 << 10,
+    Last
+}
+
+#[cfg(test)]
+#[derive(Copy, Clone, Debug, PartialEq, FromRepr, IntoRepr, EnumIter, EnumName)]
+#[enum_repr(crate = "self", no_display)]
+#[repr(u32)]
+enum WithCatchAll {
+    One = 1,
+    Two,
+    Other(u32)
+}
+
+#[test]
+fn test_discriminants() {
+    use std::convert::TryFrom;
+
+    let values: Vec<i16> = Mixed::iter().map(i16::from).collect();
+    assert_eq!(values, vec![0, -3, -2, 1024, 1025]);
+    for value in Mixed::iter() {
+        assert_eq!(i16::from(value), value as i16);
+        assert_eq!(Mixed::try_from(value as i16), Ok(value));
+    }
+    assert_eq!(Mixed::from_repr(-2), Some(Mixed::Next));
+    assert_eq!(Mixed::try_from(7), Err(InvalidRepr(7)));
+    assert_eq!(InvalidRepr(7).to_string(), "no variant has the discriminant 7");
+}
+
+#[test]
+fn test_variants_and_names() {
+    assert_eq!(Mixed::VARIANT_COUNT, 5);
+    assert_eq!(Mixed::VARIANTS[3], Mixed::High);
+    assert_eq!(Mixed::High.to_string(), "High");
+    assert_eq!("Last".parse(), Ok(Mixed::Last));
+    let error = "last".parse::<Mixed>().unwrap_err();
+    assert_eq!(error.to_string(), "\"last\" isn't a variant of Mixed");
+}
+
+#[test]
+fn test_catch_all() {
+    assert_eq!(WithCatchAll::VARIANTS, [WithCatchAll::One, WithCatchAll::Two]);
+    assert_eq!(WithCatchAll::from_repr(2), Some(WithCatchAll::Two));
+    assert_eq!(WithCatchAll::from_repr(3), None);
+    assert_eq!(u32::from(WithCatchAll::Other(3)), 3);
+    assert_eq!(u32::from(WithCatchAll::Two), 2);
+    assert_eq!(WithCatchAll::Other(3).variant_name(), "Other");
+    assert_eq!(WithCatchAll::from_variant_name("One"), Ok(WithCatchAll::One));
+    let error = WithCatchAll::from_variant_name("Other").unwrap_err();
+    assert_eq!(error, ParseVariantError { enum_name: "WithCatchAll", text: "Other".to_string() });
+}
@@ -0,0 +1,647 @@
+#![allow(dead_code)]
+
+// HTTP caching: entity tags, conditional requests (RFC 9110 section 13),
+// and Cache-Control and freshness (RFC 9111). This is what decides when
+// a server can answer 304 Not Modified or 412 Precondition Failed instead
+// of sending the whole body, and how long a cache may keep reusing a
+// response before it has to ask again. Times are whole seconds, as HTTP
+// only ever deals in those.
+
+use crate::calendar::DateTime;
+use crate::http_message::{Headers, Request, Response};
+use crate::http_status::HttpStatus;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// An entity tag, the opaque validator in ETag, If-Match and If-None-Match.
+/// A strong tag changes whenever the bytes do; a weak one only promises the
+/// content means the same thing.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EntityTag {
+    weak: bool,
+    opaque: String
+}
+
+/// Characters allowed between the quotes of an entity tag
+fn is_etag_char(c: char) -> bool {
+    c == '!' || ('#'..='~').contains(&c) || u32::from(c) >= 0x80
+}
+
+impl EntityTag {
+    /// A strong tag, or `None` if `opaque` has characters tags can't hold
+    pub fn strong(opaque: &str) -> Option<EntityTag> {
+        EntityTag::new(false, opaque)
+    }
+
+    /// A weak tag, or `None` if `opaque` has characters tags can't hold
+    pub fn weak(opaque: &str) -> Option<EntityTag> {
+        EntityTag::new(true, opaque)
+    }
+
+    fn new(weak: bool, opaque: &str) -> Option<EntityTag> {
+        if opaque.chars().all(is_etag_char) {
+            Some(EntityTag { weak, opaque: opaque.to_string() })
+        } else {
+            None
+        }
+    }
+
+    /// A strong tag for exactly these bytes, from their FNV-1a hash
+    pub fn for_content(body: &[u8]) -> EntityTag {
+        let hash = body.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        EntityTag { weak: false, opaque: format!("{:016x}", hash) }
+    }
+
+    /// A weak tag for a file from its length and modification time, which
+    /// is cheap but can't tell apart two versions written in one second
+    pub fn for_metadata(length: u64, modified: DateTime) -> EntityTag {
+        EntityTag { weak: true, opaque: format!("{:x}-{:x}", length, modified.to_unix()) }
+    }
+
+    /// Read a tag like "xyzzy" or W/"xyzzy", quotes and all
+    pub fn parse(text: &str) -> Option<EntityTag> {
+        let (weak, quoted) = match text.strip_prefix("W/") {
+            Some(rest) => (true, rest),
+            None => (false, text)
+        };
+        let opaque = quoted.strip_prefix('"')?.strip_suffix('"')?;
+        EntityTag::new(weak, opaque)
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// The tag without its quotes or weakness marker
+    pub fn opaque(&self) -> &str {
+        &self.opaque
+    }
+
+    /// Both tags are strong and the same, as If-Match requires
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.opaque == other.opaque
+    }
+
+    /// The tags are the same once weakness is ignored, as If-None-Match requires
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.opaque == other.opaque
+    }
+}
+
+impl fmt::Display for EntityTag {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        if self.weak {
+            dest.write_str("W/")?;
+        }
+        write!(dest, "\"{}\"", self.opaque)
+    }
+}
+
+/// The value of If-Match or If-None-Match
+#[derive(Clone, Debug, PartialEq)]
+pub enum EntityTagList {
+    /// "*", which matches any current representation
+    Any,
+    Tags(Vec<EntityTag>)
+}
+
+impl EntityTagList {
+    /// Read a comma separated list of tags, or "*". Tags may themselves
+    /// hold commas, so this can't just split on them.
+    pub fn parse(text: &str) -> Option<EntityTagList> {
+        if text.trim() == "*" {
+            return Some(EntityTagList::Any);
+        }
+        let mut tags = Vec::new();
+        let mut rest = text;
+        loop {
+            rest = rest.trim_start_matches([' ', '\t', ',']);
+            if rest.is_empty() {
+                break;
+            }
+            let start = if rest.starts_with("W/") { 2 } else { 0 };
+            let closing = rest.get(start + 1..)?.find('"')? + start + 1;
+            tags.push(EntityTag::parse(&rest[..=closing])?);
+            rest = &rest[closing + 1..];
+            if !rest.trim_start().is_empty() && !rest.trim_start().starts_with(',') {
+                return None;
+            }
+        }
+        Some(EntityTagList::Tags(tags))
+    }
+
+    /// The list from every `name` field in `headers`, or `None` if there
+    /// are none or they don't parse
+    pub fn from_headers(headers: &Headers, name: &str) -> Option<EntityTagList> {
+        let fields: Vec<&str> = headers.get_all(name).collect();
+        if fields.is_empty() {
+            return None;
+        }
+        EntityTagList::parse(&fields.join(", "))
+    }
+
+    fn matches(&self, current: Option<&EntityTag>, eq: fn(&EntityTag, &EntityTag) -> bool) -> bool {
+        match (self, current) {
+            (EntityTagList::Any, _) => true,
+            (EntityTagList::Tags(tags), Some(current)) => tags.iter().any(|tag| eq(tag, current)),
+            (EntityTagList::Tags(_), None) => false
+        }
+    }
+}
+
+/// Decide how to answer a conditional request for a representation with
+/// the given validators, following RFC 9110 section 13.2.2. Returns `Ok`
+/// if the request should go ahead, `NotModified` if a GET or HEAD can be
+/// answered without the body, and `PreconditionFailed` otherwise.
+///
+/// The representation is taken to exist, so If-Match: * always passes.
+/// Dates that don't parse are ignored, and tag lists that don't parse
+/// match nothing.
+pub fn evaluate_preconditions(request: &Request, etag: Option<&EntityTag>,
+                              last_modified: Option<DateTime>) -> HttpStatus {
+    let headers = &request.headers;
+    let safe = request.method == "GET" || request.method == "HEAD";
+    let header_date = |name| headers.get(name).and_then(DateTime::parse_http_date);
+
+    if headers.contains("If-Match") {
+        let passes = EntityTagList::from_headers(headers, "If-Match")
+            .is_some_and(|list| list.matches(etag, EntityTag::strong_eq));
+        if !passes {
+            return HttpStatus::PreconditionFailed;
+        }
+    } else if let (Some(since), Some(modified)) = (header_date("If-Unmodified-Since"), last_modified) {
+        if modified > since {
+            return HttpStatus::PreconditionFailed;
+        }
+    }
+
+    if headers.contains("If-None-Match") {
+        let matched = EntityTagList::from_headers(headers, "If-None-Match")
+            .is_some_and(|list| list.matches(etag, EntityTag::weak_eq));
+        if matched {
+            return if safe { HttpStatus::NotModified } else { HttpStatus::PreconditionFailed };
+        }
+    } else if safe {
+        if let (Some(since), Some(modified)) = (header_date("If-Modified-Since"), last_modified) {
+            if modified <= since {
+                return HttpStatus::NotModified;
+            }
+        }
+    }
+    HttpStatus::Ok
+}
+
+// Cache-Control
+
+/// Delta-seconds too large to hold are taken as this, per RFC 9111
+const MAX_DELTA_SECONDS: u64 = 1 << 31;
+
+/// The directives of a Cache-Control header, from a request or a response.
+/// Unknown directives are kept in `extensions`.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct CacheControl {
+    pub max_age: Option<u64>,
+    /// Responses only: max-age for shared caches
+    pub s_maxage: Option<u64>,
+    /// Stored responses must be revalidated before every use
+    pub no_cache: bool,
+    pub no_store: bool,
+    pub no_transform: bool,
+    /// Responses only: shared caches mustn't store this
+    pub private: bool,
+    pub public: bool,
+    /// Responses only: never serve this stale
+    pub must_revalidate: bool,
+    pub proxy_revalidate: bool,
+    pub must_understand: bool,
+    /// Requests only: a stale response will do, if stale by no more than
+    /// this, or by any amount for `Some(None)`
+    pub max_stale: Option<Option<u64>>,
+    /// Requests only: the response must stay fresh at least this much longer
+    pub min_fresh: Option<u64>,
+    pub only_if_cached: bool,
+    /// RFC 5861 and 8246 extensions, which are common enough to name
+    pub stale_while_revalidate: Option<u64>,
+    pub stale_if_error: Option<u64>,
+    pub immutable: bool,
+    pub extensions: Vec<(String, Option<String>)>
+}
+
+/// Split a Cache-Control value into directive names and arguments,
+/// keeping commas inside quoted arguments
+fn directives(text: &str) -> Vec<(String, Option<String>)> {
+    let mut directives = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|&c| c == ',' || c == ' ' || c == '\t') {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            return directives;
+        }
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == '=' || c == ',' || c == ' ' || c == '\t' {
+                break;
+            }
+            name.push(c.to_ascii_lowercase());
+            chars.next();
+        }
+        while chars.peek().is_some_and(|&c| c == ' ' || c == '\t') {
+            chars.next();
+        }
+        let mut argument = None;
+        if chars.peek() == Some(&'=') {
+            chars.next();
+            let mut value = String::new();
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => value.extend(chars.next()),
+                        _ => value.push(c)
+                    }
+                }
+            }
+            while let Some(&c) = chars.peek() {
+                if c == ',' {
+                    break;
+                }
+                value.push(c);
+                chars.next();
+            }
+            argument = Some(value.trim_end().to_string());
+        }
+        // Skip whatever junk follows up to the next comma
+        while chars.peek().is_some_and(|&c| c != ',') {
+            chars.next();
+        }
+        directives.push((name, argument));
+    }
+}
+
+/// Delta-seconds, saturating at `MAX_DELTA_SECONDS`. Anything malformed
+/// counts as 0, which errs on the side of not reusing a response.
+fn delta_seconds(argument: &Option<String>) -> u64 {
+    match *argument {
+        Some(ref digits) if !digits.is_empty() && digits.bytes().all(|c| c.is_ascii_digit()) =>
+            digits.parse().unwrap_or(MAX_DELTA_SECONDS).min(MAX_DELTA_SECONDS),
+        _ => 0
+    }
+}
+
+/// Keep the smaller of two given limits, the more cautious reading when a
+/// directive is repeated
+fn min_seconds(current: Option<u64>, new: u64) -> Option<u64> {
+    Some(current.map_or(new, |current| current.min(new)))
+}
+
+impl CacheControl {
+    /// Read the directives from one Cache-Control value
+    pub fn parse(text: &str) -> CacheControl {
+        let mut control = CacheControl::default();
+        for (name, argument) in directives(text) {
+            match name.as_str() {
+                "max-age" => control.max_age = min_seconds(control.max_age, delta_seconds(&argument)),
+                "s-maxage" => control.s_maxage = min_seconds(control.s_maxage, delta_seconds(&argument)),
+                "min-fresh" => control.min_fresh = min_seconds(control.min_fresh, delta_seconds(&argument)),
+                "stale-while-revalidate" =>
+                    control.stale_while_revalidate =
+                        min_seconds(control.stale_while_revalidate, delta_seconds(&argument)),
+                "stale-if-error" =>
+                    control.stale_if_error = min_seconds(control.stale_if_error, delta_seconds(&argument)),
+                "max-stale" => control.max_stale = Some(argument.as_ref().map(|_| delta_seconds(&argument))),
+                // Field names after no-cache or private are allowed, but
+                // treating them as unqualified is always safe
+                "no-cache" => control.no_cache = true,
+                "private" => control.private = true,
+                "no-store" => control.no_store = true,
+                "no-transform" => control.no_transform = true,
+                "public" => control.public = true,
+                "must-revalidate" => control.must_revalidate = true,
+                "proxy-revalidate" => control.proxy_revalidate = true,
+                "must-understand" => control.must_understand = true,
+                "only-if-cached" => control.only_if_cached = true,
+                "immutable" => control.immutable = true,
+                _ => control.extensions.push((name, argument))
+            }
+        }
+        control
+    }
+
+    /// The directives from every Cache-Control field in `headers`
+    pub fn from_headers(headers: &Headers) -> CacheControl {
+        let fields: Vec<&str> = headers.get_all("Cache-Control").collect();
+        CacheControl::parse(&fields.join(", "))
+    }
+}
+
+/// Prints the directives in a fixed order, like "max-age=60, must-revalidate"
+impl fmt::Display for CacheControl {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts: Vec<String> = Vec::new();
+        let flags = [(self.public, "public"), (self.private, "private"), (self.no_cache, "no-cache"),
+                     (self.no_store, "no-store"), (self.no_transform, "no-transform"),
+                     (self.must_revalidate, "must-revalidate"),
+                     (self.proxy_revalidate, "proxy-revalidate"),
+                     (self.must_understand, "must-understand"), (self.immutable, "immutable"),
+                     (self.only_if_cached, "only-if-cached")];
+        let seconds = [(self.max_age, "max-age"), (self.s_maxage, "s-maxage"),
+                       (self.min_fresh, "min-fresh"),
+                       (self.stale_while_revalidate, "stale-while-revalidate"),
+                       (self.stale_if_error, "stale-if-error")];
+        parts.extend(seconds.iter().filter_map(|&(value, name)| value.map(|v| format!("{}={}", name, v))));
+        match self.max_stale {
+            Some(Some(limit)) => parts.push(format!("max-stale={}", limit)),
+            Some(None) => parts.push("max-stale".to_string()),
+            None => {}
+        }
+        parts.extend(flags.iter().filter(|flag| flag.0).map(|flag| flag.1.to_string()));
+        for (name, argument) in &self.extensions {
+            parts.push(match *argument {
+                Some(ref value) if value.chars().all(|c| c.is_ascii_alphanumeric() || "-._".contains(c)) =>
+                    format!("{}={}", name, value),
+                Some(ref value) =>
+                    format!("{}=\"{}\"", name, value.replace('\\', "\\\\").replace('"', "\\\"")),
+                None => name.clone()
+            });
+        }
+        dest.write_str(&parts.join(", "))
+    }
+}
+
+// Freshness
+
+/// Statuses a cache may guess a lifetime for when the response doesn't
+/// give one (RFC 9110 section 15.1)
+const HEURISTICALLY_CACHEABLE: [u16; 12] = [200, 203, 204, 206, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// A response as a cache keeps it, with when it was asked for and received
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredResponse {
+    pub response: Response,
+    /// When the request was sent
+    pub request_time: DateTime,
+    /// When the response arrived
+    pub response_time: DateTime
+}
+
+impl StoredResponse {
+    pub fn new(response: Response, request_time: DateTime, response_time: DateTime) -> StoredResponse {
+        StoredResponse { response, request_time, response_time }
+    }
+
+    fn header_date(&self, name: &str) -> Option<DateTime> {
+        self.response.headers.get(name).and_then(DateTime::parse_http_date)
+    }
+
+    /// The Date header, or when the response arrived if it has none
+    fn date(&self) -> DateTime {
+        self.header_date("Date").unwrap_or(self.response_time)
+    }
+
+    /// How many seconds the response stays fresh for, per RFC 9111 section
+    /// 4.2.1: s-maxage in a shared cache, then max-age, then Expires, and
+    /// failing those a tenth of the time since Last-Modified
+    pub fn freshness_lifetime(&self, shared: bool) -> u64 {
+        let control = CacheControl::from_headers(&self.response.headers);
+        if let (true, Some(s_maxage)) = (shared, control.s_maxage) {
+            return s_maxage;
+        }
+        if let Some(max_age) = control.max_age {
+            return max_age;
+        }
+        if self.response.headers.contains("Expires") {
+            // An Expires that doesn't parse means already expired
+            return match self.header_date("Expires") {
+                Some(expires) => seconds_between(self.date(), expires),
+                None => 0
+            };
+        }
+
+        let code = self.response.status.code();
+        if !HEURISTICALLY_CACHEABLE.contains(&code) && !control.public {
+            return 0;
+        }
+        match self.header_date("Last-Modified") {
+            Some(modified) => seconds_between(modified, self.date()) / 10,
+            None => 0
+        }
+    }
+
+    /// How old the response is at `now`, in seconds, per RFC 9111 section
+    /// 4.2.3. This counts the time it spent in caches upstream, which
+    /// they report in the Age header, and the time it took to arrive.
+    pub fn current_age(&self, now: DateTime) -> u64 {
+        let age_value = delta_seconds(&self.response.headers.get("Age").map(|age| age.trim().to_string()));
+        let apparent_age = seconds_between(self.date(), self.response_time);
+        let response_delay = seconds_between(self.request_time, self.response_time);
+        let corrected_initial_age = apparent_age.max(age_value.saturating_add(response_delay));
+        corrected_initial_age.saturating_add(seconds_between(self.response_time, now))
+    }
+
+    pub fn is_fresh(&self, now: DateTime, shared: bool) -> bool {
+        self.freshness_lifetime(shared) > self.current_age(now)
+    }
+
+    /// Whether this response can answer `request` at `now` without asking
+    /// the origin server, taking the request's own Cache-Control into
+    /// account. A stale response is only reused if the request allows it
+    /// and the response doesn't forbid it.
+    pub fn can_reuse(&self, request: &Request, now: DateTime, shared: bool) -> bool {
+        let response_control = CacheControl::from_headers(&self.response.headers);
+        let request_control = CacheControl::from_headers(&request.headers);
+        if response_control.no_cache || request_control.no_cache
+            || request.headers.get("Pragma").is_some_and(|pragma| pragma.contains("no-cache")) {
+            return false;
+        }
+
+        let lifetime = self.freshness_lifetime(shared);
+        let age = self.current_age(now);
+        if request_control.max_age.is_some_and(|max_age| age > max_age) {
+            return false;
+        }
+        let min_fresh = request_control.min_fresh.unwrap_or(0);
+        if lifetime >= age.saturating_add(min_fresh) && lifetime > age {
+            return true;
+        }
+
+        let no_stale = response_control.must_revalidate
+            || (shared && (response_control.proxy_revalidate || response_control.s_maxage.is_some()));
+        let staleness = age - lifetime.min(age);
+        match request_control.max_stale {
+            Some(limit) if !no_stale => limit.is_none_or(|limit| staleness <= limit),
+            _ => false
+        }
+    }
+}
+
+/// Whole seconds from `earlier` to `later`, or 0 if it's actually later
+fn seconds_between(earlier: DateTime, later: DateTime) -> u64 {
+    u64::try_from(later.to_unix() - earlier.to_unix()).unwrap_or(0)
+}
+
+#[cfg(test)]
+fn http_date(text: &str) -> DateTime {
+    DateTime::parse_http_date(text).unwrap()
+}
+
+#[test]
+fn test_entity_tags() {
+    let strong = EntityTag::parse("\"1\"").unwrap();
+    let weak = EntityTag::parse("W/\"1\"").unwrap();
+    let other = EntityTag::weak("2").unwrap();
+    assert_eq!((strong.is_weak(), weak.is_weak(), weak.opaque()), (false, true, "1"));
+    assert_eq!((weak.to_string(), strong.to_string()), ("W/\"1\"".to_string(), "\"1\"".to_string()));
+
+    // The comparison table from RFC 9110 section 8.8.3.2
+    assert_eq!((weak.strong_eq(&weak), weak.weak_eq(&weak)), (false, true));
+    assert_eq!((weak.strong_eq(&other), weak.weak_eq(&other)), (false, false));
+    assert_eq!((weak.strong_eq(&strong), weak.weak_eq(&strong)), (false, true));
+    assert_eq!((strong.strong_eq(&strong), strong.weak_eq(&strong)), (true, true));
+
+    assert_eq!(EntityTag::parse("1"), None);
+    assert_eq!(EntityTag::parse("w/\"1\""), None);
+    assert_eq!(EntityTag::strong("has \"quotes\""), None);
+    assert_eq!(EntityTag::for_content(b"hello world\n"), EntityTag::strong("782e1488cd5a68b7").unwrap());
+    let modified = DateTime::from_unix(0x5e0b_e100).unwrap();
+    assert_eq!(EntityTag::for_metadata(1234, modified).to_string(), "W/\"4d2-5e0be100\"");
+
+    assert_eq!(EntityTagList::parse(" * "), Some(EntityTagList::Any));
+    assert_eq!(EntityTagList::parse("\"a,b\", W/\"c\",,\"\""),
+               Some(EntityTagList::Tags(vec![EntityTag::strong("a,b").unwrap(),
+                                             EntityTag::weak("c").unwrap(),
+                                             EntityTag::strong("").unwrap()])));
+    assert_eq!(EntityTagList::parse("\"a\" \"b\""), None);
+    assert_eq!(EntityTagList::parse("\"unterminated"), None);
+}
+
+#[test]
+fn test_preconditions() {
+    let etag = EntityTag::strong("v2").unwrap();
+    let modified = http_date("Tue, 09 Feb 2016 21:42:00 GMT");
+    let status = |method: &str, headers: &[(&str, &str)]| {
+        let mut request = Request::new(method, "/");
+        for &(name, value) in headers {
+            request.headers.append(name, value);
+        }
+        evaluate_preconditions(&request, Some(&etag), Some(modified))
+    };
+    use self::HttpStatus::*;
+
+    assert_eq!(status("GET", &[]), Ok);
+    assert_eq!(status("GET", &[("If-None-Match", "\"v1\", W/\"v2\"")]), NotModified);
+    assert_eq!(status("HEAD", &[("If-None-Match", "*")]), NotModified);
+    assert_eq!(status("PUT", &[("If-None-Match", "*")]), PreconditionFailed);
+    assert_eq!(status("GET", &[("If-None-Match", "\"v1\"")]), Ok);
+
+    assert_eq!(status("PUT", &[("If-Match", "\"v2\"")]), Ok);
+    assert_eq!(status("PUT", &[("If-Match", "W/\"v2\"")]), PreconditionFailed);
+    assert_eq!(status("PUT", &[("If-Match", "\"v1\"")]), PreconditionFailed);
+    assert_eq!(status("PUT", &[("If-Match", "*")]), Ok);
+
+    let before = "Tue, 09 Feb 2016 21:41:59 GMT";
+    let same = "Tue, 09 Feb 2016 21:42:00 GMT";
+    assert_eq!(status("GET", &[("If-Modified-Since", same)]), NotModified);
+    assert_eq!(status("GET", &[("If-Modified-Since", before)]), Ok);
+    assert_eq!(status("GET", &[("If-Modified-Since", "yesterday")]), Ok);
+    assert_eq!(status("POST", &[("If-Modified-Since", same)]), Ok);
+    assert_eq!(status("PUT", &[("If-Unmodified-Since", before)]), PreconditionFailed);
+    assert_eq!(status("PUT", &[("If-Unmodified-Since", same)]), Ok);
+
+    // If-None-Match overrides If-Modified-Since, and If-Match overrides
+    // If-Unmodified-Since
+    assert_eq!(status("GET", &[("If-None-Match", "\"v1\""), ("If-Modified-Since", same)]), Ok);
+    assert_eq!(status("PUT", &[("If-Match", "\"v2\""), ("If-Unmodified-Since", before)]), Ok);
+
+    let request = Request::new("GET", "/").header("If-None-Match", "\"v2\"");
+    assert_eq!(evaluate_preconditions(&request, None, None), Ok);
+}
+
+#[test]
+fn test_cache_control() {
+    let control = CacheControl::parse("Max-Age=60, s-maxage=\"30\", no-cache=\"Set-Cookie, Vary\", \
+                                       private, max-stale, community=\"UCI\", foo=bar baz, immutable");
+    assert_eq!(control, CacheControl {
+        max_age: Some(60),
+        s_maxage: Some(30),
+        no_cache: true,
+        private: true,
+        max_stale: Some(None),
+        immutable: true,
+        extensions: vec![("community".to_string(), Some("UCI".to_string())),
+                         ("foo".to_string(), Some("bar baz".to_string()))],
+        ..CacheControl::default()
+    });
+    assert_eq!(control.to_string(), "max-age=60, s-maxage=30, max-stale, private, no-cache, immutable, \
+                                     community=UCI, foo=\"bar baz\"");
+    assert_eq!(CacheControl::parse(&control.to_string()), control);
+
+    // Repeats keep the smaller value, and bad or huge numbers are clamped
+    assert_eq!(CacheControl::parse("max-age=60, max-age=10").max_age, Some(10));
+    assert_eq!(CacheControl::parse("max-age=soon").max_age, Some(0));
+    assert_eq!(CacheControl::parse("max-age=99999999999999999999").max_age, Some(MAX_DELTA_SECONDS));
+    assert_eq!(CacheControl::parse("max-stale=5").max_stale, Some(Some(5)));
+    assert_eq!(CacheControl::parse(""), CacheControl::default());
+
+    let headers = Response::new(HttpStatus::Ok)
+        .header("Cache-Control", "public")
+        .header("cache-control", "max-age=5").headers;
+    let control = CacheControl::from_headers(&headers);
+    assert_eq!((control.public, control.max_age), (true, Some(5)));
+}
+
+#[test]
+fn test_freshness() {
+    let sent = http_date("Tue, 09 Feb 2016 21:42:00 GMT");
+    let received = http_date("Tue, 09 Feb 2016 21:42:02 GMT");
+    let stored = |headers: &[(&str, &str)]| {
+        let mut response = Response::new(HttpStatus::Ok).header("Date", "Tue, 09 Feb 2016 21:42:01 GMT");
+        for &(name, value) in headers {
+            response.headers.append(name, value);
+        }
+        StoredResponse::new(response, sent, received)
+    };
+
+    let explicit = stored(&[("Cache-Control", "max-age=60, s-maxage=600"),
+                            ("Expires", "Tue, 09 Feb 2016 23:42:01 GMT")]);
+    assert_eq!((explicit.freshness_lifetime(false), explicit.freshness_lifetime(true)), (60, 600));
+    let expires = stored(&[("Expires", "Tue, 09 Feb 2016 23:42:01 GMT")]);
+    assert_eq!(expires.freshness_lifetime(false), 7200);
+    assert_eq!(stored(&[("Expires", "0")]).freshness_lifetime(false), 0);
+    let heuristic = stored(&[("Last-Modified", "Tue, 09 Feb 2016 18:55:21 GMT")]);
+    assert_eq!(heuristic.freshness_lifetime(false), 1000);
+    let mut created = heuristic.clone();
+    created.response.status = HttpStatus::Created;
+    assert_eq!(created.freshness_lifetime(false), 0);
+
+    // Age counts the upstream Age plus the round trip, then time stored
+    let aged = stored(&[("Cache-Control", "max-age=60"), ("Age", "10")]);
+    assert_eq!(aged.current_age(received), 12);
+    let later = received.add_nanos(47_000_000_000).unwrap();
+    assert_eq!(aged.current_age(later), 59);
+    assert!(aged.is_fresh(later, false));
+    assert!(!aged.is_fresh(later.add_nanos(1_000_000_000).unwrap(), false));
+    // Without an Age header, the round trip still counts
+    assert_eq!(stored(&[]).current_age(received), 2);
+    // An Age too big to count is as old as can be, not brand new
+    let ancient = stored(&[("Cache-Control", "max-age=60"), ("Age", "99999999999999999999")]);
+    assert_eq!(ancient.current_age(received), MAX_DELTA_SECONDS + 2);
+    assert!(!ancient.is_fresh(received, false));
+
+    let get = |cache_control: &str| Request::new("GET", "/").header("Cache-Control", cache_control);
+    assert!(aged.can_reuse(&Request::new("GET", "/"), later, false));
+    assert!(!aged.can_reuse(&get("no-cache"), later, false));
+    assert!(!aged.can_reuse(&get("max-age=30"), later, false));
+    assert!(!aged.can_reuse(&get("min-fresh=5"), later, false));
+    let stale = later.add_nanos(20_000_000_000).unwrap();
+    assert!(!aged.can_reuse(&Request::new("GET", "/"), stale, false));
+    assert!(aged.can_reuse(&get("max-stale"), stale, false));
+    assert!(aged.can_reuse(&get("max-stale=20"), stale, false));
+    assert!(!aged.can_reuse(&get("max-stale=5"), stale, false));
+    let strict = stored(&[("Cache-Control", "max-age=60, must-revalidate")]);
+    assert!(!strict.can_reuse(&get("max-stale"), stale, false));
+}
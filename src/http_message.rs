@@ -0,0 +1,1013 @@
+#![allow(dead_code)]
+
+// HTTP/1.1 messages (RFC 9112): requests and responses, written out to any
+// io::Write and read back incrementally. Bytes are fed to a parser as they
+// arrive, in pieces of any size, and whole messages come out once they're
+// complete. Anything the parser has to hold on to is capped by Limits, so
+// a misbehaving peer can't make it buffer without end, and every error
+// knows which HttpStatus to answer it with.
+
+use crate::http_status::HttpStatus;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Write};
+
+/// Caps on what a parser will buffer
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Limits {
+    /// Longest start line and headers together, including line endings
+    pub max_head_bytes: usize,
+    /// Most header fields, trailers included
+    pub max_headers: usize,
+    /// Longest body, after any chunked coding is removed
+    pub max_body_bytes: usize
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { max_head_bytes: 8 * 1024, max_headers: 100, max_body_bytes: 1024 * 1024 }
+    }
+}
+
+/// What was wrong with a message
+#[derive(Clone, Debug, PartialEq)]
+pub enum HttpError {
+    /// The request or status line didn't parse; holds the line
+    BadStartLine(String),
+    /// Some HTTP version other than 1.0 or 1.1
+    UnsupportedVersion(String),
+    /// A header line didn't parse; holds the line
+    BadHeader(String),
+    HeadTooLarge,
+    TooManyHeaders,
+    /// Content-Length wasn't a number, or disagreed with itself
+    BadContentLength(String),
+    /// Both Content-Length and Transfer-Encoding were given
+    ConflictingFraming,
+    /// A transfer coding other than chunked
+    UnsupportedTransferEncoding(String),
+    BadChunk,
+    BodyTooLarge,
+    /// The connection closed partway through a message
+    UnexpectedEof
+}
+
+impl HttpError {
+    /// The status to answer a request that failed this way with
+    pub fn status(&self) -> HttpStatus {
+        match *self {
+            HttpError::HeadTooLarge | HttpError::TooManyHeaders =>
+                HttpStatus::RequestHeaderFieldsTooLarge,
+            HttpError::BodyTooLarge => HttpStatus::ContentTooLarge,
+            HttpError::UnsupportedVersion(_) => HttpStatus::HttpVersionNotSupported,
+            HttpError::UnsupportedTransferEncoding(_) => HttpStatus::NotImplemented,
+            _ => HttpStatus::BadRequest
+        }
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HttpError::BadStartLine(ref line) => write!(dest, "malformed start line {:?}", line),
+            HttpError::UnsupportedVersion(ref version) =>
+                write!(dest, "unsupported HTTP version {:?}", version),
+            HttpError::BadHeader(ref line) => write!(dest, "malformed header {:?}", line),
+            HttpError::HeadTooLarge => write!(dest, "message head is too large"),
+            HttpError::TooManyHeaders => write!(dest, "too many header fields"),
+            HttpError::BadContentLength(ref value) =>
+                write!(dest, "invalid Content-Length {:?}", value),
+            HttpError::ConflictingFraming =>
+                write!(dest, "both Content-Length and Transfer-Encoding were given"),
+            HttpError::UnsupportedTransferEncoding(ref coding) =>
+                write!(dest, "unsupported transfer coding {:?}", coding),
+            HttpError::BadChunk => write!(dest, "malformed chunk"),
+            HttpError::BodyTooLarge => write!(dest, "message body is too large"),
+            HttpError::UnexpectedEof => write!(dest, "connection closed in the middle of a message")
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11
+}
+
+impl Version {
+    fn parse(text: &str) -> Result<Version, HttpError> {
+        match text {
+            "HTTP/1.1" => Ok(Version::Http11),
+            "HTTP/1.0" => Ok(Version::Http10),
+            _ if text.starts_with("HTTP/") => Err(HttpError::UnsupportedVersion(text.to_string())),
+            _ => Err(HttpError::BadStartLine(text.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        dest.write_str(match *self {
+            Version::Http10 => "HTTP/1.0",
+            Version::Http11 => "HTTP/1.1"
+        })
+    }
+}
+
+/// Header fields in the order they were given. Names compare without
+/// regard to case.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Headers {
+    fields: Vec<(String, String)>
+}
+
+impl Headers {
+    ///Constructor
+    pub fn new() -> Self {
+        Headers::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// The first value of the field `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields.iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Every value of the field `name`, in order
+    pub fn get_all<'h>(&'h self, name: &'h str) -> impl Iterator<Item = &'h str> + 'h {
+        self.fields.iter()
+            .filter(move |(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// The comma separated elements of every `name` field, trimmed, with
+    /// empty ones left out, as for Connection or Cache-Control
+    pub fn list<'h>(&'h self, name: &'h str) -> Vec<&'h str> {
+        self.get_all(name)
+            .flat_map(|value| value.split(','))
+            .map(|element| element.trim())
+            .filter(|element| !element.is_empty())
+            .collect()
+    }
+
+    /// Add a field, keeping any others with the same name
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.fields.push((name.to_string(), value.to_string()));
+    }
+
+    /// Replace every `name` field with this one
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.remove(name);
+        self.append(name, value);
+    }
+
+    /// Remove every `name` field, returning true if there were any
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.fields.len();
+        self.fields.retain(|(field, _)| !field.eq_ignore_ascii_case(name));
+        self.fields.len() != before
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// True if the Connection field lists `option`, like "close"
+    fn connection_has(&self, option: &str) -> bool {
+        self.list("Connection").iter().any(|element| element.eq_ignore_ascii_case(option))
+    }
+
+    fn is_chunked(&self) -> bool {
+        self.list("Transfer-Encoding").last()
+            .is_some_and(|coding| coding.eq_ignore_ascii_case("chunked"))
+    }
+}
+
+/// Whether a connection stays open after a message with these headers
+fn keep_alive(version: Version, headers: &Headers) -> bool {
+    match version {
+        Version::Http11 => !headers.connection_has("close"),
+        Version::Http10 => headers.connection_has("keep-alive")
+    }
+}
+
+/// True if `text` can go in a start line or header value as it is, without
+/// ending the line early
+fn is_single_line(text: &str) -> bool {
+    !text.bytes().any(|c| c == b'\r' || c == b'\n' || c == 0)
+}
+
+fn invalid_field(what: &str, text: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("invalid {} {:?}", what, text))
+}
+
+/// Write the head and body of a message. Unless the headers already say
+/// how the body is framed, a Content-Length is added when `length_needed`.
+/// Nothing is written if a header would break the message's framing.
+fn write_message(out: &mut dyn Write, start_line: &str, headers: &Headers, body: &[u8],
+                 length_needed: bool, send_body: bool) -> io::Result<()> {
+    let mut head = format!("{}\r\n", start_line);
+    for (name, value) in headers.iter() {
+        if !is_token(name) {
+            return Err(invalid_field("header name", name));
+        }
+        if !is_single_line(value) {
+            return Err(invalid_field("header value", value));
+        }
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    let chunked = headers.is_chunked();
+    if !chunked && length_needed && !headers.contains("Content-Length") {
+        head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    head.push_str("\r\n");
+    out.write_all(head.as_bytes())?;
+
+    // A message that can't have a body doesn't get an empty chunked one
+    // either, or the reader would take the terminator for the next message
+    if !send_body {
+        Ok(())
+    } else if chunked {
+        if !body.is_empty() {
+            write!(out, "{:x}\r\n", body.len())?;
+            out.write_all(body)?;
+            out.write_all(b"\r\n")?;
+        }
+        out.write_all(b"0\r\n\r\n")
+    } else {
+        out.write_all(body)
+    }
+}
+
+/// An HTTP request
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    /// The request target, usually a path like "/index.html?q=1"
+    pub target: String,
+    pub version: Version,
+    pub headers: Headers,
+    pub body: Vec<u8>
+}
+
+impl Request {
+    /// An HTTP/1.1 request with no headers or body
+    pub fn new(method: &str, target: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            target: target.to_string(),
+            version: Version::Http11,
+            headers: Headers::new(),
+            body: Vec::new()
+        }
+    }
+
+    /// The same request with another header field
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// The same request with a body
+    pub fn with_body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Whether the client wants the connection kept open afterwards
+    pub fn keep_alive(&self) -> bool {
+        keep_alive(self.version, &self.headers)
+    }
+
+    /// Fails with InvalidInput, writing nothing, if the method, target or a
+    /// header has something in it that would let it end the line early
+    pub fn write_to(&self, out: &mut dyn Write) -> io::Result<()> {
+        if !is_token(&self.method) {
+            return Err(invalid_field("method", &self.method));
+        }
+        if self.target.is_empty() || self.target.contains(' ') || !is_single_line(&self.target) {
+            return Err(invalid_field("request target", &self.target));
+        }
+        let start_line = format!("{} {} {}", self.method, self.target, self.version);
+        write_message(out, &start_line, &self.headers, &self.body, !self.body.is_empty(), true)
+    }
+
+    /// Panics where write_to would fail
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        if let Err(error) = self.write_to(&mut bytes) {
+            panic!("{}", error);
+        }
+        bytes
+    }
+}
+
+/// An HTTP response
+#[derive(Clone, Debug, PartialEq)]
+pub struct Response {
+    pub version: Version,
+    pub status: HttpStatus,
+    /// The reason phrase, which defaults to the registered one
+    pub reason: String,
+    pub headers: Headers,
+    pub body: Vec<u8>
+}
+
+impl Response {
+    /// An HTTP/1.1 response with no headers or body
+    pub fn new(status: HttpStatus) -> Response {
+        Response {
+            version: Version::Http11,
+            status,
+            reason: status.reason_phrase().unwrap_or("").to_string(),
+            headers: Headers::new(),
+            body: Vec::new()
+        }
+    }
+
+    /// The same response with another header field
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// The same response with a body
+    pub fn with_body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Whether the server will keep the connection open afterwards
+    pub fn keep_alive(&self) -> bool {
+        keep_alive(self.version, &self.headers)
+    }
+
+    /// Informational, 204 and 304 responses never have a body
+    pub fn status_allows_body(status: HttpStatus) -> bool {
+        !status.is_informational() && status != HttpStatus::NoContent
+            && status != HttpStatus::NotModified
+    }
+
+    /// Fails with InvalidInput, writing nothing, if the reason phrase or a
+    /// header has a line break in it
+    pub fn write_to(&self, out: &mut dyn Write) -> io::Result<()> {
+        self.write(out, false)
+    }
+
+    /// Write this as the answer to a HEAD request: the headers the full
+    /// response would have, Content-Length included, but no body
+    pub fn write_head_to(&self, out: &mut dyn Write) -> io::Result<()> {
+        self.write(out, true)
+    }
+
+    fn write(&self, out: &mut dyn Write, head_only: bool) -> io::Result<()> {
+        if !is_single_line(&self.reason) {
+            return Err(invalid_field("reason phrase", &self.reason));
+        }
+        let start_line = format!("{} {} {}", self.version, self.status.code(), self.reason);
+        let has_body = Response::status_allows_body(self.status);
+        let body = if has_body { &self.body[..] } else { &[] };
+        write_message(out, &start_line, &self.headers, body, has_body, has_body && !head_only)
+    }
+
+    /// Panics where write_to would fail
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        if let Err(error) = self.write_to(&mut bytes) {
+            panic!("{}", error);
+        }
+        bytes
+    }
+}
+
+// Parsing
+
+/// Token characters, as allowed in methods and header names
+fn is_token(text: &str) -> bool {
+    !text.is_empty() && text.bytes().all(|c| {
+        c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c)
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+enum StartLine {
+    Request { method: String, target: String },
+    Status { status: HttpStatus, reason: String }
+}
+
+struct Head {
+    start: StartLine,
+    version: Version,
+    headers: Headers
+}
+
+fn parse_start_line(line: &str, response: bool) -> Result<(StartLine, Version), HttpError> {
+    let bad = || HttpError::BadStartLine(line.to_string());
+    let mut parts = line.splitn(3, ' ');
+    let (first, second) = (parts.next().ok_or_else(bad)?, parts.next().ok_or_else(bad)?);
+    let third = parts.next();
+
+    if response {
+        let version = Version::parse(first)?;
+        if second.len() != 3 || !second.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(bad());
+        }
+        let status = HttpStatus::try_from(second.parse::<u16>().unwrap()).map_err(|_| bad())?;
+        let reason = third.unwrap_or("").to_string();
+        Ok((StartLine::Status { status, reason }, version))
+    } else {
+        let third = third.ok_or_else(bad)?;
+        if !is_token(first) || second.is_empty() || second.contains(' ') || third.contains(' ') {
+            return Err(bad());
+        }
+        let version = Version::parse(third)?;
+        Ok((StartLine::Request { method: first.to_string(), target: second.to_string() }, version))
+    }
+}
+
+fn parse_header_line(line: &str, headers: &mut Headers, limits: &Limits) -> Result<(), HttpError> {
+    let bad = || HttpError::BadHeader(line.to_string());
+    // Whitespace before the colon, or a value folded onto this line, are
+    // both ways to smuggle a header past a proxy, so neither is allowed
+    let colon = line.find(':').ok_or_else(bad)?;
+    let name = &line[..colon];
+    if !is_token(name) {
+        return Err(bad());
+    }
+    let value = line[colon + 1..].trim_matches(|c| c == ' ' || c == '\t');
+    if value.chars().any(|c| c.is_control() && c != '\t') {
+        return Err(bad());
+    }
+    if headers.len() >= limits.max_headers {
+        return Err(HttpError::TooManyHeaders);
+    }
+    headers.append(name, value);
+    Ok(())
+}
+
+fn parse_head(bytes: &[u8], response: bool, limits: &Limits) -> Result<Head, HttpError> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut lines = text.split("\r\n");
+    let start_line = lines.next().unwrap_or("");
+    if start_line.chars().any(|c| c.is_control()) {
+        return Err(HttpError::BadStartLine(start_line.to_string()));
+    }
+    let (start, version) = parse_start_line(start_line, response)?;
+
+    let mut headers = Headers::new();
+    for line in lines {
+        parse_header_line(line, &mut headers, limits)?;
+    }
+    Ok(Head { start, version, headers })
+}
+
+enum ChunkStage {
+    Size,
+    Data(usize),
+    Trailers
+}
+
+enum Framing {
+    Length(usize),
+    Chunked(ChunkStage),
+    /// A response with no length, which ends when the connection does
+    UntilClose
+}
+
+enum Stage {
+    Head,
+    Body { head: Head, framing: Framing, body: Vec<u8> }
+}
+
+// The state shared by request and response parsers
+struct MessageParser {
+    limits: Limits,
+    response: bool,
+    /// Set for a response to a HEAD request, which has no body
+    no_body: bool,
+    buffer: Vec<u8>,
+    /// How much of `buffer` is known not to end the head
+    scanned: usize,
+    stage: Stage,
+    /// Once a message fails to parse, nothing after it can be trusted
+    error: Option<HttpError>
+}
+
+impl MessageParser {
+    fn new(limits: Limits, response: bool) -> MessageParser {
+        MessageParser {
+            limits,
+            response,
+            no_body: false,
+            buffer: Vec::new(),
+            scanned: 0,
+            stage: Stage::Head,
+            error: None
+        }
+    }
+
+    fn next_message(&mut self) -> Result<Option<(Head, Vec<u8>)>, HttpError> {
+        if let Some(ref error) = self.error {
+            return Err(error.clone());
+        }
+        let result = self.advance();
+        if let Err(ref error) = result {
+            self.error = Some(error.clone());
+        }
+        result
+    }
+
+    fn advance(&mut self) -> Result<Option<(Head, Vec<u8>)>, HttpError> {
+        if let Stage::Head = self.stage {
+            match self.read_head()? {
+                Some(stage) => self.stage = stage,
+                None => return Ok(None)
+            }
+        }
+        if !self.read_body()? {
+            return Ok(None);
+        }
+        match std::mem::replace(&mut self.stage, Stage::Head) {
+            Stage::Body { head, body, .. } => Ok(Some((head, body))),
+            Stage::Head => unreachable!()
+        }
+    }
+
+    /// Parse the head if it's all here, returning the body stage to go to
+    fn read_head(&mut self) -> Result<Option<Stage>, HttpError> {
+        // Servers should ignore blank lines before a request
+        if !self.response {
+            let blank = self.buffer.iter().take_while(|&&c| c == b'\r' || c == b'\n').count();
+            self.buffer.drain(..blank);
+            self.scanned = self.scanned.saturating_sub(blank);
+        }
+
+        let search_from = self.scanned.saturating_sub(3);
+        let end = match find(&self.buffer[search_from..], b"\r\n\r\n") {
+            Some(i) => search_from + i,
+            None => {
+                self.scanned = self.buffer.len();
+                if self.buffer.len() > self.limits.max_head_bytes {
+                    return Err(HttpError::HeadTooLarge);
+                }
+                return Ok(None);
+            }
+        };
+        if end + 4 > self.limits.max_head_bytes {
+            return Err(HttpError::HeadTooLarge);
+        }
+        let head = parse_head(&self.buffer[..end], self.response, &self.limits)?;
+        self.buffer.drain(..end + 4);
+        self.scanned = 0;
+
+        let framing = self.framing(&head)?;
+        self.no_body = false;
+        Ok(Some(Stage::Body { head, framing, body: Vec::new() }))
+    }
+
+    fn framing(&self, head: &Head) -> Result<Framing, HttpError> {
+        if let StartLine::Status { status, .. } = head.start {
+            if self.no_body || !Response::status_allows_body(status) {
+                return Ok(Framing::Length(0));
+            }
+        }
+
+        let headers = &head.headers;
+        if headers.contains("Transfer-Encoding") {
+            if headers.contains("Content-Length") {
+                return Err(HttpError::ConflictingFraming);
+            }
+            let codings = headers.list("Transfer-Encoding");
+            return match codings.iter().find(|coding| !coding.eq_ignore_ascii_case("chunked")) {
+                Some(coding) => Err(HttpError::UnsupportedTransferEncoding(coding.to_string())),
+                None if codings.len() == 1 => Ok(Framing::Chunked(ChunkStage::Size)),
+                None => Err(HttpError::UnsupportedTransferEncoding(codings.join(", ")))
+            };
+        }
+
+        let lengths = headers.list("Content-Length");
+        if let Some(first) = lengths.first() {
+            let bad = || HttpError::BadContentLength(lengths.join(", "));
+            if lengths.iter().any(|length| length != first)
+                || !first.bytes().all(|c| c.is_ascii_digit()) {
+                return Err(bad());
+            }
+            let length: usize = first.parse().map_err(|_| bad())?;
+            if length > self.limits.max_body_bytes {
+                return Err(HttpError::BodyTooLarge);
+            }
+            return Ok(Framing::Length(length));
+        }
+
+        Ok(if self.response { Framing::UntilClose } else { Framing::Length(0) })
+    }
+
+    /// Move as much of the body out of the buffer as is here, returning
+    /// true once it's complete
+    fn read_body(&mut self) -> Result<bool, HttpError> {
+        let limits = self.limits;
+        let (head, framing, body) = match self.stage {
+            Stage::Body { ref mut head, ref mut framing, ref mut body } => (head, framing, body),
+            Stage::Head => return Ok(false)
+        };
+        let buffer = &mut self.buffer;
+
+        match *framing {
+            Framing::Length(length) => {
+                let wanted = (length - body.len()).min(buffer.len());
+                body.extend(buffer.drain(..wanted));
+                Ok(body.len() == length)
+            }
+            Framing::UntilClose => {
+                if body.len() + buffer.len() > limits.max_body_bytes {
+                    return Err(HttpError::BodyTooLarge);
+                }
+                body.append(buffer);
+                Ok(false)
+            }
+            Framing::Chunked(ref mut stage) => loop {
+                match *stage {
+                    ChunkStage::Size => {
+                        let end = match find(buffer, b"\r\n") {
+                            Some(end) => end,
+                            None if buffer.len() > 1024 => return Err(HttpError::BadChunk),
+                            None => return Ok(false)
+                        };
+                        // Chunk extensions after a ';' are allowed and ignored
+                        let line = String::from_utf8_lossy(&buffer[..end]).into_owned();
+                        let size = line.split(';').next().unwrap().trim_end();
+                        if size.is_empty() || !size.bytes().all(|c| c.is_ascii_hexdigit()) {
+                            return Err(HttpError::BadChunk);
+                        }
+                        let size = usize::from_str_radix(size, 16).map_err(|_| HttpError::BadChunk)?;
+                        if size > limits.max_body_bytes - body.len() {
+                            return Err(HttpError::BodyTooLarge);
+                        }
+                        buffer.drain(..end + 2);
+                        *stage = if size == 0 { ChunkStage::Trailers } else { ChunkStage::Data(size) };
+                    }
+                    ChunkStage::Data(size) => {
+                        if buffer.len() < size + 2 {
+                            return Ok(false);
+                        }
+                        if &buffer[size..size + 2] != b"\r\n" {
+                            return Err(HttpError::BadChunk);
+                        }
+                        body.extend(buffer.drain(..size));
+                        buffer.drain(..2);
+                        *stage = ChunkStage::Size;
+                    }
+                    ChunkStage::Trailers => {
+                        let end = match find(buffer, b"\r\n") {
+                            Some(end) => end,
+                            None if buffer.len() > limits.max_head_bytes =>
+                                return Err(HttpError::HeadTooLarge),
+                            None => return Ok(false)
+                        };
+                        if end == 0 {
+                            buffer.drain(..2);
+                            return Ok(true);
+                        }
+                        let line = String::from_utf8_lossy(&buffer[..end]).into_owned();
+                        parse_header_line(&line, &mut head.headers, &limits)?;
+                        buffer.drain(..end + 2);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The connection closed. Finishes a body that runs until close, and
+    /// complains about anything else left half done.
+    fn finish(&mut self) -> Result<Option<(Head, Vec<u8>)>, HttpError> {
+        if let Some(ref error) = self.error {
+            return Err(error.clone());
+        }
+        match std::mem::replace(&mut self.stage, Stage::Head) {
+            Stage::Body { head, mut body, framing: Framing::UntilClose } => {
+                body.append(&mut self.buffer);
+                Ok(Some((head, body)))
+            }
+            Stage::Head if self.buffer.iter().all(|&c| c == b'\r' || c == b'\n') => Ok(None),
+            _ => {
+                self.error = Some(HttpError::UnexpectedEof);
+                Err(HttpError::UnexpectedEof)
+            }
+        }
+    }
+}
+
+/// Reads requests out of bytes fed to it a piece at a time
+pub struct RequestParser {
+    inner: MessageParser
+}
+
+impl RequestParser {
+    ///Constructor, with the default limits
+    pub fn new() -> RequestParser {
+        RequestParser::with_limits(Limits::default())
+    }
+
+    pub fn with_limits(limits: Limits) -> RequestParser {
+        RequestParser { inner: MessageParser::new(limits, false) }
+    }
+
+    /// Hand over bytes as they arrive
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.inner.buffer.extend_from_slice(bytes);
+    }
+
+    /// The next complete request, or `None` until more bytes are fed.
+    /// Call this until it returns `None`, since one feed may hold several
+    /// pipelined requests.
+    pub fn next_request(&mut self) -> Result<Option<Request>, HttpError> {
+        Ok(self.inner.next_message()?.map(|(head, body)| match head.start {
+            StartLine::Request { method, target } =>
+                Request { method, target, version: head.version, headers: head.headers, body },
+            StartLine::Status { .. } => unreachable!()
+        }))
+    }
+
+    /// The connection closed; fails if a request was left unfinished
+    pub fn finish(&mut self) -> Result<(), HttpError> {
+        self.inner.finish().map(|_| ())
+    }
+
+    /// True if no part of a request is waiting for more bytes
+    pub fn is_idle(&self) -> bool {
+        matches!(self.inner.stage, Stage::Head) && self.inner.buffer.is_empty()
+    }
+}
+
+impl Default for RequestParser {
+    fn default() -> Self {
+        RequestParser::new()
+    }
+}
+
+/// Reads responses out of bytes fed to it a piece at a time
+pub struct ResponseParser {
+    inner: MessageParser
+}
+
+impl ResponseParser {
+    ///Constructor, with the default limits
+    pub fn new() -> ResponseParser {
+        ResponseParser::with_limits(Limits::default())
+    }
+
+    pub fn with_limits(limits: Limits) -> ResponseParser {
+        ResponseParser { inner: MessageParser::new(limits, true) }
+    }
+
+    /// The next response answers a HEAD request, so it has no body
+    /// whatever its headers say
+    pub fn expect_no_body(&mut self) {
+        self.inner.no_body = true;
+    }
+
+    /// Hand over bytes as they arrive
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.inner.buffer.extend_from_slice(bytes);
+    }
+
+    /// The next complete response, or `None` until more bytes are fed
+    pub fn next_response(&mut self) -> Result<Option<Response>, HttpError> {
+        Ok(self.inner.next_message()?.map(response_from_parts))
+    }
+
+    /// The connection closed. Returns the last response if its body ran
+    /// until the close, and fails if one was left unfinished.
+    pub fn finish(&mut self) -> Result<Option<Response>, HttpError> {
+        Ok(self.inner.finish()?.map(response_from_parts))
+    }
+}
+
+impl Default for ResponseParser {
+    fn default() -> Self {
+        ResponseParser::new()
+    }
+}
+
+fn response_from_parts((head, body): (Head, Vec<u8>)) -> Response {
+    match head.start {
+        StartLine::Status { status, reason } =>
+            Response { version: head.version, status, reason, headers: head.headers, body },
+        StartLine::Request { .. } => unreachable!()
+    }
+}
+
+#[test]
+fn test_parse_requests_incrementally() {
+    let bytes = b"GET /index.html?q=1 HTTP/1.1\r\nHost: example.com\r\nAccept:  */* \r\n\r\n";
+
+    // However the bytes are split up, the same request comes out
+    for piece in 1..bytes.len() {
+        let mut parser = RequestParser::new();
+        let mut requests = Vec::new();
+        for chunk in bytes.chunks(piece) {
+            parser.feed(chunk);
+            while let Some(request) = parser.next_request().unwrap() {
+                requests.push(request);
+            }
+        }
+        assert!(parser.is_idle());
+        assert_eq!(requests, vec![Request::new("GET", "/index.html?q=1")
+                                  .header("Host", "example.com")
+                                  .header("Accept", "*/*")]);
+    }
+
+    // Pipelined requests, with bodies framed both ways
+    let mut parser = RequestParser::new();
+    parser.feed(b"\r\nPOST /notes HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello");
+    parser.feed(b"PUT /notes/1 HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n");
+    parser.feed(b"3;name=value\r\nabc\r\n10\r\n0123456789abcdef\r\n0\r\nDigest: x\r\n\r\n");
+    parser.feed(b"GET / HTTP/1.0\r\n\r\n");
+
+    let post = parser.next_request().unwrap().unwrap();
+    assert_eq!((post.method.as_str(), post.body.as_slice()), ("POST", &b"hello"[..]));
+    let put = parser.next_request().unwrap().unwrap();
+    assert_eq!(put.body, b"abc0123456789abcdef".to_vec());
+    assert_eq!(put.headers.get("digest"), Some("x"));
+    let get = parser.next_request().unwrap().unwrap();
+    assert_eq!(get.version, Version::Http10);
+    assert!(!get.keep_alive());
+    assert_eq!(parser.next_request(), Ok(None));
+    assert_eq!(parser.finish(), Ok(()));
+}
+
+#[test]
+fn test_write_and_reparse() {
+    let request = Request::new("POST", "/notes")
+        .header("Host", "localhost")
+        .with_body("a note");
+    assert_eq!(String::from_utf8(request.to_bytes()).unwrap(),
+               "POST /notes HTTP/1.1\r\nHost: localhost\r\nContent-Length: 6\r\n\r\na note");
+    let mut parser = RequestParser::new();
+    parser.feed(&request.to_bytes());
+    let mut parsed = parser.next_request().unwrap().unwrap();
+    assert!(parsed.headers.remove("content-length"));
+    assert_eq!(parsed, request);
+
+    let chunked = Response::new(HttpStatus::Ok)
+        .header("Transfer-Encoding", "chunked")
+        .with_body("streamed");
+    assert_eq!(String::from_utf8(chunked.to_bytes()).unwrap(),
+               "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n8\r\nstreamed\r\n0\r\n\r\n");
+
+    let mut responses = ResponseParser::new();
+    for response in &[chunked.clone(),
+                      Response::new(HttpStatus::NotFound).with_body("nothing here"),
+                      Response::new(HttpStatus::NotModified).header("ETag", "\"v1\""),
+                      Response::new(HttpStatus::try_from(299).unwrap()).with_body("odd")] {
+        responses.feed(&response.to_bytes());
+        let mut parsed = responses.next_response().unwrap().unwrap();
+        parsed.headers.remove("Content-Length");
+        assert_eq!(&parsed, response);
+    }
+    assert_eq!(Response::new(HttpStatus::NotModified).to_bytes(), b"HTTP/1.1 304 Not Modified\r\n\r\n");
+
+    // No chunked terminator where there can't be a body
+    let no_content = Response::new(HttpStatus::NoContent).header("Transfer-Encoding", "chunked");
+    assert_eq!(no_content.to_bytes(), b"HTTP/1.1 204 No Content\r\nTransfer-Encoding: chunked\r\n\r\n");
+    let mut head = Vec::new();
+    chunked.write_head_to(&mut head).unwrap();
+    assert_eq!(head, b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n");
+    head.clear();
+    Response::new(HttpStatus::NotFound).with_body("nothing here").write_head_to(&mut head).unwrap();
+    assert_eq!(head, b"HTTP/1.1 404 Not Found\r\nContent-Length: 12\r\n\r\n");
+
+    // Nothing that could split the message gets written
+    let refused = |result: io::Result<()>, out: &[u8]| {
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(out.is_empty());
+    };
+    let injected = "a\r\nSet-Cookie: admin=1";
+    for response in &[Response::new(HttpStatus::Ok).header("X-Note", injected),
+                      Response::new(HttpStatus::Ok).header(injected, "b"),
+                      Response::new(HttpStatus::Ok).header("X-Note", "a\nb"),
+                      Response::new(HttpStatus::Ok).header("X Note", "b"),
+                      Response { reason: injected.to_string(), ..Response::new(HttpStatus::Ok) }] {
+        let mut out = Vec::new();
+        refused(response.write_to(&mut out), &out);
+    }
+    for request in &[Request::new("GET", "/a\r\nHost: evil"),
+                     Request::new("GET", "/a HTTP/1.0"),
+                     Request::new("GET", ""),
+                     Request::new("GET /", "/"),
+                     Request::new("GET", "/").header("Host", injected)] {
+        let mut out = Vec::new();
+        refused(request.write_to(&mut out), &out);
+    }
+    assert_eq!(Response::new(HttpStatus::Ok).header("X-Note", "tab\tand caf\u{e9}").to_bytes(),
+               "HTTP/1.1 200 OK\r\nX-Note: tab\tand caf\u{e9}\r\nContent-Length: 0\r\n\r\n".as_bytes());
+}
+
+#[test]
+fn test_response_framing() {
+    // A body with no length runs until the connection closes
+    let mut parser = ResponseParser::new();
+    parser.feed(b"HTTP/1.0 200 Fine Thanks\r\n\r\nall of ");
+    assert_eq!(parser.next_response(), Ok(None));
+    parser.feed(b"this");
+    let response = parser.finish().unwrap().unwrap();
+    assert_eq!((response.status, response.reason.as_str()), (HttpStatus::Ok, "Fine Thanks"));
+    assert_eq!(response.body, b"all of this".to_vec());
+
+    // Responses to HEAD don't have bodies, whatever Content-Length says
+    let mut parser = ResponseParser::new();
+    parser.expect_no_body();
+    parser.feed(b"HTTP/1.1 200 OK\r\nContent-Length: 42\r\n\r\nHTTP/1.1 204 No Content\r\n\r\n");
+    assert_eq!(parser.next_response().unwrap().unwrap().body, Vec::<u8>::new());
+    assert_eq!(parser.next_response().unwrap().unwrap().status, HttpStatus::NoContent);
+
+    // A status line with no reason phrase at all
+    let mut parser = ResponseParser::new();
+    parser.feed(b"HTTP/1.1 404\r\nContent-Length: 0\r\n\r\n");
+    let response = parser.next_response().unwrap().unwrap();
+    assert_eq!((response.status, response.reason.as_str()), (HttpStatus::NotFound, ""));
+
+    let mut parser = ResponseParser::new();
+    parser.feed(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort");
+    assert_eq!(parser.next_response(), Ok(None));
+    assert_eq!(parser.finish(), Err(HttpError::UnexpectedEof));
+}
+
+#[test]
+fn test_keep_alive() {
+    let request = |version, connection: Option<&str>| {
+        let mut request = Request::new("GET", "/");
+        request.version = version;
+        if let Some(value) = connection {
+            request.headers.append("Connection", value);
+        }
+        request.keep_alive()
+    };
+    assert!(request(Version::Http11, None));
+    assert!(!request(Version::Http11, Some("Upgrade, close")));
+    assert!(!request(Version::Http10, None));
+    assert!(request(Version::Http10, Some("Keep-Alive")));
+}
+
+#[test]
+fn test_parse_errors() {
+    let error = |bytes: &[u8]| {
+        let mut parser = RequestParser::with_limits(Limits {
+            max_head_bytes: 256, max_headers: 4, max_body_bytes: 64
+        });
+        parser.feed(bytes);
+        let error = parser.next_request().unwrap_err();
+        // The parser stays failed
+        assert_eq!(parser.next_request(), Err(error.clone()));
+        error
+    };
+    use self::HttpError::*;
+
+    assert_eq!(error(b"GET /\r\n\r\n"), BadStartLine("GET /".to_string()));
+    assert_eq!(error(b"GET / HTTP/1.1 extra\r\n\r\n"), BadStartLine("GET / HTTP/1.1 extra".to_string()));
+    assert_eq!(error(b"G(T / HTTP/1.1\r\n\r\n"), BadStartLine("G(T / HTTP/1.1".to_string()));
+    assert_eq!(error(b"GET / HTTP/2.0\r\n\r\n"), UnsupportedVersion("HTTP/2.0".to_string()));
+    assert_eq!(error(b"GET / HTTP/1.1\r\nHost : x\r\n\r\n"), BadHeader("Host : x".to_string()));
+    assert_eq!(error(b"GET / HTTP/1.1\r\nA: b\r\n  folded\r\n\r\n"), BadHeader("  folded".to_string()));
+    assert_eq!(error(b"GET / HTTP/1.1\r\nA: b\r\nA: b\r\nA: b\r\nA: b\r\nA: b\r\n\r\n"), TooManyHeaders);
+    assert_eq!(error(&[b'x'; 300]), HeadTooLarge);
+    assert_eq!(error(b"POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 4\r\n\r\n"),
+               BadContentLength("3, 4".to_string()));
+    assert_eq!(error(b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n"), BadContentLength("-1".to_string()));
+    assert_eq!(error(b"POST / HTTP/1.1\r\nContent-Length: 65\r\n\r\n"), BodyTooLarge);
+    assert_eq!(error(b"POST / HTTP/1.1\r\nContent-Length: 1\r\nTransfer-Encoding: chunked\r\n\r\n"),
+               ConflictingFraming);
+    assert_eq!(error(b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n"),
+               UnsupportedTransferEncoding("gzip".to_string()));
+    assert_eq!(error(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n"), BadChunk);
+    assert_eq!(error(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nabc\r\n"), BadChunk);
+    assert_eq!(error(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n41\r\n"), BodyTooLarge);
+
+    assert_eq!(HeadTooLarge.status(), HttpStatus::RequestHeaderFieldsTooLarge);
+    assert_eq!(BodyTooLarge.status(), HttpStatus::ContentTooLarge);
+    assert_eq!(UnsupportedVersion(String::new()).status(), HttpStatus::HttpVersionNotSupported);
+    assert_eq!(BadChunk.status(), HttpStatus::BadRequest);
+    assert_eq!(BadHeader("Host : x".to_string()).to_string(), "malformed header \"Host : x\"");
+
+    let mut parser = RequestParser::new();
+    parser.feed(b"GET / HTTP/1.1\r\nHost: x\r\n");
+    assert_eq!(parser.finish(), Err(UnexpectedEof));
+}
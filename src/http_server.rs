@@ -0,0 +1,472 @@
+#![allow(dead_code)]
+
+// A small blocking HTTP/1.1 server, one thread per connection, up to a
+// limit past which new connections get a 503. Handlers are
+// closures from a request to a status and body, looked up by method and
+// path in a Router. The server keeps connections alive between requests,
+// tags successful GET responses with an ETag so clients can revalidate and
+// get a 304 instead of the body again (see http_cache), and shuts down
+// gracefully: it stops accepting, lets each connection finish the request
+// it's on, and waits for them all before returning.
+
+use crate::chapter_11::say_hello;
+use crate::http_cache::{evaluate_preconditions, EntityTag};
+use crate::http_message::{Limits, Request, RequestParser, Response};
+use crate::http_status::HttpStatus;
+use std::io::{self, ErrorKind, Read};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Turns a request into a status and body
+pub type Handler = Box<dyn Fn(&Request) -> (HttpStatus, Vec<u8>) + Send + Sync>;
+
+/// Handlers by method and path
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<(String, String, Handler)>
+}
+
+impl Router {
+    ///Constructor
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    /// The same router with a handler for `method` requests to `path`.
+    /// A GET handler answers HEAD requests too.
+    pub fn route<F>(mut self, method: &str, path: &str, handler: F) -> Self
+        where F: Fn(&Request) -> (HttpStatus, Vec<u8>) + Send + Sync + 'static {
+        self.routes.push((method.to_string(), path.to_string(), Box::new(handler)));
+        self
+    }
+
+    /// Answer a request: 404 for an unknown path, 405 for a method the path
+    /// has no handler for, and otherwise whatever the handler says. A HEAD
+    /// request gets the full GET response; the body is left out when it's
+    /// written.
+    pub fn respond(&self, request: &Request) -> Response {
+        let path = request.target.split('?').next().unwrap();
+        let routes: Vec<_> = self.routes.iter().filter(|route| route.1 == path).collect();
+        if routes.is_empty() {
+            return error_response(HttpStatus::NotFound);
+        }
+
+        let head = request.method == "HEAD";
+        let handler = routes.iter()
+            .find(|route| route.0 == request.method || (head && route.0 == "GET"))
+            .map(|route| &route.2);
+        let handler = match handler {
+            Some(handler) => handler,
+            None => {
+                let mut allowed: Vec<&str> = routes.iter().map(|route| route.0.as_str()).collect();
+                if allowed.contains(&"GET") {
+                    allowed.push("HEAD");
+                }
+                return error_response(HttpStatus::MethodNotAllowed)
+                    .header("Allow", &allowed.join(", "));
+            }
+        };
+
+        let (status, body) = handler(request);
+        let mut response = Response::new(status);
+        if (head || request.method == "GET") && status == HttpStatus::Ok {
+            let etag = EntityTag::for_content(&body);
+            match evaluate_preconditions(request, Some(&etag), None) {
+                HttpStatus::Ok => response.headers.append("ETag", &etag.to_string()),
+                HttpStatus::NotModified =>
+                    return Response::new(HttpStatus::NotModified).header("ETag", &etag.to_string()),
+                failed => return error_response(failed)
+            }
+        }
+        response.body = body;
+        response
+    }
+}
+
+fn error_response(status: HttpStatus) -> Response {
+    Response::new(status).with_body(format!("{}\n", status))
+}
+
+/// Write `response`, leaving its body out if it answers a HEAD request so
+/// the client doesn't read the body as the start of the next response
+fn send(stream: &mut TcpStream, response: &Response, head: bool) -> io::Result<()> {
+    if head {
+        response.write_head_to(stream)
+    } else {
+        response.write_to(stream)
+    }
+}
+
+/// Settings for a `Server`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ServerConfig {
+    pub limits: Limits,
+    /// How long a connection may sit without sending anything
+    pub idle_timeout: Duration,
+    /// How long a client has to send the whole of a request, from its
+    /// first byte, however steadily the bytes trickle in
+    pub request_timeout: Duration,
+    /// Close a connection after it has made this many requests
+    pub max_requests_per_connection: usize,
+    /// Most connections served at once. Any more are answered with 503
+    /// and closed.
+    pub max_connections: usize
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            limits: Limits::default(),
+            idle_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(30),
+            max_requests_per_connection: 100,
+            max_connections: 256
+        }
+    }
+}
+
+/// How often a waiting connection checks whether the server is shutting down
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub struct Server {
+    listener: TcpListener,
+    router: Router,
+    config: ServerConfig,
+    shutting_down: Arc<AtomicBool>
+}
+
+/// Stops a running `Server` from another thread
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    shutting_down: Arc<AtomicBool>,
+    address: SocketAddr
+}
+
+impl ShutdownHandle {
+    /// Ask the server to stop. `Server::run` returns once every open
+    /// connection has finished its current request.
+    pub fn shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        // The accept loop only looks at the flag when a connection comes in
+        let _ = TcpStream::connect(self.address);
+    }
+}
+
+impl Server {
+    /// Listen on `address` with the default settings
+    pub fn bind<A: ToSocketAddrs>(address: A, router: Router) -> io::Result<Server> {
+        Server::bind_with(address, router, ServerConfig::default())
+    }
+
+    pub fn bind_with<A: ToSocketAddrs>(address: A, router: Router, config: ServerConfig)
+                                       -> io::Result<Server> {
+        Ok(Server {
+            listener: TcpListener::bind(address)?,
+            router,
+            config,
+            shutting_down: Arc::new(AtomicBool::new(false))
+        })
+    }
+
+    /// The address actually bound, useful after binding port 0
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn shutdown_handle(&self) -> io::Result<ShutdownHandle> {
+        let mut address = self.local_addr()?;
+        if address.ip().is_unspecified() {
+            address.set_ip(Ipv4Addr::LOCALHOST.into());
+        }
+        Ok(ShutdownHandle { shutting_down: Arc::clone(&self.shutting_down), address })
+    }
+
+    /// Serve connections until shut down
+    pub fn run(self) -> io::Result<()> {
+        let router = Arc::new(self.router);
+        let mut workers: Vec<JoinHandle<()>> = Vec::new();
+
+        for stream in self.listener.incoming() {
+            if self.shutting_down.load(Ordering::SeqCst) {
+                break;
+            }
+            // A client giving up before we accept isn't our problem
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue
+            };
+            workers.retain(|worker| !worker.is_finished());
+            if workers.len() >= self.config.max_connections {
+                let _ = turn_away(stream, self.config.idle_timeout);
+                continue;
+            }
+
+            let router = Arc::clone(&router);
+            let shutting_down = Arc::clone(&self.shutting_down);
+            let config = self.config;
+            workers.push(thread::spawn(move || {
+                let _ = serve_connection(stream, &router, &config, &shutting_down);
+            }));
+        }
+
+        for worker in workers {
+            let _ = worker.join();
+        }
+        Ok(())
+    }
+}
+
+/// Tell a client there are too many connections already, without reading
+/// its request
+fn turn_away(mut stream: TcpStream, write_timeout: Duration) -> io::Result<()> {
+    stream.set_write_timeout(Some(write_timeout))?;
+    error_response(HttpStatus::ServiceUnavailable)
+        .header("Connection", "close")
+        .header("Retry-After", "1")
+        .write_to(&mut stream)
+}
+
+/// Answer requests on one connection until either side is done with it
+fn serve_connection(mut stream: TcpStream, router: &Router, config: &ServerConfig,
+                    shutting_down: &AtomicBool) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    // A client that won't read its responses can't hold the thread forever
+    stream.set_write_timeout(Some(config.idle_timeout))?;
+    let mut parser = RequestParser::with_limits(config.limits);
+    let mut buffer = [0; 4096];
+    let mut last_active = Instant::now();
+    // When the first byte of the request being read arrived
+    let mut request_started = None;
+    let mut served = 0;
+
+    loop {
+        loop {
+            let request = match parser.next_request() {
+                Ok(Some(request)) => request,
+                Ok(None) => break,
+                Err(error) => {
+                    error_response(error.status()).header("Connection", "close").write_to(&mut stream)?;
+                    return Ok(());
+                }
+            };
+            served += 1;
+            let head = request.method == "HEAD";
+            let mut response = router.respond(&request);
+            let close = !request.keep_alive() || served >= config.max_requests_per_connection
+                || shutting_down.load(Ordering::SeqCst);
+            if close {
+                response.headers.set("Connection", "close");
+            }
+            if let Err(error) = send(&mut stream, &response, head) {
+                // A handler that put a line break in a header gets a 500
+                // rather than splitting the response in two
+                if error.kind() != ErrorKind::InvalidInput {
+                    return Err(error);
+                }
+                let failed = error_response(HttpStatus::InternalServerError).header("Connection", "close");
+                send(&mut stream, &failed, head)?;
+                return Ok(());
+            }
+            if close {
+                return Ok(());
+            }
+            last_active = Instant::now();
+            // Any pipelined bytes left over start the next request
+            request_started = if parser.is_idle() { None } else { Some(last_active) };
+        }
+
+        if parser.is_idle() && shutting_down.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        if request_started.is_some_and(|started: Instant| started.elapsed() >= config.request_timeout) {
+            error_response(HttpStatus::RequestTimeout).header("Connection", "close").write_to(&mut stream)?;
+            return Ok(());
+        }
+        match stream.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => {
+                parser.feed(&buffer[..n]);
+                last_active = Instant::now();
+                request_started.get_or_insert(last_active);
+            }
+            Err(ref error) if error.kind() == ErrorKind::WouldBlock || error.kind() == ErrorKind::TimedOut => {
+                if last_active.elapsed() < config.idle_timeout {
+                    continue;
+                }
+                // Someone who stopped halfway through a request gets told why
+                if !parser.is_idle() {
+                    error_response(HttpStatus::RequestTimeout).header("Connection", "close")
+                        .write_to(&mut stream)?;
+                }
+                return Ok(());
+            }
+            Err(ref error) if error.kind() == ErrorKind::Interrupted => {}
+            Err(error) => return Err(error)
+        }
+    }
+}
+
+/// The routes `rust_practice serve` answers
+pub fn demo_router() -> Router {
+    Router::new()
+        .route("GET", "/", |_| {
+            let mut body = Vec::new();
+            match say_hello(&mut body) {
+                Ok(()) => (HttpStatus::Ok, body),
+                Err(_) => (HttpStatus::InternalServerError, Vec::new())
+            }
+        })
+        .route("POST", "/echo", |request| (HttpStatus::Ok, request.body.clone()))
+}
+
+#[cfg(test)]
+fn read_response(stream: &mut TcpStream, parser: &mut crate::http_message::ResponseParser)
+                 -> Option<Response> {
+    let mut buffer = [0; 1024];
+    loop {
+        if let Some(response) = parser.next_response().unwrap() {
+            return Some(response);
+        }
+        match stream.read(&mut buffer).unwrap() {
+            0 => return parser.finish().unwrap(),
+            n => parser.feed(&buffer[..n])
+        }
+    }
+}
+
+#[test]
+fn test_router() {
+    let router = demo_router();
+    let hello = router.respond(&Request::new("GET", "/?lang=en"));
+    assert_eq!((hello.status, hello.body.as_slice()), (HttpStatus::Ok, &b"hello world\n"[..]));
+    let etag = hello.headers.get("ETag").unwrap().to_string();
+
+    let head = router.respond(&Request::new("HEAD", "/"));
+    assert_eq!(head.body, hello.body);
+    assert_eq!(head.headers.get("ETag"), Some(etag.as_str()));
+
+    for tags in &[etag.clone(), format!("\"other\", W/{}", etag), "*".to_string()] {
+        let revalidated = router.respond(&Request::new("GET", "/").header("If-None-Match", tags));
+        assert_eq!(revalidated.status, HttpStatus::NotModified);
+        assert_eq!(revalidated.headers.get("ETag"), Some(etag.as_str()));
+    }
+    let changed = router.respond(&Request::new("GET", "/").header("If-None-Match", "\"other\""));
+    assert_eq!(changed.status, HttpStatus::Ok);
+    let mismatch = router.respond(&Request::new("GET", "/").header("If-Match", "\"other\""));
+    assert_eq!(mismatch.status, HttpStatus::PreconditionFailed);
+
+    assert_eq!(router.respond(&Request::new("GET", "/missing")).status, HttpStatus::NotFound);
+    let wrong_method = router.respond(&Request::new("DELETE", "/"));
+    assert_eq!(wrong_method.status, HttpStatus::MethodNotAllowed);
+    assert_eq!(wrong_method.headers.get("Allow"), Some("GET, HEAD"));
+
+    let echo = router.respond(&Request::new("POST", "/echo").with_body("ping"));
+    assert_eq!(echo.body, b"ping".to_vec());
+    assert!(!echo.headers.contains("ETag"));
+}
+
+#[test]
+fn test_server_on_localhost() {
+    use crate::http_message::ResponseParser;
+    use std::io::Write;
+
+    let server = Server::bind("127.0.0.1:0", demo_router()).unwrap();
+    let address = server.local_addr().unwrap();
+    let shutdown = server.shutdown_handle().unwrap();
+    let running = thread::spawn(move || server.run());
+
+    // Pipelined requests on one kept-alive connection
+    let mut stream = TcpStream::connect(address).unwrap();
+    let mut parser = ResponseParser::new();
+    stream.write_all(&Request::new("GET", "/").to_bytes()).unwrap();
+    stream.write_all(&Request::new("POST", "/echo").with_body("ping").to_bytes()).unwrap();
+    let hello = read_response(&mut stream, &mut parser).unwrap();
+    assert_eq!(hello.body, b"hello world\n".to_vec());
+    let echo = read_response(&mut stream, &mut parser).unwrap();
+    assert_eq!((echo.status, echo.body.as_slice()), (HttpStatus::Ok, &b"ping"[..]));
+
+    let etag = hello.headers.get("ETag").unwrap();
+    stream.write_all(&Request::new("GET", "/").header("If-None-Match", etag).to_bytes()).unwrap();
+    let not_modified = read_response(&mut stream, &mut parser).unwrap();
+    assert_eq!(not_modified.status, HttpStatus::NotModified);
+    assert!(not_modified.body.is_empty());
+
+    // HEAD gets the headers without the body, errors included, so the
+    // connection stays in step for the next request
+    for path in &["/", "/missing"] {
+        stream.write_all(&Request::new("HEAD", path).to_bytes()).unwrap();
+        parser.expect_no_body();
+        let head = read_response(&mut stream, &mut parser).unwrap();
+        assert!(head.body.is_empty());
+        let length = if *path == "/" { "12" } else { "14" };
+        assert_eq!(head.headers.get("Content-Length"), Some(length));
+    }
+    stream.write_all(&Request::new("GET", "/").to_bytes()).unwrap();
+    let again = read_response(&mut stream, &mut parser).unwrap();
+    assert_eq!((again.status, again.body.as_slice()), (HttpStatus::Ok, &b"hello world\n"[..]));
+
+    // A bad request is answered, then the connection is closed
+    let mut bad = TcpStream::connect(address).unwrap();
+    let mut bad_parser = ResponseParser::new();
+    bad.write_all(b"NOT HTTP AT ALL\r\n\r\n").unwrap();
+    let response = read_response(&mut bad, &mut bad_parser).unwrap();
+    assert_eq!(response.status, HttpStatus::BadRequest);
+    assert!(!response.keep_alive());
+    assert_eq!(read_response(&mut bad, &mut bad_parser), None);
+
+    // Shutting down closes the idle connection and stops the server
+    shutdown.shutdown();
+    assert_eq!(read_response(&mut stream, &mut parser), None);
+    running.join().unwrap().unwrap();
+    assert!(TcpStream::connect(address).is_err());
+}
+
+#[test]
+fn test_server_limits() {
+    use crate::http_message::ResponseParser;
+    use std::io::Write;
+
+    let config = ServerConfig {
+        request_timeout: Duration::from_millis(300),
+        max_connections: 1,
+        ..ServerConfig::default()
+    };
+    let server = Server::bind_with("127.0.0.1:0", demo_router(), config).unwrap();
+    let address = server.local_addr().unwrap();
+    let shutdown = server.shutdown_handle().unwrap();
+    let running = thread::spawn(move || server.run());
+
+    // A client sending a byte at a time never goes idle, but still runs
+    // out of time for the whole request. It stops just short of the
+    // deadline, so none of its bytes are left unread when the server
+    // answers and closes.
+    let mut slow = TcpStream::connect(address).unwrap();
+    slow.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+    let mut trickle = slow.try_clone().unwrap();
+    let started = Instant::now();
+    let trickling = thread::spawn(move || {
+        while started.elapsed() < Duration::from_millis(200) {
+            trickle.write_all(b"X").unwrap();
+            thread::sleep(Duration::from_millis(20));
+        }
+    });
+
+    // and while it holds the only slot, anyone else is turned away
+    thread::sleep(Duration::from_millis(100));
+    let mut turned_away = TcpStream::connect(address).unwrap();
+    let busy = read_response(&mut turned_away, &mut ResponseParser::new()).unwrap();
+    assert_eq!(busy.status, HttpStatus::ServiceUnavailable);
+    assert!(!busy.keep_alive());
+
+    let timed_out = read_response(&mut slow, &mut ResponseParser::new()).unwrap();
+    assert_eq!(timed_out.status, HttpStatus::RequestTimeout);
+    assert!(started.elapsed() < Duration::from_secs(2));
+    trickling.join().unwrap();
+
+    shutdown.shutdown();
+    running.join().unwrap().unwrap();
+}
@@ -0,0 +1,334 @@
+#![allow(dead_code)]
+
+// HTTP status codes, grown out of chapter_10's three-variant HttpStatus.
+// Every code in the IANA HTTP Status Code Registry gets a variant and its
+// reason phrase; anything else from 100 to 999 is kept as Unknown(code), so
+// a status read off the wire always survives a round trip. Only TryFrom
+// makes an UnknownCode, so a registered code is always its own variant.
+
+use crate::enum_repr::{EnumIter, EnumName, FromRepr, IntoRepr};
+use crate::json::Json;
+use crate::json_convert::{FromJson, FromJsonError, ToJson};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+// Builds the enum and its reason phrases from one table, so they can't
+// drift apart. The enum_repr derives handle the codes and variant names;
+// Display shows the code and reason phrase instead.
+macro_rules! http_statuses {
+    ($($variant:ident = $code:expr, $reason:expr;)*) => {
+        /// A response status code
+        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, FromRepr, IntoRepr, EnumIter, EnumName)]
+        #[enum_repr(no_display)]
+        #[repr(u16)]
+        pub enum HttpStatus {
+            $($variant = $code,)*
+            /// A code that isn't in the registry
+            Unknown(UnknownCode)
+        }
+
+        impl HttpStatus {
+            /// The reason phrase from the registry, like "Not Found"
+            pub fn reason_phrase(self) -> Option<&'static str> {
+                match self {
+                    $(HttpStatus::$variant => Some($reason),)*
+                    HttpStatus::Unknown(_) => None
+                }
+            }
+        }
+    }
+}
+
+http_statuses! {
+    Continue = 100, "Continue";
+    SwitchingProtocols = 101, "Switching Protocols";
+    Processing = 102, "Processing";
+    EarlyHints = 103, "Early Hints";
+
+    Ok = 200, "OK";
+    Created = 201, "Created";
+    Accepted = 202, "Accepted";
+    NonAuthoritativeInformation = 203, "Non-Authoritative Information";
+    NoContent = 204, "No Content";
+    ResetContent = 205, "Reset Content";
+    PartialContent = 206, "Partial Content";
+    MultiStatus = 207, "Multi-Status";
+    AlreadyReported = 208, "Already Reported";
+    ImUsed = 226, "IM Used";
+
+    MultipleChoices = 300, "Multiple Choices";
+    MovedPermanently = 301, "Moved Permanently";
+    Found = 302, "Found";
+    SeeOther = 303, "See Other";
+    NotModified = 304, "Not Modified";
+    UseProxy = 305, "Use Proxy";
+    TemporaryRedirect = 307, "Temporary Redirect";
+    PermanentRedirect = 308, "Permanent Redirect";
+
+    BadRequest = 400, "Bad Request";
+    Unauthorized = 401, "Unauthorized";
+    PaymentRequired = 402, "Payment Required";
+    Forbidden = 403, "Forbidden";
+    NotFound = 404, "Not Found";
+    MethodNotAllowed = 405, "Method Not Allowed";
+    NotAcceptable = 406, "Not Acceptable";
+    ProxyAuthenticationRequired = 407, "Proxy Authentication Required";
+    RequestTimeout = 408, "Request Timeout";
+    Conflict = 409, "Conflict";
+    Gone = 410, "Gone";
+    LengthRequired = 411, "Length Required";
+    PreconditionFailed = 412, "Precondition Failed";
+    ContentTooLarge = 413, "Content Too Large";
+    UriTooLong = 414, "URI Too Long";
+    UnsupportedMediaType = 415, "Unsupported Media Type";
+    RangeNotSatisfiable = 416, "Range Not Satisfiable";
+    ExpectationFailed = 417, "Expectation Failed";
+    MisdirectedRequest = 421, "Misdirected Request";
+    UnprocessableContent = 422, "Unprocessable Content";
+    Locked = 423, "Locked";
+    FailedDependency = 424, "Failed Dependency";
+    TooEarly = 425, "Too Early";
+    UpgradeRequired = 426, "Upgrade Required";
+    PreconditionRequired = 428, "Precondition Required";
+    TooManyRequests = 429, "Too Many Requests";
+    RequestHeaderFieldsTooLarge = 431, "Request Header Fields Too Large";
+    UnavailableForLegalReasons = 451, "Unavailable For Legal Reasons";
+
+    InternalServerError = 500, "Internal Server Error";
+    NotImplemented = 501, "Not Implemented";
+    BadGateway = 502, "Bad Gateway";
+    ServiceUnavailable = 503, "Service Unavailable";
+    GatewayTimeout = 504, "Gateway Timeout";
+    HttpVersionNotSupported = 505, "HTTP Version Not Supported";
+    VariantAlsoNegotiates = 506, "Variant Also Negotiates";
+    InsufficientStorage = 507, "Insufficient Storage";
+    LoopDetected = 508, "Loop Detected";
+    NotExtended = 510, "Not Extended";
+    NetworkAuthenticationRequired = 511, "Network Authentication Required";
+}
+
+/// A code from 100 to 999 that isn't in the registry
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UnknownCode(u16);
+
+impl UnknownCode {
+    pub fn get(self) -> u16 {
+        self.0
+    }
+}
+
+impl From<UnknownCode> for u16 {
+    fn from(code: UnknownCode) -> u16 {
+        code.0
+    }
+}
+
+impl HttpStatus {
+    /// The three digit code
+    pub fn code(self) -> u16 {
+        u16::from(self)
+    }
+
+    /// True for anything but `Unknown`
+    pub fn is_registered(self) -> bool {
+        !matches!(self, HttpStatus::Unknown(_))
+    }
+
+    /// 1xx: the request was received and is being worked on
+    pub fn is_informational(self) -> bool {
+        (100..200).contains(&self.code())
+    }
+
+    /// 2xx: the request worked
+    pub fn is_success(self) -> bool {
+        (200..300).contains(&self.code())
+    }
+
+    /// 3xx: the client needs to look elsewhere
+    pub fn is_redirect(self) -> bool {
+        (300..400).contains(&self.code())
+    }
+
+    /// 4xx: the request was at fault
+    pub fn is_client_error(self) -> bool {
+        (400..500).contains(&self.code())
+    }
+
+    /// 5xx: the server was at fault
+    pub fn is_server_error(self) -> bool {
+        (500..600).contains(&self.code())
+    }
+
+    /// Either a client or a server error
+    pub fn is_error(self) -> bool {
+        self.is_client_error() || self.is_server_error()
+    }
+}
+
+/// Why a number or string isn't a status code
+#[derive(Clone, Debug, PartialEq)]
+pub enum HttpStatusError {
+    /// Status codes have exactly three digits
+    OutOfRange(u32),
+    /// The text doesn't start with a three digit code
+    NotACode(String),
+    /// A registered code came with someone else's reason phrase
+    WrongReason { status: HttpStatus, reason: String }
+}
+
+impl fmt::Display for HttpStatusError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HttpStatusError::OutOfRange(code) =>
+                write!(dest, "{} isn't a three digit status code", code),
+            HttpStatusError::NotACode(ref text) =>
+                write!(dest, "{:?} doesn't start with a status code", text),
+            HttpStatusError::WrongReason { status, ref reason } =>
+                write!(dest, "{:?} is the wrong reason phrase for {}", reason, status)
+        }
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+/// Any code from 100 to 999 is accepted, registered or not
+impl TryFrom<u16> for HttpStatus {
+    type Error = HttpStatusError;
+
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        if !(100..=999).contains(&code) {
+            return Err(HttpStatusError::OutOfRange(u32::from(code)));
+        }
+        Ok(HttpStatus::from_repr(code).unwrap_or(HttpStatus::Unknown(UnknownCode(code))))
+    }
+}
+
+/// Reads "404", or "404 Not Found" with the reason phrase in any case. A
+/// reason after an unregistered code is taken on trust.
+impl FromStr for HttpStatus {
+    type Err = HttpStatusError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let (code, reason) = match text.find(' ') {
+            Some(space) => (&text[..space], text[space + 1..].trim()),
+            None => (text, "")
+        };
+        if code.len() != 3 || !code.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(HttpStatusError::NotACode(text.to_string()));
+        }
+        let status = HttpStatus::try_from(code.parse::<u16>().unwrap())?;
+        match status.reason_phrase() {
+            Some(expected) if !reason.is_empty() && !expected.eq_ignore_ascii_case(reason) =>
+                Err(HttpStatusError::WrongReason { status, reason: reason.to_string() }),
+            _ => Ok(status)
+        }
+    }
+}
+
+/// Prints the code and reason phrase, like "404 Not Found", or just the
+/// code if it isn't registered
+impl fmt::Display for HttpStatus {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        match self.reason_phrase() {
+            Some(reason) => write!(dest, "{} {}", self.code(), reason),
+            None => write!(dest, "{}", self.code())
+        }
+    }
+}
+
+#[test]
+fn test_registry() {
+    let codes: Vec<u16> = HttpStatus::iter().map(|status| status.code()).collect();
+    let mut sorted = codes.clone();
+    sorted.sort_unstable();
+    sorted.dedup();
+    assert_eq!(codes, sorted);
+    assert_eq!(codes.len(), HttpStatus::VARIANT_COUNT);
+    assert_eq!(HttpStatus::VARIANT_COUNT, 61);
+
+    for status in HttpStatus::iter() {
+        assert!(status.is_registered());
+        assert_eq!(HttpStatus::try_from(status.code()), Ok(status));
+        assert_eq!(status.to_string().parse(), Ok(status));
+    }
+
+    assert_eq!(HttpStatus::Ok.to_string(), "200 OK");
+    assert_eq!(HttpStatus::NotFound.to_string(), "404 Not Found");
+    assert_eq!(HttpStatus::ImUsed.reason_phrase(), Some("IM Used"));
+    assert_eq!(u16::from(HttpStatus::NotModified), 304);
+    assert_eq!(HttpStatus::NotFound.variant_name(), "NotFound");
+    assert_eq!(HttpStatus::Unknown(UnknownCode(299)).variant_name(), "Unknown");
+    assert_eq!(HttpStatus::from_variant_name("PaymentRequired"), Ok(HttpStatus::PaymentRequired));
+    assert!(HttpStatus::from_variant_name("Unknown").is_err());
+}
+
+#[test]
+fn test_unknown_codes() {
+    assert_eq!(HttpStatus::try_from(418), Ok(HttpStatus::Unknown(UnknownCode(418))));
+    assert_eq!(HttpStatus::try_from(799), Ok(HttpStatus::Unknown(UnknownCode(799))));
+    assert_eq!(HttpStatus::Unknown(UnknownCode(418)).to_string(), "418");
+    assert_eq!(HttpStatus::Unknown(UnknownCode(418)).reason_phrase(), None);
+    assert!(!HttpStatus::Unknown(UnknownCode(418)).is_registered());
+    // Registered codes never come out as Unknown
+    assert_eq!(HttpStatus::try_from(404), Ok(HttpStatus::NotFound));
+    assert_eq!(u16::from(HttpStatus::Unknown(UnknownCode(418))), 418);
+    match HttpStatus::try_from(418).unwrap() {
+        HttpStatus::Unknown(code) => assert_eq!(code.get(), 418),
+        status => panic!("{:?} is registered", status)
+    }
+    assert_eq!(HttpStatus::try_from(99), Err(HttpStatusError::OutOfRange(99)));
+    assert_eq!(HttpStatus::try_from(1000), Err(HttpStatusError::OutOfRange(1000)));
+}
+
+#[test]
+fn test_status_classes() {
+    let classes = |status: HttpStatus| {
+        [status.is_informational(), status.is_success(), status.is_redirect(),
+         status.is_client_error(), status.is_server_error(), status.is_error()]
+    };
+    assert_eq!(classes(HttpStatus::EarlyHints), [true, false, false, false, false, false]);
+    assert_eq!(classes(HttpStatus::NoContent), [false, true, false, false, false, false]);
+    assert_eq!(classes(HttpStatus::NotModified), [false, false, true, false, false, false]);
+    assert_eq!(classes(HttpStatus::Unknown(UnknownCode(418))), [false, false, false, true, false, true]);
+    assert_eq!(classes(HttpStatus::BadGateway), [false, false, false, false, true, true]);
+    assert_eq!(classes(HttpStatus::Unknown(UnknownCode(600))), [false; 6]);
+}
+
+#[test]
+fn test_parse_status() {
+    assert_eq!("404".parse(), Ok(HttpStatus::NotFound));
+    assert_eq!(" 404 not found ".parse(), Ok(HttpStatus::NotFound));
+    assert_eq!("299 Mostly Fine".parse(), Ok(HttpStatus::Unknown(UnknownCode(299))));
+    assert_eq!("404 Gone".parse::<HttpStatus>(),
+               Err(HttpStatusError::WrongReason { status: HttpStatus::NotFound,
+                                                  reason: "Gone".to_string() }));
+    assert_eq!("4040".parse::<HttpStatus>(), Err(HttpStatusError::NotACode("4040".to_string())));
+    assert_eq!("Not Found".parse::<HttpStatus>(),
+               Err(HttpStatusError::NotACode("Not Found".to_string())));
+    assert_eq!("099".parse::<HttpStatus>(), Err(HttpStatusError::OutOfRange(99)));
+    assert_eq!("404 Gone".parse::<HttpStatus>().unwrap_err().to_string(),
+               "\"Gone\" is the wrong reason phrase for 404 Not Found");
+}
+
+/// A status is written as its code
+impl ToJson for HttpStatus {
+    fn to_json(&self) -> Json {
+        self.code().to_json()
+    }
+}
+
+impl FromJson for HttpStatus {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+        HttpStatus::try_from(u16::from_json(json)?).map_err(|error| FromJsonError::new(error.to_string()))
+    }
+}
+
+#[test]
+fn test_json_conversions() {
+    assert_eq!(HttpStatus::NotFound.to_json(), Json::from(404));
+    assert_eq!(HttpStatus::from_json(&Json::from(418)), Ok(HttpStatus::Unknown(UnknownCode(418))));
+    assert_eq!(HttpStatus::from_json(&Json::from(42)).unwrap_err().reason, "42 isn't a three digit status code");
+    assert!(HttpStatus::from_json(&Json::from("404")).is_err());
+}
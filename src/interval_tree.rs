@@ -0,0 +1,458 @@
+#![allow(dead_code)]
+
+// An interval tree built the same way as chapter_10's BinaryTree: boxed
+// nodes ordered by interval start, where each node also remembers the
+// largest end point anywhere in its subtree. That extra number lets a query
+// skip whole subtrees whose intervals all finish before the query begins.
+// Unlike BinaryTree it keeps itself balanced (AVL style), so inserting
+// intervals in sorted order doesn't turn it into a long list, and the
+// recursive walks below stay logarithmically deep.
+
+use std::cmp::Ordering;
+use std::mem;
+use std::ops::Range;
+
+/// A collection of half-open intervals `start..end`, each carrying a value
+#[derive(Debug)]
+pub enum IntervalTree<K, V> {
+    Empty,
+    NonEmpty(Box<IntervalNode<K, V>>)
+}
+
+// A part of an IntervalTree.
+#[derive(Debug)]
+pub struct IntervalNode<K, V> {
+    interval: Range<K>,
+    value: V,
+    max_end: K, // largest `interval.end` in this node's subtree
+    height: u32, // longest path down to an empty subtree, counting this node
+    left: IntervalTree<K, V>,
+    right: IntervalTree<K, V>
+}
+
+use self::IntervalTree::*;
+
+impl<K: Ord + Copy, V> IntervalNode<K, V> {
+    /// Recompute `max_end` and `height` after one of the subtrees has changed
+    fn update(&mut self) {
+        let mut max_end = self.interval.end;
+        for child in &[&self.left, &self.right] {
+            if let Some(end) = child.max_end() {
+                if end > max_end {
+                    max_end = end;
+                }
+            }
+        }
+        self.max_end = max_end;
+        self.height = 1 + self.left.height().max(self.right.height());
+    }
+}
+
+/// Turn `node` and its left child around, so the child becomes the root
+fn rotate_right<K: Ord + Copy, V>(mut node: Box<IntervalNode<K, V>>) -> Box<IntervalNode<K, V>> {
+    let mut left = match mem::replace(&mut node.left, Empty) {
+        NonEmpty(left) => left,
+        Empty => unreachable!("rotate_right needs a left child")
+    };
+    node.left = mem::replace(&mut left.right, Empty);
+    node.update();
+    left.right = NonEmpty(node);
+    left.update();
+    left
+}
+
+/// Turn `node` and its right child around, so the child becomes the root
+fn rotate_left<K: Ord + Copy, V>(mut node: Box<IntervalNode<K, V>>) -> Box<IntervalNode<K, V>> {
+    let mut right = match mem::replace(&mut node.right, Empty) {
+        NonEmpty(right) => right,
+        Empty => unreachable!("rotate_left needs a right child")
+    };
+    node.right = mem::replace(&mut right.left, Empty);
+    node.update();
+    right.left = NonEmpty(node);
+    right.update();
+    right
+}
+
+/// Refresh `node` after one of its subtrees changed height by at most one,
+/// rotating if the two sides now differ by more than one
+fn rebalance<K: Ord + Copy, V>(mut node: Box<IntervalNode<K, V>>) -> Box<IntervalNode<K, V>> {
+    node.update();
+    let (left, right) = (node.left.height(), node.right.height());
+    if left > right + 1 {
+        // A left child leaning right needs turning first (left-right case)
+        if let NonEmpty(child) = mem::replace(&mut node.left, Empty) {
+            let child = if child.left.height() < child.right.height() {
+                rotate_left(child)
+            } else {
+                child
+            };
+            node.left = NonEmpty(child);
+        }
+        rotate_right(node)
+    } else if right > left + 1 {
+        if let NonEmpty(child) = mem::replace(&mut node.right, Empty) {
+            let child = if child.right.height() < child.left.height() {
+                rotate_right(child)
+            } else {
+                child
+            };
+            node.right = NonEmpty(child);
+        }
+        rotate_left(node)
+    } else {
+        node
+    }
+}
+
+/// Detach the node with the smallest start from the subtree rooted at `node`,
+/// returning it along with whatever is left of the subtree
+fn remove_min<K: Ord + Copy, V>(mut node: Box<IntervalNode<K, V>>)
+                                -> (Box<IntervalNode<K, V>>, IntervalTree<K, V>)
+{
+    match mem::replace(&mut node.left, Empty) {
+        Empty => {
+            let rest = mem::replace(&mut node.right, Empty);
+            (node, rest)
+        }
+        NonEmpty(left) => {
+            let (min, rest) = remove_min(left);
+            node.left = rest;
+            (min, NonEmpty(rebalance(node)))
+        }
+    }
+}
+
+/// Combine the two subtrees of a removed node into one tree
+fn join<K: Ord + Copy, V>(left: IntervalTree<K, V>, right: IntervalTree<K, V>)
+                          -> IntervalTree<K, V>
+{
+    match (left, right) {
+        (left, Empty) => left,
+        (Empty, right) => right,
+        (left, NonEmpty(right)) => {
+            let (mut min, rest) = remove_min(right);
+            min.left = left;
+            min.right = rest;
+            NonEmpty(rebalance(min))
+        }
+    }
+}
+
+impl<K: Ord + Copy, V> IntervalTree<K, V> {
+    ///Constructor
+    pub fn new() -> Self {
+        Empty
+    }
+
+    /// Returns true if the tree holds no intervals
+    pub fn is_empty(&self) -> bool {
+        match *self {
+            Empty => true,
+            NonEmpty(_) => false
+        }
+    }
+
+    /// The largest end point of any interval in the tree
+    pub fn max_end(&self) -> Option<K> {
+        match *self {
+            Empty => None,
+            NonEmpty(ref node) => Some(node.max_end)
+        }
+    }
+
+    /// Height of the tree, 0 when empty
+    fn height(&self) -> u32 {
+        match *self {
+            Empty => 0,
+            NonEmpty(ref node) => node.height
+        }
+    }
+
+    /// Add an interval and its value. Duplicate intervals are allowed.
+    pub fn insert(&mut self, interval: Range<K>, value: V) {
+        let node = match mem::replace(self, Empty) {
+            Empty =>
+                Box::new(IntervalNode {
+                    max_end: interval.end,
+                    height: 1,
+                    interval,
+                    value,
+                    left: Empty,
+                    right: Empty
+                }),
+            NonEmpty(mut node) => {
+                if interval.start <= node.interval.start {
+                    node.left.insert(interval, value);
+                } else {
+                    node.right.insert(interval, value);
+                }
+                rebalance(node)
+            }
+        };
+        *self = NonEmpty(node);
+    }
+
+    /// Remove one interval equal to `interval`, returning its value if
+    /// there was one
+    pub fn remove(&mut self, interval: &Range<K>) -> Option<V> {
+        let mut node = match mem::replace(self, Empty) {
+            Empty => return None,
+            NonEmpty(node) => node
+        };
+
+        if node.interval == *interval {
+            let IntervalNode { value, left, right, .. } = *node;
+            *self = join(left, right);
+            return Some(value);
+        }
+
+        // Insert sends equal starts left, but rotations and `join` can move
+        // a node above others with the same start, so those can be on
+        // either side
+        let removed = match interval.start.cmp(&node.interval.start) {
+            Ordering::Less => node.left.remove(interval),
+            Ordering::Greater => node.right.remove(interval),
+            Ordering::Equal => node.left.remove(interval).or_else(|| node.right.remove(interval))
+        };
+        *self = NonEmpty(rebalance(node));
+        removed
+    }
+
+    /// Stabbing query: every interval containing `point`, ordered by start
+    pub fn stab(&self, point: K) -> Vec<(&Range<K>, &V)> {
+        let mut found = Vec::new();
+        self.stab_into(point, &mut found);
+        found
+    }
+
+    fn stab_into<'a>(&'a self, point: K, found: &mut Vec<(&'a Range<K>, &'a V)>) {
+        if let NonEmpty(ref node) = *self {
+            // Everything down here ends at or before `point`
+            if point >= node.max_end {
+                return;
+            }
+            node.left.stab_into(point, found);
+            // The right subtree starts no earlier than this node, so if this
+            // node starts after `point` nothing on the right can contain it
+            if node.interval.start <= point {
+                if point < node.interval.end {
+                    found.push((&node.interval, &node.value));
+                }
+                node.right.stab_into(point, found);
+            }
+        }
+    }
+
+    /// Overlap query: every interval sharing at least one point with
+    /// `query`, ordered by start
+    pub fn overlapping(&self, query: &Range<K>) -> Vec<(&Range<K>, &V)> {
+        let mut found = Vec::new();
+        self.overlap_into(query, &mut found);
+        found
+    }
+
+    fn overlap_into<'a>(&'a self, query: &Range<K>, found: &mut Vec<(&'a Range<K>, &'a V)>) {
+        if let NonEmpty(ref node) = *self {
+            if query.start >= node.max_end {
+                return;
+            }
+            node.left.overlap_into(query, found);
+            if node.interval.start < query.end {
+                if query.start < node.interval.end {
+                    found.push((&node.interval, &node.value));
+                }
+                node.right.overlap_into(query, found);
+            }
+        }
+    }
+
+    /// Range update: call `f` on the value of every interval overlapping
+    /// `query`. Returns how many values were visited.
+    pub fn update_overlapping<F>(&mut self, query: &Range<K>, mut f: F) -> usize
+        where F: FnMut(&Range<K>, &mut V)
+    {
+        self.update_into(query, &mut f)
+    }
+
+    fn update_into<F>(&mut self, query: &Range<K>, f: &mut F) -> usize
+        where F: FnMut(&Range<K>, &mut V)
+    {
+        match *self {
+            Empty => 0,
+            NonEmpty(ref mut node) => {
+                if query.start >= node.max_end {
+                    return 0;
+                }
+                let mut count = node.left.update_into(query, f);
+                if node.interval.start < query.end {
+                    if query.start < node.interval.end {
+                        f(&node.interval, &mut node.value);
+                        count += 1;
+                    }
+                    count += node.right.update_into(query, f);
+                }
+                count
+            }
+        }
+    }
+}
+
+impl<K: Ord + Copy, V> Default for IntervalTree<K, V> {
+    fn default() -> Self {
+        IntervalTree::new()
+    }
+}
+
+#[cfg(test)]
+fn meetings() -> IntervalTree<u32, &'static str> {
+    let mut tree = IntervalTree::new();
+    tree.insert(900..1000, "standup");
+    tree.insert(1300..1500, "design review");
+    tree.insert(930..1130, "interview");
+    tree.insert(1200..1300, "lunch");
+    tree.insert(1000..1700, "on call");
+    tree.insert(1600..1630, "one on one");
+    tree
+}
+
+#[test]
+fn test_interval_stab() {
+    let tree = meetings();
+    assert_eq!(tree.max_end(), Some(1700));
+
+    let names = |found: Vec<(&Range<u32>, &&'static str)>| -> Vec<&'static str> {
+        found.into_iter().map(|(_, name)| *name).collect()
+    };
+    assert_eq!(names(tree.stab(945)), vec!["standup", "interview"]);
+    assert_eq!(names(tree.stab(1000)), vec!["interview", "on call"]);
+    // Intervals are half open, so lunch is over at 1300
+    assert_eq!(names(tree.stab(1300)), vec!["on call", "design review"]);
+    assert!(tree.stab(1700).is_empty());
+    assert!(tree.stab(800).is_empty());
+}
+
+#[test]
+fn test_interval_overlapping() {
+    let tree = meetings();
+
+    let found: Vec<_> = tree.overlapping(&(1130..1330))
+        .into_iter()
+        .map(|(range, _)| range.clone())
+        .collect();
+    assert_eq!(found, vec![1000..1700, 1200..1300, 1300..1500]);
+
+    assert!(tree.overlapping(&(1700..1800)).is_empty());
+    assert_eq!(tree.overlapping(&(0..2400)).len(), 6);
+}
+
+#[test]
+fn test_interval_update_and_remove() {
+    let mut tree = IntervalTree::new();
+    for (i, start) in [50, 20, 80, 10, 30, 70, 90, 25].iter().enumerate() {
+        tree.insert(*start..*start + 15, i);
+    }
+
+    let visited = tree.update_overlapping(&(28..40), |_, value| *value += 100);
+    assert_eq!(visited, 3);
+    let values: Vec<usize> = tree.stab(32).into_iter().map(|(_, v)| *v).collect();
+    assert_eq!(values, vec![101, 107, 104]);
+
+    // Removing a node with two children keeps the tree searchable
+    assert_eq!(tree.remove(&(20..35)), Some(101));
+    assert_eq!(tree.remove(&(20..35)), None);
+    assert_eq!(tree.remove(&(50..64)), None);
+    assert_eq!(tree.remove(&(50..65)), Some(0));
+    assert_eq!(tree.remove(&(90..105)), Some(6));
+    assert_eq!(tree.max_end(), Some(95));
+
+    let left: Vec<u32> = tree.overlapping(&(0..100))
+        .into_iter()
+        .map(|(range, _)| range.start)
+        .collect();
+    assert_eq!(left, vec![10, 25, 30, 70, 80]);
+}
+
+#[test]
+fn test_interval_matches_brute_force() {
+    let mut tree = IntervalTree::new();
+    let mut all = Vec::new();
+    // A small linear congruential generator keeps the test deterministic
+    let mut seed = 12345u32;
+    let mut next = || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (seed >> 16) % 200
+    };
+    for i in 0..100 {
+        let start = next();
+        let interval = start..start + 1 + next() % 30;
+        // Keep intervals unique so `remove` has only one candidate
+        if all.iter().any(|(existing, _)| *existing == interval) {
+            continue;
+        }
+        tree.insert(interval.clone(), i);
+        all.push((interval, i));
+    }
+    for i in (0..all.len()).step_by(3) {
+        assert_eq!(tree.remove(&all[i].0), Some(all[i].1));
+    }
+    let remaining: Vec<_> = all.iter()
+        .enumerate()
+        .filter(|&(i, _)| i % 3 != 0)
+        .map(|(_, entry)| entry)
+        .collect();
+
+    for point in 0..240 {
+        let mut expected: Vec<usize> = remaining.iter()
+            .filter(|(range, _)| range.start <= point && point < range.end)
+            .map(|(_, value)| *value)
+            .collect();
+        let mut actual: Vec<usize> = tree.stab(point).into_iter().map(|(_, v)| *v).collect();
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+    }
+}
+
+#[test]
+fn test_interval_remove_after_join_with_equal_starts() {
+    let mut tree = IntervalTree::new();
+    tree.insert(10..20, 'a');
+    tree.insert(5..6, 'b');
+    tree.insert(30..40, 'c');
+    tree.insert(30..35, 'd');
+    // Promotes 30..35 to the root, leaving 30..40 to its right
+    assert_eq!(tree.remove(&(10..20)), Some('a'));
+    assert_eq!(tree.stab(37), [(&(30..40), &'c')]);
+    assert_eq!(tree.remove(&(30..40)), Some('c'));
+    assert_eq!(tree.stab(37), []);
+    assert_eq!(tree.remove(&(30..35)), Some('d'));
+    assert_eq!(tree.remove(&(5..6)), Some('b'));
+    assert!(tree.is_empty());
+}
+
+#[test]
+fn test_interval_sorted_inserts_stay_balanced() {
+    const COUNT: u32 = 200_000;
+    let mut tree = IntervalTree::new();
+    for start in 0..COUNT {
+        tree.insert(start * 10..start * 10 + 15, start);
+    }
+    // An AVL tree is never more than about 1.44 * log2(n) high
+    assert!(tree.height() <= 26, "height {}", tree.height());
+    assert_eq!(tree.max_end(), Some((COUNT - 1) * 10 + 15));
+
+    let values: Vec<u32> = tree.stab(1_000_004).into_iter().map(|(_, v)| *v).collect();
+    assert_eq!(values, vec![99_999, 100_000]);
+    assert_eq!(tree.overlapping(&(500..530)).len(), 4);
+
+    for start in (0..COUNT).rev().step_by(2) {
+        assert_eq!(tree.remove(&(start * 10..start * 10 + 15)), Some(start));
+    }
+    assert!(tree.height() <= 26, "height {}", tree.height());
+    // Only the odd starts were removed
+    assert_eq!(tree.stab(1_000_004), [(&(1_000_000..1_000_015), &100_000)]);
+    assert_eq!(tree.overlapping(&(0..COUNT * 10 + 15)).len(), COUNT as usize / 2);
+    // Dropping walks the tree recursively, which is fine at this height
+    drop(tree);
+}
@@ -0,0 +1,647 @@
+#![allow(dead_code)]
+
+// JSON values (RFC 8259), grown out of the Json enum sketched in chapter_10.
+// The parser is strict: no comments, trailing commas, leading zeros, NaN or
+// unescaped control characters, and \u escapes must pair their surrogates.
+// Errors give the line and column they were found at, counted in
+// characters from 1, and nesting is limited so hostile input can't blow
+// the stack. Objects are kept sorted by key, so output is always the same
+// for the same value; a repeated key keeps its last value.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::Index;
+use std::str::FromStr;
+
+/// A JSON value
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Box<BTreeMap<String, Json>>)
+}
+
+const I64_MIN: f64 = i64::MIN as f64;
+
+/// How deeply arrays and objects may nest unless told otherwise
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// Why some text isn't JSON
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseJsonError {
+    pub line: usize,
+    pub column: usize,
+    pub reason: &'static str
+}
+
+impl fmt::Display for ParseJsonError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(dest, "invalid JSON at line {}, column {}: {}", self.line, self.column, self.reason)
+    }
+}
+
+impl std::error::Error for ParseJsonError {}
+
+// Reads a JSON value a token at a time
+struct JsonReader<'t> {
+    text: &'t str,
+    at: usize,
+    depth: usize,
+    max_depth: usize
+}
+
+/// The value of a hex digit
+pub(crate) fn hex_value(byte: u8) -> Option<u16> {
+    (byte as char).to_digit(16).map(|digit| digit as u16)
+}
+
+impl<'t> JsonReader<'t> {
+    fn error_at(&self, at: usize, reason: &'static str) -> ParseJsonError {
+        let before = &self.text[..at];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        ParseJsonError {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            reason
+        }
+    }
+
+    fn error(&self, reason: &'static str) -> ParseJsonError {
+        self.error_at(self.at, reason)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.at).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.at += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8, reason: &'static str) -> Result<(), ParseJsonError> {
+        if self.peek() == Some(byte) {
+            self.at += 1;
+            Ok(())
+        } else {
+            Err(self.error(reason))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, ParseJsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b't') => self.literal("true", Json::Boolean(true)),
+            Some(b'f') => self.literal("false", Json::Boolean(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-') | Some(b'0'..=b'9') => self.number().map(Json::Number),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input"))
+        }
+    }
+
+    fn literal(&mut self, word: &'static str, value: Json) -> Result<Json, ParseJsonError> {
+        if !self.text[self.at..].starts_with(word) {
+            return Err(self.error("expected a value"));
+        }
+        self.at += word.len();
+        Ok(value)
+    }
+
+    fn nest(&mut self) -> Result<(), ParseJsonError> {
+        self.depth += 1;
+        if self.depth > self.max_depth {
+            return Err(self.error("nested too deeply"));
+        }
+        self.at += 1;
+        self.skip_whitespace();
+        Ok(())
+    }
+
+    fn array(&mut self) -> Result<Json, ParseJsonError> {
+        self.nest()?;
+        let mut elements = Vec::new();
+        if self.peek() == Some(b']') {
+            self.at += 1;
+        } else {
+            loop {
+                elements.push(self.value()?);
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(b',') => self.at += 1,
+                    Some(b']') => {
+                        self.at += 1;
+                        break;
+                    }
+                    _ => return Err(self.error("expected ',' or ']'"))
+                }
+            }
+        }
+        self.depth -= 1;
+        Ok(Json::Array(elements))
+    }
+
+    fn object(&mut self) -> Result<Json, ParseJsonError> {
+        self.nest()?;
+        let mut members = BTreeMap::new();
+        if self.peek() == Some(b'}') {
+            self.at += 1;
+        } else {
+            loop {
+                self.skip_whitespace();
+                if self.peek() != Some(b'"') {
+                    return Err(self.error("expected a string key"));
+                }
+                let key = self.string()?;
+                self.skip_whitespace();
+                self.expect(b':', "expected ':'")?;
+                let value = self.value()?;
+                members.insert(key, value);
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(b',') => self.at += 1,
+                    Some(b'}') => {
+                        self.at += 1;
+                        break;
+                    }
+                    _ => return Err(self.error("expected ',' or '}'"))
+                }
+            }
+        }
+        self.depth -= 1;
+        Ok(Json::Object(Box::new(members)))
+    }
+
+    fn string(&mut self) -> Result<String, ParseJsonError> {
+        self.at += 1;
+        let mut text = String::new();
+        loop {
+            // Copy over everything up to the next quote, escape or control
+            let run = self.text[self.at..].bytes()
+                .take_while(|&c| c != b'"' && c != b'\\' && c >= 0x20)
+                .count();
+            text.push_str(&self.text[self.at..self.at + run]);
+            self.at += run;
+            match self.peek() {
+                Some(b'"') => {
+                    self.at += 1;
+                    return Ok(text);
+                }
+                Some(b'\\') => text.push(self.escape()?),
+                Some(_) => return Err(self.error("control characters must be escaped")),
+                None => return Err(self.error("unterminated string"))
+            }
+        }
+    }
+
+    /// The character a backslash escape stands for
+    fn escape(&mut self) -> Result<char, ParseJsonError> {
+        let start = self.at;
+        self.at += 1;
+        let c = match self.peek() {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.at += 1;
+                let unit = self.hex4()?;
+                let code = match unit {
+                    0xd800..=0xdbff => {
+                        // A high surrogate has to be followed by a low one
+                        if !self.text[self.at..].starts_with("\\u") {
+                            return Err(self.error_at(start, "unpaired surrogate"));
+                        }
+                        self.at += 2;
+                        let low = self.hex4()?;
+                        if !(0xdc00..=0xdfff).contains(&low) {
+                            return Err(self.error_at(start, "unpaired surrogate"));
+                        }
+                        0x10000 + ((u32::from(unit) - 0xd800) << 10) + (u32::from(low) - 0xdc00)
+                    }
+                    0xdc00..=0xdfff => return Err(self.error_at(start, "unpaired surrogate")),
+                    _ => u32::from(unit)
+                };
+                return Ok(std::char::from_u32(code).unwrap());
+            }
+            _ => return Err(self.error_at(start, "invalid escape"))
+        };
+        self.at += 1;
+        Ok(c)
+    }
+
+    fn hex4(&mut self) -> Result<u16, ParseJsonError> {
+        let digits = self.text.as_bytes().get(self.at..self.at + 4)
+            .ok_or_else(|| self.error("expected four hex digits"))?;
+        let mut unit = 0;
+        for &digit in digits {
+            unit = unit * 16 + hex_value(digit).ok_or_else(|| self.error("expected four hex digits"))?;
+        }
+        self.at += 4;
+        Ok(unit)
+    }
+
+    fn digits(&mut self) -> usize {
+        let count = self.text[self.at..].bytes().take_while(u8::is_ascii_digit).count();
+        self.at += count;
+        count
+    }
+
+    fn number(&mut self) -> Result<f64, ParseJsonError> {
+        let start = self.at;
+        if self.peek() == Some(b'-') {
+            self.at += 1;
+        }
+        match self.peek() {
+            Some(b'0') => {
+                self.at += 1;
+                if self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    return Err(self.error("leading zeros aren't allowed"));
+                }
+            }
+            Some(b'1'..=b'9') => {
+                self.digits();
+            }
+            _ => return Err(self.error("expected a digit"))
+        }
+        if self.peek() == Some(b'.') {
+            self.at += 1;
+            if self.digits() == 0 {
+                return Err(self.error("expected digits after '.'"));
+            }
+        }
+        if let Some(b'e') | Some(b'E') = self.peek() {
+            self.at += 1;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.at += 1;
+            }
+            if self.digits() == 0 {
+                return Err(self.error("expected digits in the exponent"));
+            }
+        }
+        let n: f64 = self.text[start..self.at].parse().unwrap();
+        if n.is_infinite() {
+            return Err(self.error_at(start, "number out of range"));
+        }
+        Ok(n)
+    }
+}
+
+/// Append `text` as a quoted JSON string
+pub(crate) fn write_string(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+}
+
+/// A number as JSON. Whole numbers have no ".0", very large and very
+/// small ones use an exponent, and since JSON has no NaN or infinity
+/// those become null.
+pub(crate) fn format_number(n: f64) -> String {
+    if !n.is_finite() {
+        "null".to_string()
+    } else if n == 0.0 || (1e-6..1e17).contains(&n.abs()) {
+        n.to_string()
+    } else {
+        format!("{:?}", n)
+    }
+}
+
+impl Json {
+    /// Parse a JSON text, allowing the default nesting depth
+    pub fn parse(text: &str) -> Result<Json, ParseJsonError> {
+        Json::parse_with(text, DEFAULT_MAX_DEPTH)
+    }
+
+    /// Parse a JSON text, allowing arrays and objects to nest `max_depth` deep
+    pub fn parse_with(text: &str, max_depth: usize) -> Result<Json, ParseJsonError> {
+        // A byte order mark may be ignored, and Windows editors like them
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        let mut reader = JsonReader { text, at: 0, depth: 0, max_depth };
+        let value = reader.value()?;
+        reader.skip_whitespace();
+        if reader.at != text.len() {
+            return Err(reader.error("unexpected text after the value"));
+        }
+        Ok(value)
+    }
+
+    /// The value laid out over several lines, indented two spaces a level
+    pub fn to_pretty_string(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, Some("  "), 0);
+        out
+    }
+
+    fn write(&self, out: &mut String, indent: Option<&str>, level: usize) {
+        let newline = |out: &mut String, level: usize| {
+            if let Some(indent) = indent {
+                out.push('\n');
+                out.push_str(&indent.repeat(level));
+            }
+        };
+        match *self {
+            Json::Null => out.push_str("null"),
+            Json::Boolean(b) => out.push_str(if b { "true" } else { "false" }),
+            Json::Number(n) => out.push_str(&format_number(n)),
+            Json::String(ref s) => write_string(out, s),
+            Json::Array(ref elements) if elements.is_empty() => out.push_str("[]"),
+            Json::Array(ref elements) => {
+                out.push('[');
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, level + 1);
+                    element.write(out, indent, level + 1);
+                }
+                newline(out, level);
+                out.push(']');
+            }
+            Json::Object(ref members) if members.is_empty() => out.push_str("{}"),
+            Json::Object(ref members) => {
+                out.push('{');
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, level + 1);
+                    write_string(out, key);
+                    out.push_str(if indent.is_some() { ": " } else { ":" });
+                    value.write(out, indent, level + 1);
+                }
+                newline(out, level);
+                out.push('}');
+            }
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Json::Null
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Json::Boolean(b) => Some(b),
+            _ => None
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(n) => Some(n),
+            _ => None
+        }
+    }
+
+    /// The number, if it's a whole one that fits in an i64
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Json::Number(n) if n.fract() == 0.0 && (I64_MIN..-I64_MIN).contains(&n) => Some(n as i64),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref s) => Some(s),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match *self {
+            Json::Array(ref elements) => Some(elements),
+            _ => None
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&BTreeMap<String, Json>> {
+        match *self {
+            Json::Object(ref members) => Some(members),
+            _ => None
+        }
+    }
+
+    /// The member called `key`, if this is an object that has one
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        self.as_object().and_then(|members| members.get(key))
+    }
+
+    /// The element at `index`, if this is an array that long
+    pub fn get_index(&self, index: usize) -> Option<&Json> {
+        self.as_array().and_then(|elements| elements.get(index))
+    }
+}
+
+static NULL: Json = Json::Null;
+
+/// `json["key"]` is the member called "key", or null if there isn't one,
+/// so lookups can be chained without checking each step
+impl Index<&str> for Json {
+    type Output = Json;
+
+    fn index(&self, key: &str) -> &Json {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+/// `json[3]` is the element at 3, or null if there isn't one
+impl Index<usize> for Json {
+    type Output = Json;
+
+    fn index(&self, index: usize) -> &Json {
+        self.get_index(index).unwrap_or(&NULL)
+    }
+}
+
+/// Prints the value compactly, with no whitespace
+impl fmt::Display for Json {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out, None, 0);
+        dest.write_str(&out)
+    }
+}
+
+impl FromStr for Json {
+    type Err = ParseJsonError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Json::parse(text)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Boolean(b)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(n: f64) -> Json {
+        Json::Number(n)
+    }
+}
+
+impl From<i32> for Json {
+    fn from(n: i32) -> Json {
+        Json::Number(f64::from(n))
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(elements: Vec<Json>) -> Json {
+        Json::Array(elements)
+    }
+}
+
+/// Collects key and value pairs into an object
+impl<K: Into<String>> FromIterator<(K, Json)> for Json {
+    fn from_iter<I: IntoIterator<Item = (K, Json)>>(members: I) -> Json {
+        Json::Object(Box::new(members.into_iter().map(|(key, value)| (key.into(), value)).collect()))
+    }
+}
+
+#[test]
+fn test_parse_values() {
+    // The example from RFC 8259 section 13
+    let image = Json::parse(r#"
+        {
+          "Image": {
+              "Width":  800,
+              "Height": 600,
+              "Title":  "View from 15th Floor",
+              "Thumbnail": {
+                  "Url":    "http://www.example.com/image/481989943",
+                  "Height": 125,
+                  "Width":  100
+              },
+              "Animated" : false,
+              "IDs": [116, 943, 234, 38793]
+            }
+        }"#).unwrap();
+    assert_eq!(image["Image"]["Width"].as_i64(), Some(800));
+    assert_eq!(image["Image"]["Thumbnail"]["Url"].as_str(), Some("http://www.example.com/image/481989943"));
+    assert_eq!(image["Image"]["Animated"].as_bool(), Some(false));
+    assert_eq!(image["Image"]["IDs"][3], Json::Number(38793.0));
+    assert!(image["Image"]["IDs"][4].is_null());
+    assert!(image["Missing"]["Deeper"][0].is_null());
+
+    assert_eq!(Json::parse(" null "), Ok(Json::Null));
+    assert_eq!(Json::parse("\u{feff}[]"), Ok(Json::Array(vec![])));
+    assert_eq!(Json::parse("-0.5e-3"), Ok(Json::Number(-0.0005)));
+    assert_eq!(Json::parse("1E+2"), Ok(Json::Number(100.0)));
+    assert_eq!(Json::parse(r#"{"a": 1, "a": 2}"#).unwrap()["a"], Json::Number(2.0));
+    assert_eq!(Json::parse(r#""\"\\\/\b\f\n\r\t\u00e9\u20AC""#),
+               Ok(Json::from("\"\\/\u{8}\u{c}\n\r\té€")));
+    // U+1D11E MUSICAL SYMBOL G CLEF, as a surrogate pair
+    assert_eq!(Json::parse(r#""\ud834\udd1e""#), Ok(Json::from("\u{1d11e}")));
+    assert_eq!(Json::parse("\"raw 𝄞 is fine\""), Ok(Json::from("raw 𝄞 is fine")));
+}
+
+#[test]
+fn test_parse_errors() {
+    let error = |text: &str| {
+        let e = Json::parse(text).unwrap_err();
+        (e.line, e.column, e.reason)
+    };
+    assert_eq!(error(""), (1, 1, "unexpected end of input"));
+    assert_eq!(error("[1,]"), (1, 4, "expected a value"));
+    assert_eq!(error("[1 2]"), (1, 4, "expected ',' or ']'"));
+    assert_eq!(error("{\n  \"a\": 1,\n  b: 2\n}"), (3, 3, "expected a string key"));
+    assert_eq!(error("{\"é\" 1}"), (1, 6, "expected ':'"));
+    assert_eq!(error("01"), (1, 2, "leading zeros aren't allowed"));
+    assert_eq!(error("1."), (1, 3, "expected digits after '.'"));
+    assert_eq!(error("-"), (1, 2, "expected a digit"));
+    assert_eq!(error("1e400"), (1, 1, "number out of range"));
+    assert_eq!(error("NaN"), (1, 1, "expected a value"));
+    assert_eq!(error("tru"), (1, 1, "expected a value"));
+    assert_eq!(error("\"tab\there\""), (1, 5, "control characters must be escaped"));
+    assert_eq!(error("\"\\x\""), (1, 2, "invalid escape"));
+    assert_eq!(error("\"\\u12\""), (1, 4, "expected four hex digits"));
+    assert_eq!(error("\"\\ud834\""), (1, 2, "unpaired surrogate"));
+    assert_eq!(error("\"\\ud834\\u0041\""), (1, 2, "unpaired surrogate"));
+    assert_eq!(error("\"\\udd1e\""), (1, 2, "unpaired surrogate"));
+    assert_eq!(error("\"open"), (1, 6, "unterminated string"));
+    assert_eq!(error("{} {}"), (1, 4, "unexpected text after the value"));
+
+    let deep = "[".repeat(DEFAULT_MAX_DEPTH + 1);
+    assert_eq!(error(&deep), (1, DEFAULT_MAX_DEPTH + 1, "nested too deeply"));
+    assert!(Json::parse_with("[[1]]", 2).is_ok());
+    assert!(Json::parse_with("[[1]]", 1).is_err());
+
+    assert_eq!(Json::parse("[1,]").unwrap_err().to_string(),
+               "invalid JSON at line 1, column 4: expected a value");
+}
+
+#[test]
+fn test_write_values() {
+    let value: Json = vec![
+        ("name", Json::from("Ada \"Countess\" Lovelace\n")),
+        ("born", Json::from(1815)),
+        ("ratio", Json::from(0.25)),
+        ("tags", Json::from(vec![Json::Null, Json::from(true), Json::Array(vec![])])),
+        ("empty", Json::Object(Box::default()))
+    ].into_iter().collect();
+
+    let compact = value.to_string();
+    assert_eq!(compact, r#"{"born":1815,"empty":{},"name":"Ada \"Countess\" Lovelace\n","ratio":0.25,"tags":[null,true,[]]}"#);
+    assert_eq!(value.to_pretty_string(), "{
+  \"born\": 1815,
+  \"empty\": {},
+  \"name\": \"Ada \\\"Countess\\\" Lovelace\\n\",
+  \"ratio\": 0.25,
+  \"tags\": [
+    null,
+    true,
+    []
+  ]
+}");
+    assert_eq!(compact.parse(), Ok(value.clone()));
+    assert_eq!(Json::parse(&value.to_pretty_string()), Ok(value));
+
+    let numbers = [0.0, -0.0, 1.0, -1.5, 1e20, 1.5e-9, 123456789.125, f64::MAX, f64::MIN_POSITIVE];
+    for &n in &numbers {
+        assert_eq!(Json::parse(&Json::Number(n).to_string()), Ok(Json::Number(n)));
+    }
+    assert_eq!(format_number(1e20), "1e20");
+    assert_eq!(format_number(f64::NAN), "null");
+    assert_eq!(Json::from("\u{1}").to_string(), "\"\\u0001\"");
+}
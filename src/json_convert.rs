@@ -0,0 +1,423 @@
+#![allow(dead_code)]
+
+// Converting Rust values to and from Json without serde. ToJson makes a
+// Json value; FromJson reads one back, saying where it didn't fit:
+//
+//     #[derive(ToJson, FromJson)]
+//     struct Reading { sensor: String, #[json(rename = "value")] celsius: f64 }
+//
+// The derives come from the json_derive crate and are re-exported here. The
+// code they generate calls the helpers below, and Json, as
+// crate::json_convert; a type that sees this module by another path names
+// it with #[json(crate = "...")].
+//
+// Numbers are f64 in Json, so integers beyond 2^53 lose precision on the
+// way out. A missing object member reads as null, which suits Options;
+// members nobody asked for are ignored.
+
+pub use crate::json::Json;
+pub use json_derive::{FromJson, ToJson};
+
+use crate::json::format_number;
+use crate::json_patch::JsonPointer;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::BuildHasher;
+
+/// Why a Json value couldn't be converted, and where in it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FromJsonError {
+    pub path: JsonPointer,
+    pub reason: String
+}
+
+impl FromJsonError {
+    ///Constructor, for a problem with the value itself
+    pub fn new<S: Into<String>>(reason: S) -> FromJsonError {
+        FromJsonError { path: JsonPointer::root(), reason: reason.into() }
+    }
+
+    /// "expected {what}, found {kind of value}"
+    pub fn expected(what: &str, found: &Json) -> FromJsonError {
+        FromJsonError::new(format!("expected {}, found {}", what, kind(found)))
+    }
+
+    pub fn unknown_variant(enum_name: &str, variant: &str) -> FromJsonError {
+        FromJsonError::new(format!("{:?} isn't a variant of {}", variant, enum_name))
+    }
+
+    /// The same error, found inside the member or element `token`
+    pub fn within(self, token: &str) -> FromJsonError {
+        FromJsonError { path: self.path.under(token), reason: self.reason }
+    }
+}
+
+impl fmt::Display for FromJsonError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(dest, "can't convert the JSON at \"{}\": {}", self.path, self.reason)
+    }
+}
+
+impl std::error::Error for FromJsonError {}
+
+fn kind(json: &Json) -> &'static str {
+    match *json {
+        Json::Null => "null",
+        Json::Boolean(_) => "a boolean",
+        Json::Number(_) => "a number",
+        Json::String(_) => "a string",
+        Json::Array(_) => "an array",
+        Json::Object(_) => "an object"
+    }
+}
+
+/// Things that can be written as Json
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+/// Things that can be read back from Json
+pub trait FromJson: Sized {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError>;
+}
+
+// Helpers for the derives
+
+pub fn object(json: &Json) -> Result<&BTreeMap<String, Json>, FromJsonError> {
+    json.as_object().ok_or_else(|| FromJsonError::expected("an object", json))
+}
+
+/// Read the member `name`, treating a missing one as null
+pub fn field<T: FromJson>(members: &BTreeMap<String, Json>, name: &str) -> Result<T, FromJsonError> {
+    match members.get(name) {
+        Some(value) => T::from_json(value).map_err(|error| error.within(name)),
+        None => T::from_json(&Json::Null).map_err(|_| FromJsonError::new(format!("missing the member {:?}", name)))
+    }
+}
+
+/// The elements of an array that must have exactly `count` of them
+pub fn elements(json: &Json, count: usize) -> Result<&[Json], FromJsonError> {
+    match *json {
+        Json::Array(ref elements) if elements.len() == count => Ok(elements),
+        Json::Array(ref elements) =>
+            Err(FromJsonError::new(format!("expected {} elements, found {}", count, elements.len()))),
+        _ => Err(FromJsonError::expected("an array", json))
+    }
+}
+
+pub fn element<T: FromJson>(elements: &[Json], index: usize) -> Result<T, FromJsonError> {
+    T::from_json(&elements[index]).map_err(|error| error.within(&index.to_string()))
+}
+
+pub fn null(json: &Json) -> Result<(), FromJsonError> {
+    if json.is_null() { Ok(()) } else { Err(FromJsonError::expected("null", json)) }
+}
+
+/// An enum variant with fields, as {"Variant": fields}
+pub fn tagged(variant: &str, value: Json) -> Json {
+    Some((variant, value)).into_iter().collect()
+}
+
+/// The name of an enum variant, and its fields if it has any: "Variant"
+/// or {"Variant": fields}
+pub fn variant(json: &Json) -> Result<(&str, Option<&Json>), FromJsonError> {
+    match *json {
+        Json::String(ref name) => Ok((name, None)),
+        Json::Object(ref members) if members.len() == 1 => {
+            let (name, value) = members.iter().next().unwrap();
+            Ok((name, Some(value)))
+        }
+        _ => Err(FromJsonError::expected("a variant name or an object with one member", json))
+    }
+}
+
+/// Run `convert`, putting any error it has inside `token`
+pub fn within<T, F>(token: &str, convert: F) -> Result<T, FromJsonError>
+    where F: FnOnce() -> Result<T, FromJsonError>
+{
+    convert().map_err(|error| error.within(token))
+}
+
+// Conversions for standard types
+
+impl ToJson for Json {
+    fn to_json(&self) -> Json {
+        self.clone()
+    }
+}
+
+impl FromJson for Json {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+        Ok(json.clone())
+    }
+}
+
+impl ToJson for bool {
+    fn to_json(&self) -> Json {
+        Json::Boolean(*self)
+    }
+}
+
+impl FromJson for bool {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+        json.as_bool().ok_or_else(|| FromJsonError::expected("a boolean", json))
+    }
+}
+
+impl ToJson for str {
+    fn to_json(&self) -> Json {
+        Json::String(self.to_string())
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> Json {
+        Json::String(self.clone())
+    }
+}
+
+impl FromJson for String {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+        json.as_str().map(str::to_string).ok_or_else(|| FromJsonError::expected("a string", json))
+    }
+}
+
+impl ToJson for char {
+    fn to_json(&self) -> Json {
+        Json::String(self.to_string())
+    }
+}
+
+impl FromJson for char {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+        let mut chars = json.as_str().map(str::chars);
+        match chars.as_mut().map(|chars| (chars.next(), chars.next())) {
+            Some((Some(c), None)) => Ok(c),
+            _ => Err(FromJsonError::expected("a one-character string", json))
+        }
+    }
+}
+
+impl ToJson for f64 {
+    fn to_json(&self) -> Json {
+        Json::Number(*self)
+    }
+}
+
+impl FromJson for f64 {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+        json.as_f64().ok_or_else(|| FromJsonError::expected("a number", json))
+    }
+}
+
+impl ToJson for f32 {
+    fn to_json(&self) -> Json {
+        Json::Number(f64::from(*self))
+    }
+}
+
+impl FromJson for f32 {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+        f64::from_json(json).map(|n| n as f32)
+    }
+}
+
+macro_rules! integer_conversions {
+    ($($t:ty),*) => {$(
+        impl ToJson for $t {
+            fn to_json(&self) -> Json {
+                Json::Number(*self as f64)
+            }
+        }
+
+        impl FromJson for $t {
+            fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+                match *json {
+                    Json::Number(n) if n.fract() == 0.0 && (<$t>::MIN as f64..<$t>::MAX as f64 + 1.0).contains(&n) =>
+                        Ok(n as $t),
+                    Json::Number(n) =>
+                        Err(FromJsonError::new(format!("{} isn't a {}", format_number(n), stringify!($t)))),
+                    _ => Err(FromJsonError::expected("an integer", json))
+                }
+            }
+        }
+    )*}
+}
+
+integer_conversions!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+impl<T: ToJson + ?Sized> ToJson for &T {
+    fn to_json(&self) -> Json {
+        (**self).to_json()
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for Box<T> {
+    fn to_json(&self) -> Json {
+        (**self).to_json()
+    }
+}
+
+impl<T: FromJson> FromJson for Box<T> {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+        T::from_json(json).map(Box::new)
+    }
+}
+
+/// None is null
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Json {
+        self.as_ref().map_or(Json::Null, ToJson::to_json)
+    }
+}
+
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+        if json.is_null() { Ok(None) } else { T::from_json(json).map(Some) }
+    }
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn to_json(&self) -> Json {
+        Json::Array(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> Json {
+        self[..].to_json()
+    }
+}
+
+impl<T: FromJson> FromJson for Vec<T> {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+        let elements = json.as_array().ok_or_else(|| FromJsonError::expected("an array", json))?;
+        (0..elements.len()).map(|i| element(elements, i)).collect()
+    }
+}
+
+impl<T: ToJson> ToJson for BTreeMap<String, T> {
+    fn to_json(&self) -> Json {
+        self.iter().map(|(key, value)| (key.clone(), value.to_json())).collect()
+    }
+}
+
+impl<T: FromJson> FromJson for BTreeMap<String, T> {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+        let members = object(json)?;
+        members.keys().map(|key| Ok((key.clone(), field(members, key)?))).collect()
+    }
+}
+
+impl<T: ToJson, S> ToJson for HashMap<String, T, S> {
+    fn to_json(&self) -> Json {
+        self.iter().map(|(key, value)| (key.clone(), value.to_json())).collect()
+    }
+}
+
+impl<T: FromJson, S: BuildHasher + Default> FromJson for HashMap<String, T, S> {
+    fn from_json(json: &Json) -> Result<Self, FromJsonError> {
+        let members = object(json)?;
+        members.keys().map(|key| Ok((key.clone(), field(members, key)?))).collect()
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, ToJson, FromJson)]
+struct Reading<T> {
+    sensor: String,
+    #[json(rename = "value")]
+    celsius: T,
+    note: Option<String>,
+    tags: Vec<Tag>
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, ToJson, FromJson)]
+#[json(crate = "self")]
+enum Tag {
+    Indoor,
+    #[json(rename = "outdoor")]
+    Outdoor,
+    Floor(u8),
+    Near(String, f64),
+    Box { width: u32, height: u32 }
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, ToJson, FromJson)]
+struct Meters(f64);
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, ToJson, FromJson)]
+#[json(crate = "self")]
+struct Pair(i32, bool);
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, ToJson, FromJson)]
+struct Marker;
+
+#[test]
+fn test_standard_types() {
+    assert_eq!(true.to_json(), Json::Boolean(true));
+    assert_eq!("hi".to_json(), Json::from("hi"));
+    assert_eq!(u8::from_json(&Json::from(255)), Ok(255));
+    assert_eq!(u8::from_json(&Json::from(256)).unwrap_err().reason, "256 isn't a u8");
+    assert_eq!(i64::from_json(&Json::from(-2.5)).unwrap_err().reason, "-2.5 isn't a i64");
+    assert_eq!(i32::from_json(&Json::from("1")).unwrap_err().reason, "expected an integer, found a string");
+    assert_eq!(char::from_json(&Json::from("é")), Ok('é'));
+    assert!(char::from_json(&Json::from("ab")).is_err());
+    assert_eq!(Some(3).to_json(), Json::from(3));
+    assert_eq!(None::<i32>.to_json(), Json::Null);
+    assert_eq!(Option::<i32>::from_json(&Json::Null), Ok(None));
+
+    let nested = vec![vec![1u16], vec![], vec![2, 3]];
+    assert_eq!(nested.to_json().to_string(), "[[1],[],[2,3]]");
+    assert_eq!(Vec::<Vec<u16>>::from_json(&nested.to_json()), Ok(nested));
+    let error = Vec::<Vec<u16>>::from_json(&Json::parse("[[1], [2, true]]").unwrap()).unwrap_err();
+    assert_eq!(error.to_string(), "can't convert the JSON at \"/1/1\": expected an integer, found a boolean");
+
+    let mut map = HashMap::new();
+    map.insert("a/b".to_string(), Box::new(1.5));
+    assert_eq!(map.to_json().to_string(), r#"{"a/b":1.5}"#);
+    assert_eq!(HashMap::from_json(&map.to_json()), Ok(map));
+    let ordered: BTreeMap<String, bool> = BTreeMap::from_json(&Json::parse(r#"{"x": true}"#).unwrap()).unwrap();
+    assert!(ordered["x"]);
+}
+
+#[test]
+fn test_derives() {
+    let reading = Reading {
+        sensor: "attic".to_string(),
+        celsius: 21.5,
+        note: None,
+        tags: vec![Tag::Indoor, Tag::Outdoor, Tag::Floor(3), Tag::Near("vent".to_string(), 0.5),
+                   Tag::Box { width: 2, height: 1 }]
+    };
+    let json = reading.to_json();
+    assert_eq!(json.to_string(), concat!(r#"{"note":null,"sensor":"attic","tags":["Indoor","outdoor",{"Floor":3},"#,
+                                         r#"{"Near":["vent",0.5]},{"Box":{"height":1,"width":2}}],"value":21.5}"#));
+    assert_eq!(Reading::from_json(&json), Ok(reading));
+
+    // Missing Options are fine, other missing members aren't
+    let sparse = Json::parse(r#"{"sensor": "cellar", "value": 12, "tags": []}"#).unwrap();
+    assert_eq!(Reading::<i32>::from_json(&sparse).unwrap().celsius, 12);
+    let error = |json: &str| Reading::<u8>::from_json(&Json::parse(json).unwrap()).unwrap_err().to_string();
+    assert_eq!(error(r#"{"value": 1, "tags": []}"#), "can't convert the JSON at \"\": missing the member \"sensor\"");
+    assert_eq!(error(r#"{"sensor": "x", "value": 1, "tags": ["Outdoor"]}"#),
+               "can't convert the JSON at \"/tags/0\": \"Outdoor\" isn't a variant of Tag");
+    assert_eq!(error(r#"{"sensor": "x", "value": 1, "tags": [{"Box": {"width": -1, "height": 1}}]}"#),
+               "can't convert the JSON at \"/tags/0/Box/width\": -1 isn't a u32");
+    assert_eq!(error(r#"{"sensor": "x", "value": 1, "tags": [{"Near": ["x"]}]}"#),
+               "can't convert the JSON at \"/tags/0/Near\": expected 2 elements, found 1");
+    assert_eq!(error(r#"{"sensor": "x", "value": 1, "tags": [{"Floor": 1, "Indoor": null}]}"#),
+               "can't convert the JSON at \"/tags/0\": expected a variant name or an object with one member, \
+                found an object");
+
+    assert_eq!(Meters(2.5).to_json(), Json::from(2.5));
+    assert_eq!(Meters::from_json(&Json::from(2.5)), Ok(Meters(2.5)));
+    assert_eq!(Pair(-1, false).to_json().to_string(), "[-1,false]");
+    assert_eq!(Pair::from_json(&Json::parse("[-1, false]").unwrap()), Ok(Pair(-1, false)));
+    assert_eq!(Marker.to_json(), Json::Null);
+    assert_eq!(Marker::from_json(&Json::Null), Ok(Marker));
+}
@@ -0,0 +1,617 @@
+#![allow(dead_code)]
+
+// Addressing and changing parts of a Json value: JSON Pointer (RFC 6901)
+// paths like "/servers/0/port", JSON Patch (RFC 6902) lists of operations
+// on those paths, and JSON Merge Patch (RFC 7396), where the patch is a
+// sparse copy of the document with nulls for what to delete.
+//
+// A patch either applies completely or not at all. It's applied to a copy,
+// which only replaces the original once every operation has worked, so a
+// failed "test" halfway through leaves the document as it was.
+
+use crate::json::Json;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Why a pointer or patch couldn't be read or applied. `index` is the
+/// position of the offending operation in the patch.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchError {
+    /// Pointers are "" or start with '/', and '~' is only followed by 0 or 1
+    InvalidPointer(String),
+    /// A patch has to be an array of operation objects
+    NotAnArray,
+    InvalidOperation { index: usize, reason: &'static str },
+    /// Nothing is at the path, or its parent isn't there to add to
+    PathNotFound { index: usize, path: String },
+    /// A "test" operation found a different value
+    TestFailed { index: usize, path: String },
+    /// A value can't be moved inside itself
+    MoveIntoChild { index: usize, from: String, path: String }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatchError::InvalidPointer(ref pointer) => write!(dest, "{:?} isn't a JSON pointer", pointer),
+            PatchError::NotAnArray => write!(dest, "a JSON patch has to be an array"),
+            PatchError::InvalidOperation { index, reason } =>
+                write!(dest, "operation {} is invalid: {}", index, reason),
+            PatchError::PathNotFound { index, ref path } =>
+                write!(dest, "operation {}: nothing at {:?}", index, path),
+            PatchError::TestFailed { index, ref path } =>
+                write!(dest, "operation {}: test of {:?} failed", index, path),
+            PatchError::MoveIntoChild { index, ref from, ref path } =>
+                write!(dest, "operation {}: can't move {:?} into its own child {:?}", index, from, path)
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+// JSON Pointer
+
+/// A path to a value inside a document, as a list of object keys and
+/// array indices
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct JsonPointer {
+    tokens: Vec<String>
+}
+
+/// The array index a token names, if it's a number without leading zeros
+fn array_index(token: &str) -> Option<usize> {
+    if token.is_empty() || !token.bytes().all(|c| c.is_ascii_digit()) || (token.len() > 1 && token.starts_with('0')) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+impl JsonPointer {
+    /// The pointer to the whole document, written ""
+    pub fn root() -> JsonPointer {
+        JsonPointer::default()
+    }
+
+    /// Read a pointer like "/a~1b/0", where "~1" stands for '/' and "~0" for '~'
+    pub fn parse(text: &str) -> Result<JsonPointer, PatchError> {
+        if text.is_empty() {
+            return Ok(JsonPointer::root());
+        }
+        let invalid = || PatchError::InvalidPointer(text.to_string());
+        let rest = text.strip_prefix('/').ok_or_else(invalid)?;
+        let mut tokens = Vec::new();
+        for raw in rest.split('/') {
+            let mut token = String::new();
+            let mut chars = raw.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '~' => match chars.next() {
+                        Some('0') => token.push('~'),
+                        Some('1') => token.push('/'),
+                        _ => return Err(invalid())
+                    },
+                    c => token.push(c)
+                }
+            }
+            tokens.push(token);
+        }
+        Ok(JsonPointer { tokens })
+    }
+
+    /// The unescaped keys and indices, outermost first
+    pub fn tokens(&self) -> &[String] {
+        &self.tokens
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// This pointer with one more token on the end
+    pub fn child(&self, token: &str) -> JsonPointer {
+        let mut tokens = self.tokens.clone();
+        tokens.push(token.to_string());
+        JsonPointer { tokens }
+    }
+
+    /// This pointer with one more token at the start, for building a
+    /// path from the inside out
+    pub fn under(&self, token: &str) -> JsonPointer {
+        let mut tokens = vec![token.to_string()];
+        tokens.extend(self.tokens.iter().cloned());
+        JsonPointer { tokens }
+    }
+
+    /// The pointer to the containing value, and the last token, unless
+    /// this is the root
+    pub fn split_last(&self) -> Option<(JsonPointer, &str)> {
+        let (last, parent) = self.tokens.split_last()?;
+        Some((JsonPointer { tokens: parent.to_vec() }, last))
+    }
+
+    /// True if `other` points somewhere inside what this points to
+    pub fn is_ancestor_of(&self, other: &JsonPointer) -> bool {
+        other.tokens.len() > self.tokens.len() && other.tokens.starts_with(&self.tokens)
+    }
+
+    /// The value this points to in `document`
+    pub fn resolve<'j>(&self, document: &'j Json) -> Option<&'j Json> {
+        self.tokens.iter().try_fold(document, |value, token| match *value {
+            Json::Object(ref members) => members.get(token),
+            Json::Array(ref elements) => elements.get(array_index(token)?),
+            _ => None
+        })
+    }
+
+    pub fn resolve_mut<'j>(&self, document: &'j mut Json) -> Option<&'j mut Json> {
+        self.tokens.iter().try_fold(document, |value, token| match *value {
+            Json::Object(ref mut members) => members.get_mut(token),
+            Json::Array(ref mut elements) => elements.get_mut(array_index(token)?),
+            _ => None
+        })
+    }
+}
+
+impl fmt::Display for JsonPointer {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        for token in &self.tokens {
+            write!(dest, "/{}", token.replace('~', "~0").replace('/', "~1"))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for JsonPointer {
+    type Err = PatchError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        JsonPointer::parse(text)
+    }
+}
+
+impl Json {
+    /// The value at a JSON pointer like "/servers/0/port", or `None` if
+    /// there's nothing there or the pointer isn't valid
+    pub fn pointer(&self, pointer: &str) -> Option<&Json> {
+        JsonPointer::parse(pointer).ok()?.resolve(self)
+    }
+
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut Json> {
+        JsonPointer::parse(pointer).ok()?.resolve_mut(self)
+    }
+}
+
+// JSON Patch
+
+/// One step of a JSON patch
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchOperation {
+    /// Insert into an array, or set an object member, or replace the root
+    Add { path: JsonPointer, value: Json },
+    Remove { path: JsonPointer },
+    /// Like remove then add, but the value must already exist
+    Replace { path: JsonPointer, value: Json },
+    Move { from: JsonPointer, path: JsonPointer },
+    Copy { from: JsonPointer, path: JsonPointer },
+    /// Fail the whole patch unless the value at `path` equals `value`
+    Test { path: JsonPointer, value: Json }
+}
+
+impl PatchOperation {
+    fn from_json(index: usize, json: &Json) -> Result<PatchOperation, PatchError> {
+        let invalid = |reason| PatchError::InvalidOperation { index, reason };
+        if json.as_object().is_none() {
+            return Err(invalid("operations have to be objects"));
+        }
+        let pointer = |name, missing| match json.get(name) {
+            Some(Json::String(text)) => JsonPointer::parse(text),
+            Some(_) => Err(invalid("pointers have to be strings")),
+            None => Err(invalid(missing))
+        };
+        let path = pointer("path", "missing \"path\"")?;
+        let value = || json.get("value").cloned().ok_or_else(|| invalid("missing \"value\""));
+        let from = || pointer("from", "missing \"from\"");
+
+        Ok(match json.get("op").and_then(Json::as_str) {
+            Some("add") => PatchOperation::Add { path, value: value()? },
+            Some("remove") => PatchOperation::Remove { path },
+            Some("replace") => PatchOperation::Replace { path, value: value()? },
+            Some("move") => PatchOperation::Move { from: from()?, path },
+            Some("copy") => PatchOperation::Copy { from: from()?, path },
+            Some("test") => PatchOperation::Test { path, value: value()? },
+            Some(_) => return Err(invalid("unknown \"op\"")),
+            None => return Err(invalid("missing \"op\""))
+        })
+    }
+
+    /// The operation as a JSON object, like {"op": "remove", "path": "/a"}
+    pub fn to_json(&self) -> Json {
+        let (op, path, from, value) = match *self {
+            PatchOperation::Add { ref path, ref value } => ("add", path, None, Some(value)),
+            PatchOperation::Remove { ref path } => ("remove", path, None, None),
+            PatchOperation::Replace { ref path, ref value } => ("replace", path, None, Some(value)),
+            PatchOperation::Move { ref from, ref path } => ("move", path, Some(from), None),
+            PatchOperation::Copy { ref from, ref path } => ("copy", path, Some(from), None),
+            PatchOperation::Test { ref path, ref value } => ("test", path, None, Some(value))
+        };
+        let mut members = vec![("op", Json::from(op)), ("path", Json::from(path.to_string()))];
+        members.extend(from.map(|from| ("from", Json::from(from.to_string()))));
+        members.extend(value.map(|value| ("value", value.clone())));
+        members.into_iter().collect()
+    }
+
+    fn apply(&self, index: usize, document: &mut Json) -> Result<(), PatchError> {
+        let not_found = |path: &JsonPointer| PatchError::PathNotFound { index, path: path.to_string() };
+        match *self {
+            PatchOperation::Add { ref path, ref value } =>
+                add(document, path, value.clone()).ok_or_else(|| not_found(path)),
+            PatchOperation::Remove { ref path } => {
+                if path.is_root() {
+                    return Err(PatchError::InvalidOperation { index, reason: "can't remove the whole document" });
+                }
+                remove(document, path).map(|_| ()).ok_or_else(|| not_found(path))
+            }
+            PatchOperation::Replace { ref path, ref value } => {
+                *path.resolve_mut(document).ok_or_else(|| not_found(path))? = value.clone();
+                Ok(())
+            }
+            PatchOperation::Move { ref from, ref path } => {
+                if from == path {
+                    return from.resolve(document).map(|_| ()).ok_or_else(|| not_found(from));
+                }
+                if from.is_ancestor_of(path) {
+                    return Err(PatchError::MoveIntoChild { index, from: from.to_string(), path: path.to_string() });
+                }
+                if from.is_root() {
+                    return Err(PatchError::InvalidOperation { index, reason: "can't move the whole document" });
+                }
+                let value = remove(document, from).ok_or_else(|| not_found(from))?;
+                add(document, path, value).ok_or_else(|| not_found(path))
+            }
+            PatchOperation::Copy { ref from, ref path } => {
+                let value = from.resolve(document).ok_or_else(|| not_found(from))?.clone();
+                add(document, path, value).ok_or_else(|| not_found(path))
+            }
+            PatchOperation::Test { ref path, ref value } => match path.resolve(document) {
+                Some(found) if found == value => Ok(()),
+                Some(_) => Err(PatchError::TestFailed { index, path: path.to_string() }),
+                None => Err(not_found(path))
+            }
+        }
+    }
+}
+
+/// Put `value` at `path`, inserting into arrays and replacing object members
+fn add(document: &mut Json, path: &JsonPointer, value: Json) -> Option<()> {
+    let (parent, last) = match path.split_last() {
+        Some(split) => split,
+        None => {
+            *document = value;
+            return Some(());
+        }
+    };
+    match *parent.resolve_mut(document)? {
+        Json::Object(ref mut members) => {
+            members.insert(last.to_string(), value);
+        }
+        Json::Array(ref mut elements) => {
+            // "-" means after the last element
+            let at = if last == "-" { elements.len() } else { array_index(last)? };
+            if at > elements.len() {
+                return None;
+            }
+            elements.insert(at, value);
+        }
+        _ => return None
+    }
+    Some(())
+}
+
+/// Take the value at `path` out of the document
+fn remove(document: &mut Json, path: &JsonPointer) -> Option<Json> {
+    let (parent, last) = path.split_last()?;
+    match *parent.resolve_mut(document)? {
+        Json::Object(ref mut members) => members.remove(last),
+        Json::Array(ref mut elements) => {
+            let at = array_index(last).filter(|&at| at < elements.len())?;
+            Some(elements.remove(at))
+        }
+        _ => None
+    }
+}
+
+/// A JSON patch: operations applied in order
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Patch {
+    pub operations: Vec<PatchOperation>
+}
+
+impl Patch {
+    /// Read a patch from its JSON form, an array of operation objects
+    pub fn from_json(json: &Json) -> Result<Patch, PatchError> {
+        let elements = json.as_array().ok_or(PatchError::NotAnArray)?;
+        let operations = elements.iter().enumerate()
+            .map(|(index, element)| PatchOperation::from_json(index, element))
+            .collect::<Result<_, _>>()?;
+        Ok(Patch { operations })
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::Array(self.operations.iter().map(PatchOperation::to_json).collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Apply every operation to `document`, or if any fails, none of them
+    pub fn apply(&self, document: &mut Json) -> Result<(), PatchError> {
+        let mut patched = document.clone();
+        for (index, operation) in self.operations.iter().enumerate() {
+            operation.apply(index, &mut patched)?;
+        }
+        *document = patched;
+        Ok(())
+    }
+
+    /// A patch that turns `from` into `to`. Objects are compared member by
+    /// member and arrays element by element, so an insertion near the
+    /// start of an array shows up as replacements after it rather than a
+    /// single add.
+    pub fn diff(from: &Json, to: &Json) -> Patch {
+        let mut patch = Patch::default();
+        diff_into(&mut patch.operations, &JsonPointer::root(), from, to);
+        patch
+    }
+}
+
+fn diff_into(operations: &mut Vec<PatchOperation>, path: &JsonPointer, from: &Json, to: &Json) {
+    if from == to {
+        return;
+    }
+    match (from, to) {
+        (Json::Object(old), Json::Object(new)) => {
+            for key in old.keys().filter(|key| !new.contains_key(*key)) {
+                operations.push(PatchOperation::Remove { path: path.child(key) });
+            }
+            for (key, value) in new.iter() {
+                match old.get(key) {
+                    Some(old_value) => diff_into(operations, &path.child(key), old_value, value),
+                    None => operations.push(PatchOperation::Add { path: path.child(key), value: value.clone() })
+                }
+            }
+        }
+        (Json::Array(old), Json::Array(new)) => {
+            let shared = old.len().min(new.len());
+            for i in 0..shared {
+                diff_into(operations, &path.child(&i.to_string()), &old[i], &new[i]);
+            }
+            // Remove from the end so the indices stay put
+            for i in (shared..old.len()).rev() {
+                operations.push(PatchOperation::Remove { path: path.child(&i.to_string()) });
+            }
+            for (i, value) in new.iter().enumerate().skip(shared) {
+                operations.push(PatchOperation::Add { path: path.child(&i.to_string()), value: value.clone() });
+            }
+        }
+        _ => operations.push(PatchOperation::Replace { path: path.clone(), value: to.clone() })
+    }
+}
+
+// JSON Merge Patch
+
+/// Apply a merge patch: object members in `patch` are merged in
+/// recursively, nulls delete members, and anything else replaces the
+/// target outright. This can't fail.
+pub fn merge_patch(target: &mut Json, patch: &Json) {
+    let members = match *patch {
+        Json::Object(ref members) => members,
+        _ => {
+            *target = patch.clone();
+            return;
+        }
+    };
+    if target.as_object().is_none() {
+        *target = Json::Object(Box::default());
+    }
+    if let Json::Object(ref mut target_members) = *target {
+        for (key, value) in members.iter() {
+            if value.is_null() {
+                target_members.remove(key);
+            } else {
+                merge_patch(target_members.entry(key.clone()).or_insert(Json::Null), value);
+            }
+        }
+    }
+}
+
+/// A merge patch that turns `from` into `to`. Merge patches can't set a
+/// member to null or change part of an array, so those get replaced whole,
+/// and a null member in `to` comes out missing.
+pub fn merge_diff(from: &Json, to: &Json) -> Json {
+    match (from, to) {
+        (Json::Object(old), Json::Object(new)) => {
+            let mut members = BTreeMap::new();
+            for key in old.keys().filter(|key| !new.contains_key(*key)) {
+                members.insert(key.clone(), Json::Null);
+            }
+            for (key, value) in new.iter() {
+                match old.get(key) {
+                    Some(old_value) if old_value == value => {}
+                    Some(old_value) => {
+                        members.insert(key.clone(), merge_diff(old_value, value));
+                    }
+                    None => {
+                        members.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            Json::Object(Box::new(members))
+        }
+        _ => to.clone()
+    }
+}
+
+#[cfg(test)]
+fn json(text: &str) -> Json {
+    Json::parse(text).unwrap()
+}
+
+#[test]
+fn test_pointers() {
+    // The examples from RFC 6901 section 5
+    let document = json(r#"{"foo": ["bar", "baz"], "": 0, "a/b": 1, "c%d": 2, "e^f": 3,
+                             "g|h": 4, "i\\j": 5, "k\"l": 6, " ": 7, "m~n": 8}"#);
+    assert_eq!(document.pointer(""), Some(&document));
+    assert_eq!(document.pointer("/foo"), Some(&json(r#"["bar", "baz"]"#)));
+    let expected = [("/foo/0", json("\"bar\"")), ("/", json("0")), ("/a~1b", json("1")),
+                    ("/c%d", json("2")), ("/e^f", json("3")), ("/g|h", json("4")),
+                    ("/i\\j", json("5")), ("/k\"l", json("6")), ("/ ", json("7")), ("/m~0n", json("8"))];
+    for (pointer, value) in &expected {
+        assert_eq!(document.pointer(pointer), Some(value), "{}", pointer);
+        assert_eq!(JsonPointer::parse(pointer).unwrap().to_string(), *pointer);
+    }
+
+    assert_eq!(document.pointer("/foo/2"), None);
+    assert_eq!(document.pointer("/foo/01"), None);
+    assert_eq!(document.pointer("/foo/-"), None);
+    assert_eq!(document.pointer("/foo/0/deeper"), None);
+    assert_eq!(document.pointer("foo"), None);
+    assert_eq!(JsonPointer::parse("/a~2"), Err(PatchError::InvalidPointer("/a~2".to_string())));
+    assert_eq!(JsonPointer::parse("/~01").unwrap().tokens(), ["~1"]);
+
+    let mut document = document;
+    *document.pointer_mut("/foo/1").unwrap() = Json::from("qux");
+    assert_eq!(document["foo"][1], Json::from("qux"));
+
+    let pointer: JsonPointer = "/a/b".parse().unwrap();
+    assert!(JsonPointer::root().is_ancestor_of(&pointer));
+    assert!(!pointer.is_ancestor_of(&pointer));
+    assert_eq!(pointer.split_last(), Some(("/a".parse().unwrap(), "b")));
+}
+
+#[cfg(test)]
+fn apply(document: &str, patch: &str) -> Result<Json, PatchError> {
+    let mut document = json(document);
+    Patch::from_json(&json(patch))?.apply(&mut document)?;
+    Ok(document)
+}
+
+#[test]
+fn test_apply_patches() {
+    // Examples from RFC 6902 appendix A
+    assert_eq!(apply(r#"{"foo": "bar"}"#, r#"[{"op": "add", "path": "/baz", "value": "qux"}]"#),
+               Ok(json(r#"{"baz": "qux", "foo": "bar"}"#)));
+    assert_eq!(apply(r#"{"foo": ["bar", "baz"]}"#, r#"[{"op": "add", "path": "/foo/1", "value": "qux"}]"#),
+               Ok(json(r#"{"foo": ["bar", "qux", "baz"]}"#)));
+    assert_eq!(apply(r#"{"foo": ["bar", "qux", "baz"]}"#, r#"[{"op": "remove", "path": "/foo/1"}]"#),
+               Ok(json(r#"{"foo": ["bar", "baz"]}"#)));
+    assert_eq!(apply(r#"{"baz": "qux", "foo": "bar"}"#, r#"[{"op": "replace", "path": "/baz", "value": "boo"}]"#),
+               Ok(json(r#"{"baz": "boo", "foo": "bar"}"#)));
+    assert_eq!(apply(r#"{"foo": {"bar": "baz", "waldo": "fred"}, "qux": {"corge": "grault"}}"#,
+                     r#"[{"op": "move", "from": "/foo/waldo", "path": "/qux/thud"}]"#),
+               Ok(json(r#"{"foo": {"bar": "baz"}, "qux": {"corge": "grault", "thud": "fred"}}"#)));
+    assert_eq!(apply(r#"{"foo": ["all", "grass", "cows", "eat"]}"#,
+                     r#"[{"op": "move", "from": "/foo/1", "path": "/foo/3"}]"#),
+               Ok(json(r#"{"foo": ["all", "cows", "eat", "grass"]}"#)));
+    assert_eq!(apply(r#"{"foo": ["bar"]}"#, r#"[{"op": "add", "path": "/foo/-", "value": ["abc", "def"]}]"#),
+               Ok(json(r#"{"foo": ["bar", ["abc", "def"]]}"#)));
+    assert_eq!(apply(r#"{"foo": null}"#, r#"[{"op": "add", "path": "/foo", "value": 1}]"#),
+               Ok(json(r#"{"foo": 1}"#)));
+    assert_eq!(apply(r#"{"/": 9, "~1": 10}"#, r#"[{"op": "test", "path": "/~01", "value": 10}]"#),
+               Ok(json(r#"{"/": 9, "~1": 10}"#)));
+    assert_eq!(apply(r#"{"baz": "qux", "foo": ["a", 2, "c"]}"#,
+                     r#"[{"op": "test", "path": "/baz", "value": "qux"},
+                         {"op": "test", "path": "/foo/1", "value": 2.0}]"#),
+               Ok(json(r#"{"baz": "qux", "foo": ["a", 2, "c"]}"#)));
+    assert_eq!(apply(r#"{"a": 1}"#, r#"[{"op": "copy", "from": "/a", "path": "/b"},
+                                       {"op": "replace", "path": "", "value": [1]}]"#),
+               Ok(json("[1]")));
+
+    assert_eq!(apply(r#"{"baz": "qux"}"#, r#"[{"op": "test", "path": "/baz", "value": "bar"}]"#),
+               Err(PatchError::TestFailed { index: 0, path: "/baz".to_string() }));
+    assert_eq!(apply(r#"{"foo": "bar"}"#, r#"[{"op": "add", "path": "/baz/bat", "value": "qux"}]"#),
+               Err(PatchError::PathNotFound { index: 0, path: "/baz/bat".to_string() }));
+    assert_eq!(apply(r#"{"a": [1]}"#, r#"[{"op": "add", "path": "/a/2", "value": 3}]"#),
+               Err(PatchError::PathNotFound { index: 0, path: "/a/2".to_string() }));
+    assert_eq!(apply(r#"{"a": {"b": 1}}"#, r#"[{"op": "move", "from": "/a", "path": "/a/b/c"}]"#),
+               Err(PatchError::MoveIntoChild { index: 0, from: "/a".to_string(), path: "/a/b/c".to_string() }));
+    assert_eq!(apply("{}", r#"[{"op": "add", "path": "/a"}]"#),
+               Err(PatchError::InvalidOperation { index: 0, reason: "missing \"value\"" }));
+    assert_eq!(apply("{}", r#"[{"op": "shuffle", "path": "/a"}]"#),
+               Err(PatchError::InvalidOperation { index: 0, reason: "unknown \"op\"" }));
+    assert_eq!(apply("{}", r#"{"op": "remove", "path": "/a"}"#), Err(PatchError::NotAnArray));
+
+    // Nothing changes unless everything works
+    let mut document = json(r#"{"counter": 1, "log": []}"#);
+    let patch = Patch::from_json(&json(r#"[{"op": "replace", "path": "/counter", "value": 2},
+                                           {"op": "add", "path": "/log/-", "value": "bumped"},
+                                           {"op": "test", "path": "/counter", "value": 1}]"#)).unwrap();
+    assert_eq!(patch.apply(&mut document).unwrap_err().to_string(), "operation 2: test of \"/counter\" failed");
+    assert_eq!(document, json(r#"{"counter": 1, "log": []}"#));
+    assert_eq!(Patch::from_json(&patch.to_json()), Ok(patch));
+}
+
+#[test]
+fn test_diff() {
+    let pairs = [
+        (r#"{"a": 1, "b": [1, 2, 3], "c": {"d": true}}"#, r#"{"a": 1, "b": [1, 5], "c": {"e": null}, "f": "new"}"#),
+        ("[1, 2]", "[1, 2, [3], {}]"),
+        (r#"{"a": {"b": 1}}"#, "[]"),
+        ("null", "null"),
+        (r#"{"a/b": {"~": 1}}"#, r#"{"a/b": {"~": 2}}"#)
+    ];
+    for &(from, to) in &pairs {
+        let (from, to) = (json(from), json(to));
+        let patch = Patch::diff(&from, &to);
+        let mut patched = from.clone();
+        patch.apply(&mut patched).unwrap();
+        assert_eq!(patched, to);
+    }
+
+    let patch = Patch::diff(&json(r#"{"a": 1, "b": [1, 2, 3]}"#), &json(r#"{"b": [1, 5], "c": 3}"#));
+    assert_eq!(patch.to_json(), json(r#"[{"op": "remove", "path": "/a"},
+                                         {"op": "replace", "path": "/b/1", "value": 5},
+                                         {"op": "remove", "path": "/b/2"},
+                                         {"op": "add", "path": "/c", "value": 3}]"#));
+    assert!(Patch::diff(&json("[1]"), &json("[1.0]")).is_empty());
+}
+
+#[test]
+fn test_merge_patch() {
+    // The examples from RFC 7396 appendix A
+    let cases = [
+        (r#"{"a":"b"}"#, r#"{"a":"c"}"#, r#"{"a":"c"}"#),
+        (r#"{"a":"b"}"#, r#"{"b":"c"}"#, r#"{"a":"b","b":"c"}"#),
+        (r#"{"a":"b"}"#, r#"{"a":null}"#, r#"{}"#),
+        (r#"{"a":"b","b":"c"}"#, r#"{"a":null}"#, r#"{"b":"c"}"#),
+        (r#"{"a":["b"]}"#, r#"{"a":"c"}"#, r#"{"a":"c"}"#),
+        (r#"{"a":"c"}"#, r#"{"a":["b"]}"#, r#"{"a":["b"]}"#),
+        (r#"{"a":{"b":"c"}}"#, r#"{"a":{"b":"d","c":null}}"#, r#"{"a":{"b":"d"}}"#),
+        (r#"{"a":[{"b":"c"}]}"#, r#"{"a":[1]}"#, r#"{"a":[1]}"#),
+        (r#"["a","b"]"#, r#"["c","d"]"#, r#"["c","d"]"#),
+        (r#"{"a":"b"}"#, r#"["c"]"#, r#"["c"]"#),
+        (r#"{"a":"foo"}"#, "null", "null"),
+        (r#"{"a":"foo"}"#, r#""bar""#, r#""bar""#),
+        (r#"{"e":null}"#, r#"{"a":1}"#, r#"{"e":null,"a":1}"#),
+        (r#"[1,2]"#, r#"{"a":"b","c":null}"#, r#"{"a":"b"}"#),
+        (r#"{}"#, r#"{"a":{"bb":{"ccc":null}}}"#, r#"{"a":{"bb":{}}}"#)
+    ];
+    for &(target, patch, result) in &cases {
+        let mut merged = json(target);
+        merge_patch(&mut merged, &json(patch));
+        assert_eq!(merged, json(result), "{} + {}", target, patch);
+    }
+
+    let from = json(r#"{"title": "Goodbye!", "author": {"givenName": "John", "familyName": "Doe"},
+                        "tags": ["example", "sample"], "content": "This will be unchanged"}"#);
+    let to = json(r#"{"title": "Hello!", "author": {"givenName": "John"}, "tags": ["example"],
+                      "content": "This will be unchanged", "phoneNumber": "+01-123-456-7890"}"#);
+    let patch = merge_diff(&from, &to);
+    assert_eq!(patch, json(r#"{"title": "Hello!", "phoneNumber": "+01-123-456-7890",
+                               "author": {"familyName": null}, "tags": ["example"]}"#));
+    let mut merged = from;
+    merge_patch(&mut merged, &patch);
+    assert_eq!(merged, to);
+}
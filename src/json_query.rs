@@ -0,0 +1,739 @@
+#![allow(dead_code)]
+
+// Queries over Json values in a JSONPath-like language, for picking fields
+// out of payloads without a ladder of matches. A query is compiled once and
+// can then be run against any number of documents.
+//
+//   $.store.book[0].title          members and array indices
+//   $.store.book[-1]               counting from the end
+//   $.store.book[0:2], [::-1]      slices, like Python's
+//   $.store.book[0,2], ['a','b']   several selectors at once
+//   $.store.*, $.store.book[*]     every member or element
+//   $..author                      at any depth
+//   $..book[?(@.price < 10)]       filters: == != < <= > >=, && || !, and a
+//                                  bare path for "has this member"
+//   $..book[*].{title, cost: @.price}
+//                                  projections, making an object per match
+//
+// Inside filters and projections @ is the value being looked at and $ the
+// whole document. A path used as a value takes its first match; a missing
+// value equals only another missing value and is never less or greater
+// than anything. Numbers compare numerically and strings by code point.
+
+use crate::json::{hex_value, Json};
+use std::fmt;
+use std::str::FromStr;
+
+/// Where and why a query couldn't be compiled. `position` is a byte offset.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryError {
+    pub position: usize,
+    pub reason: &'static str
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(dest, "invalid query at position {}: {}", self.position, self.reason)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Selector {
+    Name(String),
+    Index(i64),
+    Slice(Option<i64>, Option<i64>, i64),
+    Wildcard,
+    Filter(Box<Expression>)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Child(Vec<Selector>),
+    Descendant(Vec<Selector>)
+}
+
+/// A path from the root ($) or from the current value (@)
+#[derive(Clone, Debug, PartialEq)]
+struct Path {
+    absolute: bool,
+    segments: Vec<Segment>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    Literal(Json),
+    Path(Path)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expression {
+    Or(Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Compare(Operand, Comparison, Operand),
+    Exists(Path)
+}
+
+/// A compiled query
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    source: String,
+    path: Path,
+    projection: Option<Vec<(String, Path)>>
+}
+
+// Compiling
+
+/// Filters can't nest deeper than this, counting each `?`, `!`, `(`, `&&`
+/// and `||`, since they're compiled and run recursively
+const MAX_NESTING: usize = 128;
+
+struct QueryParser<'t> {
+    text: &'t str,
+    position: usize,
+    depth: usize
+}
+
+fn is_name_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c >= 0x80
+}
+
+impl<'t> QueryParser<'t> {
+    fn error<T>(&self, reason: &'static str) -> Result<T, QueryError> {
+        Err(QueryError { position: self.position, reason })
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.position).cloned()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.text.as_bytes().get(self.position + offset).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    /// Consume `token` if it's next
+    fn eat(&mut self, token: &str) -> bool {
+        if self.text[self.position..].starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str, reason: &'static str) -> Result<(), QueryError> {
+        if self.eat(token) { Ok(()) } else { self.error(reason) }
+    }
+
+    fn name(&mut self) -> Result<String, QueryError> {
+        let start = self.position;
+        while self.peek().is_some_and(is_name_byte) {
+            self.position += 1;
+        }
+        if start == self.position {
+            return self.error("expected a name");
+        }
+        Ok(self.text[start..self.position].to_string())
+    }
+
+    /// A string in single or double quotes, with JSON's backslash escapes
+    fn quoted(&mut self) -> Result<String, QueryError> {
+        let quote = self.peek().unwrap_or(b'\'');
+        self.position += 1;
+        let mut result = String::new();
+        loop {
+            let rest = &self.text[self.position..];
+            let c = match rest.chars().next() {
+                Some(c) => c,
+                None => return self.error("unterminated string")
+            };
+            self.position += c.len_utf8();
+            match c {
+                c if c as u32 == u32::from(quote) => return Ok(result),
+                '\\' => {
+                    let escaped = match self.peek() {
+                        Some(b'u') => {
+                            let digits = self.text.as_bytes().get(self.position + 1..self.position + 5);
+                            let code = digits.and_then(|digits| {
+                                digits.iter().try_fold(0u32, |code, &c| Some(code * 16 + u32::from(hex_value(c)?)))
+                            });
+                            match code.and_then(std::char::from_u32) {
+                                Some(c) => {
+                                    self.position += 4;
+                                    c
+                                }
+                                None => return self.error("expected four hex digits for a character")
+                            }
+                        }
+                        Some(b'n') => '\n',
+                        Some(b't') => '\t',
+                        Some(b'r') => '\r',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(c @ b'\\') | Some(c @ b'/') | Some(c @ b'\'') | Some(c @ b'"') => char::from(c),
+                        _ => return self.error("invalid escape")
+                    };
+                    self.position += 1;
+                    result.push(escaped);
+                }
+                c => result.push(c)
+            }
+        }
+    }
+
+    fn integer(&mut self) -> Result<i64, QueryError> {
+        let start = self.position;
+        self.eat("-");
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        match self.text[start..self.position].parse() {
+            Ok(n) => Ok(n),
+            Err(_) if self.position - start > 1 => {
+                self.position = start;
+                self.error("number out of range")
+            }
+            Err(_) => {
+                self.position = start;
+                self.error("expected an integer")
+            }
+        }
+    }
+
+    /// The segments after a $ or @
+    fn path(&mut self, absolute: bool) -> Result<Path, QueryError> {
+        let mut segments = Vec::new();
+        loop {
+            match self.peek() {
+                Some(b'.') if self.peek_at(1) == Some(b'.') => {
+                    self.position += 2;
+                    let selectors = match self.peek() {
+                        Some(b'[') => self.bracket()?,
+                        Some(b'*') => {
+                            self.position += 1;
+                            vec![Selector::Wildcard]
+                        }
+                        _ => vec![Selector::Name(self.name()?)]
+                    };
+                    segments.push(Segment::Descendant(selectors));
+                }
+                // A projection, which only the top level handles
+                Some(b'.') if self.peek_at(1) == Some(b'{') => break,
+                Some(b'.') => {
+                    self.position += 1;
+                    if self.eat("*") {
+                        segments.push(Segment::Child(vec![Selector::Wildcard]));
+                    } else {
+                        segments.push(Segment::Child(vec![Selector::Name(self.name()?)]));
+                    }
+                }
+                Some(b'[') => {
+                    let selectors = self.bracket()?;
+                    segments.push(Segment::Child(selectors));
+                }
+                _ => break
+            }
+        }
+        Ok(Path { absolute, segments })
+    }
+
+    /// Selectors between [ and ], separated by commas
+    fn bracket(&mut self) -> Result<Vec<Selector>, QueryError> {
+        self.position += 1;
+        let mut selectors = Vec::new();
+        loop {
+            self.skip_whitespace();
+            selectors.push(self.selector()?);
+            self.skip_whitespace();
+            if self.eat("]") {
+                return Ok(selectors);
+            }
+            self.expect(",", "expected ',' or ']'")?;
+        }
+    }
+
+    fn selector(&mut self) -> Result<Selector, QueryError> {
+        match self.peek() {
+            Some(b'\'') | Some(b'"') => Ok(Selector::Name(self.quoted()?)),
+            Some(b'*') => {
+                self.position += 1;
+                Ok(Selector::Wildcard)
+            }
+            Some(b'?') => {
+                self.position += 1;
+                // A filter inside a filter costs an extra level, for the
+                // path it's in
+                let depth = self.depth;
+                self.nest()?;
+                let filter = self.or()?;
+                self.depth = depth;
+                Ok(Selector::Filter(Box::new(filter)))
+            }
+            Some(c) if c == b'-' || c == b':' || c.is_ascii_digit() => {
+                let start = if c == b':' { None } else { Some(self.integer()?) };
+                self.skip_whitespace();
+                if !self.eat(":") {
+                    return Ok(Selector::Index(start.unwrap_or(0)));
+                }
+                let bound = |parser: &mut Self| {
+                    parser.skip_whitespace();
+                    match parser.peek() {
+                        Some(c) if c == b'-' || c.is_ascii_digit() => parser.integer().map(Some),
+                        _ => Ok(None)
+                    }
+                };
+                let end = bound(self)?;
+                self.skip_whitespace();
+                let step = if self.eat(":") { bound(self)?.unwrap_or(1) } else { 1 };
+                if step == 0 {
+                    return self.error("a slice's step can't be zero");
+                }
+                Ok(Selector::Slice(start, end, step))
+            }
+            _ => self.error("expected a selector")
+        }
+    }
+
+    /// Go one level deeper into a filter
+    fn nest(&mut self) -> Result<(), QueryError> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return self.error("filter nested too deeply");
+        }
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<Expression, QueryError> {
+        let depth = self.depth;
+        let mut left = self.and()?;
+        while self.eat("||") {
+            self.nest()?;
+            left = Expression::Or(Box::new(left), Box::new(self.and()?));
+        }
+        self.depth = depth;
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expression, QueryError> {
+        let depth = self.depth;
+        let mut left = self.unary()?;
+        while self.eat("&&") {
+            self.nest()?;
+            left = Expression::And(Box::new(left), Box::new(self.unary()?));
+        }
+        self.depth = depth;
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expression, QueryError> {
+        self.skip_whitespace();
+        let depth = self.depth;
+        let expression = if self.eat("!") {
+            self.nest()?;
+            Expression::Not(Box::new(self.unary()?))
+        } else if self.eat("(") {
+            self.nest()?;
+            let inner = self.or()?;
+            self.expect(")", "expected ')'")?;
+            inner
+        } else {
+            self.comparison()?
+        };
+        self.depth = depth;
+        self.skip_whitespace();
+        Ok(expression)
+    }
+
+    fn comparison(&mut self) -> Result<Expression, QueryError> {
+        let left = self.operand()?;
+        self.skip_whitespace();
+        let operators = [("==", Comparison::Equal), ("!=", Comparison::NotEqual),
+                         ("<=", Comparison::LessOrEqual), (">=", Comparison::GreaterOrEqual),
+                         ("<", Comparison::Less), (">", Comparison::Greater)];
+        match operators.iter().find(|&&(token, _)| self.eat(token)) {
+            Some(&(_, comparison)) => {
+                self.skip_whitespace();
+                Ok(Expression::Compare(left, comparison, self.operand()?))
+            }
+            None => match left {
+                Operand::Path(path) => Ok(Expression::Exists(path)),
+                Operand::Literal(_) => self.error("expected a comparison")
+            }
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, QueryError> {
+        match self.peek() {
+            Some(b'@') | Some(b'$') => {
+                let absolute = self.peek() == Some(b'$');
+                self.position += 1;
+                Ok(Operand::Path(self.path(absolute)?))
+            }
+            Some(b'\'') | Some(b'"') => Ok(Operand::Literal(Json::String(self.quoted()?))),
+            Some(c) if c == b'-' || c.is_ascii_digit() => {
+                let start = self.position;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || b"+-.eE".contains(&c)) {
+                    self.position += 1;
+                }
+                match self.text[start..self.position].parse() {
+                    Ok(n) => Ok(Operand::Literal(Json::Number(n))),
+                    Err(_) => {
+                        self.position = start;
+                        self.error("invalid number")
+                    }
+                }
+            }
+            _ => {
+                for &(word, ref value) in &[("true", Json::Boolean(true)), ("false", Json::Boolean(false)),
+                                            ("null", Json::Null)] {
+                    if self.eat(word) {
+                        return Ok(Operand::Literal(value.clone()));
+                    }
+                }
+                self.error("expected a value")
+            }
+        }
+    }
+
+    /// The fields of a projection, after the ".{"
+    fn projection(&mut self) -> Result<Vec<(String, Path)>, QueryError> {
+        let mut fields = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let key = match self.peek() {
+                Some(b'\'') | Some(b'"') => self.quoted()?,
+                _ => self.name()?
+            };
+            self.skip_whitespace();
+            let path = if self.eat(":") {
+                self.skip_whitespace();
+                match self.operand()? {
+                    Operand::Path(path) => path,
+                    Operand::Literal(_) => return self.error("expected a path")
+                }
+            } else {
+                // {title} is short for {title: @.title}
+                Path { absolute: false, segments: vec![Segment::Child(vec![Selector::Name(key.clone())])] }
+            };
+            fields.push((key, path));
+            self.skip_whitespace();
+            if self.eat("}") {
+                return Ok(fields);
+            }
+            self.expect(",", "expected ',' or '}'")?;
+        }
+    }
+}
+
+// Running
+
+fn children(value: &Json) -> Vec<&Json> {
+    match *value {
+        Json::Array(ref elements) => elements.iter().collect(),
+        Json::Object(ref members) => members.values().collect(),
+        _ => Vec::new()
+    }
+}
+
+/// `value` and everything inside it, parents before children
+fn descendants<'j>(value: &'j Json, out: &mut Vec<&'j Json>) {
+    out.push(value);
+    for child in children(value) {
+        descendants(child, out);
+    }
+}
+
+/// The indices a slice picks out of `len` elements, in order
+fn slice_indices(len: usize, start: Option<i64>, end: Option<i64>, step: i64) -> Vec<usize> {
+    let len = len as i64;
+    let normalize = |i: i64| if i >= 0 { i } else { len + i };
+    let mut indices = Vec::new();
+    if step > 0 {
+        let mut i = normalize(start.unwrap_or(0)).clamp(0, len);
+        let upper = normalize(end.unwrap_or(len)).clamp(0, len);
+        while i < upper {
+            indices.push(i as usize);
+            i = match i.checked_add(step) {
+                Some(next) => next,
+                None => break
+            };
+        }
+    } else {
+        let mut i = normalize(start.unwrap_or(len - 1)).clamp(-1, len - 1);
+        let lower = end.map_or(-1, |end| normalize(end).clamp(-1, len - 1));
+        while i > lower {
+            indices.push(i as usize);
+            i = match i.checked_add(step) {
+                Some(next) => next,
+                None => break
+            };
+        }
+    }
+    indices
+}
+
+impl Selector {
+    fn select<'j>(&self, value: &'j Json, root: &'j Json, out: &mut Vec<&'j Json>) {
+        match (self, value) {
+            (Selector::Name(name), Json::Object(members)) => out.extend(members.get(name)),
+            (&Selector::Index(index), Json::Array(elements)) => {
+                let index = if index < 0 { elements.len() as i64 + index } else { index };
+                if index >= 0 {
+                    out.extend(elements.get(index as usize));
+                }
+            }
+            (&Selector::Slice(start, end, step), Json::Array(elements)) =>
+                out.extend(slice_indices(elements.len(), start, end, step).into_iter().map(|i| &elements[i])),
+            (Selector::Wildcard, _) => out.extend(children(value)),
+            (Selector::Filter(filter), _) =>
+                out.extend(children(value).into_iter().filter(|child| filter.test(root, child))),
+            _ => {}
+        }
+    }
+}
+
+impl Path {
+    fn select<'j>(&self, root: &'j Json, current: &'j Json) -> Vec<&'j Json> {
+        let mut values = vec![if self.absolute { root } else { current }];
+        for segment in &self.segments {
+            let mut next = Vec::new();
+            match *segment {
+                Segment::Child(ref selectors) => for value in values {
+                    for selector in selectors {
+                        selector.select(value, root, &mut next);
+                    }
+                },
+                Segment::Descendant(ref selectors) => {
+                    let mut all = Vec::new();
+                    for value in values {
+                        descendants(value, &mut all);
+                    }
+                    for value in all {
+                        for selector in selectors {
+                            selector.select(value, root, &mut next);
+                        }
+                    }
+                }
+            }
+            values = next;
+        }
+        values
+    }
+}
+
+impl Operand {
+    fn value<'a>(&'a self, root: &'a Json, current: &'a Json) -> Option<&'a Json> {
+        match *self {
+            Operand::Literal(ref value) => Some(value),
+            Operand::Path(ref path) => path.select(root, current).into_iter().next()
+        }
+    }
+}
+
+impl Expression {
+    fn test(&self, root: &Json, current: &Json) -> bool {
+        match *self {
+            Expression::Or(ref left, ref right) => left.test(root, current) || right.test(root, current),
+            Expression::And(ref left, ref right) => left.test(root, current) && right.test(root, current),
+            Expression::Not(ref inner) => !inner.test(root, current),
+            Expression::Exists(ref path) => !path.select(root, current).is_empty(),
+            Expression::Compare(ref left, comparison, ref right) => {
+                let (left, right) = (left.value(root, current), right.value(root, current));
+                let ordering = match (left, right) {
+                    (Some(Json::Number(a)), Some(Json::Number(b))) => a.partial_cmp(b),
+                    (Some(Json::String(a)), Some(Json::String(b))) => Some(a.cmp(b)),
+                    _ => None
+                };
+                match comparison {
+                    Comparison::Equal => left == right,
+                    Comparison::NotEqual => left != right,
+                    Comparison::Less => ordering.is_some_and(|o| o.is_lt()),
+                    Comparison::LessOrEqual => ordering.is_some_and(|o| o.is_le()),
+                    Comparison::Greater => ordering.is_some_and(|o| o.is_gt()),
+                    Comparison::GreaterOrEqual => ordering.is_some_and(|o| o.is_ge())
+                }
+            }
+        }
+    }
+}
+
+impl Query {
+    /// Compile a query; it has to start with $
+    pub fn compile(text: &str) -> Result<Query, QueryError> {
+        let mut parser = QueryParser { text, position: 0, depth: 0 };
+        parser.expect("$", "a query starts with '$'")?;
+        let path = parser.path(true)?;
+        let projection = if parser.eat(".{") { Some(parser.projection()?) } else { None };
+        if parser.position < text.len() {
+            return parser.error("unexpected text after the query");
+        }
+        Ok(Query { source: text.to_string(), path, projection })
+    }
+
+    /// The values the path picks out of `document`, in document order.
+    /// This ignores any projection, which needs to build new values.
+    pub fn select<'j>(&self, document: &'j Json) -> Vec<&'j Json> {
+        self.path.select(document, document)
+    }
+
+    /// The results of the query, with projections applied. A projected
+    /// field is the first value its path finds, or null if there isn't one.
+    pub fn run(&self, document: &Json) -> Vec<Json> {
+        let selected = self.select(document);
+        match self.projection {
+            None => selected.into_iter().cloned().collect(),
+            Some(ref fields) => selected.into_iter().map(|value| {
+                fields.iter().map(|(key, path)| {
+                    let field = path.select(document, value).into_iter().next();
+                    (key.clone(), field.cloned().unwrap_or(Json::Null))
+                }).collect()
+            }).collect()
+        }
+    }
+
+    /// The first result, if there is one
+    pub fn first(&self, document: &Json) -> Option<Json> {
+        self.run(document).into_iter().next()
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        dest.write_str(&self.source)
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Query::compile(text)
+    }
+}
+
+#[cfg(test)]
+fn store() -> Json {
+    Json::parse(r#"{ "store": {
+        "book": [
+          { "category": "reference", "author": "Nigel Rees", "title": "Sayings of the Century", "price": 8.95 },
+          { "category": "fiction", "author": "Evelyn Waugh", "title": "Sword of Honour", "price": 12.99 },
+          { "category": "fiction", "author": "Herman Melville", "title": "Moby Dick", "isbn": "0-553-21311-3",
+            "price": 8.99 },
+          { "category": "fiction", "author": "J. R. R. Tolkien", "title": "The Lord of the Rings",
+            "isbn": "0-395-19395-8", "price": 22.99 }
+        ],
+        "bicycle": { "color": "red", "price": 399 }
+      } }"#).unwrap()
+}
+
+#[cfg(test)]
+fn titles(query: &str) -> Vec<String> {
+    let store = store();
+    Query::compile(query).unwrap().select(&store).iter().map(|book| book["title"].as_str().unwrap().to_string()).collect()
+}
+
+#[test]
+fn test_paths() {
+    let store = store();
+    let run = |query: &str| Query::compile(query).unwrap().run(&store);
+    assert_eq!(run("$.store.book[0].author"), [Json::from("Nigel Rees")]);
+    assert_eq!(run("$['store']['bicycle'][\"color\"]"), [Json::from("red")]);
+    assert_eq!(run("$.store.book[*].author"), run("$..author"));
+    assert_eq!(run("$..author").len(), 4);
+    assert_eq!(run("$.store.*").len(), 2);
+    assert_eq!(run("$..price"), [Json::from(399), Json::from(8.95), Json::from(12.99), Json::from(8.99),
+                                 Json::from(22.99)]);
+    assert_eq!(run("$..*").len(), 27);
+    assert_eq!(run("$").len(), 1);
+    assert_eq!(run("$")[0], store);
+    assert_eq!(run("$.nothing.here"), []);
+    assert_eq!(run("$.store.book.title"), []);
+    assert_eq!(run("$.store.book[9]"), []);
+
+    assert_eq!(titles("$.store.book[-1]"), ["The Lord of the Rings"]);
+    assert_eq!(titles("$.store.book[0,2]"), ["Sayings of the Century", "Moby Dick"]);
+    assert_eq!(titles("$.store.book[:2]"), ["Sayings of the Century", "Sword of Honour"]);
+    assert_eq!(titles("$.store.book[-2:]"), ["Moby Dick", "The Lord of the Rings"]);
+    assert_eq!(titles("$.store.book[::2]"), ["Sayings of the Century", "Moby Dick"]);
+    assert_eq!(titles("$.store.book[::-1]")[0], "The Lord of the Rings");
+    assert_eq!(titles("$.store.book[2:0:-1]"), ["Moby Dick", "Sword of Honour"]);
+    assert_eq!(titles("$.store.book[5:9]").len(), 0);
+    assert_eq!(slice_indices(5, Some(-10), Some(10), 1), [0, 1, 2, 3, 4]);
+    assert_eq!(slice_indices(5, None, None, -2), [4, 2, 0]);
+}
+
+#[test]
+fn test_filters_and_projections() {
+    assert_eq!(titles("$..book[?(@.price < 10)]"), ["Sayings of the Century", "Moby Dick"]);
+    assert_eq!(titles("$..book[?@.isbn]"), ["Moby Dick", "The Lord of the Rings"]);
+    assert_eq!(titles("$..book[?(!@.isbn)]"), ["Sayings of the Century", "Sword of Honour"]);
+    assert_eq!(titles("$..book[?(@.category == 'fiction' && @.price >= 12.99)]"),
+               ["Sword of Honour", "The Lord of the Rings"]);
+    assert_eq!(titles("$..book[?(@.author == \"Nigel Rees\" || @.price > 20)]"),
+               ["Sayings of the Century", "The Lord of the Rings"]);
+    assert_eq!(titles("$..book[?(@.price < $.store.bicycle.price && @.title > 'S')]"),
+               ["Sayings of the Century", "Sword of Honour", "The Lord of the Rings"]);
+    assert_eq!(titles("$..book[?(@.isbn != null)]").len(), 4);
+    assert_eq!(titles("$..book[?(@.missing == @.absent)]").len(), 4);
+    assert_eq!(titles("$..book[?(@.price > 'cheap')]").len(), 0);
+
+    let store = store();
+    let query = Query::compile("$.store.book[?(@.price > 20)].{title, cost: @.price, isbn, shop: $.store.bicycle.color}")
+        .unwrap();
+    assert_eq!(query.run(&store), [Json::parse(r#"{"title": "The Lord of the Rings", "cost": 22.99,
+                                                   "isbn": "0-395-19395-8", "shop": "red"}"#).unwrap()]);
+    let query: Query = "$..book[0:2].{'the author': @.author, isbn}".parse().unwrap();
+    assert_eq!(query.first(&store), Some(Json::parse(r#"{"the author": "Nigel Rees", "isbn": null}"#).unwrap()));
+    assert_eq!(query.to_string(), "$..book[0:2].{'the author': @.author, isbn}");
+}
+
+#[test]
+fn test_query_errors() {
+    let error = |query: &str| Query::compile(query).unwrap_err();
+    assert_eq!(error("store"), QueryError { position: 0, reason: "a query starts with '$'" });
+    assert_eq!(error("$."), QueryError { position: 2, reason: "expected a name" });
+    assert_eq!(error("$[0"), QueryError { position: 3, reason: "expected ',' or ']'" });
+    assert_eq!(error("$[]"), QueryError { position: 2, reason: "expected a selector" });
+    assert_eq!(error("$[::0]"), QueryError { position: 5, reason: "a slice's step can't be zero" });
+    assert_eq!(error("$['a]"), QueryError { position: 5, reason: "unterminated string" });
+    assert_eq!(error("$['\\q']"), QueryError { position: 4, reason: "invalid escape" });
+    assert_eq!(error("$[?(@.a == )]"), QueryError { position: 11, reason: "expected a value" });
+    assert_eq!(error("$[?(@.a == 1]"), QueryError { position: 12, reason: "expected ')'" });
+    assert_eq!(error("$[?(3)]"), QueryError { position: 5, reason: "expected a comparison" });
+    assert_eq!(error("$.a.{b: 3}"), QueryError { position: 9, reason: "expected a path" });
+    assert_eq!(error("$.a b"), QueryError { position: 3, reason: "unexpected text after the query" });
+    assert_eq!(error("$[?(@.a.{b})]"), QueryError { position: 7, reason: "expected ')'" });
+    assert_eq!(error("$[99999999999999999999]").to_string(), "invalid query at position 2: number out of range");
+    let nots = format!("$[?({}@.a)]", "!".repeat(200_000));
+    assert_eq!(error(&nots), QueryError { position: 131, reason: "filter nested too deeply" });
+    let ors = format!("$[?(@.a{})]", " || @.a".repeat(200_000));
+    assert_eq!(error(&ors).reason, "filter nested too deeply");
+    let nested = format!("$[?({}@.a{})]", "@[?(".repeat(300), ")]".repeat(300));
+    assert_eq!(error(&nested).reason, "filter nested too deeply");
+    let shallow = format!("$[?({}@.a{})]", "!(".repeat(60), ")".repeat(60));
+    assert!(Query::compile(&shallow).is_ok());
+    assert_eq!(Query::compile("$[1::9223372036854775807]").unwrap().run(&Json::parse("[1, 2, 3]").unwrap()),
+               [Json::from(2)]);
+    assert_eq!(Query::compile("$[1::-9223372036854775807]").unwrap().run(&Json::parse("[1, 2, 3]").unwrap()),
+               [Json::from(2)]);
+    assert_eq!(Query::compile("$['\\u00e9t\\u00E9']").unwrap().run(&Json::parse(r#"{"été": 1}"#).unwrap()),
+               [Json::from(1)]);
+}
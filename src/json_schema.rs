@@ -0,0 +1,617 @@
+#![allow(dead_code)]
+
+// Validating Json values against a JSON Schema (draft 2020-12). Only the
+// everyday keywords are understood:
+//
+//   type, enum, const
+//   properties, additionalProperties, required, minProperties, maxProperties
+//   items, prefixItems, minItems, maxItems
+//   minimum, maximum, exclusiveMinimum, exclusiveMaximum
+//   minLength, maxLength, pattern
+//   allOf, anyOf, oneOf, not, and $ref to "#" or "#/json/pointer"
+//
+// Anything else, $defs included, is ignored like the annotation it would be
+// to a validator that doesn't know it. The schema is compiled once, so
+// mistakes in it show up then rather than halfway through validating.
+// Validation doesn't stop at the first problem: every error is reported,
+// with a pointer to the value that failed and one to the schema keyword it
+// failed. References are followed to where they point, so the schema
+// pointer is where the keyword really is.
+
+use crate::json::{format_number, Json};
+use crate::json_patch::JsonPointer;
+use crate::pattern::Pattern;
+use std::collections::HashMap;
+use std::fmt;
+
+/// What's wrong with a schema, and where in it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaError {
+    pub pointer: JsonPointer,
+    pub reason: &'static str
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(dest, "invalid schema at \"{}\": {}", self.pointer, self.reason)
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// One way a value doesn't fit the schema
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationError {
+    /// The value that failed
+    pub instance: JsonPointer,
+    /// The schema keyword it failed
+    pub schema: JsonPointer,
+    pub message: String
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(dest, "\"{}\": {} (schema \"{}\")", self.instance, self.message, self.schema)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// How many schemas validation may be inside of at once, which stops a
+/// reference loop like {"$ref": "#"} from going around forever
+pub const MAX_SCHEMA_DEPTH: usize = 256;
+
+/// How many subschema checks one validation may make in total. Depth alone
+/// doesn't stop {"anyOf": [{"$ref": "#"}, {"$ref": "#"}]} doubling its work
+/// at every level, so past this validation gives up with an error.
+pub const MAX_SCHEMA_STEPS: usize = 1_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum JsonType {
+    Null,
+    Boolean,
+    Object,
+    Array,
+    Number,
+    String,
+    Integer
+}
+
+impl JsonType {
+    fn from_name(name: &str) -> Option<JsonType> {
+        Some(match name {
+            "null" => JsonType::Null,
+            "boolean" => JsonType::Boolean,
+            "object" => JsonType::Object,
+            "array" => JsonType::Array,
+            "number" => JsonType::Number,
+            "string" => JsonType::String,
+            "integer" => JsonType::Integer,
+            _ => return None
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            JsonType::Null => "null",
+            JsonType::Boolean => "boolean",
+            JsonType::Object => "object",
+            JsonType::Array => "array",
+            JsonType::Number => "number",
+            JsonType::String => "string",
+            JsonType::Integer => "integer"
+        }
+    }
+
+    /// The type of a value, as specific as possible
+    fn of(value: &Json) -> JsonType {
+        match *value {
+            Json::Null => JsonType::Null,
+            Json::Boolean(_) => JsonType::Boolean,
+            Json::Object(_) => JsonType::Object,
+            Json::Array(_) => JsonType::Array,
+            Json::Number(n) if n.fract() == 0.0 => JsonType::Integer,
+            Json::Number(_) => JsonType::Number,
+            Json::String(_) => JsonType::String
+        }
+    }
+
+    fn admits(self, value: &Json) -> bool {
+        let actual = JsonType::of(value);
+        actual == self || (self == JsonType::Number && actual == JsonType::Integer)
+    }
+}
+
+/// A compiled schema object. Subschemas are indices into `Schema::nodes`.
+#[derive(Clone, Debug, Default)]
+struct Node {
+    pointer: JsonPointer,
+    /// The false schema, which nothing fits
+    reject: bool,
+    types: Option<Vec<JsonType>>,
+    enumeration: Option<Vec<Json>>,
+    constant: Option<Json>,
+    properties: Vec<(String, usize)>,
+    additional_properties: Option<usize>,
+    required: Vec<String>,
+    min_properties: Option<usize>,
+    max_properties: Option<usize>,
+    prefix_items: Vec<usize>,
+    items: Option<usize>,
+    min_items: Option<usize>,
+    max_items: Option<usize>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    exclusive_minimum: Option<f64>,
+    exclusive_maximum: Option<f64>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    pattern: Option<Pattern>,
+    all_of: Vec<usize>,
+    any_of: Vec<usize>,
+    one_of: Vec<usize>,
+    not: Option<usize>,
+    reference: Option<usize>
+}
+
+/// A compiled schema
+#[derive(Clone, Debug)]
+pub struct Schema {
+    nodes: Vec<Node>
+}
+
+struct SchemaCompiler<'s> {
+    document: &'s Json,
+    nodes: Vec<Node>,
+    /// Where each schema that's been compiled so far came from
+    compiled: HashMap<JsonPointer, usize>
+}
+
+/// Undo the %XX escapes a URI fragment may have
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = text.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+impl<'s> SchemaCompiler<'s> {
+    /// Compile the schema at `pointer`, unless that's been done already
+    fn compile(&mut self, pointer: JsonPointer) -> Result<usize, SchemaError> {
+        if let Some(&index) = self.compiled.get(&pointer) {
+            return Ok(index);
+        }
+        let schema = match pointer.resolve(self.document) {
+            Some(schema) => schema,
+            None => return Err(SchemaError { pointer, reason: "$ref points at nothing" })
+        };
+        // Claim the slot first, so a reference back to here finds it
+        let index = self.nodes.len();
+        self.nodes.push(Node::default());
+        self.compiled.insert(pointer.clone(), index);
+        let node = self.node(schema, pointer)?;
+        self.nodes[index] = node;
+        Ok(index)
+    }
+
+    fn node(&mut self, schema: &Json, pointer: JsonPointer) -> Result<Node, SchemaError> {
+        let members = match *schema {
+            Json::Boolean(allowed) => return Ok(Node { pointer, reject: !allowed, ..Node::default() }),
+            Json::Object(ref members) => members,
+            _ => return Err(SchemaError { pointer, reason: "a schema must be an object or a boolean" })
+        };
+        let mut node = Node { pointer: pointer.clone(), ..Node::default() };
+        for (keyword, value) in members.iter() {
+            let here = pointer.child(keyword);
+            let error = |reason| SchemaError { pointer: here.clone(), reason };
+            let count = || match value.as_f64() {
+                Some(n) if n >= 0.0 && n.fract() == 0.0 => Ok(Some(n as usize)),
+                _ => Err(error("expected a non-negative integer"))
+            };
+            let number = || match *value {
+                Json::Number(n) => Ok(Some(n)),
+                _ => Err(error("expected a number"))
+            };
+            match keyword.as_str() {
+                "type" => {
+                    let names = match *value {
+                        Json::String(_) => vec![value],
+                        Json::Array(ref names) => names.iter().collect(),
+                        _ => return Err(error("expected a type name or an array of them"))
+                    };
+                    let types = names.into_iter().map(|name| name.as_str().and_then(JsonType::from_name));
+                    match types.collect() {
+                        Some(types) => node.types = Some(types),
+                        None => return Err(error("unknown type"))
+                    }
+                }
+                "enum" => match *value {
+                    Json::Array(ref values) => node.enumeration = Some(values.clone()),
+                    _ => return Err(error("expected an array"))
+                },
+                "const" => node.constant = Some(value.clone()),
+                "properties" => match *value {
+                    Json::Object(ref properties) => for name in properties.keys() {
+                        let index = self.compile(here.child(name))?;
+                        node.properties.push((name.clone(), index));
+                    },
+                    _ => return Err(error("expected an object"))
+                },
+                "additionalProperties" => node.additional_properties = Some(self.compile(here)?),
+                "required" => {
+                    let names: Option<Vec<&str>> =
+                        value.as_array().and_then(|names| names.iter().map(Json::as_str).collect());
+                    match names {
+                        Some(names) => node.required = names.into_iter().map(str::to_string).collect(),
+                        None => return Err(error("expected an array of strings"))
+                    }
+                }
+                "minProperties" => node.min_properties = count()?,
+                "maxProperties" => node.max_properties = count()?,
+                "prefixItems" => node.prefix_items = self.schema_array(value, &here)?,
+                "items" => node.items = Some(self.compile(here)?),
+                "minItems" => node.min_items = count()?,
+                "maxItems" => node.max_items = count()?,
+                "minimum" => node.minimum = number()?,
+                "maximum" => node.maximum = number()?,
+                "exclusiveMinimum" => node.exclusive_minimum = number()?,
+                "exclusiveMaximum" => node.exclusive_maximum = number()?,
+                "minLength" => node.min_length = count()?,
+                "maxLength" => node.max_length = count()?,
+                "pattern" => match value.as_str().map(Pattern::new) {
+                    Some(Ok(pattern)) => node.pattern = Some(pattern),
+                    Some(Err(_)) => return Err(error("invalid regular expression")),
+                    None => return Err(error("expected a string"))
+                },
+                "allOf" => node.all_of = self.schema_array(value, &here)?,
+                "anyOf" => node.any_of = self.schema_array(value, &here)?,
+                "oneOf" => node.one_of = self.schema_array(value, &here)?,
+                "not" => node.not = Some(self.compile(here)?),
+                "$ref" => {
+                    let target = value.as_str()
+                        .and_then(|uri| uri.strip_prefix('#'))
+                        .and_then(percent_decode)
+                        .and_then(|fragment| JsonPointer::parse(&fragment).ok());
+                    match target {
+                        Some(ref target) if target.resolve(self.document).is_none() =>
+                            return Err(error("$ref points at nothing")),
+                        Some(target) => node.reference = Some(self.compile(target)?),
+                        None => return Err(error("only references within this schema, like \"#/$defs/name\", are supported"))
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(node)
+    }
+
+    /// A non-empty array of schemas, for allOf and friends
+    fn schema_array(&mut self, value: &Json, pointer: &JsonPointer) -> Result<Vec<usize>, SchemaError> {
+        match *value {
+            Json::Array(ref schemas) if !schemas.is_empty() =>
+                (0..schemas.len()).map(|i| self.compile(pointer.child(&i.to_string()))).collect(),
+            _ => Err(SchemaError { pointer: pointer.clone(), reason: "expected a non-empty array of schemas" })
+        }
+    }
+}
+
+impl Schema {
+    /// Compile a schema document
+    pub fn compile(document: &Json) -> Result<Schema, SchemaError> {
+        let mut compiler = SchemaCompiler { document, nodes: Vec::new(), compiled: HashMap::new() };
+        compiler.compile(JsonPointer::root())?;
+        Ok(Schema { nodes: compiler.nodes })
+    }
+
+    /// Check `instance` against the schema, returning every problem found
+    pub fn validate(&self, instance: &Json) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        let mut steps = MAX_SCHEMA_STEPS;
+        self.check(0, instance, &JsonPointer::root(), 0, &mut steps, &mut errors);
+        if steps == 0 {
+            // Whatever was found so far is incomplete, and the branches
+            // that ran out may have been counted as failures
+            return Err(vec![ValidationError {
+                instance: JsonPointer::root(),
+                schema: JsonPointer::root(),
+                message: format!("gave up after checking {} subschemas", MAX_SCHEMA_STEPS)
+            }]);
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    pub fn is_valid(&self, instance: &Json) -> bool {
+        self.validate(instance).is_ok()
+    }
+
+    /// Errors from checking `instance` against one subschema, kept apart
+    /// so anyOf and friends can count which branches passed
+    fn branch(&self, index: usize, instance: &Json, path: &JsonPointer, depth: usize, steps: &mut usize)
+              -> Vec<ValidationError>
+    {
+        let mut errors = Vec::new();
+        self.check(index, instance, path, depth, steps, &mut errors);
+        errors
+    }
+
+    /// Check `instance` against one node, spending one of the `steps` left
+    /// (and doing nothing once they're gone)
+    fn check(&self, index: usize, instance: &Json, path: &JsonPointer, depth: usize, steps: &mut usize,
+             errors: &mut Vec<ValidationError>)
+    {
+        if *steps == 0 {
+            return;
+        }
+        *steps -= 1;
+        let node = &self.nodes[index];
+        let mut fail = |keyword: &str, message: String| errors.push(ValidationError {
+            instance: path.clone(),
+            schema: if keyword.is_empty() { node.pointer.clone() } else { node.pointer.child(keyword) },
+            message
+        });
+        if depth >= MAX_SCHEMA_DEPTH {
+            return fail("", "the schema nests too deeply here".to_string());
+        }
+        if node.reject {
+            return fail("", "no value is allowed here".to_string());
+        }
+
+        if let Some(ref types) = node.types {
+            if !types.iter().any(|t| t.admits(instance)) {
+                let names: Vec<_> = types.iter().map(|t| t.name()).collect();
+                let expected = if names.len() == 1 { names[0].to_string() } else { format!("one of {}", names.join(", ")) };
+                fail("type", format!("expected {}, found {}", expected, JsonType::of(instance).name()));
+            }
+        }
+        if let Some(ref values) = node.enumeration {
+            if !values.contains(instance) {
+                fail("enum", "not one of the allowed values".to_string());
+            }
+        }
+        if let Some(ref value) = node.constant {
+            if instance != value {
+                fail("const", format!("expected {}", value));
+            }
+        }
+
+        match *instance {
+            Json::Number(n) => {
+                let bounds = [("minimum", node.minimum, "less than the minimum of"),
+                              ("maximum", node.maximum, "more than the maximum of"),
+                              ("exclusiveMinimum", node.exclusive_minimum, "not more than"),
+                              ("exclusiveMaximum", node.exclusive_maximum, "not less than")];
+                for &(keyword, bound, complaint) in &bounds {
+                    let fits = |limit: f64| match keyword {
+                        "minimum" => n >= limit,
+                        "maximum" => n <= limit,
+                        "exclusiveMinimum" => n > limit,
+                        _ => n < limit
+                    };
+                    if let Some(limit) = bound.filter(|&limit| !fits(limit)) {
+                        fail(keyword, format!("{} is {} {}", format_number(n), complaint, format_number(limit)));
+                    }
+                }
+            }
+            Json::String(ref text) => {
+                let length = text.chars().count();
+                if let Some(min) = node.min_length.filter(|&min| length < min) {
+                    fail("minLength", format!("shorter than {} characters", min));
+                }
+                if let Some(max) = node.max_length.filter(|&max| length > max) {
+                    fail("maxLength", format!("longer than {} characters", max));
+                }
+                if let Some(ref pattern) = node.pattern {
+                    if !pattern.is_match(text) {
+                        fail("pattern", format!("doesn't match the pattern {:?}", pattern.as_str()));
+                    }
+                }
+            }
+            Json::Array(ref elements) => {
+                if let Some(min) = node.min_items.filter(|&min| elements.len() < min) {
+                    fail("minItems", format!("fewer than {} items", min));
+                }
+                if let Some(max) = node.max_items.filter(|&max| elements.len() > max) {
+                    fail("maxItems", format!("more than {} items", max));
+                }
+            }
+            Json::Object(ref members) => {
+                for name in node.required.iter().filter(|name| !members.contains_key(*name)) {
+                    fail("required", format!("missing the required property {:?}", name));
+                }
+                if let Some(min) = node.min_properties.filter(|&min| members.len() < min) {
+                    fail("minProperties", format!("fewer than {} properties", min));
+                }
+                if let Some(max) = node.max_properties.filter(|&max| members.len() > max) {
+                    fail("maxProperties", format!("more than {} properties", max));
+                }
+            }
+            _ => {}
+        }
+
+        let depth = depth + 1;
+        if let Json::Array(ref elements) = *instance {
+            for (i, element) in elements.iter().enumerate() {
+                let schema = node.prefix_items.get(i).cloned().or(node.items.filter(|_| i >= node.prefix_items.len()));
+                if let Some(schema) = schema {
+                    self.check(schema, element, &path.child(&i.to_string()), depth, steps, errors);
+                }
+            }
+        }
+        if let Json::Object(ref members) = *instance {
+            for (name, value) in members.iter() {
+                let declared = node.properties.iter().find(|(property, _)| property == name);
+                if let Some(schema) = declared.map(|&(_, schema)| schema).or(node.additional_properties) {
+                    self.check(schema, value, &path.child(name), depth, steps, errors);
+                }
+            }
+        }
+
+        for &schema in node.reference.iter().chain(&node.all_of) {
+            self.check(schema, instance, path, depth, steps, errors);
+        }
+        let mut fail = |keyword: &str, message: String| errors.push(ValidationError {
+            instance: path.clone(),
+            schema: node.pointer.child(keyword),
+            message
+        });
+        if !node.any_of.is_empty()
+            && !node.any_of.iter().any(|&schema| self.branch(schema, instance, path, depth, steps).is_empty()) {
+            fail("anyOf", "doesn't match any of the anyOf schemas".to_string());
+        }
+        if !node.one_of.is_empty() {
+            let matched = node.one_of.iter().filter(|&&schema| self.branch(schema, instance, path, depth, steps).is_empty()).count();
+            if matched != 1 {
+                fail("oneOf", format!("matches {} of the oneOf schemas instead of exactly one", matched));
+            }
+        }
+        if let Some(schema) = node.not {
+            if self.branch(schema, instance, path, depth, steps).is_empty() {
+                fail("not", "matches the schema it must not".to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn json(text: &str) -> Json {
+    Json::parse(text).unwrap()
+}
+
+#[cfg(test)]
+fn problems(schema: &str, instance: &str) -> Vec<String> {
+    let schema = Schema::compile(&json(schema)).unwrap();
+    match schema.validate(&json(instance)) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors.iter().map(ValidationError::to_string).collect()
+    }
+}
+
+#[test]
+fn test_simple_keywords() {
+    assert!(problems("true", r#"{"anything": [1]}"#).is_empty());
+    assert_eq!(problems("false", "1"), ["\"\": no value is allowed here (schema \"\")"]);
+    assert_eq!(problems(r#"{"type": "integer"}"#, "2.5"), ["\"\": expected integer, found number (schema \"/type\")"]);
+    assert!(problems(r#"{"type": "integer"}"#, "2.0").is_empty());
+    assert!(problems(r#"{"type": "number"}"#, "2").is_empty());
+    assert_eq!(problems(r#"{"type": ["string", "null"]}"#, "false"),
+               ["\"\": expected one of string, null, found boolean (schema \"/type\")"]);
+    assert_eq!(problems(r#"{"enum": ["red", "green", 3]}"#, "\"blue\""),
+               ["\"\": not one of the allowed values (schema \"/enum\")"]);
+    assert!(problems(r#"{"enum": ["red", "green", 3]}"#, "3.0").is_empty());
+    assert_eq!(problems(r#"{"const": {"a": [1]}}"#, r#"{"a": [2]}"#),
+               [r#""": expected {"a":[1]} (schema "/const")"#]);
+    assert_eq!(problems(r#"{"minimum": 1, "exclusiveMaximum": 10}"#, "10"),
+               ["\"\": 10 is not less than 10 (schema \"/exclusiveMaximum\")"]);
+    assert_eq!(problems(r#"{"maximum": 1.5, "exclusiveMinimum": 3}"#, "2"),
+               ["\"\": 2 is more than the maximum of 1.5 (schema \"/maximum\")",
+                "\"\": 2 is not more than 3 (schema \"/exclusiveMinimum\")"]);
+    assert_eq!(problems(r#"{"minLength": 3, "pattern": "^[a-z]+$"}"#, "\"é1\""),
+               ["\"\": shorter than 3 characters (schema \"/minLength\")",
+                "\"\": doesn't match the pattern \"^[a-z]+$\" (schema \"/pattern\")"]);
+    assert!(problems(r#"{"maxLength": 2}"#, "\"éé\"").is_empty());
+    assert!(problems(r#"{"pattern": "^[a-z]+$"}"#, &format!("\"{}\"", "a".repeat(10_000))).is_empty());
+    // Keywords for other types don't apply
+    assert!(problems(r#"{"minimum": 5, "required": ["a"], "items": false}"#, "\"abc\"").is_empty());
+}
+
+#[test]
+fn test_objects_and_arrays() {
+    let schema = r#"{
+        "type": "object",
+        "properties": {
+            "name": {"type": "string", "minLength": 1},
+            "age": {"type": "integer", "minimum": 0},
+            "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 2}
+        },
+        "required": ["name", "email"],
+        "additionalProperties": false
+    }"#;
+    assert_eq!(problems(schema, r#"{"name": "Ann", "email": "a@example.com", "age": 3}"#),
+               ["\"/email\": no value is allowed here (schema \"/additionalProperties\")"]);
+    assert_eq!(problems(schema, r#"{"name": "", "age": -1.5, "tags": ["a", 2, "c"], "nickname": "x"}"#), [
+        "\"\": missing the required property \"email\" (schema \"/required\")",
+        "\"/age\": expected integer, found number (schema \"/properties/age/type\")",
+        "\"/age\": -1.5 is less than the minimum of 0 (schema \"/properties/age/minimum\")",
+        "\"/name\": shorter than 1 characters (schema \"/properties/name/minLength\")",
+        "\"/nickname\": no value is allowed here (schema \"/additionalProperties\")",
+        "\"/tags\": more than 2 items (schema \"/properties/tags/maxItems\")",
+        "\"/tags/1\": expected string, found integer (schema \"/properties/tags/items/type\")"
+    ]);
+
+    let tuple = r#"{"prefixItems": [{"type": "number"}, {"type": "string"}], "items": {"type": "boolean"},
+                    "minItems": 2}"#;
+    assert!(problems(tuple, r#"[1, "a", true, false]"#).is_empty());
+    assert_eq!(problems(tuple, r#"["a", 1, null]"#), [
+        "\"/0\": expected number, found string (schema \"/prefixItems/0/type\")",
+        "\"/1\": expected string, found integer (schema \"/prefixItems/1/type\")",
+        "\"/2\": expected boolean, found null (schema \"/items/type\")"
+    ]);
+    assert_eq!(problems(tuple, "[1]"), ["\"\": fewer than 2 items (schema \"/minItems\")"]);
+    assert_eq!(problems(r#"{"minProperties": 1, "maxProperties": 1}"#, "{}"),
+               ["\"\": fewer than 1 properties (schema \"/minProperties\")"]);
+}
+
+#[test]
+fn test_combinators_and_references() {
+    let schema = r##"{
+        "$defs": {
+            "positive": {"type": "number", "exclusiveMinimum": 0},
+            "node": {
+                "type": "object",
+                "properties": {"value": {"$ref": "#/$defs/positive"}, "children": {"items": {"$ref": "#/$defs/node"}}},
+                "required": ["value"]
+            }
+        },
+        "$ref": "#/$defs/node"
+    }"##;
+    assert!(problems(schema, r#"{"value": 1, "children": [{"value": 2}, {"value": 3, "children": []}]}"#).is_empty());
+    assert_eq!(problems(schema, r#"{"value": 1, "children": [{"value": -2}, {"children": [{"value": 0}]}]}"#), [
+        "\"/children/0/value\": -2 is not more than 0 (schema \"/$defs/positive/exclusiveMinimum\")",
+        "\"/children/1\": missing the required property \"value\" (schema \"/$defs/node/required\")",
+        "\"/children/1/children/0/value\": 0 is not more than 0 (schema \"/$defs/positive/exclusiveMinimum\")"
+    ]);
+
+    let one_of = r#"{"oneOf": [{"type": "integer"}, {"minimum": 2}]}"#;
+    assert!(problems(one_of, "1").is_empty() && problems(one_of, "2.5").is_empty());
+    assert_eq!(problems(one_of, "3"), ["\"\": matches 2 of the oneOf schemas instead of exactly one (schema \"/oneOf\")"]);
+    assert_eq!(problems(one_of, "1.5"), ["\"\": matches 0 of the oneOf schemas instead of exactly one (schema \"/oneOf\")"]);
+    let any_of = r#"{"anyOf": [{"type": "string"}, {"type": "null"}], "not": {"const": "no"}}"#;
+    assert!(problems(any_of, "null").is_empty());
+    assert_eq!(problems(any_of, "1"), ["\"\": doesn't match any of the anyOf schemas (schema \"/anyOf\")"]);
+    assert_eq!(problems(any_of, "\"no\""), ["\"\": matches the schema it must not (schema \"/not\")"]);
+    assert_eq!(problems(r#"{"allOf": [{"minimum": 3}, {"maximum": 1}]}"#, "2").len(), 2);
+    assert!(problems(r##"{"$defs": {"a b": {"type": "null"}}, "$ref": "#/$defs/a%20b"}"##, "null").is_empty());
+
+    let looping = Schema::compile(&json(r##"{"$ref": "#"}"##)).unwrap();
+    assert_eq!(looping.validate(&json("1")).unwrap_err()[0].message, "the schema nests too deeply here");
+    // Each level doubles the work, so this would never finish without a budget
+    let doubling = Schema::compile(&json(r##"{"anyOf": [{"$ref": "#"}, {"$ref": "#"}]}"##)).unwrap();
+    let errors = doubling.validate(&json("1")).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message, format!("gave up after checking {} subschemas", MAX_SCHEMA_STEPS));
+}
+
+#[test]
+fn test_schema_errors() {
+    let error = |schema: &str| Schema::compile(&json(schema)).unwrap_err().to_string();
+    assert_eq!(error("3"), "invalid schema at \"\": a schema must be an object or a boolean");
+    assert_eq!(error(r#"{"type": "text"}"#), "invalid schema at \"/type\": unknown type");
+    assert_eq!(error(r#"{"properties": {"a": {"minLength": -1}}}"#),
+               "invalid schema at \"/properties/a/minLength\": expected a non-negative integer");
+    assert_eq!(error(r#"{"pattern": "a("}"#), "invalid schema at \"/pattern\": invalid regular expression");
+    assert_eq!(error(r#"{"anyOf": []}"#), "invalid schema at \"/anyOf\": expected a non-empty array of schemas");
+    assert_eq!(error(r##"{"$ref": "#/$defs/missing"}"##), "invalid schema at \"/$ref\": $ref points at nothing");
+    assert_eq!(error(r#"{"$ref": "other.json#/a"}"#),
+               "invalid schema at \"/$ref\": only references within this schema, like \"#/$defs/name\", are supported");
+    assert_eq!(error(r#"{"required": ["a", 1]}"#), "invalid schema at \"/required\": expected an array of strings");
+}
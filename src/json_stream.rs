@@ -0,0 +1,921 @@
+#![allow(dead_code)]
+
+// Streaming JSON, for documents too big to hold as a Json tree. The reader
+// pulls events (StartObject, Key, String, EndArray...) out of any io::Read
+// through a fixed buffer, so memory use depends only on the nesting depth
+// and the longest single string, both of which are capped. The writer is
+// the mirror image over any io::Write, and produces exactly what Json's
+// Display and to_pretty_string would. Either can drop down to whole Json
+// values for the parts small enough to want them, like one record at a
+// time from a huge array.
+
+use crate::json::{format_number, hex_value, write_string, Json, ParseJsonError, DEFAULT_MAX_DEPTH};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+
+/// One step through a JSON document
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonEvent {
+    StartObject,
+    EndObject,
+    StartArray,
+    EndArray,
+    /// An object member's name; its value comes next
+    Key(String),
+    String(String),
+    Number(f64),
+    Boolean(bool),
+    Null
+}
+
+/// What went wrong reading or writing a stream
+#[derive(Debug)]
+pub enum JsonStreamError {
+    Io(io::Error),
+    /// The input isn't JSON
+    Syntax(ParseJsonError),
+    /// The caller asked for something that would make the output invalid,
+    /// like a key outside an object
+    Misuse(&'static str)
+}
+
+impl fmt::Display for JsonStreamError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JsonStreamError::Io(ref error) => write!(dest, "{}", error),
+            JsonStreamError::Syntax(ref error) => write!(dest, "{}", error),
+            JsonStreamError::Misuse(reason) => write!(dest, "can't write JSON: {}", reason)
+        }
+    }
+}
+
+impl std::error::Error for JsonStreamError {}
+
+impl From<io::Error> for JsonStreamError {
+    fn from(error: io::Error) -> Self {
+        JsonStreamError::Io(error)
+    }
+}
+
+/// Settings for a `JsonStreamReader`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ReaderConfig {
+    pub max_depth: usize,
+    /// Longest string or number, in bytes
+    pub max_token_bytes: usize,
+    /// Read a sequence of values, as in JSON Lines, instead of exactly one.
+    /// Strings, arrays and objects end themselves, but a number, true,
+    /// false or null must be followed by whitespace before the next value.
+    pub multiple_values: bool
+}
+
+impl Default for ReaderConfig {
+    fn default() -> Self {
+        ReaderConfig { max_depth: DEFAULT_MAX_DEPTH, max_token_bytes: 1024 * 1024, multiple_values: false }
+    }
+}
+
+const BUFFER_SIZE: usize = 8 * 1024;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Container {
+    Array,
+    Object
+}
+
+// Where the reader is in the grammar
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+    /// Before a top level value
+    Start,
+    /// After ':', or ',' in an array
+    Value,
+    /// After '[', where ']' may come instead of a value
+    ArrayStart,
+    /// After '{', where '}' may come instead of a key
+    ObjectStart,
+    /// After ',' in an object
+    Key,
+    /// After a value, where ',' or the end of the container comes next
+    AfterValue
+}
+
+/// Pulls events out of JSON text as it's read
+pub struct JsonStreamReader<R> {
+    source: R,
+    buffer: Box<[u8]>,
+    start: usize,
+    end: usize,
+    line: usize,
+    column: usize,
+    config: ReaderConfig,
+    stack: Vec<Container>,
+    state: State,
+    /// Whether a leading byte order mark has been looked for yet
+    bom_checked: bool,
+    /// The last value was a number or literal, which can't be told apart
+    /// from one run straight into it
+    after_bare_value: bool,
+    /// Once the input turns out not to be JSON, the rest can't be trusted
+    failed: Option<ParseJsonError>
+}
+
+impl<R: Read> JsonStreamReader<R> {
+    ///Constructor, with the default settings
+    pub fn new(source: R) -> Self {
+        JsonStreamReader::with_config(source, ReaderConfig::default())
+    }
+
+    pub fn with_config(source: R, config: ReaderConfig) -> Self {
+        JsonStreamReader {
+            source,
+            buffer: vec![0; BUFFER_SIZE].into_boxed_slice(),
+            start: 0,
+            end: 0,
+            line: 1,
+            column: 1,
+            config,
+            stack: Vec::new(),
+            state: State::Start,
+            bom_checked: false,
+            after_bare_value: false,
+            failed: None
+        }
+    }
+
+    /// The line and column of the next character to be read
+    pub fn position(&self) -> (usize, usize) {
+        (self.line, self.column)
+    }
+
+    /// How many arrays and objects the reader is inside
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, JsonStreamError> {
+        if self.start == self.end {
+            self.start = 0;
+            self.end = loop {
+                match self.source.read(&mut self.buffer) {
+                    Ok(n) => break n,
+                    Err(ref error) if error.kind() == ErrorKind::Interrupted => {}
+                    Err(error) => return Err(error.into())
+                }
+            };
+        }
+        Ok(if self.start < self.end { Some(self.buffer[self.start]) } else { None })
+    }
+
+    /// Move past the byte `peek` returned
+    fn bump(&mut self) {
+        let byte = self.buffer[self.start];
+        self.start += 1;
+        if byte == b'\n' {
+            self.line += 1;
+            self.column = 1;
+        } else if byte & 0xc0 != 0x80 {
+            // Continuation bytes are part of the character before them
+            self.column += 1;
+        }
+    }
+
+    fn error_at(&self, (line, column): (usize, usize), reason: &'static str) -> JsonStreamError {
+        JsonStreamError::Syntax(ParseJsonError { line, column, reason })
+    }
+
+    fn error(&self, reason: &'static str) -> JsonStreamError {
+        self.error_at(self.position(), reason)
+    }
+
+    fn skip_whitespace(&mut self) -> Result<Option<u8>, JsonStreamError> {
+        loop {
+            match self.peek()? {
+                Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') => self.bump(),
+                byte => return Ok(byte)
+            }
+        }
+    }
+
+    /// Step over a UTF-8 byte order mark at the very start. Like Json::parse,
+    /// it doesn't count towards the column.
+    fn skip_bom(&mut self) -> Result<(), JsonStreamError> {
+        self.bom_checked = true;
+        for (i, &expected) in b"\xef\xbb\xbf".iter().enumerate() {
+            match self.peek()? {
+                Some(byte) if byte == expected => self.start += 1,
+                _ if i == 0 => return Ok(()),
+                _ => return Err(self.error("expected a value"))
+            }
+        }
+        Ok(())
+    }
+
+    /// The next event, or `None` once the input is used up
+    pub fn next_event(&mut self) -> Result<Option<JsonEvent>, JsonStreamError> {
+        if let Some(ref error) = self.failed {
+            return Err(JsonStreamError::Syntax(error.clone()));
+        }
+        let result = self.advance();
+        if let Err(JsonStreamError::Syntax(ref error)) = result {
+            self.failed = Some(error.clone());
+        }
+        result
+    }
+
+    fn advance(&mut self) -> Result<Option<JsonEvent>, JsonStreamError> {
+        if !self.bom_checked {
+            self.skip_bom()?;
+        }
+        loop {
+            let before = self.position();
+            let byte = self.skip_whitespace()?;
+            match self.state {
+                State::Start if byte.is_none() && self.config.multiple_values => return Ok(None),
+                State::Start | State::Value => return self.value_event(byte).map(Some),
+                State::ArrayStart if byte == Some(b']') => return Ok(Some(self.close())),
+                State::ArrayStart => return self.value_event(byte).map(Some),
+                State::ObjectStart if byte == Some(b'}') => return Ok(Some(self.close())),
+                State::ObjectStart | State::Key => return self.key_event(byte).map(Some),
+                State::AfterValue => match (self.stack.last().cloned(), byte) {
+                    (None, None) => return Ok(None),
+                    (None, Some(_)) if self.config.multiple_values => {
+                        if self.after_bare_value && self.position() == before {
+                            return Err(self.error("expected whitespace between values"));
+                        }
+                        self.state = State::Start;
+                    }
+                    (None, Some(_)) => return Err(self.error("unexpected text after the value")),
+                    (Some(container), Some(b',')) => {
+                        self.bump();
+                        self.state = if container == Container::Array { State::Value } else { State::Key };
+                    }
+                    (Some(Container::Array), Some(b']')) | (Some(Container::Object), Some(b'}')) =>
+                        return Ok(Some(self.close())),
+                    (Some(Container::Array), _) => return Err(self.error("expected ',' or ']'")),
+                    (Some(Container::Object), _) => return Err(self.error("expected ',' or '}'"))
+                }
+            }
+        }
+    }
+
+    fn open(&mut self, container: Container) -> Result<JsonEvent, JsonStreamError> {
+        if self.stack.len() >= self.config.max_depth {
+            return Err(self.error("nested too deeply"));
+        }
+        self.bump();
+        self.stack.push(container);
+        Ok(match container {
+            Container::Array => {
+                self.state = State::ArrayStart;
+                JsonEvent::StartArray
+            }
+            Container::Object => {
+                self.state = State::ObjectStart;
+                JsonEvent::StartObject
+            }
+        })
+    }
+
+    fn close(&mut self) -> JsonEvent {
+        self.bump();
+        self.state = State::AfterValue;
+        self.after_bare_value = false;
+        match self.stack.pop() {
+            Some(Container::Array) => JsonEvent::EndArray,
+            _ => JsonEvent::EndObject
+        }
+    }
+
+    fn value_event(&mut self, byte: Option<u8>) -> Result<JsonEvent, JsonStreamError> {
+        let event = match byte {
+            Some(b'{') => return self.open(Container::Object),
+            Some(b'[') => return self.open(Container::Array),
+            Some(b'"') => JsonEvent::String(self.read_string()?),
+            Some(b't') => self.literal("true", JsonEvent::Boolean(true))?,
+            Some(b'f') => self.literal("false", JsonEvent::Boolean(false))?,
+            Some(b'n') => self.literal("null", JsonEvent::Null)?,
+            Some(b'-') | Some(b'0'..=b'9') => JsonEvent::Number(self.read_number()?),
+            Some(_) => return Err(self.error("expected a value")),
+            None => return Err(self.error("unexpected end of input"))
+        };
+        self.state = State::AfterValue;
+        self.after_bare_value = !matches!(event, JsonEvent::String(_));
+        Ok(event)
+    }
+
+    fn key_event(&mut self, byte: Option<u8>) -> Result<JsonEvent, JsonStreamError> {
+        if byte != Some(b'"') {
+            return Err(self.error("expected a string key"));
+        }
+        let key = self.read_string()?;
+        if self.skip_whitespace()? != Some(b':') {
+            return Err(self.error("expected ':'"));
+        }
+        self.bump();
+        self.state = State::Value;
+        Ok(JsonEvent::Key(key))
+    }
+
+    fn literal(&mut self, word: &'static str, event: JsonEvent) -> Result<JsonEvent, JsonStreamError> {
+        let start = self.position();
+        for expected in word.bytes() {
+            if self.peek()? != Some(expected) {
+                return Err(self.error_at(start, "expected a value"));
+            }
+            self.bump();
+        }
+        Ok(event)
+    }
+
+    fn read_string(&mut self) -> Result<String, JsonStreamError> {
+        self.bump();
+        let mut bytes = Vec::new();
+        loop {
+            match self.peek()? {
+                Some(b'"') => {
+                    self.bump();
+                    break;
+                }
+                Some(b'\\') => {
+                    let c = self.read_escape()?;
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                Some(0..=0x1f) => return Err(self.error("control characters must be escaped")),
+                Some(byte) => {
+                    self.bump();
+                    bytes.push(byte);
+                }
+                None => return Err(self.error("unterminated string"))
+            }
+            if bytes.len() > self.config.max_token_bytes {
+                return Err(self.error("string too long"));
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8"))
+    }
+
+    /// The character a backslash escape stands for
+    fn read_escape(&mut self) -> Result<char, JsonStreamError> {
+        let start = self.position();
+        self.bump();
+        let c = match self.peek()? {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.bump();
+                let unit = self.read_hex4()?;
+                let code = match unit {
+                    0xd800..=0xdbff => {
+                        // A high surrogate has to be followed by a low one
+                        for &expected in b"\\u" {
+                            if self.peek()? != Some(expected) {
+                                return Err(self.error_at(start, "unpaired surrogate"));
+                            }
+                            self.bump();
+                        }
+                        let low = self.read_hex4()?;
+                        if !(0xdc00..=0xdfff).contains(&low) {
+                            return Err(self.error_at(start, "unpaired surrogate"));
+                        }
+                        0x10000 + ((u32::from(unit) - 0xd800) << 10) + (u32::from(low) - 0xdc00)
+                    }
+                    0xdc00..=0xdfff => return Err(self.error_at(start, "unpaired surrogate")),
+                    _ => u32::from(unit)
+                };
+                return Ok(std::char::from_u32(code).unwrap());
+            }
+            _ => return Err(self.error_at(start, "invalid escape"))
+        };
+        self.bump();
+        Ok(c)
+    }
+
+    fn read_hex4(&mut self) -> Result<u16, JsonStreamError> {
+        let start = self.position();
+        let mut unit = 0;
+        for _ in 0..4 {
+            match self.peek()?.and_then(hex_value) {
+                Some(digit) => unit = unit * 16 + digit,
+                None => return Err(self.error_at(start, "expected four hex digits"))
+            }
+            self.bump();
+        }
+        Ok(unit)
+    }
+
+    /// Move digits into `text`, returning how many there were
+    fn read_digits(&mut self, text: &mut String) -> Result<usize, JsonStreamError> {
+        let mut count = 0;
+        while let Some(digit @ b'0'..=b'9') = self.peek()? {
+            self.bump();
+            text.push(digit as char);
+            count += 1;
+            if text.len() > self.config.max_token_bytes {
+                return Err(self.error("number too long"));
+            }
+        }
+        Ok(count)
+    }
+
+    fn read_number(&mut self) -> Result<f64, JsonStreamError> {
+        let start = self.position();
+        let mut text = String::new();
+        if self.peek()? == Some(b'-') {
+            self.bump();
+            text.push('-');
+        }
+        match self.peek()? {
+            Some(b'0') => {
+                self.bump();
+                text.push('0');
+                if let Some(b'0'..=b'9') = self.peek()? {
+                    return Err(self.error("leading zeros aren't allowed"));
+                }
+            }
+            Some(b'1'..=b'9') => {
+                self.read_digits(&mut text)?;
+            }
+            _ => return Err(self.error("expected a digit"))
+        }
+        if self.peek()? == Some(b'.') {
+            self.bump();
+            text.push('.');
+            if self.read_digits(&mut text)? == 0 {
+                return Err(self.error("expected digits after '.'"));
+            }
+        }
+        if let Some(b'e') | Some(b'E') = self.peek()? {
+            self.bump();
+            text.push('e');
+            if let Some(sign @ b'+') | Some(sign @ b'-') = self.peek()? {
+                self.bump();
+                text.push(sign as char);
+            }
+            if self.read_digits(&mut text)? == 0 {
+                return Err(self.error("expected digits in the exponent"));
+            }
+        }
+        let n: f64 = text.parse().unwrap();
+        if n.is_infinite() {
+            return Err(self.error_at(start, "number out of range"));
+        }
+        Ok(n)
+    }
+
+    /// Read the next whole value as a tree. Returns `None` instead if the
+    /// enclosing array or object ends, or the input does, so this can walk
+    /// the elements of an array one at a time. Call it where a value is
+    /// due, not where an object member's key is.
+    pub fn read_value(&mut self) -> Result<Option<Json>, JsonStreamError> {
+        match self.next_event()? {
+            None | Some(JsonEvent::EndArray) | Some(JsonEvent::EndObject) => Ok(None),
+            Some(JsonEvent::Key(_)) => Err(JsonStreamError::Misuse("expected a value but found a key")),
+            Some(event) => self.build(event).map(Some)
+        }
+    }
+
+    fn build(&mut self, first: JsonEvent) -> Result<Json, JsonStreamError> {
+        Ok(match first {
+            JsonEvent::Null => Json::Null,
+            JsonEvent::Boolean(b) => Json::Boolean(b),
+            JsonEvent::Number(n) => Json::Number(n),
+            JsonEvent::String(s) => Json::String(s),
+            JsonEvent::StartArray => {
+                let mut elements = Vec::new();
+                while let Some(element) = self.read_value()? {
+                    elements.push(element);
+                }
+                Json::Array(elements)
+            }
+            JsonEvent::StartObject => {
+                let mut members = BTreeMap::new();
+                while let Some(JsonEvent::Key(key)) = self.next_event()? {
+                    if let Some(value) = self.read_value()? {
+                        members.insert(key, value);
+                    }
+                }
+                Json::Object(Box::new(members))
+            }
+            JsonEvent::EndArray | JsonEvent::EndObject | JsonEvent::Key(_) => unreachable!()
+        })
+    }
+
+    /// Skip over the next whole value without building it. Returns false
+    /// instead if the enclosing array or object ends, or the input does.
+    pub fn skip_value(&mut self) -> Result<bool, JsonStreamError> {
+        let mut depth = 0;
+        loop {
+            match self.next_event()? {
+                None => return Ok(false),
+                Some(JsonEvent::StartArray) | Some(JsonEvent::StartObject) => depth += 1,
+                Some(JsonEvent::EndArray) | Some(JsonEvent::EndObject) if depth == 0 => return Ok(false),
+                Some(JsonEvent::EndArray) | Some(JsonEvent::EndObject) => depth -= 1,
+                Some(JsonEvent::Key(_)) => continue,
+                Some(_) => {}
+            }
+            if depth == 0 {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// Writes JSON a piece at a time, checking as it goes that the pieces make
+/// a single valid value
+pub struct JsonStreamWriter<W: Write> {
+    out: W,
+    indent: Option<&'static str>,
+    /// The open containers, and how many members each has so far
+    stack: Vec<(Container, usize)>,
+    /// A key was written and its value hasn't been yet
+    after_key: bool,
+    /// The top level value has been started
+    started: bool
+}
+
+impl<W: Write> JsonStreamWriter<W> {
+    /// A writer with no whitespace, as Json's Display
+    pub fn new(out: W) -> Self {
+        JsonStreamWriter { out, indent: None, stack: Vec::new(), after_key: false, started: false }
+    }
+
+    /// A writer that lays values out over lines, as Json's to_pretty_string
+    pub fn pretty(out: W) -> Self {
+        JsonStreamWriter { indent: Some("  "), ..JsonStreamWriter::new(out) }
+    }
+
+    fn newline(&mut self, level: usize) -> io::Result<()> {
+        if let Some(indent) = self.indent {
+            self.out.write_all(b"\n")?;
+            for _ in 0..level {
+                self.out.write_all(indent.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The comma and newline before the next array element or object member
+    fn separate(&mut self) -> io::Result<()> {
+        let level = self.stack.len();
+        let count = &mut self.stack.last_mut().unwrap().1;
+        *count += 1;
+        if *count > 1 {
+            self.out.write_all(b",")?;
+        }
+        self.newline(level)
+    }
+
+    fn before_value(&mut self) -> Result<(), JsonStreamError> {
+        match self.stack.last() {
+            None if self.started => return Err(JsonStreamError::Misuse("only one top level value is allowed")),
+            None => self.started = true,
+            Some(&(Container::Object, _)) if !self.after_key =>
+                return Err(JsonStreamError::Misuse("object members need a key first")),
+            Some(&(Container::Object, _)) => self.after_key = false,
+            Some(&(Container::Array, _)) => self.separate()?
+        }
+        Ok(())
+    }
+
+    fn scalar(&mut self, text: &str) -> Result<(), JsonStreamError> {
+        self.before_value()?;
+        self.out.write_all(text.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn start_object(&mut self) -> Result<(), JsonStreamError> {
+        self.before_value()?;
+        self.out.write_all(b"{")?;
+        self.stack.push((Container::Object, 0));
+        Ok(())
+    }
+
+    pub fn start_array(&mut self) -> Result<(), JsonStreamError> {
+        self.before_value()?;
+        self.out.write_all(b"[")?;
+        self.stack.push((Container::Array, 0));
+        Ok(())
+    }
+
+    fn end(&mut self, container: Container, close: &[u8]) -> Result<(), JsonStreamError> {
+        match self.stack.last() {
+            Some(&(open, count)) if open == container && !self.after_key => {
+                self.stack.pop();
+                if count > 0 {
+                    let level = self.stack.len();
+                    self.newline(level)?;
+                }
+                self.out.write_all(close)?;
+                Ok(())
+            }
+            _ => Err(JsonStreamError::Misuse(match container {
+                Container::Array => "there's no array to end here",
+                Container::Object => "there's no object to end here"
+            }))
+        }
+    }
+
+    pub fn end_object(&mut self) -> Result<(), JsonStreamError> {
+        self.end(Container::Object, b"}")
+    }
+
+    pub fn end_array(&mut self) -> Result<(), JsonStreamError> {
+        self.end(Container::Array, b"]")
+    }
+
+    /// The name of the next object member
+    pub fn key(&mut self, key: &str) -> Result<(), JsonStreamError> {
+        match self.stack.last() {
+            Some(&(Container::Object, _)) if !self.after_key => {}
+            _ => return Err(JsonStreamError::Misuse("keys only go directly inside objects"))
+        }
+        self.separate()?;
+        let mut quoted = String::new();
+        write_string(&mut quoted, key);
+        quoted.push_str(if self.indent.is_some() { ": " } else { ":" });
+        self.out.write_all(quoted.as_bytes())?;
+        self.after_key = true;
+        Ok(())
+    }
+
+    pub fn string(&mut self, s: &str) -> Result<(), JsonStreamError> {
+        let mut quoted = String::new();
+        write_string(&mut quoted, s);
+        self.scalar(&quoted)
+    }
+
+    /// A number; NaN and infinities come out as null
+    pub fn number(&mut self, n: f64) -> Result<(), JsonStreamError> {
+        self.scalar(&format_number(n))
+    }
+
+    pub fn boolean(&mut self, b: bool) -> Result<(), JsonStreamError> {
+        self.scalar(if b { "true" } else { "false" })
+    }
+
+    pub fn null(&mut self) -> Result<(), JsonStreamError> {
+        self.scalar("null")
+    }
+
+    /// A whole value at once
+    pub fn value(&mut self, value: &Json) -> Result<(), JsonStreamError> {
+        match *value {
+            Json::Null => self.null(),
+            Json::Boolean(b) => self.boolean(b),
+            Json::Number(n) => self.number(n),
+            Json::String(ref s) => self.string(s),
+            Json::Array(ref elements) => {
+                self.start_array()?;
+                for element in elements {
+                    self.value(element)?;
+                }
+                self.end_array()
+            }
+            Json::Object(ref members) => {
+                self.start_object()?;
+                for (key, member) in members.iter() {
+                    self.key(key)?;
+                    self.value(member)?;
+                }
+                self.end_object()
+            }
+        }
+    }
+
+    /// Write whatever an event from a `JsonStreamReader` stands for
+    pub fn event(&mut self, event: &JsonEvent) -> Result<(), JsonStreamError> {
+        match *event {
+            JsonEvent::StartObject => self.start_object(),
+            JsonEvent::EndObject => self.end_object(),
+            JsonEvent::StartArray => self.start_array(),
+            JsonEvent::EndArray => self.end_array(),
+            JsonEvent::Key(ref key) => self.key(key),
+            JsonEvent::String(ref s) => self.string(s),
+            JsonEvent::Number(n) => self.number(n),
+            JsonEvent::Boolean(b) => self.boolean(b),
+            JsonEvent::Null => self.null()
+        }
+    }
+
+    /// Check the value is complete, flush, and hand back the output
+    pub fn finish(mut self) -> Result<W, JsonStreamError> {
+        if !self.started || !self.stack.is_empty() {
+            return Err(JsonStreamError::Misuse("the value isn't finished"));
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+// Hands out its bytes one at a time, to push the reader across buffer
+// boundaries everywhere it could meet one
+#[cfg(test)]
+struct Trickle<'b>(&'b [u8]);
+
+#[cfg(test)]
+impl Read for Trickle<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self.0.split_first() {
+            Some((&byte, rest)) if !buffer.is_empty() => {
+                buffer[0] = byte;
+                self.0 = rest;
+                Ok(1)
+            }
+            _ => Ok(0)
+        }
+    }
+}
+
+#[cfg(test)]
+fn events<R: Read>(reader: &mut JsonStreamReader<R>) -> Result<Vec<JsonEvent>, JsonStreamError> {
+    let mut events = Vec::new();
+    while let Some(event) = reader.next_event()? {
+        events.push(event);
+    }
+    Ok(events)
+}
+
+#[test]
+fn test_read_events() {
+    use self::JsonEvent::*;
+    let text = "{\"name\": \"G clef \\ud834\\udd1e\", \"sizes\": [1, -2.5e1, []],\n \"ok\": true, \"none\": null, \"é\": {}}";
+    let expected = vec![
+        StartObject,
+        Key("name".to_string()), String("G clef \u{1d11e}".to_string()),
+        Key("sizes".to_string()), StartArray, Number(1.0), Number(-25.0), StartArray, EndArray, EndArray,
+        Key("ok".to_string()), Boolean(true),
+        Key("none".to_string()), Null,
+        Key("é".to_string()), StartObject, EndObject,
+        EndObject
+    ];
+    let mut reader = JsonStreamReader::new(text.as_bytes());
+    assert_eq!(events(&mut reader).unwrap(), expected);
+    assert_eq!(reader.position(), (2, 36));
+    assert_eq!(reader.next_event().unwrap(), None);
+
+    let mut trickled = JsonStreamReader::new(Trickle(text.as_bytes()));
+    assert_eq!(events(&mut trickled).unwrap(), expected);
+
+    let lines = "{\"n\": 1}\n{\"n\": 2}\n\"three\" 4\n";
+    let config = ReaderConfig { multiple_values: true, ..ReaderConfig::default() };
+    let mut reader = JsonStreamReader::with_config(lines.as_bytes(), config);
+    let mut values = Vec::new();
+    while let Some(value) = reader.read_value().unwrap() {
+        values.push(value.to_string());
+    }
+    assert_eq!(values, vec!["{\"n\":1}", "{\"n\":2}", "\"three\"", "4"]);
+    let mut empty = JsonStreamReader::with_config(&b"  "[..], config);
+    assert_eq!(empty.next_event().unwrap(), None);
+}
+
+#[test]
+fn test_read_value_separators_and_bom() {
+    // The values read, one per line, or the error that stopped them
+    let values = |text: &str| -> Result<Vec<String>, String> {
+        let config = ReaderConfig { multiple_values: true, ..ReaderConfig::default() };
+        let mut reader = JsonStreamReader::with_config(Trickle(text.as_bytes()), config);
+        let mut values = Vec::new();
+        while let Some(value) = reader.read_value().map_err(|error| error.to_string())? {
+            values.push(value.to_string());
+        }
+        Ok(values)
+    };
+    // Self-delimiting values may touch, but bare ones need a gap
+    assert_eq!(values("{}[]\"a\"1 true\nnull").unwrap(), ["{}", "[]", "\"a\"", "1", "true", "null"]);
+    let gap = "expected whitespace between values";
+    assert_eq!(values("truefalse"), Err(format!("invalid JSON at line 1, column 5: {}", gap)));
+    assert_eq!(values("1[2]"), Err(format!("invalid JSON at line 1, column 2: {}", gap)));
+
+    // A byte order mark is skipped and isn't counted as a column
+    assert_eq!(values("\u{feff}1 2").unwrap(), ["1", "2"]);
+    let mut reader = JsonStreamReader::new(Trickle("\u{feff}[1 2]".as_bytes()));
+    let expected = Json::parse("\u{feff}[1 2]").unwrap_err();
+    assert_eq!(events(&mut reader).unwrap_err().to_string(), expected.to_string());
+    let mut reader = JsonStreamReader::new(&b"\xef\xbb["[..]);
+    assert_eq!(reader.next_event().unwrap_err().to_string(), "invalid JSON at line 1, column 1: expected a value");
+}
+
+#[test]
+fn test_read_errors_match_the_tree_parser() {
+    let inputs = ["", "[1,]", "[1 2]", "{\n  \"a\": 1,\n  b: 2\n}", "{\"é\" 1}", "01", "1.", "-",
+                  "1e400", "NaN", "tru", "\"tab\there\"", "\"\\x\"", "\"\\u12\"", "\"\\ud834\"",
+                  "\"\\ud834\\u0041\"", "\"\\udd1e\"", "\"open", "{} {}", "{\"a\" : [1, 2}"];
+    for input in &inputs {
+        let expected = Json::parse(input).unwrap_err();
+        let mut reader = JsonStreamReader::new(Trickle(input.as_bytes()));
+        match events(&mut reader) {
+            Err(JsonStreamError::Syntax(error)) => assert_eq!(error, expected, "{:?}", input),
+            other => panic!("{:?} gave {:?}", input, other)
+        }
+        // The reader stays failed
+        assert!(reader.next_event().is_err());
+    }
+
+    let deep = "[".repeat(DEFAULT_MAX_DEPTH + 1);
+    let mut reader = JsonStreamReader::new(deep.as_bytes());
+    match events(&mut reader) {
+        Err(JsonStreamError::Syntax(error)) => assert_eq!(error, Json::parse(&deep).unwrap_err()),
+        other => panic!("{:?}", other)
+    }
+
+    let config = ReaderConfig { max_token_bytes: 4, ..ReaderConfig::default() };
+    let mut reader = JsonStreamReader::with_config(&b"[\"long string\"]"[..], config);
+    assert_eq!(reader.next_event().unwrap(), Some(JsonEvent::StartArray));
+    assert_eq!(reader.next_event().unwrap_err().to_string(), "invalid JSON at line 1, column 8: string too long");
+    let mut reader = JsonStreamReader::new(&b"\"\xff\""[..]);
+    assert_eq!(reader.next_event().unwrap_err().to_string(), "invalid JSON at line 1, column 4: invalid UTF-8");
+}
+
+#[test]
+fn test_stream_large_array() {
+    // A million bytes of records, never all in memory at once
+    struct Records {
+        emitted: usize,
+        pending: Vec<u8>
+    }
+    impl Read for Records {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            if self.pending.is_empty() && self.emitted <= 20_000 {
+                self.pending = match self.emitted {
+                    0 => b"[".to_vec(),
+                    20_000 => b"]".to_vec(),
+                    n => format!("{}{{\"id\": {}, \"level\": \"info\"}}", if n > 1 { "," } else { "" }, n)
+                        .into_bytes()
+                };
+                self.emitted += 1;
+            }
+            let n = buffer.len().min(self.pending.len());
+            buffer[..n].copy_from_slice(&self.pending[..n]);
+            self.pending.drain(..n);
+            Ok(n)
+        }
+    }
+
+    let mut reader = JsonStreamReader::new(Records { emitted: 0, pending: Vec::new() });
+    assert_eq!(reader.next_event().unwrap(), Some(JsonEvent::StartArray));
+    let mut count = 0;
+    let mut id_sum = 0;
+    while let Some(record) = reader.read_value().unwrap() {
+        count += 1;
+        id_sum += record["id"].as_i64().unwrap();
+        if count == 10 {
+            // Skipping works a value at a time too
+            assert!(reader.skip_value().unwrap());
+            count += 1;
+            id_sum += 11;
+        }
+    }
+    assert_eq!(count, 19_999);
+    assert_eq!(id_sum, 19_999 * 20_000 / 2);
+    assert_eq!(reader.next_event().unwrap(), None);
+}
+
+#[test]
+fn test_write_events() {
+    let value = Json::parse(r#"{"b": [1, "two", {"three": null}], "a": {}, "c": [], "d": true}"#).unwrap();
+
+    let mut compact = JsonStreamWriter::new(Vec::new());
+    compact.value(&value).unwrap();
+    assert_eq!(String::from_utf8(compact.finish().unwrap()).unwrap(), value.to_string());
+    let mut pretty = JsonStreamWriter::pretty(Vec::new());
+    pretty.value(&value).unwrap();
+    assert_eq!(String::from_utf8(pretty.finish().unwrap()).unwrap(), value.to_pretty_string());
+
+    // Piping events from a reader to a writer copies the document
+    let mut reader = JsonStreamReader::new(io::Cursor::new(value.to_pretty_string()));
+    let mut writer = JsonStreamWriter::new(Vec::new());
+    while let Some(event) = reader.next_event().unwrap() {
+        writer.event(&event).unwrap();
+    }
+    assert_eq!(writer.finish().unwrap(), value.to_string().into_bytes());
+
+    let mut writer = JsonStreamWriter::new(Vec::new());
+    writer.start_object().unwrap();
+    writer.key("log").unwrap();
+    writer.start_array().unwrap();
+    writer.string("line \"one\"").unwrap();
+    writer.number(f64::NAN).unwrap();
+    writer.end_array().unwrap();
+    assert!(writer.string("no key").is_err());
+    assert!(writer.end_array().is_err());
+    writer.key("done").unwrap();
+    assert!(writer.key("twice").is_err());
+    assert!(writer.end_object().is_err());
+    writer.boolean(true).unwrap();
+    writer.end_object().unwrap();
+    assert!(writer.null().is_err());
+    assert_eq!(writer.finish().unwrap(), br#"{"log":["line \"one\"",null],"done":true}"#.to_vec());
+
+    let mut unfinished = JsonStreamWriter::new(Vec::new());
+    unfinished.start_array().unwrap();
+    assert_eq!(unfinished.finish().unwrap_err().to_string(), "can't write JSON: the value isn't finished");
+    assert!(JsonStreamWriter::new(Vec::new()).finish().is_err());
+}
@@ -4,7 +4,60 @@ pub mod chapter_9;
 pub mod chapter_10;
 pub mod chapter_11;
 pub mod chapter_12;
+pub mod comparator;
+pub mod calendar;
+pub mod disk_btree;
+pub mod duration_format;
+pub mod enum_repr;
+pub mod http_cache;
+pub mod http_message;
+pub mod http_server;
+pub mod http_status;
+pub mod interval_tree;
+pub mod json;
+pub mod json_convert;
+pub mod json_patch;
+pub mod json_query;
+pub mod json_schema;
+pub mod json_stream;
+pub mod pattern;
+pub mod pet_registry;
+pub mod radix_trie;
+pub mod rough_time_locale;
+pub mod rough_time_parse;
+pub mod segment_tree;
+pub mod sorting;
+pub mod time_zone;
 
 fn main() {
-    println!("Hello, world!");
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        // rust_practice serve [address]
+        Some("serve") => {
+            let address = args.next().unwrap_or_else(|| "127.0.0.1:8080".to_string());
+            let served = http_server::Server::bind(&address, http_server::demo_router())
+                .and_then(|server| {
+                    println!("Listening on http://{}", server.local_addr()?);
+                    server.run()
+                });
+            if let Err(error) = served {
+                eprintln!("rust_practice: {}", error);
+                std::process::exit(1);
+            }
+        }
+        // rust_practice bench-sort [length]
+        Some("bench-sort") => {
+            let len = args.next().and_then(|len| len.parse().ok()).unwrap_or(1_000_000);
+            match sorting::benchmark(len) {
+                Ok(results) => for result in results {
+                    println!("{}", result);
+                },
+                Err(error) => {
+                    eprintln!("rust_practice: {}", error);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => println!("Hello, world!")
+    }
 }
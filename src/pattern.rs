@@ -0,0 +1,604 @@
+#![allow(dead_code)]
+
+// A small regular expression matcher, enough for the patterns
+// people put in JSON schemas. It understands the common part of ECMAScript
+// syntax: literals, '.', classes like [a-z] and [^,], \d \w \s and their
+// negations, ^ and $, groups with or without capturing (captures aren't
+// reported), alternation, and the * + ? {n} {n,} {n,m} quantifiers, greedy
+// or lazy. Backreferences, lookaround and \b aren't supported.
+//
+// Like JSON schema's "pattern", matching looks for the pattern anywhere in
+// the text unless it's anchored. Patterns are compiled to a little program
+// that runs over the text once, tracking every way it could match at the
+// same time, so there's no backtracking: (a*)*b takes linear time, and long
+// texts don't use any stack.
+
+use std::fmt;
+
+/// Where and why a pattern couldn't be compiled. `position` counts
+/// characters from 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatternError {
+    pub position: usize,
+    pub reason: &'static str
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(dest, "invalid pattern at position {}: {}", self.position, self.reason)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Literal(char),
+    Any,
+    /// Inclusive ranges
+    Class { ranges: Vec<(char, char)>, negated: bool },
+    Start,
+    End,
+    Sequence(Vec<Node>),
+    Alternatives(Vec<Node>),
+    Repeat { node: Box<Node>, min: u32, max: Option<u32>, greedy: bool }
+}
+
+const DIGITS: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+const SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' '), ('\u{a0}', '\u{a0}'), ('\u{2028}', '\u{2029}'),
+                                 ('\u{feff}', '\u{feff}')];
+
+/// Every character not in `ranges`, which must be sorted and not overlap
+fn complement(ranges: &[(char, char)]) -> Vec<(char, char)> {
+    let mut result = Vec::new();
+    let mut next = 0u32;
+    for &(low, high) in ranges {
+        if u32::from(low) > next {
+            result.extend(char_range(next, u32::from(low) - 1));
+        }
+        next = u32::from(high) + 1;
+    }
+    result.extend(char_range(next, u32::from(char::MAX)));
+    result
+}
+
+/// The range between two code points, skipping the surrogates, which
+/// aren't chars
+fn char_range(low: u32, high: u32) -> Vec<(char, char)> {
+    let pieces = [(low, high.min(0xd7ff)), (low.max(0xe000), high)];
+    pieces.iter()
+        .filter(|&&(low, high)| low <= high)
+        .filter_map(|&(low, high)| Some((std::char::from_u32(low)?, std::char::from_u32(high)?)))
+        .collect()
+}
+
+struct PatternParser {
+    chars: Vec<char>,
+    position: usize,
+    /// How many groups we're inside
+    depth: usize
+}
+
+impl PatternParser {
+    fn error<T>(&self, reason: &'static str) -> Result<T, PatternError> {
+        Err(PatternError { position: self.position, reason })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).cloned()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn alternatives(&mut self) -> Result<Node, PatternError> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.eat('|') {
+            alternatives.push(self.sequence()?);
+        }
+        Ok(if alternatives.len() == 1 { alternatives.remove(0) } else { Node::Alternatives(alternatives) })
+    }
+
+    fn sequence(&mut self) -> Result<Node, PatternError> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+        Ok(Node::Sequence(nodes))
+    }
+
+    fn atom(&mut self) -> Result<Node, PatternError> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return self.error("unexpected end of pattern")
+        };
+        self.position += 1;
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                if self.eat('?') && !self.eat(':') {
+                    return self.error("only (?: groups are supported");
+                }
+                if self.depth == MAX_NESTING {
+                    return self.error("groups nested too deeply");
+                }
+                self.depth += 1;
+                let inner = self.alternatives()?;
+                self.depth -= 1;
+                if !self.eat(')') {
+                    return self.error("expected ')'");
+                }
+                inner
+            }
+            '[' => self.class()?,
+            '\\' => match self.escape()? {
+                Ok(c) => Node::Literal(c),
+                Err(ranges) => Node::Class { ranges, negated: false }
+            },
+            '*' | '+' | '?' | '{' => {
+                self.position -= 1;
+                return self.error("nothing to repeat");
+            }
+            c => Node::Literal(c)
+        })
+    }
+
+    /// What follows a backslash: a single character, or a class like \d
+    fn escape(&mut self) -> Result<Result<char, Vec<(char, char)>>, PatternError> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return self.error("unexpected end of pattern")
+        };
+        self.position += 1;
+        Ok(match c {
+            'd' => Err(DIGITS.to_vec()),
+            'D' => Err(complement(DIGITS)),
+            'w' => Err(WORD.to_vec()),
+            'W' => Err(complement(WORD)),
+            's' => Err(SPACE.to_vec()),
+            'S' => Err(complement(SPACE)),
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            't' => Ok('\t'),
+            'f' => Ok('\u{c}'),
+            'v' => Ok('\u{b}'),
+            '0' => Ok('\0'),
+            'u' | 'x' => {
+                let count = if c == 'u' { 4 } else { 2 };
+                let digits: String = self.chars.iter().skip(self.position).take(count).collect();
+                let code = u32::from_str_radix(&digits, 16).ok()
+                    .filter(|_| digits.len() == count && digits.chars().all(|c| c.is_ascii_hexdigit()));
+                match code.and_then(std::char::from_u32) {
+                    Some(c) => {
+                        self.position += count;
+                        Ok(c)
+                    }
+                    None => return self.error("expected hex digits")
+                }
+            }
+            c if c.is_ascii_alphanumeric() => {
+                self.position -= 1;
+                return self.error("unsupported escape");
+            }
+            c => Ok(c)
+        })
+    }
+
+    /// A [...] class, after the '['
+    fn class(&mut self) -> Result<Node, PatternError> {
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+        loop {
+            let c = match self.peek() {
+                Some(']') => {
+                    self.position += 1;
+                    break;
+                }
+                Some(c) => c,
+                None => return self.error("expected ']'")
+            };
+            self.position += 1;
+            let low = if c == '\\' {
+                match self.escape()? {
+                    Ok(c) => c,
+                    Err(class) => {
+                        ranges.extend(class);
+                        continue;
+                    }
+                }
+            } else {
+                c
+            };
+            // A '-' at the end is just a '-'
+            if self.peek() == Some('-') && self.chars.get(self.position + 1).is_some_and(|&c| c != ']') {
+                self.position += 1;
+                let high = match self.peek() {
+                    Some('\\') => {
+                        self.position += 1;
+                        match self.escape()? {
+                            Ok(c) => c,
+                            Err(_) => return self.error("a class can't end a range")
+                        }
+                    }
+                    Some(c) => {
+                        self.position += 1;
+                        c
+                    }
+                    None => return self.error("expected ']'")
+                };
+                if high < low {
+                    return self.error("range out of order");
+                }
+                ranges.push((low, high));
+            } else {
+                ranges.push((low, low));
+            }
+        }
+        Ok(Node::Class { ranges, negated })
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect::<String>().parse().ok()
+    }
+
+    fn quantified(&mut self, node: Node) -> Result<Node, PatternError> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let start = self.position;
+                self.position += 1;
+                let min = match self.number() {
+                    Some(min) => min,
+                    None => return self.error("expected a number")
+                };
+                let max = if !self.eat(',') {
+                    Some(min)
+                } else if self.peek() == Some('}') {
+                    None
+                } else {
+                    match self.number() {
+                        Some(max) => Some(max),
+                        None => return self.error("expected a number")
+                    }
+                };
+                if self.peek() != Some('}') {
+                    return self.error("expected '}'");
+                }
+                if max.is_some_and(|max| max < min) {
+                    self.position = start;
+                    return self.error("numbers out of order in {}");
+                }
+                (min, max)
+            }
+            _ => return Ok(node)
+        };
+        self.position += 1;
+        let greedy = !self.eat('?');
+        if let Some('*') | Some('+') | Some('?') | Some('{') = self.peek() {
+            return self.error("nothing to repeat");
+        }
+        Ok(Node::Repeat { node: Box::new(node), min, max, greedy })
+    }
+}
+
+/// One step of a compiled pattern. Char, Any and Class consume a
+/// character; the rest don't.
+#[derive(Clone, Debug, PartialEq)]
+enum Inst {
+    Char(char),
+    Any,
+    Class { ranges: Vec<(char, char)>, negated: bool },
+    Start,
+    End,
+    /// Carry on at both, preferring the first
+    Split(usize, usize),
+    Jump(usize),
+    Match
+}
+
+/// Patterns compiling to more instructions than this are refused, since
+/// matching takes time proportional to the program's length
+const MAX_PROGRAM_LEN: usize = 50_000;
+
+/// Groups can't be nested deeper than this
+const MAX_NESTING: usize = 256;
+
+/// A Split to `next` and `skip`, with `next` preferred if `greedy`
+fn split(next: usize, skip: usize, greedy: bool) -> Inst {
+    if greedy { Inst::Split(next, skip) } else { Inst::Split(skip, next) }
+}
+
+/// Append the instructions for `node` to `program`, or return false if
+/// it's grown too long
+fn compile(node: &Node, program: &mut Vec<Inst>) -> bool {
+    if program.len() > MAX_PROGRAM_LEN {
+        return false;
+    }
+    match *node {
+        Node::Literal(c) => program.push(Inst::Char(c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class { ref ranges, negated } => program.push(Inst::Class { ranges: ranges.clone(), negated }),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Sequence(ref nodes) => return nodes.iter().all(|node| compile(node, program)),
+        Node::Alternatives(ref alternatives) => {
+            let (last, others) = alternatives.split_last().unwrap();
+            let mut jumps = Vec::new();
+            for alternative in others {
+                let split_at = program.len();
+                program.push(Inst::Match);
+                if !compile(alternative, program) {
+                    return false;
+                }
+                jumps.push(program.len());
+                program.push(Inst::Match);
+                program[split_at] = Inst::Split(split_at + 1, program.len());
+            }
+            if !compile(last, program) {
+                return false;
+            }
+            for jump in jumps {
+                program[jump] = Inst::Jump(program.len());
+            }
+        }
+        Node::Repeat { ref node, min, max, greedy } => {
+            for _ in 0..min {
+                if !compile(node, program) {
+                    return false;
+                }
+            }
+            // The placeholders below are filled in once the jump targets
+            // are known
+            match max {
+                None => {
+                    let split_at = program.len();
+                    program.push(Inst::Match);
+                    if !compile(node, program) {
+                        return false;
+                    }
+                    program.push(Inst::Jump(split_at));
+                    program[split_at] = split(split_at + 1, program.len(), greedy);
+                }
+                Some(max) => {
+                    // Skipping one optional copy skips all the rest
+                    let mut splits = Vec::new();
+                    for _ in min..max {
+                        splits.push(program.len());
+                        program.push(Inst::Match);
+                        if !compile(node, program) {
+                            return false;
+                        }
+                    }
+                    for split_at in splits {
+                        program[split_at] = split(split_at + 1, program.len(), greedy);
+                    }
+                }
+            }
+        }
+    }
+    program.len() <= MAX_PROGRAM_LEN
+}
+
+/// A compiled regular expression
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    source: String,
+    program: Vec<Inst>
+}
+
+/// A thread of the matcher: where it is in the program, and where in the
+/// text its match started
+#[derive(Copy, Clone)]
+struct Thread {
+    pc: usize,
+    start: usize
+}
+
+/// The threads at one position in the text, highest priority first
+struct ThreadList {
+    threads: Vec<Thread>,
+    /// The position each instruction was last added at, so a thread that
+    /// loops back without consuming anything is only added once
+    added_at: Vec<usize>
+}
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Pattern, PatternError> {
+        let mut parser = PatternParser { chars: source.chars().collect(), position: 0, depth: 0 };
+        let root = parser.alternatives()?;
+        if parser.position < parser.chars.len() {
+            return parser.error("unmatched ')'");
+        }
+        let mut program = Vec::new();
+        if !compile(&root, &mut program) {
+            return Err(PatternError { position: 0, reason: "pattern is too big" });
+        }
+        program.push(Inst::Match);
+        Ok(Pattern { source: source.to_string(), program })
+    }
+
+    /// Add a thread at `pc`, and the threads it leads to without consuming
+    /// anything, in priority order
+    fn add_thread(&self, list: &mut ThreadList, pc: usize, start: usize, text: &[char], position: usize) {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if list.added_at[pc] == position {
+                continue;
+            }
+            list.added_at[pc] = position;
+            match self.program[pc] {
+                Inst::Jump(to) => stack.push(to),
+                Inst::Split(first, second) => {
+                    stack.push(second);
+                    stack.push(first);
+                }
+                Inst::Start => if position == 0 {
+                    stack.push(pc + 1);
+                },
+                Inst::End => if position == text.len() {
+                    stack.push(pc + 1);
+                },
+                _ => list.threads.push(Thread { pc, start })
+            }
+        }
+    }
+
+    /// Run the program over `text` as a Pike VM, stepping every thread a
+    /// character at a time. This takes time proportional to the text's
+    /// length times the program's, and finds the match a backtracking
+    /// matcher would: the leftmost, with each quantifier getting what it
+    /// would try first. Stops at the first match found if `any` is true.
+    fn run(&self, text: &[char], any: bool) -> Option<(usize, usize)> {
+        let new_list = || ThreadList { threads: Vec::new(), added_at: vec![usize::MAX; self.program.len()] };
+        let (mut current, mut next) = (new_list(), new_list());
+        let mut found = None;
+        for position in 0..=text.len() {
+            // Matches starting here rank below those that started earlier
+            if found.is_none() {
+                self.add_thread(&mut current, 0, position, text, position);
+            }
+            if current.threads.is_empty() {
+                break;
+            }
+            for i in 0..current.threads.len() {
+                let Thread { pc, start } = current.threads[i];
+                let consumed = match self.program[pc] {
+                    Inst::Match => {
+                        found = Some((start, position));
+                        if any {
+                            return found;
+                        }
+                        // Every thread after this one has lower priority
+                        break;
+                    }
+                    Inst::Char(c) => text.get(position) == Some(&c),
+                    Inst::Any => text.get(position)
+                        .is_some_and(|&c| c != '\n' && c != '\r' && c != '\u{2028}' && c != '\u{2029}'),
+                    Inst::Class { ref ranges, negated } => text.get(position).is_some_and(|&c| {
+                        ranges.iter().any(|&(low, high)| low <= c && c <= high) != negated
+                    }),
+                    _ => false
+                };
+                if consumed {
+                    self.add_thread(&mut next, pc + 1, start, text, position + 1);
+                }
+            }
+            current.threads.clear();
+            std::mem::swap(&mut current, &mut next);
+        }
+        found
+    }
+
+    /// True if the pattern matches somewhere in `text`
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        self.run(&chars, true).is_some()
+    }
+
+    /// The first match in `text`, as a byte range. Greedy quantifiers make
+    /// it as long as they can.
+    pub fn find(&self, text: &str) -> Option<(usize, usize)> {
+        let chars: Vec<char> = text.chars().collect();
+        let offsets: Vec<usize> = text.char_indices().map(|(i, _)| i).chain(Some(text.len())).collect();
+        self.run(&chars, false).map(|(start, end)| (offsets[start], offsets[end]))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        dest.write_str(&self.source)
+    }
+}
+
+#[test]
+fn test_matching() {
+    let matches = |pattern: &str, text: &str| Pattern::new(pattern).unwrap().is_match(text);
+    assert!(matches("abc", "xxabcxx"));
+    assert!(!matches("^abc", "xxabc"));
+    assert!(matches("^abc$", "abc"));
+    assert!(!matches("^abc$", "abcd"));
+    assert!(matches("^$", ""));
+    assert!(matches("a.c", "abc") && !matches("a.c", "a\nc"));
+    assert!(matches("^[a-z]+$", "hello") && !matches("^[a-z]+$", "Hello"));
+    assert!(matches("^[^,]*$", "no commas") && !matches("^[^,]*$", "a,b"));
+    assert!(matches("^[-a-c]+$", "a-b") && matches("^[a\\-]+$", "a-") && matches("^[+-]$", "-"));
+    assert!(matches("^\\d{3}-\\d{4}$", "555-1234") && !matches("^\\d{3}-\\d{4}$", "55-1234"));
+    assert!(matches("^\\w+@\\w+\\.com$", "me@example.com"));
+    assert!(matches("^\\S+\\s\\S+$", "two\twords") && !matches("^\\S+$", "two words"));
+    assert!(matches("^[\\d.]+$", "3.14") && matches("^\\D+$", "pi") && matches("^[\\W]$", "!"));
+    assert!(matches("^(cat|dog)s?$", "dogs") && !matches("^(cat|dog)s?$", "cow"));
+    assert!(matches("^(?:ab)+$", "ababab") && !matches("^(?:ab)+$", "aba"));
+    assert!(matches("^a{2,}$", "aaa") && !matches("^a{2,}$", "a"));
+    assert!(matches("^a{1,2}b$", "aab") && !matches("^a{1,2}b$", "aaab"));
+    assert!(matches("^(a|ab)(c|bcd)(d*)$", "abcd"));
+    assert!(matches("^(a*)*b$", "aab") && matches("^(a?)*$", ""));
+    assert!(matches("^\\u00e9\\x41\\.\\/$", "éA./") && matches("^é+$", "ééé"));
+    assert!(matches("^(\\([0-9]{3}\\))?[0-9]{3}-[0-9]{4}$", "(888)555-1212"));
+    assert!(!matches("^(\\([0-9]{3}\\))?[0-9]{3}-[0-9]{4}$", "(800)FLOWERS"));
+    assert!(matches("", "anything"));
+}
+
+#[test]
+fn test_find() {
+    let find = |pattern: &str, text: &str| Pattern::new(pattern).unwrap().find(text);
+    assert_eq!(find("b+", "abbbc"), Some((1, 4)));
+    assert_eq!(find("b+?", "abbbc"), Some((1, 2)));
+    assert_eq!(find("<.*>", "<a><b>"), Some((0, 6)));
+    assert_eq!(find("<.*?>", "<a><b>"), Some((0, 3)));
+    assert_eq!(find("é", "caté"), Some((3, 5)));
+    assert_eq!(find("x", "abc"), None);
+}
+
+#[test]
+fn test_pattern_errors() {
+    let error = |pattern: &str| Pattern::new(pattern).unwrap_err();
+    assert_eq!(error("*a"), PatternError { position: 0, reason: "nothing to repeat" });
+    assert_eq!(error("a**"), PatternError { position: 2, reason: "nothing to repeat" });
+    assert_eq!(error("(ab"), PatternError { position: 3, reason: "expected ')'" });
+    assert_eq!(error("ab)"), PatternError { position: 2, reason: "unmatched ')'" });
+    assert_eq!(error("[ab"), PatternError { position: 3, reason: "expected ']'" });
+    assert_eq!(error("[z-a]"), PatternError { position: 4, reason: "range out of order" });
+    assert_eq!(error("a{3,1}"), PatternError { position: 1, reason: "numbers out of order in {}" });
+    assert_eq!(error("a{x}"), PatternError { position: 2, reason: "expected a number" });
+    assert_eq!(error("(?=a)"), PatternError { position: 2, reason: "only (?: groups are supported" });
+    assert_eq!(error("\\bword"), PatternError { position: 1, reason: "unsupported escape" });
+    assert_eq!(error("\\u12"), PatternError { position: 2, reason: "expected hex digits" });
+    assert_eq!(error("a\\").to_string(), "invalid pattern at position 2: unexpected end of pattern");
+}
+
+#[test]
+fn test_long_and_pathological_inputs() {
+    let long = "a".repeat(100_000);
+    assert!(Pattern::new("^[a-z]+$").unwrap().is_match(&long));
+    assert!(Pattern::new("^(a|b)*$").unwrap().is_match(&long));
+    assert_eq!(Pattern::new("a+?$").unwrap().find(&long), Some((0, 100_000)));
+    assert!(!Pattern::new("^(a*)*b$").unwrap().is_match(&long));
+    assert!(!Pattern::new("^(a?){30}a{30}$").unwrap().is_match(&"a".repeat(29)));
+
+    let nested = format!("{}a{}", "(".repeat(300), ")".repeat(300));
+    assert_eq!(Pattern::new(&nested).unwrap_err().reason, "groups nested too deeply");
+    assert_eq!(Pattern::new("(a{1000}){1000}").unwrap_err().reason, "pattern is too big");
+}
@@ -0,0 +1,582 @@
+#![allow(dead_code)]
+
+// A small entity-component store for pets. Every pet has a species, a name
+// and a birth date; anything else about it goes in components, which are
+// values of any type implementing Component, at most one of each type per
+// pet. Components of one type are kept together in a map from pet to
+// value, so going through every pet with a Collar doesn't look at the pets
+// without one.
+//
+// Queries pick pets by species, age and which components they have, like
+// "every Dog younger than 2 years with a Collar". A snapshot writes the
+// whole registry as Json, components under their NAME; loading one needs
+// the component types registered first, so their names mean something.
+// Loading is all or nothing.
+
+use crate::calendar::DateTime;
+use crate::chapter_10::{rough_time_to_english, Pet, RoughTime, TimeUnit};
+use crate::json::Json;
+use crate::json_convert::{self, FromJson, FromJsonError, ToJson};
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Identifies a pet. Ids aren't reused, even after a pet is removed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntityId(u64);
+
+impl EntityId {
+    pub fn number(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for EntityId {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(dest, "#{}", self.0)
+    }
+}
+
+/// What every pet has
+#[derive(Clone, Debug, PartialEq, ToJson, FromJson)]
+pub struct PetRecord {
+    pub species: Pet,
+    pub name: String,
+    pub born: DateTime
+}
+
+impl PetRecord {
+    /// How long ago the pet was born, roughly
+    pub fn age(&self, now: DateTime) -> RoughTime {
+        RoughTime::between_dates(self.born, now)
+    }
+
+    /// Like "Rex the Dog, born 2 years ago"
+    pub fn describe(&self, now: DateTime) -> String {
+        format!("{} the {}, born {}", self.name, self.species, rough_time_to_english(self.age(now)))
+    }
+}
+
+/// Extra data a pet can have. `NAME` labels components of this type in
+/// snapshots, so it has to be unique and shouldn't change.
+pub trait Component: ToJson + FromJson + 'static {
+    const NAME: &'static str;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RegistryError {
+    NoSuchPet(EntityId),
+    /// A snapshot has a component type that hasn't been registered
+    UnknownComponent(String),
+    /// A snapshot has two pets with the same id
+    DuplicatePet(EntityId),
+    /// Another component type already has this NAME
+    DuplicateComponentName(&'static str),
+    BadSnapshot(FromJsonError)
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RegistryError::NoSuchPet(id) => write!(dest, "there's no pet {}", id),
+            RegistryError::UnknownComponent(ref name) =>
+                write!(dest, "the component type {:?} isn't registered", name),
+            RegistryError::DuplicatePet(id) => write!(dest, "the snapshot has pet {} twice", id),
+            RegistryError::DuplicateComponentName(name) =>
+                write!(dest, "another component type is already called {:?}", name),
+            RegistryError::BadSnapshot(ref error) => write!(dest, "bad snapshot: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+impl From<FromJsonError> for RegistryError {
+    fn from(error: FromJsonError) -> Self {
+        RegistryError::BadSnapshot(error)
+    }
+}
+
+/// The components of one type, with the type erased so the registry can
+/// hold every kind together
+trait Storage {
+    fn name(&self) -> &'static str;
+    fn contains(&self, id: EntityId) -> bool;
+    fn remove(&mut self, id: EntityId);
+    fn to_json(&self, id: EntityId) -> Option<Json>;
+    fn insert_json(&mut self, id: EntityId, json: &Json) -> Result<(), FromJsonError>;
+    /// A new, empty storage for the same type
+    fn empty(&self) -> Box<dyn Storage>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<C: Component> Storage for BTreeMap<EntityId, C> {
+    fn name(&self) -> &'static str {
+        C::NAME
+    }
+
+    fn contains(&self, id: EntityId) -> bool {
+        self.contains_key(&id)
+    }
+
+    fn remove(&mut self, id: EntityId) {
+        BTreeMap::remove(self, &id);
+    }
+
+    fn to_json(&self, id: EntityId) -> Option<Json> {
+        self.get(&id).map(ToJson::to_json)
+    }
+
+    fn insert_json(&mut self, id: EntityId, json: &Json) -> Result<(), FromJsonError> {
+        self.insert(id, C::from_json(json)?);
+        Ok(())
+    }
+
+    fn empty(&self) -> Box<dyn Storage> {
+        Box::new(BTreeMap::<EntityId, C>::new())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Pets and their components
+#[derive(Default)]
+pub struct PetRegistry {
+    next_id: u64,
+    pets: BTreeMap<EntityId, PetRecord>,
+    storages: HashMap<TypeId, Box<dyn Storage>>
+}
+
+impl PetRegistry {
+    ///Constructor
+    pub fn new() -> PetRegistry {
+        PetRegistry::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pets.is_empty()
+    }
+
+    /// Add a pet, and return its id
+    pub fn spawn(&mut self, species: Pet, name: &str, born: DateTime) -> EntityId {
+        self.next_id += 1;
+        let id = EntityId(self.next_id);
+        self.pets.insert(id, PetRecord { species, name: name.to_string(), born });
+        id
+    }
+
+    /// Remove a pet and all its components
+    pub fn despawn(&mut self, id: EntityId) -> Option<PetRecord> {
+        let record = self.pets.remove(&id)?;
+        for storage in self.storages.values_mut() {
+            storage.remove(id);
+        }
+        Some(record)
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&PetRecord> {
+        self.pets.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut PetRecord> {
+        self.pets.get_mut(&id)
+    }
+
+    /// Every pet, in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &PetRecord)> {
+        self.pets.iter().map(|(&id, record)| (id, record))
+    }
+
+    /// Make a component type known, so snapshots with it can be loaded.
+    /// Inserting a component registers its type too. Snapshots tell types
+    /// apart by NAME, so a type can't share its NAME with another one.
+    pub fn register<C: Component>(&mut self) -> Result<(), RegistryError> {
+        let type_id = TypeId::of::<C>();
+        if self.storages.contains_key(&type_id) {
+            return Ok(());
+        }
+        if self.storages.values().any(|storage| storage.name() == C::NAME) {
+            return Err(RegistryError::DuplicateComponentName(C::NAME));
+        }
+        self.storages.insert(type_id, Box::new(BTreeMap::<EntityId, C>::new()));
+        Ok(())
+    }
+
+    fn storage<C: Component>(&self) -> Option<&BTreeMap<EntityId, C>> {
+        self.storages.get(&TypeId::of::<C>()).and_then(|storage| storage.as_any().downcast_ref())
+    }
+
+    fn storage_mut<C: Component>(&mut self) -> Option<&mut BTreeMap<EntityId, C>> {
+        self.storages.get_mut(&TypeId::of::<C>()).and_then(|storage| storage.as_any_mut().downcast_mut())
+    }
+
+    /// Give a pet a component, returning the one of the same type it had
+    pub fn insert<C: Component>(&mut self, id: EntityId, component: C) -> Result<Option<C>, RegistryError> {
+        if !self.pets.contains_key(&id) {
+            return Err(RegistryError::NoSuchPet(id));
+        }
+        self.register::<C>()?;
+        Ok(self.storage_mut().unwrap().insert(id, component))
+    }
+
+    pub fn remove<C: Component>(&mut self, id: EntityId) -> Option<C> {
+        self.storage_mut()?.remove(&id)
+    }
+
+    pub fn component<C: Component>(&self, id: EntityId) -> Option<&C> {
+        self.storage()?.get(&id)
+    }
+
+    pub fn component_mut<C: Component>(&mut self, id: EntityId) -> Option<&mut C> {
+        self.storage_mut()?.get_mut(&id)
+    }
+
+    pub fn has<C: Component>(&self, id: EntityId) -> bool {
+        self.component::<C>(id).is_some()
+    }
+
+    /// Every component of one type, with the pet it belongs to
+    pub fn components<C: Component>(&self) -> impl Iterator<Item = (EntityId, &C)> {
+        self.storage::<C>().into_iter().flat_map(|storage| storage.iter().map(|(&id, c)| (id, c)))
+    }
+
+    pub fn components_mut<C: Component>(&mut self) -> impl Iterator<Item = (EntityId, &mut C)> {
+        self.storage_mut::<C>().into_iter().flat_map(|storage| storage.iter_mut().map(|(&id, c)| (id, c)))
+    }
+
+    /// Start a query matching every pet
+    pub fn query(&self) -> PetQuery<'_> {
+        PetQuery {
+            registry: self,
+            species: None,
+            born_after: None,
+            born_before: None,
+            with: Vec::new(),
+            without: Vec::new(),
+            filters: Vec::new()
+        }
+    }
+
+    /// The whole registry as Json, like
+    /// {"next_id": 2, "pets": [{"id": 1, "species": "Dog", ..., "components": {"Collar": ...}}]}
+    pub fn snapshot(&self) -> Json {
+        let pets = self.pets.iter().map(|(&id, record)| {
+            let components: Json = self.storages.values()
+                .filter_map(|storage| Some((storage.name(), storage.to_json(id)?)))
+                .collect();
+            let mut json = record.to_json();
+            if let Json::Object(ref mut members) = json {
+                members.insert("id".to_string(), id.0.to_json());
+                members.insert("components".to_string(), components);
+            }
+            json
+        });
+        vec![("next_id", self.next_id.to_json()), ("pets", Json::Array(pets.collect()))].into_iter().collect()
+    }
+
+    /// Replace everything in the registry with a snapshot's contents. If
+    /// anything in it is wrong, the registry is left as it was.
+    pub fn load(&mut self, snapshot: &Json) -> Result<(), RegistryError> {
+        let mut storages: HashMap<TypeId, Box<dyn Storage>> = self.storages.iter()
+            .map(|(&type_id, storage)| (type_id, storage.empty()))
+            .collect();
+        let by_name: HashMap<&'static str, TypeId> = self.storages.iter()
+            .map(|(&type_id, storage)| (storage.name(), type_id))
+            .collect();
+
+        let members = json_convert::object(snapshot)?;
+        let next_id: u64 = json_convert::field(members, "next_id")?;
+        let entries: Vec<Json> = json_convert::field(members, "pets")?;
+        let mut pets = BTreeMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let at = |error: FromJsonError| error.within(&i.to_string()).within("pets");
+            let record = PetRecord::from_json(entry).map_err(at)?;
+            let members = json_convert::object(entry).map_err(at)?;
+            let id = EntityId(json_convert::field(members, "id").map_err(at)?);
+            if pets.insert(id, record).is_some() {
+                return Err(RegistryError::DuplicatePet(id));
+            }
+            let components: BTreeMap<String, Json> = json_convert::field(members, "components").map_err(at)?;
+            for (name, json) in &components {
+                let storage = by_name.get(name.as_str()).and_then(|type_id| storages.get_mut(type_id))
+                    .ok_or_else(|| RegistryError::UnknownComponent(name.clone()))?;
+                storage.insert_json(id, json).map_err(|error| at(error.within(name).within("components")))?;
+            }
+        }
+
+        // Don't hand out ids the snapshot's pets have
+        self.next_id = pets.keys().map(|id| id.0).max().unwrap_or(0).max(next_id);
+        self.pets = pets;
+        self.storages = storages;
+        Ok(())
+    }
+}
+
+type PetFilter<'r> = Box<dyn Fn(&PetRecord) -> bool + 'r>;
+
+/// A set of conditions for picking pets, built up a step at a time
+pub struct PetQuery<'r> {
+    registry: &'r PetRegistry,
+    species: Option<Pet>,
+    born_after: Option<DateTime>,
+    born_before: Option<DateTime>,
+    with: Vec<TypeId>,
+    without: Vec<TypeId>,
+    filters: Vec<PetFilter<'r>>
+}
+
+/// The moment `count` units before `now`, if it's in range
+fn units_before(now: DateTime, count: u32, unit: TimeUnit) -> Option<DateTime> {
+    now.add_nanos(-(i128::from(count) * i128::from(unit.as_nanos())))
+}
+
+impl<'r> PetQuery<'r> {
+    pub fn species(mut self, species: Pet) -> Self {
+        self.species = Some(species);
+        self
+    }
+
+    /// Pets born after this moment
+    pub fn born_after(mut self, moment: DateTime) -> Self {
+        self.born_after = Some(self.born_after.map_or(moment, |after| after.max(moment)));
+        self
+    }
+
+    /// Pets born before this moment
+    pub fn born_before(mut self, moment: DateTime) -> Self {
+        self.born_before = Some(self.born_before.map_or(moment, |before| before.min(moment)));
+        self
+    }
+
+    /// Pets less than `count` units old at `now`. Months and years are
+    /// their average lengths.
+    pub fn younger_than(self, count: u32, unit: TimeUnit, now: DateTime) -> Self {
+        match units_before(now, count, unit) {
+            Some(cutoff) => self.born_after(cutoff),
+            None => self
+        }
+    }
+
+    /// Pets more than `count` units old at `now`
+    pub fn older_than(self, count: u32, unit: TimeUnit, now: DateTime) -> Self {
+        match units_before(now, count, unit) {
+            Some(cutoff) => self.born_before(cutoff),
+            None => self.filter(|_| false)
+        }
+    }
+
+    /// Pets that have a `C`
+    pub fn with<C: Component>(mut self) -> Self {
+        self.with.push(TypeId::of::<C>());
+        self
+    }
+
+    /// Pets that don't have a `C`
+    pub fn without<C: Component>(mut self) -> Self {
+        self.without.push(TypeId::of::<C>());
+        self
+    }
+
+    /// Pets for which `test` is true
+    pub fn filter<F: Fn(&PetRecord) -> bool + 'r>(mut self, test: F) -> Self {
+        self.filters.push(Box::new(test));
+        self
+    }
+
+    fn matches(&self, id: EntityId, record: &PetRecord) -> bool {
+        let has = |type_id: &TypeId| self.registry.storages.get(type_id).is_some_and(|storage| storage.contains(id));
+        self.species.is_none_or(|species| record.species == species)
+            && self.born_after.is_none_or(|after| record.born > after)
+            && self.born_before.is_none_or(|before| record.born < before)
+            && self.with.iter().all(has)
+            && !self.without.iter().any(has)
+            && self.filters.iter().all(|test| test(record))
+    }
+
+    /// The matching pets, in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &'r PetRecord)> + '_ {
+        self.registry.iter().filter(move |&(id, record)| self.matches(id, record))
+    }
+
+    /// The matching pets that have a `C`, along with it
+    pub fn iter_with<C: Component>(&self) -> impl Iterator<Item = (EntityId, &'r PetRecord, &'r C)> + '_ {
+        let registry = self.registry;
+        registry.components::<C>().filter_map(move |(id, component)| {
+            let record = registry.get(id)?;
+            if self.matches(id, record) { Some((id, record, component)) } else { None }
+        })
+    }
+
+    pub fn ids(&self) -> Vec<EntityId> {
+        self.iter().map(|(id, _)| id).collect()
+    }
+
+    pub fn count(&self) -> usize {
+        self.iter().count()
+    }
+}
+
+#[cfg(test)]
+#[derive(Clone, Debug, PartialEq, ToJson, FromJson)]
+struct Collar {
+    color: String,
+    tag: Option<String>
+}
+
+#[cfg(test)]
+impl Component for Collar {
+    const NAME: &'static str = "collar";
+}
+
+#[cfg(test)]
+#[derive(Clone, Debug, PartialEq, ToJson, FromJson)]
+struct Weight(f64);
+
+#[cfg(test)]
+impl Component for Weight {
+    const NAME: &'static str = "weight";
+}
+
+/// Has the same NAME as Weight, so the two can't both be registered
+#[cfg(test)]
+#[derive(Clone, Debug, PartialEq, ToJson, FromJson)]
+struct Mass(f64);
+
+#[cfg(test)]
+impl Component for Mass {
+    const NAME: &'static str = "weight";
+}
+
+#[cfg(test)]
+fn date(year: i32, month: u8, day: u8) -> DateTime {
+    DateTime::from_ymd(year, month, day).unwrap()
+}
+
+#[cfg(test)]
+fn household() -> (PetRegistry, [EntityId; 4]) {
+    let mut pets = PetRegistry::new();
+    let rex = pets.spawn(Pet::Dog, "Rex", date(2023, 5, 1));
+    let fido = pets.spawn(Pet::Dog, "Fido", date(2015, 1, 10));
+    let tom = pets.spawn(Pet::Cat, "Tom", date(2024, 3, 3));
+    let shamu = pets.spawn(Pet::Orca, "Shamu", date(1985, 9, 26));
+    pets.insert(rex, Collar { color: "red".to_string(), tag: Some("REX-1".to_string()) }).unwrap();
+    pets.insert(tom, Collar { color: "blue".to_string(), tag: None }).unwrap();
+    pets.insert(rex, Weight(12.5)).unwrap();
+    pets.insert(fido, Weight(30.0)).unwrap();
+    (pets, [rex, fido, tom, shamu])
+}
+
+#[test]
+fn test_entities_and_components() {
+    let (mut pets, [rex, fido, tom, shamu]) = household();
+    let now = date(2025, 1, 1);
+    assert_eq!(pets.len(), 4);
+    assert_eq!(pets.get(rex).unwrap().describe(now), "Rex the Dog, born 2 years ago");
+    assert_eq!(pets.get(shamu).unwrap().age(now), RoughTime::InThePast(TimeUnit::Years, 39));
+
+    assert_eq!(pets.component::<Collar>(tom).unwrap().color, "blue");
+    assert!(pets.has::<Weight>(fido) && !pets.has::<Collar>(fido));
+    assert_eq!(pets.insert(rex, Weight(13.0)), Ok(Some(Weight(12.5))));
+    pets.component_mut::<Weight>(fido).unwrap().0 -= 2.0;
+    for (_, weight) in pets.components_mut::<Weight>() {
+        weight.0 += 1.0;
+    }
+    let weights: Vec<_> = pets.components::<Weight>().map(|(id, weight)| (id, weight.0)).collect();
+    assert_eq!(weights, [(rex, 14.0), (fido, 29.0)]);
+
+    assert_eq!(pets.despawn(rex).unwrap().name, "Rex");
+    assert_eq!(pets.components::<Weight>().count(), 1);
+    assert_eq!(pets.despawn(rex), None);
+    assert_eq!(pets.insert(rex, Weight(1.0)), Err(RegistryError::NoSuchPet(rex)));
+    assert_eq!(pets.remove::<Collar>(tom).map(|collar| collar.color), Some("blue".to_string()));
+    assert_eq!(pets.components::<Collar>().count(), 0);
+
+    // Ids aren't reused
+    let rex_again = pets.spawn(Pet::Dog, "Rex", date(2024, 12, 1));
+    assert_ne!(rex_again, rex);
+    assert_eq!(rex_again.to_string(), "#5");
+
+    // Two types can't share a name, since snapshots would mix them up
+    assert_eq!(pets.register::<Weight>(), Ok(()));
+    assert_eq!(pets.register::<Mass>(), Err(RegistryError::DuplicateComponentName("weight")));
+    assert_eq!(pets.insert(rex_again, Mass(3.0)), Err(RegistryError::DuplicateComponentName("weight")));
+
+    // Looking a type up doesn't register it
+    let mut empty = PetRegistry::new();
+    let solo = empty.spawn(Pet::Cat, "Solo", date(2020, 2, 2));
+    assert_eq!(empty.remove::<Weight>(solo), None);
+    assert_eq!(empty.component_mut::<Weight>(solo), None);
+    assert_eq!(empty.components_mut::<Weight>().count(), 0);
+    assert_eq!(empty.register::<Mass>(), Ok(()));
+    assert_eq!(empty.insert(solo, Weight(4.0)), Err(RegistryError::DuplicateComponentName("weight")));
+}
+
+#[test]
+fn test_queries() {
+    let (pets, [rex, fido, tom, shamu]) = household();
+    let now = date(2025, 1, 1);
+    assert_eq!(pets.query().species(Pet::Dog).younger_than(2, TimeUnit::Years, now).ids(), [rex]);
+    assert_eq!(pets.query().older_than(5, TimeUnit::Years, now).ids(), [fido, shamu]);
+    assert_eq!(pets.query().with::<Collar>().ids(), [rex, tom]);
+    assert_eq!(pets.query().with::<Weight>().without::<Collar>().ids(), [fido]);
+    assert_eq!(pets.query().filter(|pet| pet.name.starts_with('S')).ids(), [shamu]);
+    assert_eq!(pets.query().born_after(date(2016, 1, 1)).born_before(date(2024, 1, 1)).ids(), [rex]);
+    assert_eq!(pets.query().species(Pet::Giraffe).count(), 0);
+    assert_eq!(pets.query().count(), 4);
+
+    let collared: Vec<_> = pets.query().younger_than(18, TimeUnit::Months, now).iter_with::<Collar>()
+        .map(|(_, pet, collar)| format!("{}: {}", pet.name, collar.color))
+        .collect();
+    assert_eq!(collared, ["Tom: blue"]);
+}
+
+#[test]
+fn test_snapshots() {
+    let (pets, [rex, ..]) = household();
+    let snapshot = pets.snapshot();
+    assert_eq!(snapshot["pets"][0].to_string(),
+               concat!(r#"{"born":"2023-05-01T00:00:00Z","components":{"collar":{"color":"red","tag":"REX-1"},"#,
+                       r#""weight":12.5},"id":1,"name":"Rex","species":"Dog"}"#));
+
+    let mut copy = PetRegistry::new();
+    copy.register::<Collar>().unwrap();
+    copy.register::<Weight>().unwrap();
+    copy.load(&snapshot).unwrap();
+    assert_eq!(copy.snapshot(), snapshot);
+    assert_eq!(copy.component::<Collar>(rex), pets.component::<Collar>(rex));
+    assert_eq!(copy.spawn(Pet::Cat, "Felix", date(2025, 1, 1)), EntityId(5));
+
+    // Loading needs every component type registered
+    let mut collars_only = PetRegistry::new();
+    collars_only.register::<Collar>().unwrap();
+    assert_eq!(collars_only.load(&snapshot), Err(RegistryError::UnknownComponent("weight".to_string())));
+    assert!(collars_only.is_empty());
+
+    // and leaves the registry alone if anything is wrong
+    let mut bad = snapshot.clone();
+    if let Json::Object(ref mut members) = bad {
+        if let Some(Json::Array(ref mut entries)) = members.get_mut("pets") {
+            entries[2] = Json::parse(r#"{"id": 9, "species": "Dragon", "name": "x", "born": "2020-01-01T00:00:00Z"}"#)
+                .unwrap();
+        }
+    }
+    let error = copy.load(&bad).unwrap_err();
+    assert_eq!(error.to_string(), "bad snapshot: can't convert the JSON at \"/pets/2/species\": \
+                                   \"Dragon\" isn't a variant of Pet");
+    assert_eq!(copy.len(), 5);
+    let mut bad_weight = snapshot;
+    *bad_weight.pointer_mut("/pets/1/components/weight").unwrap() = Json::from("heavy");
+    assert_eq!(copy.load(&bad_weight).unwrap_err().to_string(),
+               "bad snapshot: can't convert the JSON at \"/pets/1/components/weight\": expected a number, found a string");
+}
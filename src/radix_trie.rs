@@ -0,0 +1,339 @@
+#![allow(dead_code)]
+
+// A compressed (radix) trie mapping strings to values. Unlike a
+// BinaryTree<&str>, which compares whole strings at every node, each edge
+// here is labelled with a chunk of key, so lookups only ever look at each
+// character of the key once, and everything sharing a prefix lives in the
+// same subtree. That makes prefix searches and autocompletion cheap.
+
+/// A map from `String` keys to values of type `V`, stored as a radix trie
+#[derive(Debug)]
+pub struct RadixTrie<V> {
+    root: RadixNode<V>,
+    len: usize
+}
+
+// A part of a RadixTrie.
+#[derive(Debug)]
+struct RadixNode<V> {
+    value: Option<V>, // the value for the key spelled out on the way here
+    // Edges to child nodes, sorted by label. No two labels start with the
+    // same character, and no label is empty.
+    children: Vec<(String, RadixNode<V>)>
+}
+
+impl<V> RadixNode<V> {
+    fn new(value: Option<V>) -> Self {
+        RadixNode { value, children: Vec::new() }
+    }
+
+    /// Find the edge whose label starts with the same character as `key`
+    fn child_index(&self, key: &str) -> Result<usize, usize> {
+        let first = key.chars().next();
+        self.children.binary_search_by(|(label, _)| label.chars().next().cmp(&first))
+    }
+}
+
+/// Length in bytes of the longest common prefix of `a` and `b`, always
+/// landing on a character boundary
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|&((_, x), y)| x != y)
+        .map(|((i, _), _)| i)
+        .unwrap_or_else(|| a.len().min(b.len()))
+}
+
+impl<V> RadixTrie<V> {
+    ///Constructor
+    pub fn new() -> Self {
+        RadixTrie { root: RadixNode::new(None), len: 0 }
+    }
+
+    /// Number of keys in the trie
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the trie holds no keys
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Add a key, returning the value it replaced if it was already present
+    pub fn insert(&mut self, key: &str, value: V) -> Option<V> {
+        let old = insert_into(&mut self.root, key, value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// Look up the value stored under exactly `key`
+    pub fn get(&self, key: &str) -> Option<&V> {
+        let mut node = &self.root;
+        let mut rest = key;
+        while !rest.is_empty() {
+            let index = node.child_index(rest).ok()?;
+            let (ref label, ref child) = node.children[index];
+            if !rest.starts_with(label.as_str()) {
+                return None;
+            }
+            rest = &rest[label.len()..];
+            node = child;
+        }
+        node.value.as_ref()
+    }
+
+    /// Returns true if `key` is in the trie
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Remove a key, returning its value if it was present
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        let removed = remove_from(&mut self.root, key);
+        if removed.is_some() {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    /// Every key starting with `prefix`, in sorted order
+    pub fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        let mut keys = Vec::new();
+        let mut node = &self.root;
+        let mut path = String::new();
+        let mut rest = prefix;
+
+        while !rest.is_empty() {
+            let index = match node.child_index(rest) {
+                Ok(index) => index,
+                Err(_) => return keys
+            };
+            let (ref label, ref child) = node.children[index];
+            if rest.starts_with(label.as_str()) {
+                rest = &rest[label.len()..];
+            } else if label.starts_with(rest) {
+                // The prefix ends partway along this edge
+                rest = "";
+            } else {
+                return keys;
+            }
+            path.push_str(label);
+            node = child;
+        }
+
+        collect_keys(node, &mut path, &mut keys);
+        keys
+    }
+
+    /// The longest key in the trie that is a prefix of `text`, along with
+    /// its value. The key is returned as a slice of `text`.
+    pub fn longest_prefix_match<'t>(&self, text: &'t str) -> Option<(&'t str, &V)> {
+        let mut node = &self.root;
+        let mut consumed = 0;
+        let mut best = node.value.as_ref().map(|value| (&text[..0], value));
+
+        loop {
+            let rest = &text[consumed..];
+            if rest.is_empty() {
+                break;
+            }
+            let index = match node.child_index(rest) {
+                Ok(index) => index,
+                Err(_) => break
+            };
+            let (ref label, ref child) = node.children[index];
+            if !rest.starts_with(label.as_str()) {
+                break;
+            }
+            consumed += label.len();
+            node = child;
+            if let Some(ref value) = node.value {
+                best = Some((&text[..consumed], value));
+            }
+        }
+        best
+    }
+}
+
+impl<V> Default for RadixTrie<V> {
+    fn default() -> Self {
+        RadixTrie::new()
+    }
+}
+
+fn insert_into<V>(node: &mut RadixNode<V>, key: &str, value: V) -> Option<V> {
+    if key.is_empty() {
+        return node.value.replace(value);
+    }
+
+    let index = match node.child_index(key) {
+        Ok(index) => index,
+        Err(index) => {
+            node.children.insert(index, (key.to_string(), RadixNode::new(Some(value))));
+            return None;
+        }
+    };
+
+    let (ref mut label, ref mut child) = node.children[index];
+    let common = common_prefix_len(label, key);
+    if common == label.len() {
+        return insert_into(child, &key[common..], value);
+    }
+
+    // The key leaves this edge partway along, so split the edge in two
+    let tail = label.split_off(common);
+    let old_child = std::mem::replace(child, RadixNode::new(None));
+    child.children.push((tail, old_child));
+    if common == key.len() {
+        child.value = Some(value);
+    } else {
+        insert_into(child, &key[common..], value);
+    }
+    None
+}
+
+fn remove_from<V>(node: &mut RadixNode<V>, key: &str) -> Option<V> {
+    if key.is_empty() {
+        return node.value.take();
+    }
+
+    let index = node.child_index(key).ok()?;
+    let removed = {
+        let (ref label, ref mut child) = node.children[index];
+        if !key.starts_with(label.as_str()) {
+            return None;
+        }
+        remove_from(child, &key[label.len()..])
+    };
+
+    // Keep the trie compressed: drop dead leaves and merge a valueless node
+    // with its only child
+    if removed.is_some() {
+        let (ref mut label, ref mut child) = node.children[index];
+        if child.value.is_none() {
+            match child.children.len() {
+                0 => {
+                    node.children.remove(index);
+                }
+                1 => {
+                    let (tail, grandchild) = child.children.pop().unwrap();
+                    label.push_str(&tail);
+                    *child = grandchild;
+                }
+                _ => {}
+            }
+        }
+    }
+    removed
+}
+
+/// Push every key under `node` onto `keys`, where `path` spells out `node`
+fn collect_keys<V>(node: &RadixNode<V>, path: &mut String, keys: &mut Vec<String>) {
+    if node.value.is_some() {
+        keys.push(path.clone());
+    }
+    for (label, child) in &node.children {
+        path.push_str(label);
+        collect_keys(child, path, keys);
+        path.truncate(path.len() - label.len());
+    }
+}
+
+#[cfg(test)]
+fn commands() -> RadixTrie<u32> {
+    let mut trie = RadixTrie::new();
+    for (i, name) in ["status", "stash", "show", "commit", "config", "clone", "checkout",
+                      "cherry-pick", "cherry", "stash-pop"].iter().enumerate() {
+        trie.insert(name, i as u32);
+    }
+    trie
+}
+
+#[test]
+fn test_radix_insert_and_get() {
+    let mut trie = commands();
+    assert_eq!(trie.len(), 10);
+    assert_eq!(trie.get("stash"), Some(&1));
+    assert_eq!(trie.get("cherry"), Some(&8));
+    assert_eq!(trie.get("cherry-pick"), Some(&7));
+    assert_eq!(trie.get("cher"), None);
+    assert_eq!(trie.get("stashed"), None);
+    assert!(!trie.contains_key(""));
+
+    assert_eq!(trie.insert("stash", 100), Some(1));
+    assert_eq!(trie.get("stash"), Some(&100));
+    assert_eq!(trie.len(), 10);
+
+    trie.insert("", 0);
+    assert!(trie.contains_key(""));
+}
+
+#[test]
+fn test_radix_keys_with_prefix() {
+    let trie = commands();
+    assert_eq!(trie.keys_with_prefix("st"), vec!["stash", "stash-pop", "status"]);
+    assert_eq!(trie.keys_with_prefix("che"), vec!["checkout", "cherry", "cherry-pick"]);
+    assert_eq!(trie.keys_with_prefix("co"), vec!["commit", "config"]);
+    assert_eq!(trie.keys_with_prefix("show"), vec!["show"]);
+    assert!(trie.keys_with_prefix("push").is_empty());
+    assert!(trie.keys_with_prefix("shows").is_empty());
+    assert_eq!(trie.keys_with_prefix("").len(), 10);
+}
+
+#[test]
+fn test_radix_longest_prefix_match() {
+    let trie = commands();
+    assert_eq!(trie.longest_prefix_match("cherry-picking"), Some(("cherry-pick", &7)));
+    assert_eq!(trie.longest_prefix_match("cherry-p"), Some(("cherry", &8)));
+    assert_eq!(trie.longest_prefix_match("stash pop"), Some(("stash", &1)));
+    assert_eq!(trie.longest_prefix_match("sta"), None);
+
+    let mut routes = RadixTrie::new();
+    routes.insert("/", "root");
+    routes.insert("/api/", "api");
+    routes.insert("/api/v1/", "v1");
+    assert_eq!(routes.longest_prefix_match("/api/v1/users"), Some(("/api/v1/", &"v1")));
+    assert_eq!(routes.longest_prefix_match("/api/v2/users"), Some(("/api/", &"api")));
+    assert_eq!(routes.longest_prefix_match("/index.html"), Some(("/", &"root")));
+}
+
+#[test]
+fn test_radix_remove() {
+    let mut trie = commands();
+    assert_eq!(trie.remove("cherry"), Some(8));
+    assert_eq!(trie.remove("cherry"), None);
+    assert_eq!(trie.remove("che"), None);
+    assert_eq!(trie.get("cherry-pick"), Some(&7));
+    assert_eq!(trie.len(), 9);
+
+    // "stash" and "stash-pop" collapse back into one edge once "stash" is gone
+    assert_eq!(trie.remove("stash"), Some(1));
+    assert_eq!(trie.keys_with_prefix("st"), vec!["stash-pop", "status"]);
+    let (ref label, _) = trie.root.children[trie.root.child_index("s").unwrap()];
+    assert_eq!(label, "s");
+
+    for name in trie.keys_with_prefix("") {
+        assert!(trie.remove(&name).is_some());
+    }
+    assert!(trie.is_empty());
+    assert!(trie.root.children.is_empty());
+}
+
+#[test]
+fn test_radix_unicode_keys() {
+    let mut trie = RadixTrie::new();
+    trie.insert("naïve", 1);
+    trie.insert("naïf", 2);
+    trie.insert("nao", 3);
+    trie.insert("日本", 4);
+    trie.insert("日本語", 5);
+
+    assert_eq!(trie.keys_with_prefix("naï"), vec!["naïf", "naïve"]);
+    assert_eq!(trie.keys_with_prefix("日"), vec!["日本", "日本語"]);
+    assert_eq!(trie.longest_prefix_match("日本語です"), Some(("日本語", &5)));
+    assert_eq!(trie.remove("naïf"), Some(2));
+    assert_eq!(trie.get("naïve"), Some(&1));
+}
@@ -0,0 +1,295 @@
+#![allow(dead_code)]
+
+// Localized versions of chapter_10's rough_time_to_english. The English
+// version gets away with tacking an `s` onto the unit, but most languages
+// need more than two forms of a noun ("1 год", "2 года", "5 лет"), so here
+// every phrase comes from a message table bundled with the crate, and the
+// form to use is picked with the CLDR plural rules for the language.
+
+use crate::chapter_10::{RoughTime, TimeUnit};
+use std::collections::HashMap;
+
+/// The CLDR plural categories. Which ones a language uses, and which
+/// numbers fall in each, is up to the language.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PluralCategory {
+    Zero, One, Two, Few, Many, Other
+}
+
+impl PluralCategory {
+    /// The CLDR name of this category, as used in message keys
+    pub fn name(self) -> &'static str {
+        match self {
+            PluralCategory::Zero => "zero",
+            PluralCategory::One => "one",
+            PluralCategory::Two => "two",
+            PluralCategory::Few => "few",
+            PluralCategory::Many => "many",
+            PluralCategory::Other => "other"
+        }
+    }
+}
+
+/// Pick the plural category of the whole number `n` in `language`, which is
+/// a primary language subtag like "ru". Unknown languages get `Other`.
+pub fn plural_category(language: &str, n: u64) -> PluralCategory {
+    use self::PluralCategory::*;
+
+    match language {
+        "en" | "de" =>
+            if n == 1 { One } else { Other },
+        "fr" =>
+            if n == 0 || n == 1 {
+                One
+            } else if n.is_multiple_of(1_000_000) {
+                Many
+            } else {
+                Other
+            },
+        "es" =>
+            if n == 1 {
+                One
+            } else if n != 0 && n.is_multiple_of(1_000_000) {
+                Many
+            } else {
+                Other
+            },
+        "ru" =>
+            if n % 10 == 1 && n % 100 != 11 {
+                One
+            } else if (2..=4).contains(&(n % 10)) && !(12..=14).contains(&(n % 100)) {
+                Few
+            } else {
+                Many
+            },
+        _ => Other
+    }
+}
+
+/// The locale every fallback chain ends with
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// The message tables compiled into the crate, by locale tag
+const BUNDLED_TABLES: &[(&str, &str)] = &[
+    ("en", include_str!("locales/en.txt")),
+    ("de", include_str!("locales/de.txt")),
+    ("fr", include_str!("locales/fr.txt")),
+    ("es", include_str!("locales/es.txt")),
+    ("ru", include_str!("locales/ru.txt")),
+    ("ja", include_str!("locales/ja.txt"))
+];
+
+/// Every locale with a bundled message table
+pub fn bundled_locales() -> Vec<&'static str> {
+    BUNDLED_TABLES.iter().map(|&(tag, _)| tag).collect()
+}
+
+/// Parse a message table: `key = value` lines, with `#` comments and blank
+/// lines ignored
+fn parse_table(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Normalize a locale tag, so "EN_us" becomes "en-US"
+fn normalize_tag(tag: &str) -> String {
+    tag.split(['-', '_'])
+        .filter(|subtag| !subtag.is_empty())
+        .enumerate()
+        .map(|(i, subtag)| {
+            if i == 0 {
+                subtag.to_lowercase()
+            } else if subtag.len() == 2 {
+                subtag.to_uppercase() // region, as in "en-US"
+            } else if subtag.len() == 4 {
+                // script, as in "zh-Hant"
+                let mut chars = subtag.chars();
+                let first = chars.next().unwrap().to_uppercase();
+                first.chain(chars.flat_map(|c| c.to_lowercase())).collect()
+            } else {
+                subtag.to_lowercase()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The locales to try, most specific first, when asked for `tag`.
+/// "de-CH-1996" gives "de-CH-1996", "de-CH", "de", then the default.
+pub fn fallback_chain(tag: &str) -> Vec<String> {
+    let tag = normalize_tag(tag);
+    let mut chain = Vec::new();
+    let mut current = tag.as_str();
+    while !current.is_empty() {
+        chain.push(current.to_string());
+        current = match current.rfind('-') {
+            Some(dash) => &current[..dash],
+            None => ""
+        };
+    }
+    if !chain.iter().any(|locale| locale == DEFAULT_LOCALE) {
+        chain.push(DEFAULT_LOCALE.to_string());
+    }
+    chain
+}
+
+/// Formats `RoughTime`s in one language
+pub struct RoughTimeFormatter {
+    language: String,
+    // Tables along the fallback chain, most specific first
+    tables: Vec<(String, HashMap<String, String>)>
+}
+
+impl RoughTimeFormatter {
+    /// A formatter for the locale `tag`, falling back along its chain to
+    /// the closest bundled table and finally to English. Messages missing
+    /// from one table are looked up in the next.
+    pub fn new(tag: &str) -> RoughTimeFormatter {
+        let tables: Vec<_> = fallback_chain(tag)
+            .into_iter()
+            .filter_map(|locale| {
+                BUNDLED_TABLES.iter()
+                    .find(|&&(bundled, _)| bundled == locale)
+                    .map(|&(_, text)| (locale, parse_table(text)))
+            })
+            .collect();
+        let language = tables[0].0.split('-').next().unwrap().to_string();
+        RoughTimeFormatter { language, tables }
+    }
+
+    /// The bundled locale this formatter settled on
+    pub fn locale(&self) -> &str {
+        &self.tables[0].0
+    }
+
+    fn message(&self, key: &str) -> Option<&str> {
+        self.first_message(&[key])
+    }
+
+    /// The first of `keys` found in the most specific table that has any
+    /// of them, so a language's own forms beat exact matches from a fallback
+    fn first_message<K: AsRef<str>>(&self, keys: &[K]) -> Option<&str> {
+        self.tables.iter()
+            .filter_map(|(_, table)| keys.iter().filter_map(|key| table.get(key.as_ref())).next())
+            .map(|message| message.as_str())
+            .next()
+    }
+
+    /// Phrase `count` `unit`s, like "2 years", without the "ago"
+    pub fn amount(&self, unit: TimeUnit, count: u32) -> String {
+        let category = plural_category(&self.language, u64::from(count));
+        let keys = [
+            format!("{}.{}", unit.plural(), count),
+            format!("{}.{}", unit.plural(), category.name()),
+            format!("{}.other", unit.plural())
+        ];
+        let message = self.first_message(&keys).unwrap_or("{n}");
+        message.replace("{n}", &count.to_string())
+    }
+
+    /// Phrase a `RoughTime`, like "vor 2 Jahren"
+    pub fn format(&self, rt: RoughTime) -> String {
+        let (template, amount) = match rt {
+            RoughTime::JustNow =>
+                return self.message("just_now").unwrap_or("").to_string(),
+            RoughTime::InThePast(unit, count) =>
+                ("past", self.amount(unit, count)),
+            RoughTime::InTheFuture(unit, count) =>
+                ("future", self.amount(unit, count))
+        };
+        self.message(template).unwrap_or("{}").replace("{}", &amount)
+    }
+}
+
+#[test]
+fn test_plural_categories() {
+    use self::PluralCategory::*;
+
+    assert_eq!(plural_category("en", 1), One);
+    assert_eq!(plural_category("en", 0), Other);
+    assert_eq!(plural_category("fr", 0), One);
+    assert_eq!(plural_category("fr", 2), Other);
+    assert_eq!(plural_category("fr", 3_000_000), Many);
+    assert_eq!(plural_category("es", 0), Other);
+    assert_eq!(plural_category("es", 1_000_000), Many);
+    assert_eq!(plural_category("ja", 1), Other);
+
+    let russian: Vec<PluralCategory> = [1, 2, 5, 11, 12, 21, 22, 25, 101, 111, 114, 0]
+        .iter()
+        .map(|n| plural_category("ru", *n))
+        .collect();
+    assert_eq!(russian, vec![One, Few, Many, Many, Many, One, Few, Many, One, Many, Many, Many]);
+}
+
+#[test]
+fn test_fallback_chain() {
+    assert_eq!(fallback_chain("de-CH-1996"), vec!["de-CH-1996", "de-CH", "de", "en"]);
+    assert_eq!(fallback_chain("zh_hant_tw"), vec!["zh-Hant-TW", "zh-Hant", "zh", "en"]);
+    assert_eq!(fallback_chain("EN-us"), vec!["en-US", "en"]);
+    assert_eq!(fallback_chain(""), vec!["en"]);
+
+    assert_eq!(RoughTimeFormatter::new("de-AT").locale(), "de");
+    assert_eq!(RoughTimeFormatter::new("ja_JP").locale(), "ja");
+    assert_eq!(RoughTimeFormatter::new("pt-BR").locale(), "en");
+}
+
+#[test]
+fn test_format_in_each_language() {
+    use crate::chapter_10::rough_time_to_english;
+    use crate::chapter_10::TimeUnit::*;
+
+    let times = [RoughTime::InThePast(Days, 1), RoughTime::InThePast(Years, 2),
+                 RoughTime::JustNow, RoughTime::InTheFuture(Seconds, 1),
+                 RoughTime::InTheFuture(Months, 5), RoughTime::InThePast(Hours, 21)];
+    let phrases = |tag: &str| -> Vec<String> {
+        let formatter = RoughTimeFormatter::new(tag);
+        times.iter().map(|rt| formatter.format(*rt)).collect()
+    };
+
+    let english: Vec<String> = times.iter().map(|rt| rough_time_to_english(*rt)).collect();
+    assert_eq!(phrases("en"), english);
+    assert_eq!(phrases("de"), vec!["vor einem Tag", "vor 2 Jahren", "gerade eben",
+                                   "in einer Sekunde", "in 5 Monaten", "vor 21 Stunden"]);
+    assert_eq!(phrases("fr"), vec!["il y a un jour", "il y a 2 ans", "à l’instant",
+                                   "dans une seconde", "dans 5 mois", "il y a 21 heures"]);
+    assert_eq!(phrases("es"), vec!["hace un día", "hace 2 años", "ahora mismo",
+                                   "dentro de un segundo", "dentro de 5 meses", "hace 21 horas"]);
+    assert_eq!(phrases("ru"), vec!["день назад", "2 года назад", "только что",
+                                   "через секунду", "через 5 месяцев", "21 час назад"]);
+    assert_eq!(phrases("ja"), vec!["1日前", "2年前", "たった今",
+                                   "1秒後", "5か月後", "21時間前"]);
+
+    let newer_units = [RoughTime::InThePast(Weeks, 3), RoughTime::InTheFuture(Decades, 2)];
+    let formatter = RoughTimeFormatter::new("ru");
+    assert_eq!(formatter.format(newer_units[0]), "3 недели назад");
+    assert_eq!(formatter.format(newer_units[1]), "через 2 десятилетия");
+    assert_eq!(RoughTimeFormatter::new("ja").format(newer_units[1]), "20年後");
+}
+
+#[test]
+fn test_bundled_tables_are_complete() {
+    for locale in bundled_locales() {
+        let table = parse_table(BUNDLED_TABLES.iter().find(|t| t.0 == locale).unwrap().1);
+        for key in &["just_now", "past", "future"] {
+            assert!(table.contains_key(*key), "{} is missing {}", locale, key);
+        }
+        for unit in TimeUnit::VARIANTS.iter() {
+            // Every category the language uses must have a message
+            for n in (0..=200).chain(vec![1_000_000, 2_000_000]) {
+                let category = plural_category(locale, n);
+                let key = format!("{}.{}", unit.plural(), category.name());
+                let exact = format!("{}.{}", unit.plural(), n);
+                assert!(table.contains_key(&key) || table.contains_key(&exact),
+                        "{} is missing {}", locale, key);
+            }
+        }
+    }
+}
@@ -0,0 +1,323 @@
+#![allow(dead_code)]
+
+// The inverse of chapter_10's rough_time_to_english: reading phrases like
+// "2 years ago", "a second from now", "in 3 weeks" or "yesterday" back into
+// RoughTimes. Anything we can't make sense of is reported along with the
+// word that tripped us up and where it was in the input.
+
+use crate::chapter_10::{RoughTime, TimeUnit};
+use std::fmt;
+use std::str::FromStr;
+
+/// What went wrong while parsing a rough time
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ParseRoughTimeErrorKind {
+    /// There was nothing but whitespace
+    Empty,
+    /// Expected a count like "3", "a" or "two"
+    ExpectedNumber,
+    /// Expected a unit like "days" or "hr"
+    UnknownUnit,
+    /// Expected "ago" or "from now" after the amount
+    ExpectedDirection,
+    /// Expected "now" after "just" or "right"
+    ExpectedNow,
+    /// The count doesn't fit in a `RoughTime`
+    NumberTooLarge,
+    /// A complete rough time was followed by more words
+    UnexpectedToken
+}
+
+/// An error from parsing a rough time, pointing at the offending word
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseRoughTimeError {
+    pub kind: ParseRoughTimeErrorKind,
+    /// The word we couldn't use, or "" if the input ended too soon
+    pub token: String,
+    /// Byte offset of `token` in the input
+    pub position: usize
+}
+
+impl fmt::Display for ParseRoughTimeError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        use self::ParseRoughTimeErrorKind::*;
+
+        let expected = match self.kind {
+            Empty => return write!(dest, "expected a time, found nothing"),
+            ExpectedNumber => "a number",
+            UnknownUnit => "a unit of time",
+            ExpectedDirection => "\"ago\" or \"from now\"",
+            ExpectedNow => "\"now\"",
+            NumberTooLarge => return write!(dest, "{:?} at offset {} is too large",
+                                            self.token, self.position),
+            UnexpectedToken => "the end of the input"
+        };
+        if self.token.is_empty() {
+            write!(dest, "expected {} at offset {}, found the end of the input",
+                   expected, self.position)
+        } else {
+            write!(dest, "expected {} at offset {}, found {:?}",
+                   expected, self.position, self.token)
+        }
+    }
+}
+
+impl std::error::Error for ParseRoughTimeError {}
+
+/// Read a count spelled with digits or as a small English number
+fn parse_count(word: &str) -> Option<Result<u32, ()>> {
+    if word.chars().all(|c| c.is_ascii_digit()) {
+        return Some(word.parse().map_err(|_| ()));
+    }
+    let count = match word {
+        "a" | "an" | "one" => 1,
+        "two" => 2,
+        "three" => 3,
+        "four" => 4,
+        "five" => 5,
+        "six" => 6,
+        "seven" => 7,
+        "eight" => 8,
+        "nine" => 9,
+        "ten" => 10,
+        "eleven" => 11,
+        "twelve" => 12,
+        _ => return None
+    };
+    Some(Ok(count))
+}
+
+// A word of input, lowercased, with its byte offset
+struct Token {
+    text: String,
+    position: usize
+}
+
+/// Split on whitespace, remembering where each word started
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices().chain(Some((text.len(), ' '))) {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                tokens.push(Token { text: text[s..i].to_lowercase(), position: s });
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    tokens
+}
+
+struct Parser<'t> {
+    tokens: &'t [Token],
+    next: usize,
+    end: usize // byte length of the input, for errors at the very end
+}
+
+impl<'t> Parser<'t> {
+    fn peek(&self) -> Option<&'t str> {
+        self.tokens.get(self.next).map(|token| token.text.as_str())
+    }
+
+    fn error(&self, kind: ParseRoughTimeErrorKind) -> ParseRoughTimeError {
+        match self.tokens.get(self.next) {
+            Some(token) =>
+                ParseRoughTimeError { kind, token: token.text.clone(), position: token.position },
+            None =>
+                ParseRoughTimeError { kind, token: String::new(), position: self.end }
+        }
+    }
+
+    /// Consume the next word if it is `word`
+    fn eat(&mut self, word: &str) -> bool {
+        if self.peek() == Some(word) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_unit(&mut self) -> Result<TimeUnit, ParseRoughTimeError> {
+        let unit = self.peek()
+            .and_then(|word| word.parse().ok())
+            .ok_or_else(|| self.error(ParseRoughTimeErrorKind::UnknownUnit))?;
+        self.next += 1;
+        Ok(unit)
+    }
+
+    /// Parse a count followed by a unit, either as two words ("3 days") or
+    /// glued together ("3d")
+    fn amount(&mut self) -> Result<(TimeUnit, u32), ParseRoughTimeError> {
+        let word = self.peek().ok_or_else(|| self.error(ParseRoughTimeErrorKind::ExpectedNumber))?;
+
+        match parse_count(word) {
+            Some(count) => {
+                let count = count.map_err(|_| self.error(ParseRoughTimeErrorKind::NumberTooLarge))?;
+                self.next += 1;
+                Ok((self.expect_unit()?, count))
+            }
+            None => {
+                let digits = word.find(|c: char| !c.is_ascii_digit()).unwrap_or(word.len());
+                if digits == 0 {
+                    return Err(self.error(ParseRoughTimeErrorKind::ExpectedNumber));
+                }
+                let count = word[..digits].parse()
+                    .map_err(|_| self.error(ParseRoughTimeErrorKind::NumberTooLarge))?;
+                let unit = word[digits..].parse()
+                    .map_err(|_| self.error(ParseRoughTimeErrorKind::UnknownUnit))?;
+                self.next += 1;
+                Ok((unit, count))
+            }
+        }
+    }
+
+    fn rough_time(&mut self) -> Result<RoughTime, ParseRoughTimeError> {
+        if self.peek().is_none() {
+            return Err(self.error(ParseRoughTimeErrorKind::Empty));
+        }
+
+        if self.eat("now") {
+            return Ok(RoughTime::JustNow);
+        }
+        if self.eat("just") || self.eat("right") {
+            if self.eat("now") {
+                return Ok(RoughTime::JustNow);
+            }
+            return Err(self.error(ParseRoughTimeErrorKind::ExpectedNow));
+        }
+        if self.eat("yesterday") {
+            return Ok(RoughTime::InThePast(TimeUnit::Days, 1));
+        }
+        if self.eat("tomorrow") {
+            return Ok(RoughTime::InTheFuture(TimeUnit::Days, 1));
+        }
+        if self.eat("last") {
+            return Ok(RoughTime::InThePast(self.expect_unit()?, 1));
+        }
+        if self.eat("next") {
+            return Ok(RoughTime::InTheFuture(self.expect_unit()?, 1));
+        }
+        if self.eat("in") {
+            let (unit, count) = self.amount()?;
+            return Ok(rough_time(false, unit, count));
+        }
+
+        let (unit, count) = self.amount()?;
+        if self.eat("ago") {
+            return Ok(rough_time(true, unit, count));
+        }
+        if self.eat("from") {
+            if self.eat("now") {
+                return Ok(rough_time(false, unit, count));
+            }
+            return Err(self.error(ParseRoughTimeErrorKind::ExpectedDirection));
+        }
+        Err(self.error(ParseRoughTimeErrorKind::ExpectedDirection))
+    }
+}
+
+/// Zero of anything is just now
+fn rough_time(past: bool, unit: TimeUnit, count: u32) -> RoughTime {
+    match (count, past) {
+        (0, _) => RoughTime::JustNow,
+        (_, true) => RoughTime::InThePast(unit, count),
+        (_, false) => RoughTime::InTheFuture(unit, count)
+    }
+}
+
+/// Parse an English description of a time relative to now.
+///
+/// Understands "just now", "yesterday", "tomorrow", "last week",
+/// "next month", "in 3 weeks", "2 years ago" and "a second from now",
+/// with counts written as digits or words and units written out or
+/// abbreviated ("3 hrs ago", "10min ago").
+pub fn parse_rough_time(text: &str) -> Result<RoughTime, ParseRoughTimeError> {
+    let tokens = tokenize(text);
+    let mut parser = Parser { tokens: &tokens, next: 0, end: text.len() };
+    let rt = parser.rough_time()?;
+    if parser.peek().is_some() {
+        return Err(parser.error(ParseRoughTimeErrorKind::UnexpectedToken));
+    }
+    Ok(rt)
+}
+
+impl FromStr for RoughTime {
+    type Err = ParseRoughTimeError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        parse_rough_time(text)
+    }
+}
+
+#[test]
+fn test_parse_rough_time() {
+    use crate::chapter_10::TimeUnit::*;
+
+    let cases = [
+        ("2 years ago", RoughTime::InThePast(Years, 2)),
+        ("a second from now", RoughTime::InTheFuture(Seconds, 1)),
+        ("in 3 weeks", RoughTime::InTheFuture(Weeks, 3)),
+        ("yesterday", RoughTime::InThePast(Days, 1)),
+        ("Tomorrow", RoughTime::InTheFuture(Days, 1)),
+        ("just now", RoughTime::JustNow),
+        ("  Just   NOW ", RoughTime::JustNow),
+        ("an hour ago", RoughTime::InThePast(Hours, 1)),
+        ("three days ago", RoughTime::InThePast(Days, 3)),
+        ("10min ago", RoughTime::InThePast(Minutes, 10)),
+        ("in 5 hrs", RoughTime::InTheFuture(Hours, 5)),
+        ("last month", RoughTime::InThePast(Months, 1)),
+        ("next week", RoughTime::InTheFuture(Weeks, 1)),
+        ("a decade ago", RoughTime::InThePast(Decades, 1)),
+        ("in 250ms", RoughTime::InTheFuture(Milliseconds, 250)),
+        ("0 seconds ago", RoughTime::JustNow)
+    ];
+    for &(text, expected) in cases.iter() {
+        assert_eq!(text.parse::<RoughTime>(), Ok(expected), "parsing {:?}", text);
+    }
+}
+
+#[test]
+fn test_parse_round_trips_english() {
+    use crate::chapter_10::rough_time_to_english;
+
+    for unit in TimeUnit::VARIANTS.iter() {
+        for count in &[1, 2, 17] {
+            for rt in &[RoughTime::InThePast(*unit, *count), RoughTime::InTheFuture(*unit, *count)] {
+                assert_eq!(parse_rough_time(&rough_time_to_english(*rt)), Ok(*rt));
+            }
+        }
+    }
+    assert_eq!(parse_rough_time(&rough_time_to_english(RoughTime::JustNow)),
+               Ok(RoughTime::JustNow));
+}
+
+#[test]
+fn test_parse_rough_time_errors() {
+    use self::ParseRoughTimeErrorKind::*;
+
+    let error = |text: &str| {
+        let e = parse_rough_time(text).unwrap_err();
+        (e.kind, e.token, e.position)
+    };
+    assert_eq!(error("   "), (Empty, String::new(), 3));
+    assert_eq!(error("3 fortnights ago"), (UnknownUnit, "fortnights".to_string(), 2));
+    assert_eq!(error("some days ago"), (ExpectedNumber, "some".to_string(), 0));
+    assert_eq!(error("3 days"), (ExpectedDirection, String::new(), 6));
+    assert_eq!(error("3 days hence"), (ExpectedDirection, "hence".to_string(), 7));
+    assert_eq!(error("3 days from tomorrow"), (ExpectedDirection, "tomorrow".to_string(), 12));
+    assert_eq!(error("in 3"), (UnknownUnit, String::new(), 4));
+    assert_eq!(error("3x ago"), (UnknownUnit, "3x".to_string(), 0));
+    assert_eq!(error("99999999999 days ago"), (NumberTooLarge, "99999999999".to_string(), 0));
+    assert_eq!(error("in 99999999999ms"), (NumberTooLarge, "99999999999ms".to_string(), 3));
+    assert_eq!(error("yesterday morning"), (UnexpectedToken, "morning".to_string(), 10));
+    assert_eq!(error("just then"), (ExpectedNow, "then".to_string(), 5));
+    assert_eq!(error("right"), (ExpectedNow, String::new(), 5));
+    assert_eq!(parse_rough_time("just then").unwrap_err().to_string(),
+               "expected \"now\" at offset 5, found \"then\"");
+
+    let message = parse_rough_time("3 fortnights ago").unwrap_err().to_string();
+    assert_eq!(message, "expected a unit of time at offset 2, found \"fortnights\"");
+}
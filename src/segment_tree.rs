@@ -0,0 +1,333 @@
+#![allow(dead_code)]
+
+// Two ways of answering "what is the sum of this stretch of the array" in
+// O(log n) while the array keeps changing. A segment tree keeps a sum for
+// every power-of-two chunk and defers range updates until a query needs
+// them; a Fenwick tree gets the same bounds out of two flat arrays.
+
+use num::FromPrimitive;
+use std::ops::{Add, Mul, Range, Sub};
+
+/// Turn a count of elements into the element type, so a per-element
+/// update can be scaled up to a whole range
+fn count_as<T: FromPrimitive>(count: usize) -> T {
+    T::from_usize(count).expect("range length does not fit in the element type")
+}
+
+/// A segment tree with lazy propagation, supporting range updates and
+/// range sums over a fixed number of elements.
+///
+/// Like slice indexing, every operation panics if its range runs past the end.
+pub struct SegmentTree<T> {
+    len: usize,
+    sums: Vec<T>,    // sum of each node's range, including its pending update
+    pending: Vec<T>  // amount still to be added to each element of the node's children
+}
+
+impl<T> SegmentTree<T>
+    where T: Copy + Default + Add<Output=T> + Mul<Output=T> + FromPrimitive
+{
+    /// A tree over `len` elements that all start out as `T::default()`
+    pub fn new(len: usize) -> Self {
+        let size = 4 * len.max(1);
+        SegmentTree { len, sums: vec![T::default(); size], pending: vec![T::default(); size] }
+    }
+
+    /// A tree over a copy of `values`
+    pub fn from_slice(values: &[T]) -> Self {
+        let mut tree = SegmentTree::new(values.len());
+        if !values.is_empty() {
+            tree.build(0, 0, values.len(), values);
+        }
+        tree
+    }
+
+    /// Number of elements covered by the tree
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the tree covers no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn build(&mut self, node: usize, lo: usize, hi: usize, values: &[T]) {
+        if hi - lo == 1 {
+            self.sums[node] = values[lo];
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        self.build(2 * node + 1, lo, mid, values);
+        self.build(2 * node + 2, mid, hi, values);
+        self.sums[node] = self.sums[2 * node + 1] + self.sums[2 * node + 2];
+    }
+
+    /// Add `delta` to every element of the node covering `lo..hi`
+    fn apply(&mut self, node: usize, lo: usize, hi: usize, delta: T) {
+        self.sums[node] = self.sums[node] + delta * count_as(hi - lo);
+        self.pending[node] = self.pending[node] + delta;
+    }
+
+    /// Hand a node's pending update down to its two children
+    fn push_down(&mut self, node: usize, lo: usize, mid: usize, hi: usize) {
+        let delta = self.pending[node];
+        self.apply(2 * node + 1, lo, mid, delta);
+        self.apply(2 * node + 2, mid, hi, delta);
+        self.pending[node] = T::default();
+    }
+
+    fn check_range(&self, range: &Range<usize>) {
+        assert!(range.start <= range.end && range.end <= self.len,
+                "range {:?} out of bounds for length {}", range, self.len);
+    }
+
+    /// Range update: add `delta` to every element in `range`
+    pub fn add_range(&mut self, range: Range<usize>, delta: T) {
+        self.check_range(&range);
+        if range.start < range.end {
+            self.add_in(0, 0, self.len, &range, delta);
+        }
+    }
+
+    fn add_in(&mut self, node: usize, lo: usize, hi: usize, range: &Range<usize>, delta: T) {
+        if range.end <= lo || hi <= range.start {
+            return;
+        }
+        if range.start <= lo && hi <= range.end {
+            self.apply(node, lo, hi, delta);
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        self.push_down(node, lo, mid, hi);
+        self.add_in(2 * node + 1, lo, mid, range, delta);
+        self.add_in(2 * node + 2, mid, hi, range, delta);
+        self.sums[node] = self.sums[2 * node + 1] + self.sums[2 * node + 2];
+    }
+
+    /// Add `delta` to a single element
+    pub fn add(&mut self, index: usize, delta: T) {
+        self.add_range(index..index + 1, delta);
+    }
+
+    /// Range query: the sum of the elements in `range`
+    pub fn sum(&mut self, range: Range<usize>) -> T {
+        self.check_range(&range);
+        if range.start == range.end {
+            return T::default();
+        }
+        self.sum_in(0, 0, self.len, &range)
+    }
+
+    fn sum_in(&mut self, node: usize, lo: usize, hi: usize, range: &Range<usize>) -> T {
+        if range.end <= lo || hi <= range.start {
+            return T::default();
+        }
+        if range.start <= lo && hi <= range.end {
+            return self.sums[node];
+        }
+        let mid = (lo + hi) / 2;
+        self.push_down(node, lo, mid, hi);
+        self.sum_in(2 * node + 1, lo, mid, range) + self.sum_in(2 * node + 2, mid, hi, range)
+    }
+
+    /// Stabbing query: the current value of a single element
+    pub fn get(&mut self, index: usize) -> T {
+        self.sum(index..index + 1)
+    }
+}
+
+/// A Fenwick (binary indexed) tree supporting range updates and range
+/// sums, using the two-array trick: one array holds the per-element
+/// differences and the other holds each difference times its index.
+/// The differences that get added and the ones that get taken away are
+/// kept apart, so nothing is ever negated and unsigned types work too.
+pub struct FenwickTree<T> {
+    added: Vec<T>,   // 1-based, so index 0 is unused
+    removed: Vec<T>,
+    added_weighted: Vec<T>,
+    removed_weighted: Vec<T>
+}
+
+/// Add `delta` at position `pos` of a 1-based Fenwick array
+fn fenwick_update<T: Copy + Add<Output=T>>(tree: &mut [T], pos: usize, delta: T) {
+    let mut i = pos + 1;
+    while i < tree.len() {
+        tree[i] = tree[i] + delta;
+        i += i & i.wrapping_neg();
+    }
+}
+
+/// Sum positions `0..end` of a 1-based Fenwick array
+fn fenwick_query<T: Copy + Default + Add<Output=T>>(tree: &[T], end: usize) -> T {
+    let mut total = T::default();
+    let mut i = end;
+    while i > 0 {
+        total = total + tree[i];
+        i -= i & i.wrapping_neg();
+    }
+    total
+}
+
+impl<T> FenwickTree<T>
+    where T: Copy + Default + Add<Output=T> + Sub<Output=T> + Mul<Output=T> + FromPrimitive
+{
+    /// A tree over `len` elements that all start out as `T::default()`
+    pub fn new(len: usize) -> Self {
+        let zeros = vec![T::default(); len + 1];
+        FenwickTree { added: zeros.clone(), removed: zeros.clone(), added_weighted: zeros.clone(), removed_weighted: zeros }
+    }
+
+    /// A tree over a copy of `values`
+    pub fn from_slice(values: &[T]) -> Self {
+        let mut tree = FenwickTree::new(values.len());
+        for (i, value) in values.iter().enumerate() {
+            tree.add(i, *value);
+        }
+        tree
+    }
+
+    /// Number of elements covered by the tree
+    pub fn len(&self) -> usize {
+        self.added.len() - 1
+    }
+
+    /// Returns true if the tree covers no elements
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn check_range(&self, range: &Range<usize>) {
+        assert!(range.start <= range.end && range.end <= self.len(),
+                "range {:?} out of bounds for length {}", range, self.len());
+    }
+
+    /// Range update: add `delta` to every element in `range`
+    pub fn add_range(&mut self, range: Range<usize>, delta: T) {
+        self.check_range(&range);
+        fenwick_update(&mut self.added, range.start, delta);
+        fenwick_update(&mut self.removed, range.end, delta);
+        fenwick_update(&mut self.added_weighted, range.start, delta * count_as(range.start));
+        fenwick_update(&mut self.removed_weighted, range.end, delta * count_as(range.end));
+    }
+
+    /// Add `delta` to a single element
+    pub fn add(&mut self, index: usize, delta: T) {
+        self.add_range(index..index + 1, delta);
+    }
+
+    /// The sum of elements `0..end`
+    fn prefix_sum(&self, end: usize) -> T {
+        // Each side is what the sum would be with only its own updates, so
+        // subtracting them last keeps unsigned sums from going below zero
+        let end_count = count_as(end);
+        let gained = fenwick_query(&self.added, end) * end_count + fenwick_query(&self.removed_weighted, end);
+        let lost = fenwick_query(&self.removed, end) * end_count + fenwick_query(&self.added_weighted, end);
+        gained - lost
+    }
+
+    /// Range query: the sum of the elements in `range`
+    pub fn sum(&self, range: Range<usize>) -> T {
+        self.check_range(&range);
+        self.prefix_sum(range.end) - self.prefix_sum(range.start)
+    }
+
+    /// Stabbing query: the current value of a single element
+    pub fn get(&self, index: usize) -> T {
+        self.check_range(&(index..index + 1));
+        fenwick_query(&self.added, index + 1) - fenwick_query(&self.removed, index + 1)
+    }
+}
+
+#[test]
+fn test_segment_tree() {
+    let mut tree = SegmentTree::from_slice(&[5, 3, 7, 9, 6, 4, 1, 2]);
+    assert_eq!(tree.len(), 8);
+    assert_eq!(tree.sum(0..8), 37);
+    assert_eq!(tree.sum(2..5), 22);
+    assert_eq!(tree.sum(3..3), 0);
+
+    tree.add_range(1..6, 10);
+    assert_eq!(tree.sum(0..8), 87);
+    assert_eq!(tree.sum(2..5), 52);
+    assert_eq!(tree.get(0), 5);
+    assert_eq!(tree.get(5), 14);
+
+    tree.add(7, -2);
+    assert_eq!(tree.get(7), 0);
+    assert_eq!(tree.sum(6..8), 1);
+}
+
+#[test]
+fn test_fenwick_tree() {
+    let mut tree = FenwickTree::from_slice(&[5, 3, 7, 9, 6, 4, 1, 2]);
+    assert_eq!(tree.len(), 8);
+    assert_eq!(tree.sum(0..8), 37);
+    assert_eq!(tree.sum(2..5), 22);
+    assert_eq!(tree.sum(3..3), 0);
+
+    tree.add_range(1..6, 10);
+    assert_eq!(tree.sum(0..8), 87);
+    assert_eq!(tree.sum(2..5), 52);
+    assert_eq!(tree.get(0), 5);
+    assert_eq!(tree.get(5), 14);
+
+    tree.add(7, -2);
+    assert_eq!(tree.get(7), 0);
+    assert_eq!(tree.sum(6..8), 1);
+
+    let mut floats = FenwickTree::new(4);
+    floats.add_range(0..4, 0.5);
+    assert_eq!(floats.sum(1..3), 1.0);
+
+    let mut unsigned = FenwickTree::<u64>::from_slice(&[1, 2, 3]);
+    unsigned.add_range(0..2, 10);
+    unsigned.add_range(1..3, u64::MAX / 8);
+    assert_eq!(unsigned.get(0), 11);
+    assert_eq!(unsigned.get(2), 3 + u64::MAX / 8);
+    assert_eq!(unsigned.sum(0..1), 11);
+    assert_eq!(unsigned.sum(0..3), 26 + 2 * (u64::MAX / 8));
+    assert_eq!(unsigned.sum(2..3), 3 + u64::MAX / 8);
+}
+
+#[test]
+#[should_panic(expected = "out of bounds")]
+fn test_range_past_end() {
+    let tree = FenwickTree::<i32>::new(3);
+    tree.sum(1..4);
+}
+
+#[test]
+fn test_range_trees_match_brute_force() {
+    const LEN: usize = 37;
+    let mut naive = vec![0i64; LEN];
+    let mut segments = SegmentTree::new(LEN);
+    let mut fenwick = FenwickTree::new(LEN);
+
+    let mut seed = 2024u32;
+    let mut next = |bound: usize| {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (seed >> 16) as usize % bound
+    };
+    for _ in 0..500 {
+        let a = next(LEN + 1);
+        let b = next(LEN + 1);
+        let range = a.min(b)..a.max(b);
+        if next(2) == 0 {
+            let delta = next(100) as i64 - 50;
+            for value in &mut naive[range.clone()] {
+                *value += delta;
+            }
+            segments.add_range(range.clone(), delta);
+            fenwick.add_range(range, delta);
+        } else {
+            let expected: i64 = naive[range.clone()].iter().sum();
+            assert_eq!(segments.sum(range.clone()), expected);
+            assert_eq!(fenwick.sum(range), expected);
+        }
+    }
+    for (i, value) in naive.iter().enumerate() {
+        assert_eq!(segments.get(i), *value);
+        assert_eq!(fenwick.get(i), *value);
+    }
+}
@@ -0,0 +1,1014 @@
+#![allow(dead_code)]
+
+// Sorting and selection, ordered by the comparators in the comparator
+// module instead of only Ord. merge_sort_by is stable; quicksort_by and
+// heapsort_by aren't, but don't allocate. nth_element_by and
+// partial_sort_by only do as much sorting as they have to, top_k_by picks
+// the smallest few items from an iterator of any length, and
+// merge_sorted_by merges iterators that are already sorted.
+//
+// external_sort_by sorts more items than fit in memory: it sorts them a
+// run at a time, spills each run to a file in a private temporary
+// directory, and merges the runs as they're read back, a bounded number of
+// files at a time. Items go to disk in the lossless binary format of the
+// Spill trait.
+//
+// `benchmark` times all of this against the std equivalents; run it with
+// `rust_practice bench-sort`.
+
+use crate::comparator::Comparator;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::hint::black_box;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{self, AtomicUsize};
+use std::time::{Duration, Instant};
+
+/// Slices this short are insertion sorted
+const INSERTION_SORT_LEN: usize = 16;
+
+fn insertion_sort_by<T, C: Comparator<T>>(items: &mut [T], comparator: &C) {
+    for i in 1..items.len() {
+        let mut j = i;
+        // Only move past strictly greater items, so this is stable
+        while j > 0 && comparator.compare(&items[j - 1], &items[j]) == Ordering::Greater {
+            items.swap(j - 1, j);
+            j -= 1;
+        }
+    }
+}
+
+/// Is every item no greater than the next?
+pub fn is_sorted_by<T, C: Comparator<T>>(items: &[T], comparator: C) -> bool {
+    items.windows(2).all(|pair| comparator.compare(&pair[0], &pair[1]) != Ordering::Greater)
+}
+
+/// Sort `items`, keeping equal items in the order they were in. This sorts
+/// the items' positions, then moves each item once, so it needs two
+/// `usize`s of memory per item but never clones or copies a `T`.
+pub fn merge_sort_by<T, C: Comparator<T>>(items: &mut [T], comparator: C) {
+    merge_sort_with(items, &comparator);
+}
+
+fn merge_sort_with<T, C: Comparator<T>>(items: &mut [T], comparator: &C) {
+    let by_item = |&a: &usize, &b: &usize| comparator.compare(&items[a], &items[b]);
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    for run in order.chunks_mut(INSERTION_SORT_LEN) {
+        insertion_sort_by(run, &by_item);
+    }
+
+    // Merge runs of doubling length back and forth between two buffers
+    let mut spare = order.clone();
+    let mut width = INSERTION_SORT_LEN;
+    while width < order.len() {
+        for (from, to) in order.chunks(2 * width).zip(spare.chunks_mut(2 * width)) {
+            let (left, right) = from.split_at(width.min(from.len()));
+            let (mut i, mut j) = (0, 0);
+            for slot in to.iter_mut() {
+                // Take from the left run on ties, to stay stable
+                let take_left = j == right.len()
+                    || (i < left.len() && by_item(&left[i], &right[j]) != Ordering::Greater);
+                if take_left {
+                    *slot = left[i];
+                    i += 1;
+                } else {
+                    *slot = right[j];
+                    j += 1;
+                }
+            }
+        }
+        mem::swap(&mut order, &mut spare);
+        width *= 2;
+    }
+    apply_permutation(items, order);
+}
+
+/// Rearrange `items` so the item at position `order[i]` ends up at `i`
+fn apply_permutation<T>(items: &mut [T], mut order: Vec<usize>) {
+    for start in 0..items.len() {
+        // Follow the cycle through `start`, marking positions done by
+        // pointing them at themselves
+        let mut i = start;
+        loop {
+            let source = order[i];
+            order[i] = i;
+            if source == start {
+                break;
+            }
+            items.swap(i, source);
+            i = source;
+        }
+    }
+}
+
+/// Move the item at `root` of a heap down to where it belongs. The
+/// greatest item is at the root.
+fn sift_down<T, C: Comparator<T>>(heap: &mut [T], mut root: usize, comparator: &C) {
+    loop {
+        let mut child = 2 * root + 1;
+        if child >= heap.len() {
+            return;
+        }
+        if child + 1 < heap.len() && comparator.compare(&heap[child], &heap[child + 1]) == Ordering::Less {
+            child += 1;
+        }
+        if comparator.compare(&heap[root], &heap[child]) != Ordering::Less {
+            return;
+        }
+        heap.swap(root, child);
+        root = child;
+    }
+}
+
+/// Move the last item of a heap up to where it belongs
+fn sift_up<T, C: Comparator<T>>(heap: &mut [T], comparator: &C) {
+    let mut child = heap.len() - 1;
+    while child > 0 {
+        let parent = (child - 1) / 2;
+        if comparator.compare(&heap[parent], &heap[child]) != Ordering::Less {
+            return;
+        }
+        heap.swap(parent, child);
+        child = parent;
+    }
+}
+
+fn heapsort_with<T, C: Comparator<T>>(items: &mut [T], comparator: &C) {
+    for root in (0..items.len() / 2).rev() {
+        sift_down(items, root, comparator);
+    }
+    for end in (1..items.len()).rev() {
+        items.swap(0, end);
+        sift_down(&mut items[..end], 0, comparator);
+    }
+}
+
+/// Sort `items` in O(n log n) time however they start out, without
+/// allocating. Equal items may be reordered.
+pub fn heapsort_by<T, C: Comparator<T>>(items: &mut [T], comparator: C) {
+    heapsort_with(items, &comparator);
+}
+
+/// Partition around a median-of-three pivot into less, equal and greater
+/// parts, returning where the equal part starts and ends
+fn partition<T, C: Comparator<T>>(items: &mut [T], comparator: &C) -> (usize, usize) {
+    let (first, middle, last) = (0, items.len() / 2, items.len() - 1);
+    let less = |a: usize, b: usize| comparator.compare(&items[a], &items[b]) == Ordering::Less;
+    let median = if less(first, middle) {
+        if less(middle, last) { middle } else if less(first, last) { last } else { first }
+    } else if less(first, last) {
+        first
+    } else if less(middle, last) {
+        last
+    } else {
+        middle
+    };
+    items.swap(0, median);
+
+    // items[1..lt] < pivot, items[lt..i] == pivot, items[gt..] > pivot
+    let (mut lt, mut i, mut gt) = (1, 1, items.len());
+    while i < gt {
+        match comparator.compare(&items[i], &items[0]) {
+            Ordering::Less => {
+                items.swap(lt, i);
+                lt += 1;
+                i += 1;
+            }
+            Ordering::Greater => {
+                gt -= 1;
+                items.swap(i, gt);
+            }
+            Ordering::Equal => i += 1
+        }
+    }
+    items.swap(0, lt - 1);
+    (lt - 1, gt)
+}
+
+/// How many bad partitions to put up with before switching to heapsort
+fn depth_limit(len: usize) -> u32 {
+    2 * (usize::BITS - len.leading_zeros())
+}
+
+fn quicksort_with<T, C: Comparator<T>>(mut items: &mut [T], comparator: &C, mut depth: u32) {
+    loop {
+        if items.len() <= INSERTION_SORT_LEN {
+            return insertion_sort_by(items, comparator);
+        }
+        if depth == 0 {
+            return heapsort_with(items, comparator);
+        }
+        depth -= 1;
+        let (start, end) = partition(items, comparator);
+        let (less, rest) = items.split_at_mut(start);
+        let greater = &mut rest[end - start..];
+        // Recurse into the smaller part and loop on the larger, so the
+        // stack stays O(log n) deep
+        if less.len() < greater.len() {
+            quicksort_with(less, comparator, depth);
+            items = greater;
+        } else {
+            quicksort_with(greater, comparator, depth);
+            items = less;
+        }
+    }
+}
+
+/// Sort `items` in place with an introsort: quicksort with three-way
+/// partitioning, so runs of equal items cost nothing, falling back to
+/// heapsort when partitions keep coming out lopsided. Equal items may be
+/// reordered.
+pub fn quicksort_by<T, C: Comparator<T>>(items: &mut [T], comparator: C) {
+    let depth = depth_limit(items.len());
+    quicksort_with(items, &comparator, depth);
+}
+
+/// Reorder `items` so the item at `n` is the one that would be there if
+/// they were sorted, with no greater items before it and no smaller ones
+/// after it, and return it. Takes O(n) time on average. Panics if `n` is
+/// out of range.
+pub fn nth_element_by<T, C: Comparator<T>>(items: &mut [T], n: usize, comparator: C) -> &mut T {
+    assert!(n < items.len(), "nth_element_by: index {} is out of range for {} items", n, items.len());
+    select_with(items, n, &comparator);
+    &mut items[n]
+}
+
+fn select_with<T, C: Comparator<T>>(items: &mut [T], n: usize, comparator: &C) {
+    let mut depth = depth_limit(items.len());
+    let (mut rest, mut index) = (&mut items[..], n);
+    loop {
+        if rest.len() <= INSERTION_SORT_LEN {
+            return insertion_sort_by(rest, comparator);
+        }
+        if depth == 0 {
+            return heapsort_with(rest, comparator);
+        }
+        depth -= 1;
+        let (start, end) = partition(rest, comparator);
+        if index < start {
+            rest = &mut rest[..start];
+        } else if index >= end {
+            rest = &mut rest[end..];
+            index -= end;
+        } else {
+            return;
+        }
+    }
+}
+
+/// Put the `k` smallest items, sorted, at the front of `items`. The rest
+/// end up after them in no particular order.
+pub fn partial_sort_by<T, C: Comparator<T>>(items: &mut [T], k: usize, comparator: C) {
+    let k = k.min(items.len());
+    if k == 0 {
+        return;
+    }
+    select_with(items, k - 1, &comparator);
+    quicksort_with(&mut items[..k - 1], &comparator, depth_limit(k));
+}
+
+/// The `k` smallest items from `items`, sorted. Only `k` items are kept
+/// at a time, so `items` can be as long as you like.
+pub fn top_k_by<T, I: IntoIterator<Item = T>, C: Comparator<T>>(items: I, k: usize, comparator: C) -> Vec<T> {
+    // A heap with the greatest of the smallest items so far on top
+    let mut heap = Vec::with_capacity(k);
+    if k == 0 {
+        return heap;
+    }
+    for item in items {
+        if heap.len() < k {
+            heap.push(item);
+            sift_up(&mut heap, &comparator);
+        } else if comparator.compare(&item, &heap[0]) == Ordering::Less {
+            heap[0] = item;
+            sift_down(&mut heap, 0, &comparator);
+        }
+    }
+    heapsort_with(&mut heap, &comparator);
+    heap
+}
+
+/// Orders a merge heap so its root holds the smallest item, and the one
+/// from the earliest source if there's a tie
+fn merge_order<T, C: Comparator<T>>(comparator: &C) -> impl Fn(&(T, usize), &(T, usize)) -> Ordering + '_ {
+    move |a, b| comparator.compare(&b.0, &a.0).then(b.1.cmp(&a.1))
+}
+
+/// See `merge_sorted_by`
+pub struct MergeSorted<I: Iterator, C> {
+    sources: Vec<I>,
+    /// The next item from each source that has one, and which source
+    heap: Vec<(I::Item, usize)>,
+    comparator: C
+}
+
+/// Merge iterators that are each already sorted into one sorted iterator.
+/// Equal items come out in the order of the iterators they came from.
+pub fn merge_sorted_by<S, C>(sources: S, comparator: C) -> MergeSorted<<S::Item as IntoIterator>::IntoIter, C>
+    where S: IntoIterator, S::Item: IntoIterator, C: Comparator<<S::Item as IntoIterator>::Item>
+{
+    let mut sources: Vec<_> = sources.into_iter().map(IntoIterator::into_iter).collect();
+    let mut heap = Vec::with_capacity(sources.len());
+    for (i, source) in sources.iter_mut().enumerate() {
+        if let Some(item) = source.next() {
+            heap.push((item, i));
+            sift_up(&mut heap, &merge_order(&comparator));
+        }
+    }
+    MergeSorted { sources, heap, comparator }
+}
+
+impl<I: Iterator, C: Comparator<I::Item>> Iterator for MergeSorted<I, C> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let source = self.heap.first()?.1;
+        let (item, _) = match self.sources[source].next() {
+            Some(next) => mem::replace(&mut self.heap[0], (next, source)),
+            None => self.heap.swap_remove(0)
+        };
+        sift_down(&mut self.heap, 0, &merge_order(&self.comparator));
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.sources.iter().fold((self.heap.len(), Some(self.heap.len())), |(low, high), source| {
+            let (source_low, source_high) = source.size_hint();
+            (low.saturating_add(source_low), high.and_then(|high| high.checked_add(source_high?)))
+        })
+    }
+}
+
+/// How `external_sort_by` writes items to its run files and reads them
+/// back. Reading must give back exactly what was written, or the sort
+/// wouldn't return what it was given. The formats here are binary, with
+/// numbers in their full width, so nothing is rounded on the way through.
+pub trait Spill: Sized {
+    fn spill(&self, out: &mut dyn Write) -> io::Result<()>;
+
+    /// The next item, or None at the end of the input
+    fn unspill(input: &mut dyn BufRead) -> io::Result<Option<Self>>;
+}
+
+/// Fill `buffer` from `input`, or return false if the input was already
+/// at its end. Ending partway through is an error.
+fn read_exact_or_end(input: &mut dyn BufRead, buffer: &mut [u8]) -> io::Result<bool> {
+    if buffer.is_empty() || input.fill_buf()?.is_empty() {
+        return Ok(buffer.is_empty());
+    }
+    input.read_exact(buffer)?;
+    Ok(true)
+}
+
+/// Read an item that has to be there, like the second field of a pair
+fn unspill_required<T: Spill>(input: &mut dyn BufRead) -> io::Result<T> {
+    T::unspill(input)?.ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "run file ends mid-item"))
+}
+
+fn invalid_data(reason: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+macro_rules! spill_numbers {
+    ($($t:ty),*) => {
+        $(
+            impl Spill for $t {
+                fn spill(&self, out: &mut dyn Write) -> io::Result<()> {
+                    out.write_all(&self.to_le_bytes())
+                }
+
+                fn unspill(input: &mut dyn BufRead) -> io::Result<Option<Self>> {
+                    let mut bytes = [0; std::mem::size_of::<$t>()];
+                    Ok(if read_exact_or_end(input, &mut bytes)? { Some(<$t>::from_le_bytes(bytes)) } else { None })
+                }
+            }
+        )*
+    }
+}
+
+spill_numbers!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+impl Spill for usize {
+    fn spill(&self, out: &mut dyn Write) -> io::Result<()> {
+        (*self as u64).spill(out)
+    }
+
+    fn unspill(input: &mut dyn BufRead) -> io::Result<Option<Self>> {
+        match u64::unspill(input)? {
+            None => Ok(None),
+            Some(n) => usize::try_from(n).map(Some).map_err(|_| invalid_data("number too big for a usize"))
+        }
+    }
+}
+
+impl Spill for isize {
+    fn spill(&self, out: &mut dyn Write) -> io::Result<()> {
+        (*self as i64).spill(out)
+    }
+
+    fn unspill(input: &mut dyn BufRead) -> io::Result<Option<Self>> {
+        match i64::unspill(input)? {
+            None => Ok(None),
+            Some(n) => isize::try_from(n).map(Some).map_err(|_| invalid_data("number too big for an isize"))
+        }
+    }
+}
+
+impl Spill for bool {
+    fn spill(&self, out: &mut dyn Write) -> io::Result<()> {
+        u8::from(*self).spill(out)
+    }
+
+    fn unspill(input: &mut dyn BufRead) -> io::Result<Option<Self>> {
+        match u8::unspill(input)? {
+            None => Ok(None),
+            Some(0) => Ok(Some(false)),
+            Some(1) => Ok(Some(true)),
+            Some(_) => Err(invalid_data("bad bool"))
+        }
+    }
+}
+
+impl Spill for char {
+    fn spill(&self, out: &mut dyn Write) -> io::Result<()> {
+        u32::from(*self).spill(out)
+    }
+
+    fn unspill(input: &mut dyn BufRead) -> io::Result<Option<Self>> {
+        match u32::unspill(input)? {
+            None => Ok(None),
+            Some(n) => std::char::from_u32(n).map(Some).ok_or_else(|| invalid_data("bad char"))
+        }
+    }
+}
+
+/// A length, then the bytes
+impl Spill for String {
+    fn spill(&self, out: &mut dyn Write) -> io::Result<()> {
+        self.len().spill(out)?;
+        out.write_all(self.as_bytes())
+    }
+
+    fn unspill(input: &mut dyn BufRead) -> io::Result<Option<Self>> {
+        let len = match usize::unspill(input)? {
+            None => return Ok(None),
+            Some(len) => len
+        };
+        let mut bytes = Vec::new();
+        input.take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "run file ends mid-item"));
+        }
+        String::from_utf8(bytes).map(Some).map_err(|_| invalid_data("string isn't UTF-8"))
+    }
+}
+
+/// A length, then the elements
+impl<T: Spill> Spill for Vec<T> {
+    fn spill(&self, out: &mut dyn Write) -> io::Result<()> {
+        self.len().spill(out)?;
+        self.iter().try_for_each(|element| element.spill(out))
+    }
+
+    fn unspill(input: &mut dyn BufRead) -> io::Result<Option<Self>> {
+        let len = match usize::unspill(input)? {
+            None => return Ok(None),
+            Some(len) => len
+        };
+        // Don't trust the length for the allocation; it may be corrupt
+        let mut elements = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            elements.push(unspill_required(input)?);
+        }
+        Ok(Some(elements))
+    }
+}
+
+impl<T: Spill> Spill for Option<T> {
+    fn spill(&self, out: &mut dyn Write) -> io::Result<()> {
+        match *self {
+            None => false.spill(out),
+            Some(ref value) => {
+                true.spill(out)?;
+                value.spill(out)
+            }
+        }
+    }
+
+    fn unspill(input: &mut dyn BufRead) -> io::Result<Option<Self>> {
+        match bool::unspill(input)? {
+            None => Ok(None),
+            Some(false) => Ok(Some(None)),
+            Some(true) => Ok(Some(Some(unspill_required(input)?)))
+        }
+    }
+}
+
+impl<A: Spill, B: Spill> Spill for (A, B) {
+    fn spill(&self, out: &mut dyn Write) -> io::Result<()> {
+        self.0.spill(out)?;
+        self.1.spill(out)
+    }
+
+    fn unspill(input: &mut dyn BufRead) -> io::Result<Option<Self>> {
+        match A::unspill(input)? {
+            None => Ok(None),
+            Some(a) => Ok(Some((a, unspill_required(input)?)))
+        }
+    }
+}
+
+impl<A: Spill, B: Spill, C: Spill> Spill for (A, B, C) {
+    fn spill(&self, out: &mut dyn Write) -> io::Result<()> {
+        self.0.spill(out)?;
+        self.1.spill(out)?;
+        self.2.spill(out)
+    }
+
+    fn unspill(input: &mut dyn BufRead) -> io::Result<Option<Self>> {
+        match A::unspill(input)? {
+            None => Ok(None),
+            Some(a) => Ok(Some((a, unspill_required(input)?, unspill_required(input)?)))
+        }
+    }
+}
+
+/// Settings for `external_sort_by`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExternalSortConfig {
+    /// Where to make the private directory the runs are written in
+    pub dir: PathBuf,
+    /// How many items to sort in memory at once
+    pub run_len: usize,
+    /// How many runs to merge at once, which is how many files are open at
+    /// a time. With more runs than this, they're merged in several passes.
+    pub fan_in: usize
+}
+
+impl Default for ExternalSortConfig {
+    fn default() -> Self {
+        ExternalSortConfig {
+            dir: std::env::temp_dir(),
+            run_len: 100_000,
+            fan_in: 64
+        }
+    }
+}
+
+/// Numbers the external sorts in this process, to keep their directories
+/// apart
+static EXTERNAL_SORTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Make a new directory in `parent` that only we can use. Creating it
+/// fails if anything, a symlink included, is already there, so we try a
+/// few names.
+fn create_private_dir(parent: &Path) -> io::Result<PathBuf> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    let mut attempts = 0;
+    loop {
+        let sort_number = EXTERNAL_SORTS.fetch_add(1, atomic::Ordering::Relaxed);
+        let dir = parent.join(format!("rust_practice_sort_{}_{}", std::process::id(), sort_number));
+        attempts += 1;
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(ref error) if error.kind() == io::ErrorKind::AlreadyExists && attempts < 10 => continue,
+            Err(error) => return Err(error)
+        }
+    }
+}
+
+/// The sorted runs of one external sort, in input order, in a directory
+/// that's deleted along with everything in it when this is dropped
+struct RunFiles {
+    dir: PathBuf,
+    runs: Vec<PathBuf>,
+    /// How many run files have been made, to name the next one
+    made: usize
+}
+
+impl RunFiles {
+    /// Write a run of items from `items` to a new file
+    fn write<T: Spill, I: Iterator<Item = io::Result<T>>>(&mut self, items: I) -> io::Result<PathBuf> {
+        let path = self.dir.join(format!("{}.run", self.made));
+        self.made += 1;
+        let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        let mut out = BufWriter::new(file);
+        for item in items {
+            item?.spill(&mut out)?;
+        }
+        out.flush()?;
+        Ok(path)
+    }
+}
+
+impl Drop for RunFiles {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Merges open run files
+struct RunMerger<T> {
+    inputs: Vec<BufReader<File>>,
+    /// The next item from each run that has one, and which run
+    heap: Vec<(T, usize)>,
+    /// An error to report after the item before it
+    error: Option<io::Error>
+}
+
+impl<T: Spill> RunMerger<T> {
+    fn open<C: Comparator<T>>(runs: &[PathBuf], comparator: &C) -> io::Result<RunMerger<T>> {
+        let mut merger = RunMerger { inputs: Vec::with_capacity(runs.len()), heap: Vec::new(), error: None };
+        for (i, path) in runs.iter().enumerate() {
+            let mut input = BufReader::new(File::open(path)?);
+            if let Some(item) = T::unspill(&mut input)? {
+                merger.heap.push((item, i));
+                sift_up(&mut merger.heap, &merge_order(comparator));
+            }
+            merger.inputs.push(input);
+        }
+        Ok(merger)
+    }
+
+    fn next<C: Comparator<T>>(&mut self, comparator: &C) -> Option<io::Result<T>> {
+        if let Some(error) = self.error.take() {
+            return Some(Err(error));
+        }
+        let run = self.heap.first()?.1;
+        let (item, _) = match T::unspill(&mut self.inputs[run]) {
+            Ok(Some(next)) => mem::replace(&mut self.heap[0], (next, run)),
+            Ok(None) => self.heap.swap_remove(0),
+            Err(error) => {
+                self.error = Some(error);
+                let (item, _) = self.heap.swap_remove(0);
+                self.heap.clear();
+                return Some(Ok(item));
+            }
+        };
+        sift_down(&mut self.heap, 0, &merge_order(comparator));
+        Some(Ok(item))
+    }
+}
+
+/// The items from `external_sort_by`, in order. Reading a run back can
+/// fail, so each item is an io::Result; after an error, there are no more
+/// items. The run files are deleted when this is dropped.
+pub struct ExternalSorted<T, C> {
+    /// The items, if they all fit in one run and nothing was written
+    in_memory: std::vec::IntoIter<T>,
+    merger: Option<RunMerger<T>>,
+    comparator: C,
+    // Last, so the files are closed before they're deleted
+    files: Option<RunFiles>
+}
+
+/// Sort `items` using at most about `config.run_len` of them in memory at
+/// once, writing the rest to files in a new directory in `config.dir`. The
+/// sort is stable.
+pub fn external_sort_by<T, I, C>(items: I, config: &ExternalSortConfig, comparator: C)
+                                 -> io::Result<ExternalSorted<T, C>>
+    where T: Spill, I: IntoIterator<Item = T>, C: Comparator<T>
+{
+    let run_len = config.run_len.max(1);
+    let fan_in = config.fan_in.max(2);
+    let mut items = items.into_iter().peekable();
+    let mut first: Vec<T> = items.by_ref().take(run_len).collect();
+    merge_sort_with(&mut first, &comparator);
+    if items.peek().is_none() {
+        return Ok(ExternalSorted { in_memory: first.into_iter(), merger: None, comparator, files: None });
+    }
+
+    let mut files = RunFiles { dir: create_private_dir(&config.dir)?, runs: Vec::new(), made: 0 };
+    let run = files.write(first.into_iter().map(Ok))?;
+    files.runs.push(run);
+    while items.peek().is_some() {
+        let mut run: Vec<T> = items.by_ref().take(run_len).collect();
+        merge_sort_with(&mut run, &comparator);
+        let run = files.write(run.into_iter().map(Ok))?;
+        files.runs.push(run);
+    }
+
+    // Merge neighbouring runs, so ties still come out in input order,
+    // until they can all be merged at once
+    while files.runs.len() > fan_in {
+        let mut merged = Vec::new();
+        for group in mem::take(&mut files.runs).chunks(fan_in) {
+            let mut merger = RunMerger::open(group, &comparator)?;
+            merged.push(files.write(std::iter::from_fn(|| merger.next(&comparator)))?);
+            for path in group {
+                fs::remove_file(path)?;
+            }
+        }
+        files.runs = merged;
+    }
+
+    let merger = RunMerger::open(&files.runs, &comparator)?;
+    Ok(ExternalSorted { in_memory: Vec::new().into_iter(), merger: Some(merger), comparator, files: Some(files) })
+}
+
+impl<T: Spill, C: Comparator<T>> Iterator for ExternalSorted<T, C> {
+    type Item = io::Result<T>;
+
+    fn next(&mut self) -> Option<io::Result<T>> {
+        if let Some(item) = self.in_memory.next() {
+            return Some(Ok(item));
+        }
+        self.merger.as_mut()?.next(&self.comparator)
+    }
+}
+
+/// How long one operation took, and how long the std equivalent took
+#[derive(Clone, Debug)]
+pub struct BenchResult {
+    pub name: &'static str,
+    pub time: Duration,
+    /// What it was compared with, if std has anything comparable
+    pub baseline: Option<(&'static str, Duration)>
+}
+
+/// `len` pseudo-random numbers, the same ones every time
+fn bench_data(len: usize) -> Vec<u64> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..len).map(|_| {
+        // xorshift64
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state % (len as u64 * 4 + 1)
+    }).collect()
+}
+
+/// Time `operation` on a fresh copy of `data`
+fn time_on<F: FnOnce(&mut Vec<u64>)>(data: &[u64], operation: F) -> Duration {
+    let mut copy = data.to_vec();
+    let start = Instant::now();
+    operation(&mut copy);
+    let time = start.elapsed();
+    black_box(copy);
+    time
+}
+
+/// Time everything in this module on `len` random numbers. External
+/// sorting writes to the temp directory.
+pub fn benchmark(len: usize) -> io::Result<Vec<BenchResult>> {
+    let data = bench_data(len);
+    let by_value = |a: &u64, b: &u64| a.cmp(b);
+    let k = (len / 100).max(1);
+    let n = len / 2;
+    let mut results = Vec::new();
+    let mut bench = |name, ours: Duration, baseline| results.push(BenchResult { name, time: ours, baseline });
+
+    bench("merge_sort_by",
+          time_on(&data, |items| merge_sort_by(items, by_value)),
+          Some(("slice::sort_by", time_on(&data, |items| items.sort_by(by_value)))));
+    bench("quicksort_by",
+          time_on(&data, |items| quicksort_by(items, by_value)),
+          Some(("slice::sort_unstable_by", time_on(&data, |items| items.sort_unstable_by(by_value)))));
+    bench("heapsort_by",
+          time_on(&data, |items| heapsort_by(items, by_value)),
+          Some(("slice::sort_unstable_by", time_on(&data, |items| items.sort_unstable_by(by_value)))));
+    if len > 0 {
+        bench("nth_element_by",
+              time_on(&data, |items| { nth_element_by(items, n, by_value); }),
+              Some(("slice::select_nth_unstable_by",
+                    time_on(&data, |items| { items.select_nth_unstable_by(n, by_value); }))));
+    }
+    bench("partial_sort_by",
+          time_on(&data, |items| partial_sort_by(items, k, by_value)),
+          Some(("slice::sort_unstable_by", time_on(&data, |items| items.sort_unstable_by(by_value)))));
+    bench("top_k_by",
+          time_on(&data, |items| { black_box(top_k_by(items.iter().copied(), k, by_value)); }),
+          Some(("BinaryHeap", time_on(&data, |items| {
+              let mut heap = std::collections::BinaryHeap::with_capacity(k + 1);
+              for &item in items.iter() {
+                  heap.push(item);
+                  if heap.len() > k {
+                      heap.pop();
+                  }
+              }
+              black_box(heap.into_sorted_vec());
+          }))));
+
+    let mut runs: Vec<Vec<u64>> = data.chunks(len / 16 + 1).map(<[u64]>::to_vec).collect();
+    for run in &mut runs {
+        run.sort_unstable();
+    }
+    let merge_start = Instant::now();
+    let merged: Vec<u64> = merge_sorted_by(runs.clone(), by_value).collect();
+    let merge_time = merge_start.elapsed();
+    let concat_start = Instant::now();
+    let mut concatenated = runs.concat();
+    concatenated.sort_by(by_value);
+    bench("merge_sorted_by", merge_time, Some(("concat + slice::sort_by", concat_start.elapsed())));
+    assert_eq!(merged, concatenated);
+
+    // Unsorted input would be rejected after a few items
+    let sorted = merged;
+    bench("is_sorted_by",
+          time_on(&sorted, |items| { black_box(is_sorted_by(items, by_value)); }),
+          Some(("slice::is_sorted_by", time_on(&sorted, |items| { black_box(items.is_sorted_by(|a, b| a <= b)); }))));
+
+    let config = ExternalSortConfig { run_len: (len / 8).max(1), ..ExternalSortConfig::default() };
+    let start = Instant::now();
+    for item in external_sort_by(data.iter().copied(), &config, by_value)? {
+        black_box(item?);
+    }
+    bench("external_sort_by", start.elapsed(), None);
+    Ok(results)
+}
+
+impl fmt::Display for BenchResult {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(dest, "{:<18} {:>12?}", self.name, self.time)?;
+        if let Some((baseline, time)) = self.baseline {
+            write!(dest, "   {:<30} {:>12?}", baseline, time)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn scrambled(len: usize) -> Vec<(u64, usize)> {
+    // Lots of duplicate keys, each tagged with its original position
+    bench_data(len).into_iter().map(|n| n % 50).enumerate().map(|(i, n)| (n, i)).collect()
+}
+
+#[cfg(test)]
+fn by_key(a: &(u64, usize), b: &(u64, usize)) -> Ordering {
+    a.0.cmp(&b.0)
+}
+
+#[test]
+fn test_sorts() {
+    use crate::comparator::{ascending, comparing};
+
+    for &len in &[0, 1, 2, 15, 16, 17, 100, 1000, 5000] {
+        let items = scrambled(len);
+        let mut expected = items.clone();
+        expected.sort_by(by_key);
+
+        let mut stable = items.clone();
+        merge_sort_by(&mut stable, by_key);
+        assert_eq!(stable, expected);
+        assert!(is_sorted_by(&stable, by_key));
+
+        for sort in &[quicksort_by::<_, fn(&_, &_) -> Ordering>, heapsort_by] {
+            let mut unstable = items.clone();
+            sort(&mut unstable, by_key);
+            assert!(is_sorted_by(&unstable, by_key));
+            unstable.sort();
+            assert_eq!(unstable, {
+                let mut all = items.clone();
+                all.sort();
+                all
+            });
+        }
+    }
+
+    // Already sorted, reversed and all-equal input are the usual quicksort
+    // killers
+    let mut ascending_items: Vec<u32> = (0..10_000).collect();
+    quicksort_by(&mut ascending_items, comparing(|&n: &u32| std::cmp::Reverse(n)));
+    assert!(ascending_items.iter().rev().copied().eq(0..10_000));
+    quicksort_by(&mut ascending_items, ascending());
+    assert!(ascending_items.iter().copied().eq(0..10_000));
+    let mut same = vec![7; 10_000];
+    quicksort_by(&mut same, ascending());
+    assert!(same.iter().all(|&n| n == 7));
+
+    assert!(is_sorted_by::<u8, _>(&[], ascending()));
+    assert!(!is_sorted_by(&[1, 3, 2], ascending()));
+    assert!(is_sorted_by(&[3, 3, 1], |a: &i32, b: &i32| b.cmp(a)));
+}
+
+#[test]
+fn test_selection() {
+    use crate::comparator::{ascending, comparing};
+
+    let mut items = bench_data(1001);
+    let mut sorted = items.clone();
+    sorted.sort();
+    for &n in &[0, 1, 500, 999, 1000] {
+        assert_eq!(*nth_element_by(&mut items, n, ascending()), sorted[n]);
+        assert!(items[..n].iter().all(|&item| item <= sorted[n]));
+        assert!(items[n + 1..].iter().all(|&item| item >= sorted[n]));
+    }
+
+    partial_sort_by(&mut items, 10, ascending());
+    assert_eq!(items[..10], sorted[..10]);
+    partial_sort_by(&mut items, 5000, ascending());
+    assert_eq!(items, sorted);
+
+    let words = ["pear", "fig", "banana", "kiwi", "apple", "plum"];
+    assert_eq!(top_k_by(words.iter().copied(), 3, comparing(|word: &&str| (word.len(), *word))), ["fig", "kiwi", "pear"]);
+    assert_eq!(top_k_by(1..=1_000_000, 3, comparing(|&n: &u32| std::cmp::Reverse(n))), [1_000_000, 999_999, 999_998]);
+    assert_eq!(top_k_by(vec![2, 1], 5, ascending()), [1, 2]);
+    assert!(top_k_by(vec![2, 1], 0, ascending()).is_empty());
+}
+
+#[test]
+fn test_merge_sorted() {
+    use crate::comparator::{ascending, comparing};
+
+    let merged: Vec<_> = merge_sorted_by(vec![vec![1, 4, 9], vec![], vec![2, 3, 10, 11], vec![0]], ascending()).collect();
+    assert_eq!(merged, [0, 1, 2, 3, 4, 9, 10, 11]);
+    let nothing: Vec<Vec<u8>> = Vec::new();
+    assert_eq!(merge_sorted_by(nothing, ascending()).next(), None);
+
+    // Ties come out in the order of the sources, and the sources can be
+    // any iterators, even endless ones
+    let evens = (0u32..).step_by(2).map(|n| (n, "even"));
+    let threes = (0u32..).step_by(3).map(|n| (n, "three"));
+    let merged = merge_sorted_by(vec![Box::new(threes) as Box<dyn Iterator<Item = _>>, Box::new(evens)],
+                                 comparing(|&(n, _): &(u32, &str)| n));
+    assert_eq!(merged.take(6).collect::<Vec<_>>(),
+               [(0, "three"), (0, "even"), (2, "even"), (3, "three"), (4, "even"), (6, "three")]);
+    assert_eq!(merge_sorted_by(vec![1..4, 0..2], ascending()).size_hint(), (5, Some(5)));
+}
+
+#[cfg(test)]
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rust_practice_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Every file under `dir`, which has at most one private directory in it
+#[cfg(test)]
+fn run_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        for run in fs::read_dir(entry.unwrap().path()).unwrap() {
+            files.push(run.unwrap().path());
+        }
+    }
+    files.sort_by(|a, b| crate::comparator::compare_human(&a.to_string_lossy(), &b.to_string_lossy()));
+    files
+}
+
+#[test]
+fn test_external_sort() {
+    use crate::comparator::{ascending, comparing};
+
+    let dir = scratch_dir("external_sort");
+    let config = ExternalSortConfig { dir: dir.clone(), run_len: 100, fan_in: 64 };
+    let items = scrambled(1234);
+    let mut expected = items.clone();
+    expected.sort_by(by_key);
+
+    // Stable, even across runs
+    let sorted = external_sort_by(items.clone(), &config, by_key).unwrap();
+    assert_eq!(run_files(&dir).len(), 13);
+    assert_eq!(sorted.collect::<io::Result<Vec<_>>>().unwrap(), expected);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+    // and across merge passes, with only a few files open at once: 124
+    // runs are merged into 42, 14, 5 and then 2
+    let few_open = ExternalSortConfig { run_len: 10, fan_in: 3, ..config.clone() };
+    let sorted = external_sort_by(items, &few_open, by_key).unwrap();
+    assert_eq!(run_files(&dir).len(), 2);
+    assert_eq!(sorted.collect::<io::Result<Vec<_>>>().unwrap(), expected);
+
+    // Numbers come back exactly
+    let one_each = ExternalSortConfig { run_len: 1, ..config.clone() };
+    let big = vec![9_007_199_254_740_993u64, u64::MAX, 1];
+    let sorted: io::Result<Vec<_>> = external_sort_by(big, &one_each, ascending()).unwrap().collect();
+    assert_eq!(sorted.unwrap(), [1, 9_007_199_254_740_993, u64::MAX]);
+    let mixed = vec![(Some(-0.5f64), "b".to_string(), 'é'), (None, "a".to_string(), 'x'), (Some(2.0), String::new(), '\0')];
+    let sorted: io::Result<Vec<_>> =
+        external_sort_by(mixed.clone(), &one_each, comparing(|item: &(Option<f64>, String, char)| item.1.clone()))
+            .unwrap().collect();
+    assert_eq!(sorted.unwrap(), [mixed[2].clone(), mixed[1].clone(), mixed[0].clone()]);
+
+    // Short input never touches the disk
+    let words = vec!["b".to_string(), "a".to_string()];
+    let mut sorted = external_sort_by(words, &config, |a: &String, b: &String| a.cmp(b)).unwrap();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    assert_eq!(sorted.next().unwrap().unwrap(), "a");
+
+    // A run that's been cut short, past what's already been buffered
+    let padded = |n: u32| format!("{:0>100}", n);
+    let sorted = external_sort_by((0..250).map(padded), &config, |a: &String, b: &String| a.cmp(b)).unwrap();
+    let item_len = 8 + 100;
+    File::options().write(true).open(&run_files(&dir)[0]).unwrap().set_len(95 * item_len + 50).unwrap();
+    let results: Vec<_> = sorted.collect();
+    assert_eq!(results.len(), 96);
+    assert!(results[..95].iter().map(|result| result.as_ref().unwrap()).eq((0..95).map(padded).collect::<Vec<_>>().iter()));
+    assert_eq!(results[95].as_ref().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn test_benchmark() {
+    let results = benchmark(2000).unwrap();
+    assert_eq!(results.len(), 9);
+    assert!(results.iter().all(|result| !result.to_string().is_empty()));
+}
@@ -0,0 +1,827 @@
+#![allow(dead_code)]
+
+// Time zones from the IANA tz database, for showing absolute times next to
+// RoughTimes. Zones are read from compiled TZif files (RFC 8536), either
+// out of a zoneinfo directory like /usr/share/zoneinfo or from the small
+// snapshot bundled with the crate, taken from tzdata 2025b. TZif files list
+// every transition up to some year and end with a POSIX TZ string, like
+// "EST5EDT,M3.2.0,M11.1.0", which gives the rule for every year after.
+//
+// Going from UTC to local time is always unambiguous. Going back isn't:
+// when clocks spring forward some local times never happen, and when they
+// fall back some happen twice, so from_local says which case it hit and
+// callers pick what they want with a Disambiguation.
+
+use crate::calendar::{days_from_civil, days_in_month, is_leap_year, DateTime, Weekday};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Where zones are usually installed on Unix systems
+pub const SYSTEM_ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+
+/// The TZif files compiled into the crate, by zone name
+const BUNDLED_ZONES: &[(&str, &[u8])] = &[
+    ("UTC", include_bytes!("zoneinfo/UTC")),
+    ("America/New_York", include_bytes!("zoneinfo/America/New_York")),
+    ("Europe/London", include_bytes!("zoneinfo/Europe/London")),
+    ("Asia/Kolkata", include_bytes!("zoneinfo/Asia/Kolkata")),
+    ("Australia/Lord_Howe", include_bytes!("zoneinfo/Australia/Lord_Howe"))
+];
+
+/// Every zone in the bundled snapshot
+pub fn bundled_zones() -> Vec<&'static str> {
+    BUNDLED_ZONES.iter().map(|&(name, _)| name).collect()
+}
+
+/// Why a zone couldn't be loaded
+#[derive(Debug)]
+pub enum TzError {
+    /// There's no zone by this name, or it isn't a valid zone name
+    UnknownZone(String),
+    /// The zone file exists but couldn't be read
+    Io(io::Error),
+    /// The data isn't a valid TZif file or POSIX TZ string
+    Malformed(&'static str)
+}
+
+impl fmt::Display for TzError {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TzError::UnknownZone(ref name) => write!(dest, "unknown time zone {:?}", name),
+            TzError::Io(ref error) => write!(dest, "couldn't read time zone: {}", error),
+            TzError::Malformed(reason) => write!(dest, "malformed time zone data: {}", reason)
+        }
+    }
+}
+
+impl std::error::Error for TzError {}
+
+impl From<io::Error> for TzError {
+    fn from(error: io::Error) -> Self {
+        TzError::Io(error)
+    }
+}
+
+/// An offset from UTC, with the name it goes by, like EST or BST
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LocalTimeType {
+    /// Seconds ahead of UTC, negative west of Greenwich
+    pub offset: i32,
+    pub is_dst: bool,
+    pub abbreviation: String
+}
+
+// POSIX TZ strings
+
+/// A day of the year in a POSIX TZ rule
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum RuleDay {
+    /// Jn: the nth day from 1 to 365, never counting February 29th
+    Julian(u16),
+    /// n: the nth day from 0 to 365, counting February 29th
+    ZeroBased(u16),
+    /// Mm.w.d: weekday d (0 is Sunday) of week w of month m, where week 5
+    /// means the last one
+    MonthWeekDay { month: u8, week: u8, weekday: u8 }
+}
+
+impl RuleDay {
+    /// The date this rule picks in `year`, as days since 1970-01-01
+    fn days_in(self, year: i32) -> i64 {
+        let january_1st = days_from_civil(year, 1, 1);
+        match self {
+            RuleDay::Julian(n) => {
+                let skip_leap_day = is_leap_year(year) && n >= 60;
+                january_1st + i64::from(n) - 1 + if skip_leap_day { 1 } else { 0 }
+            }
+            RuleDay::ZeroBased(n) => january_1st + i64::from(n),
+            RuleDay::MonthWeekDay { month, week, weekday } => {
+                let first = days_from_civil(year, month, 1);
+                let first_weekday = Weekday::from_days(first).number_from_monday() % 7;
+                let mut day = 1 + (7 + weekday - first_weekday) % 7 + (week - 1) * 7;
+                while day > days_in_month(year, month) {
+                    day -= 7;
+                }
+                first + i64::from(day) - 1
+            }
+        }
+    }
+}
+
+/// When daylight saving time starts and ends each year
+#[derive(Clone, Debug, PartialEq)]
+struct DstRule {
+    dst: LocalTimeType,
+    start: RuleDay,
+    /// Seconds after local midnight, in standard time, that DST starts
+    start_time: i32,
+    end: RuleDay,
+    /// Seconds after local midnight, in daylight time, that DST ends
+    end_time: i32
+}
+
+/// A parsed POSIX TZ string
+#[derive(Clone, Debug, PartialEq)]
+struct PosixTz {
+    standard: LocalTimeType,
+    dst: Option<DstRule>
+}
+
+impl PosixTz {
+    fn local_time_type_at(&self, unix: i64) -> &LocalTimeType {
+        let rule = match self.dst {
+            Some(ref rule) => rule,
+            None => return &self.standard
+        };
+        let year = DateTime::from_unix(unix).map_or(1970, |date| date.year());
+        let start = rule.start.days_in(year) * 86_400 + i64::from(rule.start_time)
+            - i64::from(self.standard.offset);
+        let end = rule.end.days_in(year) * 86_400 + i64::from(rule.end_time)
+            - i64::from(rule.dst.offset);
+        // Southern hemisphere rules end DST earlier in the year than they start it
+        let in_dst = if start < end {
+            start <= unix && unix < end
+        } else {
+            unix < end || start <= unix
+        };
+        if in_dst { &rule.dst } else { &self.standard }
+    }
+}
+
+// Reads a POSIX TZ string, like "<+1030>-10:30<+11>-11,M10.1.0,M4.1.0"
+struct PosixReader<'t> {
+    text: &'t str,
+    at: usize
+}
+
+impl<'t> PosixReader<'t> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.at).cloned()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.peek() == Some(byte) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn number(&mut self) -> Result<u32, TzError> {
+        let start = self.at;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.at += 1;
+        }
+        self.text[start..self.at].parse()
+            .map_err(|_| TzError::Malformed("expected a number in TZ string"))
+    }
+
+    /// An abbreviation, either alphabetic or quoted in angle brackets
+    fn name(&mut self) -> Result<String, TzError> {
+        let (start, end) = if self.eat(b'<') {
+            let start = self.at;
+            let length = self.text[start..].find('>')
+                .ok_or(TzError::Malformed("unterminated '<' in TZ string"))?;
+            self.at += length + 1;
+            (start, start + length)
+        } else {
+            let start = self.at;
+            while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                self.at += 1;
+            }
+            (start, self.at)
+        };
+        if end - start < 3 {
+            return Err(TzError::Malformed("abbreviation too short in TZ string"));
+        }
+        Ok(self.text[start..end].to_string())
+    }
+
+    /// A signed [+-]hh[:mm[:ss]] in seconds, as used for offsets and times,
+    /// with at most `max_hours` hours
+    fn duration(&mut self, max_hours: u32) -> Result<i32, TzError> {
+        let out_of_range = TzError::Malformed("offset or time out of range in TZ string");
+        let sign = if self.eat(b'-') { -1 } else { self.eat(b'+'); 1 };
+        let hours = self.number()?;
+        let (mut minutes, mut seconds) = (0, 0);
+        if self.eat(b':') {
+            minutes = self.number()?;
+            if self.eat(b':') {
+                seconds = self.number()?;
+            }
+        }
+        if hours > max_hours || minutes > 59 || seconds > 59 {
+            return Err(out_of_range);
+        }
+        // Small enough now that none of this can overflow
+        Ok(sign * (hours * 3600 + minutes * 60 + seconds) as i32)
+    }
+
+    /// A rule day, with an optional "/time" defaulting to 02:00
+    fn rule_day(&mut self) -> Result<(RuleDay, i32), TzError> {
+        let malformed = TzError::Malformed("bad rule date in TZ string");
+        let day = if self.eat(b'J') {
+            match self.number()? {
+                n @ 1..=365 => RuleDay::Julian(n as u16),
+                _ => return Err(malformed)
+            }
+        } else if self.eat(b'M') {
+            let month = self.number()?;
+            let week = if self.eat(b'.') { self.number()? } else { 0 };
+            let weekday = if self.eat(b'.') { self.number()? } else { 7 };
+            if !(1..=12).contains(&month) || !(1..=5).contains(&week) || weekday > 6 {
+                return Err(malformed);
+            }
+            RuleDay::MonthWeekDay { month: month as u8, week: week as u8, weekday: weekday as u8 }
+        } else {
+            match self.number()? {
+                n @ 0..=365 => RuleDay::ZeroBased(n as u16),
+                _ => return Err(malformed)
+            }
+        };
+        // RFC 8536 extends POSIX to allow rule times from -167 to 167 hours
+        let time = if self.eat(b'/') { self.duration(167)? } else { 2 * 3600 };
+        Ok((day, time))
+    }
+}
+
+fn parse_posix_tz(text: &str) -> Result<PosixTz, TzError> {
+    let mut reader = PosixReader { text, at: 0 };
+
+    // POSIX offsets count hours west of Greenwich, the opposite of ours
+    let standard = LocalTimeType {
+        abbreviation: reader.name()?,
+        offset: -reader.duration(24)?,
+        is_dst: false
+    };
+    if reader.peek().is_none() {
+        return Ok(PosixTz { standard, dst: None });
+    }
+
+    let abbreviation = reader.name()?;
+    let offset = match reader.peek() {
+        Some(b',') | None => standard.offset + 3600,
+        _ => -reader.duration(24)?
+    };
+    let dst = LocalTimeType { offset, is_dst: true, abbreviation };
+
+    // With no rule given, POSIX leaves it up to us; use the US one
+    let ((start, start_time), (end, end_time)) = if reader.eat(b',') {
+        let start = reader.rule_day()?;
+        if !reader.eat(b',') {
+            return Err(TzError::Malformed("expected ',' before DST end in TZ string"));
+        }
+        (start, reader.rule_day()?)
+    } else {
+        ((RuleDay::MonthWeekDay { month: 3, week: 2, weekday: 0 }, 7200),
+         (RuleDay::MonthWeekDay { month: 11, week: 1, weekday: 0 }, 7200))
+    };
+    if reader.peek().is_some() {
+        return Err(TzError::Malformed("unexpected text at end of TZ string"));
+    }
+    Ok(PosixTz { standard, dst: Some(DstRule { dst, start, start_time, end, end_time }) })
+}
+
+#[test]
+fn test_posix_tz_strings() {
+    let new_york = parse_posix_tz("EST5EDT,M3.2.0,M11.1.0").unwrap();
+    assert_eq!(new_york.standard.offset, -5 * 3600);
+    assert_eq!(new_york.dst.as_ref().unwrap().dst.offset, -4 * 3600);
+
+    let lord_howe = parse_posix_tz("<+1030>-10:30<+11>-11,M10.1.0,M4.1.0").unwrap();
+    assert_eq!(lord_howe.standard.abbreviation, "+1030");
+    assert_eq!(lord_howe.standard.offset, 10 * 3600 + 1800);
+    assert_eq!(lord_howe.dst.as_ref().unwrap().dst.offset, 11 * 3600);
+
+    let kolkata = parse_posix_tz("IST-5:30").unwrap();
+    assert_eq!(kolkata, PosixTz {
+        standard: LocalTimeType { offset: 5 * 3600 + 1800, is_dst: false,
+                                  abbreviation: "IST".to_string() },
+        dst: None
+    });
+
+    // Rule days: Julian days skip February 29th, zero-based ones don't,
+    // and week 5 means the last such weekday in the month
+    let day = |rule: RuleDay, year| {
+        let date = DateTime::from_unix(rule.days_in(year) * 86_400).unwrap();
+        (date.month(), date.day())
+    };
+    assert_eq!(day(RuleDay::Julian(60), 2016), (3, 1));
+    assert_eq!(day(RuleDay::Julian(60), 2015), (3, 1));
+    assert_eq!(day(RuleDay::ZeroBased(59), 2016), (2, 29));
+    assert_eq!(day(RuleDay::MonthWeekDay { month: 3, week: 2, weekday: 0 }, 2016), (3, 13));
+    assert_eq!(day(RuleDay::MonthWeekDay { month: 10, week: 5, weekday: 0 }, 2016), (10, 30));
+    assert_eq!(day(RuleDay::MonthWeekDay { month: 2, week: 5, weekday: 1 }, 2016), (2, 29));
+
+    let custom = parse_posix_tz("XXX3YYY,J60/-1,300/25:30").unwrap();
+    let rule = custom.dst.unwrap();
+    assert_eq!((rule.start_time, rule.end_time), (-3600, 25 * 3600 + 1800));
+    assert_eq!(rule.dst.offset, -2 * 3600);
+
+    let late = parse_posix_tz("XXX3YYY,M3.5.0/-167,M10.5.0/167").unwrap().dst.unwrap();
+    assert_eq!((late.start_time, late.end_time), (-167 * 3600, 167 * 3600));
+    assert_eq!(parse_posix_tz("EST99999999").unwrap_err().to_string(),
+               "malformed time zone data: offset or time out of range in TZ string");
+
+    for bad in &["", "E5", "EST", "EST5EDT,M3.2.0", "EST5EDT,M13.1.0,M11.1.0", "<EST5", "EST5EDT,J0,J1",
+                 "EST25", "EST5:60", "EST5EDT99999999999", "EST5EDT,M3.2.0/168,M11.1.0", "EST-5:00:99"] {
+        assert!(parse_posix_tz(bad).is_err(), "{:?} should be rejected", bad);
+    }
+}
+
+// TZif files
+
+// Reads big-endian fields out of a TZif file
+struct TzifReader<'d> {
+    data: &'d [u8],
+    at: usize
+}
+
+impl<'d> TzifReader<'d> {
+    fn take(&mut self, n: usize) -> Result<&'d [u8], TzError> {
+        let bytes = self.at.checked_add(n)
+            .and_then(|end| self.data.get(self.at..end))
+            .ok_or(TzError::Malformed("unexpected end of TZif data"))?;
+        self.at += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, TzError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, TzError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    /// A transition time, 4 bytes in version 1 data and 8 after
+    fn time(&mut self, size: usize) -> Result<i64, TzError> {
+        if size == 4 {
+            return Ok(i64::from(self.u32()? as i32));
+        }
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(i64::from_be_bytes(bytes))
+    }
+}
+
+/// The counts from a TZif header
+struct TzifCounts {
+    is_ut: usize,
+    is_std: usize,
+    leap: usize,
+    time: usize,
+    types: usize,
+    chars: usize
+}
+
+impl TzifCounts {
+    /// Length of the data block these counts describe
+    fn block_len(&self, time_size: usize) -> usize {
+        self.time * (time_size + 1) + self.types * 6 + self.chars
+            + self.leap * (time_size + 4) + self.is_std + self.is_ut
+    }
+}
+
+fn read_tzif_header(reader: &mut TzifReader) -> Result<(u8, TzifCounts), TzError> {
+    if reader.take(4)? != b"TZif" {
+        return Err(TzError::Malformed("missing TZif magic"));
+    }
+    let version = reader.u8()?;
+    reader.take(15)?;
+    let mut count = || reader.u32().map(|n| n as usize);
+    let counts = TzifCounts {
+        is_ut: count()?,
+        is_std: count()?,
+        leap: count()?,
+        time: count()?,
+        types: count()?,
+        chars: count()?
+    };
+    if counts.types == 0 || counts.chars == 0
+        || (counts.is_ut != 0 && counts.is_ut != counts.types)
+        || (counts.is_std != 0 && counts.is_std != counts.types) {
+        return Err(TzError::Malformed("inconsistent counts in TZif header"));
+    }
+    Ok((version, counts))
+}
+
+/// A time zone's full history of offsets
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeZone {
+    name: String,
+    /// Unix times at which the offset changes, ascending
+    transitions: Vec<i64>,
+    /// Index into `types` for the time starting at each transition
+    transition_types: Vec<usize>,
+    /// Index 0 is in effect before the first transition
+    types: Vec<LocalTimeType>,
+    /// The rule for times after the last transition
+    rule: Option<PosixTz>
+}
+
+impl TimeZone {
+    /// Coordinated Universal Time, which never changes
+    pub fn utc() -> TimeZone {
+        TimeZone {
+            name: "UTC".to_string(),
+            transitions: Vec::new(),
+            transition_types: Vec::new(),
+            types: vec![LocalTimeType { offset: 0, is_dst: false, abbreviation: "UTC".to_string() }],
+            rule: None
+        }
+    }
+
+    /// Parse the contents of a TZif file. Leap second records are skipped,
+    /// since DateTime counts time the way Unix does, without them.
+    pub fn parse_tzif(name: &str, data: &[u8]) -> Result<TimeZone, TzError> {
+        let mut reader = TzifReader { data, at: 0 };
+        let (version, mut counts) = read_tzif_header(&mut reader)?;
+        let mut time_size = 4;
+        if version >= b'2' {
+            // Skip the 32-bit data for the 64-bit copy that follows it
+            reader.take(counts.block_len(4))?;
+            counts = read_tzif_header(&mut reader)?.1;
+            time_size = 8;
+        }
+
+        let mut transitions = Vec::with_capacity(counts.time.min(data.len()));
+        for _ in 0..counts.time {
+            transitions.push(reader.time(time_size)?);
+        }
+        if transitions.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(TzError::Malformed("TZif transitions out of order"));
+        }
+        let transition_types: Vec<usize> = reader.take(counts.time)?
+            .iter()
+            .map(|&index| usize::from(index))
+            .collect();
+        if transition_types.iter().any(|&index| index >= counts.types) {
+            return Err(TzError::Malformed("TZif transition has no such local time type"));
+        }
+
+        let mut raw_types = Vec::with_capacity(counts.types);
+        for _ in 0..counts.types {
+            let offset = reader.u32()? as i32;
+            let is_dst = reader.u8()? != 0;
+            let abbreviation_index = usize::from(reader.u8()?);
+            raw_types.push((offset, is_dst, abbreviation_index));
+        }
+        let chars = reader.take(counts.chars)?;
+        let mut types = Vec::with_capacity(counts.types);
+        for (offset, is_dst, abbreviation_index) in raw_types {
+            let abbreviation = chars.get(abbreviation_index..)
+                .and_then(|rest| rest.split(|&c| c == 0).next())
+                .filter(|_| abbreviation_index < chars.len())
+                .ok_or(TzError::Malformed("TZif abbreviation index out of range"))?;
+            if offset == i32::MIN {
+                return Err(TzError::Malformed("TZif offset out of range"));
+            }
+            types.push(LocalTimeType {
+                offset,
+                is_dst,
+                abbreviation: String::from_utf8_lossy(abbreviation).into_owned()
+            });
+        }
+        reader.take(counts.leap * (time_size + 4) + counts.is_std + counts.is_ut)?;
+
+        let mut rule = None;
+        if version >= b'2' {
+            let footer = &data[reader.at..];
+            if footer.len() < 2 || footer[0] != b'\n' || footer[footer.len() - 1] != b'\n' {
+                return Err(TzError::Malformed("TZif footer must be on its own line"));
+            }
+            let text = std::str::from_utf8(&footer[1..footer.len() - 1])
+                .map_err(|_| TzError::Malformed("TZif footer isn't ASCII"))?;
+            if !text.is_empty() {
+                rule = Some(parse_posix_tz(text)?);
+            }
+        }
+
+        Ok(TimeZone { name: name.to_string(), transitions, transition_types, types, rule })
+    }
+
+    /// Load the zone `name`, like "Europe/London", from a zoneinfo directory
+    pub fn from_dir<P: AsRef<Path>>(dir: P, name: &str) -> Result<TimeZone, TzError> {
+        let valid = !name.is_empty() && name.split('/').all(|part| {
+            !part.is_empty() && !part.starts_with('.')
+        });
+        if !valid {
+            return Err(TzError::UnknownZone(name.to_string()));
+        }
+        let data = match fs::read(dir.as_ref().join(name)) {
+            Ok(data) => data,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound =>
+                return Err(TzError::UnknownZone(name.to_string())),
+            Err(e) => return Err(TzError::Io(e))
+        };
+        TimeZone::parse_tzif(name, &data)
+    }
+
+    /// Load the zone `name` from the snapshot bundled with the crate
+    pub fn bundled(name: &str) -> Result<TimeZone, TzError> {
+        let &(_, data) = BUNDLED_ZONES.iter()
+            .find(|&&(bundled, _)| bundled == name)
+            .ok_or_else(|| TzError::UnknownZone(name.to_string()))?;
+        TimeZone::parse_tzif(name, data)
+    }
+
+    /// Load the zone `name` from the directory in `$TZDIR`, or the system's
+    /// zoneinfo directory, falling back to the bundled snapshot if it isn't
+    /// installed there
+    pub fn load(name: &str) -> Result<TimeZone, TzError> {
+        let dir = std::env::var_os("TZDIR")
+            .unwrap_or_else(|| SYSTEM_ZONEINFO_DIR.into());
+        match TimeZone::from_dir(dir, name) {
+            Err(TzError::UnknownZone(_)) | Err(TzError::Io(_)) => TimeZone::bundled(name),
+            result => result
+        }
+    }
+
+    /// The name this zone was loaded under
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn local_time_type_at_unix(&self, unix: i64) -> &LocalTimeType {
+        let after = self.transitions.partition_point(|&transition| transition <= unix);
+        match (after, &self.rule) {
+            (0, Some(rule)) if self.transitions.is_empty() => rule.local_time_type_at(unix),
+            (0, _) => &self.types[0],
+            (n, Some(rule)) if n == self.transitions.len() => rule.local_time_type_at(unix),
+            (n, _) => &self.types[self.transition_types[n - 1]]
+        }
+    }
+
+    /// The offset and abbreviation in effect at the UTC time `utc`
+    pub fn local_time_type(&self, utc: DateTime) -> &LocalTimeType {
+        self.local_time_type_at_unix(utc.to_unix())
+    }
+
+    /// What the clocks in this zone read at the UTC time `utc`. Returns
+    /// `None` if that's past the ends of `DateTime`'s range.
+    pub fn to_local(&self, utc: DateTime) -> Option<ZonedDateTime> {
+        let local_type = self.local_time_type(utc).clone();
+        let local = utc.add_nanos(i128::from(local_type.offset) * 1_000_000_000)?;
+        Some(ZonedDateTime { utc, local, local_type })
+    }
+
+    /// Find the UTC times at which clocks in this zone read `local`
+    pub fn from_local(&self, local: DateTime) -> LocalResult {
+        let to_utc = |offset: i32| local.add_nanos(-i128::from(offset) * 1_000_000_000);
+
+        // No zone's offset changes twice in two days, so the offsets either
+        // side of `local` are the only ones it could be using
+        let naive = local.to_unix();
+        let probes = [naive - 86_400, naive, naive + 86_400];
+        let offsets: Vec<i32> = probes.iter()
+            .map(|&unix| self.local_time_type_at_unix(unix).offset)
+            .collect();
+
+        let mut instants: Vec<DateTime> = offsets.iter()
+            .filter_map(|&offset| {
+                to_utc(offset).filter(|utc| self.local_time_type(*utc).offset == offset)
+            })
+            .collect();
+        instants.sort();
+        instants.dedup();
+
+        match instants.len() {
+            0 => {
+                // Only at the very ends of DateTime's range can these fail
+                let before = to_utc(offsets[0]).unwrap_or(local);
+                let after = to_utc(offsets[2]).unwrap_or(local);
+                LocalResult::Missing { earlier: before.min(after), later: before.max(after) }
+            }
+            1 => LocalResult::Single(instants[0]),
+            n => LocalResult::Ambiguous { earlier: instants[0], later: instants[n - 1] }
+        }
+    }
+
+    /// The UTC time at which clocks in this zone read `local`, settling
+    /// gaps and overlaps as `how` says
+    pub fn resolve(&self, local: DateTime, how: Disambiguation) -> Option<DateTime> {
+        self.from_local(local).resolve(how)
+    }
+}
+
+/// The UTC times matching a local time
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LocalResult {
+    /// The local time happened exactly once
+    Single(DateTime),
+    /// The clocks went back over this local time, so it happened twice
+    Ambiguous { earlier: DateTime, later: DateTime },
+    /// The clocks jumped forward over this local time, so it never
+    /// happened. `earlier` reads it with the offset from after the jump,
+    /// landing before the jump, and `later` with the offset from before.
+    Missing { earlier: DateTime, later: DateTime }
+}
+
+/// How to choose a UTC time for a local time that didn't happen exactly once
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Disambiguation {
+    /// The earlier of two times, but the later for a gap, so wall clock
+    /// times in a gap move forward by the length of the gap
+    Compatible,
+    Earlier,
+    Later,
+    /// Give up
+    Reject
+}
+
+impl LocalResult {
+    /// Settle on one UTC time, if `how` allows it
+    pub fn resolve(self, how: Disambiguation) -> Option<DateTime> {
+        use self::Disambiguation::*;
+
+        match (self, how) {
+            (LocalResult::Single(utc), _) => Some(utc),
+            (_, Reject) => None,
+            (LocalResult::Ambiguous { earlier, .. }, Compatible) |
+            (LocalResult::Ambiguous { earlier, .. }, Earlier) |
+            (LocalResult::Missing { earlier, .. }, Earlier) => Some(earlier),
+            (LocalResult::Ambiguous { later, .. }, Later) |
+            (LocalResult::Missing { later, .. }, Compatible) |
+            (LocalResult::Missing { later, .. }, Later) => Some(later)
+        }
+    }
+}
+
+/// A moment along with what the clocks in some zone read at it
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZonedDateTime {
+    utc: DateTime,
+    local: DateTime,
+    local_type: LocalTimeType
+}
+
+impl ZonedDateTime {
+    pub fn utc(&self) -> DateTime { self.utc }
+    pub fn local(&self) -> DateTime { self.local }
+    pub fn offset(&self) -> i32 { self.local_type.offset }
+    pub fn is_dst(&self) -> bool { self.local_type.is_dst }
+    pub fn abbreviation(&self) -> &str { &self.local_type.abbreviation }
+}
+
+/// Prints an RFC 3339 timestamp and the abbreviation, like
+/// "2016-02-09T16:42:00-05:00 EST". Offsets that aren't whole minutes, as
+/// local mean time often had, can't be written in RFC 3339, so those
+/// times are printed in UTC instead.
+impl fmt::Display for ZonedDateTime {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        let timestamp = self.utc.to_rfc3339_with_offset(self.offset())
+            .unwrap_or_else(|| self.utc.to_string());
+        write!(dest, "{} {}", timestamp, self.abbreviation())
+    }
+}
+
+#[test]
+fn test_bundled_zones_to_local() {
+    let utc = |text: &str| text.parse::<DateTime>().unwrap();
+    let show = |zone: &str, text: &str| {
+        TimeZone::bundled(zone).unwrap().to_local(utc(text)).unwrap().to_string()
+    };
+
+    assert_eq!(show("America/New_York", "2016-02-09T21:42:00Z"), "2016-02-09T16:42:00-05:00 EST");
+    assert_eq!(show("America/New_York", "2016-07-04T16:00:00Z"), "2016-07-04T12:00:00-04:00 EDT");
+    assert_eq!(show("America/New_York", "1943-06-01T12:00:00Z"), "1943-06-01T08:00:00-04:00 EWT");
+    assert_eq!(show("America/New_York", "1850-01-01T12:00:00Z"), "1850-01-01T12:00:00Z LMT");
+    assert_eq!(show("Europe/London", "2016-07-04T16:00:00Z"), "2016-07-04T17:00:00+01:00 BST");
+    assert_eq!(show("Europe/London", "2016-12-25T16:00:00Z"), "2016-12-25T16:00:00Z GMT");
+    assert_eq!(show("Asia/Kolkata", "2016-02-09T21:42:00Z"), "2016-02-10T03:12:00+05:30 IST");
+    assert_eq!(show("Australia/Lord_Howe", "2016-01-01T00:00:00Z"), "2016-01-01T11:00:00+11:00 +11");
+    assert_eq!(show("Australia/Lord_Howe", "2016-07-01T00:00:00Z"), "2016-07-01T10:30:00+10:30 +1030");
+    assert_eq!(show("UTC", "2016-02-09T21:42:00Z"), "2016-02-09T21:42:00Z UTC");
+
+    // Past the last listed transition, the TZ string rule takes over
+    assert_eq!(show("America/New_York", "2100-07-04T16:00:00Z"), "2100-07-04T12:00:00-04:00 EDT");
+    assert_eq!(show("America/New_York", "2100-12-25T16:00:00Z"), "2100-12-25T11:00:00-05:00 EST");
+    assert_eq!(show("Australia/Lord_Howe", "2100-01-01T00:00:00Z"), "2100-01-01T11:00:00+11:00 +11");
+
+    assert_eq!(TimeZone::utc().to_local(utc("2016-02-09T21:42:00Z")).unwrap().to_string(),
+               "2016-02-09T21:42:00Z UTC");
+}
+
+#[test]
+fn test_rule_matches_transition_table() {
+    // Fat TZif files list transitions until 2037, so for the years before
+    // that, the TZ string rule must agree with the table hour by hour
+    for name in &["America/New_York", "Europe/London", "Australia/Lord_Howe"] {
+        let zone = TimeZone::bundled(name).unwrap();
+        let rule = zone.rule.as_ref().unwrap();
+        let start = DateTime::from_ymd(2008, 1, 1).unwrap().to_unix();
+        let end = DateTime::from_ymd(2037, 1, 1).unwrap().to_unix();
+        for unix in (start..end).step_by(1800) {
+            assert_eq!(zone.local_time_type_at_unix(unix), rule.local_time_type_at(unix),
+                       "{} at {}", name, unix);
+        }
+    }
+}
+
+#[test]
+fn test_gaps_and_overlaps() {
+    use self::Disambiguation::*;
+
+    let local = |text: &str| text.parse::<DateTime>().unwrap();
+    let new_york = TimeZone::bundled("America/New_York").unwrap();
+
+    assert_eq!(new_york.from_local(local("2016-02-09T16:42:00Z")),
+               LocalResult::Single(local("2016-02-09T21:42:00Z")));
+
+    // Clocks sprang forward from 02:00 to 03:00 on March 13th, 2016
+    let in_gap = new_york.from_local(local("2016-03-13T02:30:00Z"));
+    assert_eq!(in_gap, LocalResult::Missing { earlier: local("2016-03-13T06:30:00Z"),
+                                              later: local("2016-03-13T07:30:00Z") });
+    assert_eq!(in_gap.resolve(Compatible), Some(local("2016-03-13T07:30:00Z")));
+    assert_eq!(in_gap.resolve(Earlier), Some(local("2016-03-13T06:30:00Z")));
+    assert_eq!(in_gap.resolve(Reject), None);
+
+    // and fell back from 02:00 to 01:00 on November 6th
+    let in_overlap = new_york.from_local(local("2016-11-06T01:30:00Z"));
+    assert_eq!(in_overlap, LocalResult::Ambiguous { earlier: local("2016-11-06T05:30:00Z"),
+                                                    later: local("2016-11-06T06:30:00Z") });
+    assert_eq!(in_overlap.resolve(Compatible), Some(local("2016-11-06T05:30:00Z")));
+    assert_eq!(in_overlap.resolve(Later), Some(local("2016-11-06T06:30:00Z")));
+    assert_eq!(new_york.resolve(local("2016-11-06T01:30:00Z"), Reject), None);
+
+    // The same, decided by the TZ string rule
+    assert!(matches!(new_york.from_local(local("2100-03-14T02:30:00Z")), LocalResult::Missing { .. }));
+    assert!(matches!(new_york.from_local(local("2100-11-07T01:30:00Z")), LocalResult::Ambiguous { .. }));
+
+    // Lord Howe Island's clocks only go back half an hour
+    let lord_howe = TimeZone::bundled("Australia/Lord_Howe").unwrap();
+    assert_eq!(lord_howe.from_local(local("2016-04-03T01:45:00Z")),
+               LocalResult::Ambiguous { earlier: local("2016-04-02T14:45:00Z"),
+                                        later: local("2016-04-02T15:15:00Z") });
+    assert_eq!(lord_howe.from_local(local("2016-04-03T02:15:00Z")),
+               LocalResult::Single(local("2016-04-02T15:45:00Z")));
+
+    // Every resolved local time maps back to itself
+    for zone in bundled_zones() {
+        let zone = TimeZone::bundled(zone).unwrap();
+        let mut utc = local("2016-01-01T00:00:00Z");
+        while utc.year() == 2016 {
+            let zoned = zone.to_local(utc).unwrap();
+            let resolved = zone.resolve(zoned.local(), Compatible).unwrap();
+            assert_eq!(zone.to_local(resolved).unwrap().local(), zoned.local());
+            utc = utc.add_nanos(3 * 60 * 1_000_000_000).unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_loading_zones() {
+    let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/src/zoneinfo");
+    let london = TimeZone::from_dir(fixtures, "Europe/London").unwrap();
+    assert_eq!(london, TimeZone::bundled("Europe/London").unwrap());
+    assert_eq!(london.name(), "Europe/London");
+    assert_eq!(TimeZone::load("Asia/Kolkata").unwrap().name(), "Asia/Kolkata");
+
+    for name in &["Mars/Olympus_Mons", "../zoneinfo/UTC", "/etc/passwd", "", "Europe//London"] {
+        match TimeZone::from_dir(fixtures, name) {
+            Err(TzError::UnknownZone(ref unknown)) => assert_eq!(unknown, name),
+            other => panic!("loading {:?} gave {:?}", name, other)
+        }
+    }
+    assert!(matches!(TimeZone::bundled("Mars/Olympus_Mons"), Err(TzError::UnknownZone(_))));
+}
+
+#[test]
+fn test_malformed_tzif() {
+    let malformed = |data: &[u8]| match TimeZone::parse_tzif("test", data) {
+        Err(TzError::Malformed(reason)) => reason,
+        other => panic!("expected an error, got {:?}", other)
+    };
+    let utc = BUNDLED_ZONES[0].1;
+
+    assert_eq!(malformed(b"TZjf2"), "missing TZif magic");
+    assert_eq!(malformed(&utc[..40]), "unexpected end of TZif data");
+    assert_eq!(malformed(&utc[..utc.len() - 1]), "TZif footer must be on its own line");
+
+    let mut bad_footer = utc[..utc.len() - 5].to_vec();
+    bad_footer.extend_from_slice(b"U0\n");
+    assert_eq!(malformed(&bad_footer), "abbreviation too short in TZ string");
+
+    // A version 1 file with no footer and a single fixed offset
+    let mut version_1 = b"TZif\0".to_vec();
+    version_1.extend_from_slice(&[0; 15]);
+    for count in &[0u32, 0, 0, 0, 1, 4] {
+        version_1.extend_from_slice(&count.to_be_bytes());
+    }
+    version_1.extend_from_slice(&3600i32.to_be_bytes());
+    version_1.extend_from_slice(&[0, 0]);
+    version_1.extend_from_slice(b"CET\0");
+    let zone = TimeZone::parse_tzif("CET", &version_1).unwrap();
+    let local = zone.to_local(DateTime::from_unix(0).unwrap()).unwrap();
+    assert_eq!(local.to_string(), "1970-01-01T01:00:00+01:00 CET");
+
+    let abbreviation_index = version_1.len() - 5;
+    version_1[abbreviation_index] = 9;
+    assert_eq!(malformed(&version_1), "TZif abbreviation index out of range");
+}